@@ -0,0 +1,868 @@
+use asm::encoder::{encode, is_mnemonic};
+use asm::expr::eval;
+use asm::operand::{split_operands, Operand};
+use asm::AsmError;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// === Source assembler ===
+//
+// Two passes over the source: the first one assigns addresses to labels,
+// the second one encodes the instructions with the complete symbol table.
+//
+// Each line is
+//
+//   [label[:]] [mnemonic or directive [operands]] [; comment]
+//
+// A label starts in the first column or ends with a colon. Labels starting
+// with a dot are local to the preceding global label: `.loop` after `print:`
+// is the symbol `print.loop`.
+//
+// Directives: ORG, EQU, DB/DEFB/DM/DEFM, DW/DEFW, DS/DEFS, END,
+// INCLUDE "file", INCBIN "file"[,offset[,length]], IF/ELSE/ENDIF,
+// REPT count ... ENDM and
+//
+//   name MACRO param1, param2
+//        ...
+//        ENDM
+//
+// In a macro body the parameters are replaced by the arguments of the
+// invocation and `\@` by a number unique to each expansion, for labels.
+// IF, REPT and ORG expressions can only use symbols already defined.
+
+/// Maximum nesting of includes, macros and repetitions.
+const MAX_DEPTH: usize = 32;
+
+/// A block of object code starting at address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// Output of the assembler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    /// Object code, one segment per ORG.
+    pub segments: Vec<Segment>,
+    /// Labels and EQU values, local labels included.
+    pub symbols: BTreeMap<String, i32>,
+    /// Assembled lines, macro expansions included.
+    pub lines: Vec<ListingLine>,
+}
+
+/// A source line with the address and object code it produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingLine {
+    /// Included file, None for the main source.
+    pub file: Option<String>,
+    /// Line number, starting from 1.
+    pub line: usize,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub source: String,
+}
+
+impl Program {
+    /// Lowest address with object code.
+    pub fn origin(&self) -> u16 {
+        self.segments
+            .iter()
+            .filter(|segment| !segment.bytes.is_empty())
+            .map(|segment| segment.address)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Object code from origin to the highest address used,
+    /// with gaps between segments filled with zeros.
+    pub fn bytes(&self) -> Vec<u8> {
+        let origin = usize::from(self.origin());
+        let end = self
+            .segments
+            .iter()
+            .map(|segment| usize::from(segment.address) + segment.bytes.len())
+            .max()
+            .unwrap_or(origin);
+
+        let mut image = vec![0; end.saturating_sub(origin)];
+        for segment in &self.segments {
+            let start = usize::from(segment.address);
+            if !segment.bytes.is_empty() {
+                image[start - origin..start - origin + segment.bytes.len()]
+                    .copy_from_slice(&segment.bytes);
+            }
+        }
+
+        image
+    }
+
+    /// Copy the object code into memory at the assembled addresses.
+    pub fn load(&self, memory: &mut [u8]) {
+        for segment in &self.segments {
+            for (offset, byte) in segment.bytes.iter().enumerate() {
+                let addr = usize::from(segment.address) + offset;
+                if addr < memory.len() {
+                    memory[addr] = *byte;
+                }
+            }
+        }
+    }
+
+    /// Value of a symbol.
+    pub fn symbol(&self, name: &str) -> Option<i32> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// Listing with line number, address, object code and source.
+    /// Object code longer than 4 bytes continues on the following rows.
+    pub fn listing(&self) -> String {
+        let mut text = String::new();
+
+        for line in &self.lines {
+            let mut rows = line.bytes.chunks(4);
+            let first: Vec<String> = rows
+                .next()
+                .unwrap_or(&[])
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+
+            text.push_str(&format!(
+                "{:5}  {:04x}  {:<12} {}\n",
+                line.line,
+                line.address,
+                first.join(" "),
+                line.source
+            ));
+
+            let mut address = line.address;
+            for row in rows {
+                address = address.wrapping_add(4);
+                let bytes: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+                text.push_str(&format!("       {:04x}  {}\n", address, bytes.join(" ")));
+            }
+        }
+
+        text
+    }
+}
+
+/// Assemble source text. Included files are relative to the current directory.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    Assembler::new(PathBuf::new()).run(&source_lines(source, None))
+}
+
+/// Assemble a source file. Included files are relative to its directory.
+pub fn assemble_file(path: &Path) -> Result<Program, AsmError> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| AsmError::new(format!("{}: {}", path.display(), e)))?;
+    let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    Assembler::new(base).run(&source_lines(&source, None))
+}
+
+/// A line of source, with its location for error messages and listings.
+#[derive(Debug, Clone)]
+struct SourceLine {
+    file: Option<Rc<String>>,
+    number: usize,
+    text: String,
+}
+
+fn source_lines(source: &str, file: Option<Rc<String>>) -> Vec<SourceLine> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(number, text)| SourceLine {
+            file: file.clone(),
+            number: number + 1,
+            text: text.to_string(),
+        })
+        .collect()
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>,
+}
+
+/// State of an IF block.
+struct Condition {
+    /// Lines of the current branch are assembled.
+    active: bool,
+    /// One of the branches has been taken.
+    taken: bool,
+    else_seen: bool,
+}
+
+struct Assembler {
+    /// Directory of the main source file.
+    base: PathBuf,
+    pass: u8,
+    /// Address of the next byte, up to 0x10000.
+    pc: u32,
+    /// Last global label, owner of the local ones.
+    global: String,
+    symbols: BTreeMap<String, i32>,
+    segments: Vec<Segment>,
+    ended: bool,
+    macros: HashMap<String, Macro>,
+    conditions: Vec<Condition>,
+    /// Number of macro expansions, for `\@`.
+    expansions: usize,
+    lines: Vec<ListingLine>,
+}
+
+/// A line split in its fields.
+struct Statement<'a> {
+    label: Option<&'a str>,
+    mnemonic: Option<&'a str>,
+    operands: &'a str,
+}
+
+impl Assembler {
+    fn new(base: PathBuf) -> Assembler {
+        Assembler {
+            base,
+            pass: 1,
+            pc: 0,
+            global: String::new(),
+            symbols: BTreeMap::new(),
+            segments: Vec::new(),
+            ended: false,
+            macros: HashMap::new(),
+            conditions: Vec::new(),
+            expansions: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    fn run(mut self, source: &[SourceLine]) -> Result<Program, AsmError> {
+        for pass in 1..=2 {
+            self.start(pass);
+            self.block(source, 0)?;
+
+            if !self.conditions.is_empty() {
+                return Err(AsmError::new("IF without ENDIF".to_string()));
+            }
+        }
+
+        Ok(self.program())
+    }
+
+    fn start(&mut self, pass: u8) {
+        self.pass = pass;
+        self.pc = 0;
+        self.global.clear();
+        self.segments = vec![Segment {
+            address: 0,
+            bytes: Vec::new(),
+        }];
+        self.ended = false;
+        self.macros.clear();
+        self.conditions.clear();
+        self.expansions = 0;
+        self.lines.clear();
+    }
+
+    fn program(self) -> Program {
+        Program {
+            segments: self
+                .segments
+                .into_iter()
+                .filter(|segment| !segment.bytes.is_empty())
+                .collect(),
+            symbols: self.symbols,
+            lines: self.lines,
+        }
+    }
+
+    /// Lines of conditionally assembled code are skipped.
+    fn active(&self) -> bool {
+        self.conditions.iter().all(|condition| condition.active)
+    }
+
+    fn is_keyword(&self, name: &str) -> bool {
+        is_mnemonic(name) || is_directive(name) || self.macros.contains_key(name)
+    }
+
+    /// Assemble a sequence of lines: a file, a macro expansion
+    /// or a repetition.
+    fn block(&mut self, lines: &[SourceLine], depth: usize) -> Result<(), AsmError> {
+        if depth > MAX_DEPTH {
+            return Err(AsmError::new("Includes or macros nested too deep".to_string()));
+        }
+
+        let mut pos = 0;
+
+        while pos < lines.len() && !self.ended {
+            let line = &lines[pos];
+            pos += 1;
+
+            self.statement(line, lines, &mut pos, depth)
+                .map_err(|error| error.at(line.file.as_ref().map(|f| f.as_str()), line.number))?;
+        }
+
+        Ok(())
+    }
+
+    fn statement(
+        &mut self,
+        line: &SourceLine,
+        lines: &[SourceLine],
+        pos: &mut usize,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let parsed = parse_line(&line.text, &|name| self.is_keyword(name));
+
+        // Skipped lines only matter for IF nesting, and may be invalid.
+        let statement = match parsed {
+            Ok(statement) => statement,
+            Err(_) if !self.active() => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let mnemonic = statement.mnemonic.map(|m| m.to_lowercase()).unwrap_or_default();
+
+        match mnemonic.as_str() {
+            "if" | "else" | "endif" => {
+                self.list(line);
+                return self.condition(&mnemonic, statement.operands);
+            }
+            _ if !self.active() => return Ok(()),
+            _ => {}
+        }
+
+        self.list(line);
+
+        match mnemonic.as_str() {
+            "macro" => {
+                let name = statement
+                    .label
+                    .ok_or_else(|| AsmError::new("MACRO without a name".to_string()))?;
+                let params = split_operands(statement.operands);
+                if let Some(param) = params.iter().find(|param| !is_symbol(param)) {
+                    return Err(AsmError::new(format!("Invalid macro parameter: {}", param)));
+                }
+                let body = self.body(lines, pos)?;
+                self.macros.insert(name.to_lowercase(), Macro { params, body });
+                Ok(())
+            }
+            "rept" => {
+                if let Some(label) = statement.label {
+                    self.define(label, self.pc as i32)?;
+                }
+                let count = self.defined_expression(statement.operands)?;
+                let body = self.body(lines, pos)?;
+                for _ in 0..count {
+                    self.block(&body, depth + 1)?;
+                }
+                Ok(())
+            }
+            "endm" | "endr" => Err(AsmError::new(format!(
+                "{} without MACRO or REPT",
+                mnemonic.to_uppercase()
+            ))),
+            "include" => {
+                if let Some(label) = statement.label {
+                    self.define(label, self.pc as i32)?;
+                }
+                let (path, source) = self.read_text(statement.operands)?;
+                let name = Rc::new(path.display().to_string());
+                self.block(&source_lines(&source, Some(name)), depth + 1)
+            }
+            _ if self.macros.contains_key(&mnemonic) => {
+                if let Some(label) = statement.label {
+                    self.define(label, self.pc as i32)?;
+                }
+                let body = self.expand(&mnemonic, statement.operands)?;
+                self.block(&body, depth + 1)
+            }
+            _ => self.line(statement),
+        }
+    }
+
+    /// Record a line in the listing. Object code emitted
+    /// until the next line is recorded belongs to it.
+    fn list(&mut self, line: &SourceLine) {
+        if self.pass == 2 {
+            self.lines.push(ListingLine {
+                file: line.file.as_ref().map(|file| file.to_string()),
+                line: line.number,
+                address: self.pc as u16,
+                bytes: Vec::new(),
+                source: line.text.clone(),
+            });
+        }
+    }
+
+    fn condition(&mut self, directive: &str, operands: &str) -> Result<(), AsmError> {
+        match directive {
+            "if" => {
+                // Nested in a skipped block the expression is not evaluated.
+                let active = self.active() && self.defined_expression(operands)? != 0;
+                let parent = self.active();
+                self.conditions.push(Condition {
+                    active,
+                    taken: active || !parent,
+                    else_seen: false,
+                });
+            }
+            "else" => {
+                let condition = self
+                    .conditions
+                    .last_mut()
+                    .ok_or_else(|| AsmError::new("ELSE without IF".to_string()))?;
+                if condition.else_seen {
+                    return Err(AsmError::new("Duplicate ELSE".to_string()));
+                }
+                condition.else_seen = true;
+                condition.active = !condition.taken;
+                condition.taken = true;
+            }
+            _ => {
+                self.conditions
+                    .pop()
+                    .ok_or_else(|| AsmError::new("ENDIF without IF".to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collect the lines up to the ENDM matching a MACRO or REPT.
+    fn body(&self, lines: &[SourceLine], pos: &mut usize) -> Result<Vec<SourceLine>, AsmError> {
+        let start = *pos;
+        let mut depth = 0;
+
+        while *pos < lines.len() {
+            let line = &lines[*pos];
+            *pos += 1;
+
+            let mnemonic = parse_line(&line.text, &|name| self.is_keyword(name))
+                .ok()
+                .and_then(|statement| statement.mnemonic)
+                .map(|mnemonic| mnemonic.to_lowercase())
+                .unwrap_or_default();
+
+            match mnemonic.as_str() {
+                "macro" | "rept" => depth += 1,
+                "endm" | "endr" if depth == 0 => return Ok(lines[start..*pos - 1].to_vec()),
+                "endm" | "endr" => depth -= 1,
+                _ => {}
+            }
+        }
+
+        Err(AsmError::new("Missing ENDM".to_string()))
+    }
+
+    /// Body of a macro with parameters replaced by the arguments.
+    fn expand(&mut self, name: &str, arguments: &str) -> Result<Vec<SourceLine>, AsmError> {
+        self.expansions += 1;
+
+        let definition = &self.macros[name];
+        let arguments = split_operands(arguments);
+
+        if arguments.len() > definition.params.len() {
+            return Err(AsmError::new(format!(
+                "Too many arguments for {}: {} expected",
+                name.to_uppercase(),
+                definition.params.len()
+            )));
+        }
+
+        let unique = self.expansions.to_string();
+
+        Ok(definition
+            .body
+            .iter()
+            .map(|line| SourceLine {
+                text: substitute(&line.text, &definition.params, &arguments)
+                    .replace("\\@", &unique),
+                ..line.clone()
+            })
+            .collect())
+    }
+
+    /// Resolve a quoted file name relative to the main source.
+    fn path(&self, operand: &str) -> Result<PathBuf, AsmError> {
+        match string_literal(operand) {
+            Some(name) if !name.is_empty() => Ok(self.base.join(name)),
+            _ => Err(AsmError::new(format!("Invalid file name: {}", operand))),
+        }
+    }
+
+    fn read_text(&self, operand: &str) -> Result<(PathBuf, String), AsmError> {
+        let path = self.path(operand.trim())?;
+        let text = fs::read_to_string(&path)
+            .map_err(|e| AsmError::new(format!("{}: {}", path.display(), e)))?;
+        Ok((path, text))
+    }
+
+    fn incbin(&mut self, operands: &str) -> Result<(), AsmError> {
+        let operands = split_operands(operands);
+        if operands.is_empty() || operands.len() > 3 {
+            return Err(AsmError::new(
+                "INCBIN takes a file name, an offset and a length".to_string(),
+            ));
+        }
+
+        let path = self.path(&operands[0])?;
+        let data =
+            fs::read(&path).map_err(|e| AsmError::new(format!("{}: {}", path.display(), e)))?;
+
+        let offset = match operands.get(1) {
+            Some(offset) => self.defined_expression(offset)? as usize,
+            None => 0,
+        };
+        let length = match operands.get(2) {
+            Some(length) => self.defined_expression(length)? as usize,
+            None => data.len().saturating_sub(offset),
+        };
+
+        if offset + length > data.len() {
+            return Err(AsmError::new(format!(
+                "{}: range out of the file size {}",
+                path.display(),
+                data.len()
+            )));
+        }
+
+        self.emit(&data[offset..offset + length])
+    }
+
+    /// Assemble an instruction or a data directive.
+    fn line(&mut self, statement: Statement) -> Result<(), AsmError> {
+        let mnemonic = statement.mnemonic.map(|m| m.to_lowercase());
+
+        if let Some(label) = statement.label {
+            let value = match mnemonic.as_deref() {
+                Some("equ") => self.expression(statement.operands, "equ")?,
+                _ => self.pc as i32,
+            };
+            self.define(label, value)?;
+        }
+
+        let mnemonic = match mnemonic {
+            Some(mnemonic) => mnemonic,
+            None => return Ok(()),
+        };
+
+        match mnemonic.as_str() {
+            "equ" if statement.label.is_none() => {
+                Err(AsmError::new("EQU without a label".to_string()))
+            }
+            "equ" => Ok(()),
+            "org" => {
+                let address = self.defined_expression(statement.operands)?;
+                if !(0..=0xffff).contains(&address) {
+                    return Err(AsmError::new(format!("Address out of range: {}", address)));
+                }
+                self.pc = address as u32;
+                self.segments.push(Segment {
+                    address: address as u16,
+                    bytes: Vec::new(),
+                });
+                Ok(())
+            }
+            "end" => {
+                self.ended = true;
+                Ok(())
+            }
+            "db" | "defb" | "dm" | "defm" => {
+                let mut bytes = Vec::new();
+                for operand in split_operands(statement.operands) {
+                    match string_literal(&operand) {
+                        Some(text) => bytes.extend(text.bytes()),
+                        None => {
+                            let value = self.expression(&operand, &mnemonic)?;
+                            if !(-128..=255).contains(&value) {
+                                return Err(AsmError::new(format!(
+                                    "Value out of range: {}",
+                                    value
+                                )));
+                            }
+                            bytes.push(value as u8);
+                        }
+                    }
+                }
+                self.emit(&bytes)
+            }
+            "dw" | "defw" => {
+                let mut bytes = Vec::new();
+                for operand in split_operands(statement.operands) {
+                    let value = self.expression(&operand, &mnemonic)?;
+                    if !(-32768..=65535).contains(&value) {
+                        return Err(AsmError::new(format!("Value out of range: {}", value)));
+                    }
+                    bytes.push(value as u8);
+                    bytes.push((value >> 8) as u8);
+                }
+                self.emit(&bytes)
+            }
+            "ds" | "defs" => {
+                let operands = split_operands(statement.operands);
+                if operands.is_empty() || operands.len() > 2 {
+                    return Err(AsmError::new("DS takes a size and a fill value".to_string()));
+                }
+                let size = self.defined_expression(&operands[0])?;
+                let fill = match operands.get(1) {
+                    Some(fill) => self.expression(fill, &mnemonic)?,
+                    None => 0,
+                };
+                if !(0..=0x10000).contains(&size) {
+                    return Err(AsmError::new(format!("Invalid size: {}", size)));
+                }
+                self.emit(&vec![fill as u8; size as usize])
+            }
+            "incbin" => self.incbin(statement.operands),
+            _ if is_mnemonic(&mnemonic) => {
+                let pc = self.pc as u16;
+                let operands = split_operands(statement.operands)
+                    .iter()
+                    .map(|operand| {
+                        Operand::parse(operand, &mut |expr: &str| {
+                            self.expression(expr, &mnemonic)
+                        })
+                    })
+                    .collect::<Result<Vec<Operand>, AsmError>>()?;
+                let bytes = encode(&mnemonic, &operands, pc)?;
+                self.emit(&bytes)
+            }
+            _ => Err(AsmError::new(format!(
+                "Unknown instruction: {}",
+                statement.mnemonic.unwrap_or_default()
+            ))),
+        }
+    }
+
+    /// Full name of a symbol: local labels belong to the last global label.
+    fn qualify(&self, name: &str) -> String {
+        if name.starts_with('.') {
+            format!("{}{}", self.global, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn define(&mut self, label: &str, value: i32) -> Result<(), AsmError> {
+        if !label.starts_with('.') {
+            self.global = label.to_string();
+        }
+        let name = self.qualify(label);
+
+        if self.pass == 1 && self.symbols.contains_key(&name) {
+            return Err(AsmError::new(format!("Duplicate symbol: {}", name)));
+        }
+
+        self.symbols.insert(name, value);
+        Ok(())
+    }
+
+    /// Evaluate an expression. In the first pass symbols may be defined
+    /// later on: a placeholder keeps the instruction size right.
+    fn expression(&self, text: &str, mnemonic: &str) -> Result<i32, AsmError> {
+        // Relative jumps to the placeholder must stay in range.
+        let placeholder = match mnemonic {
+            "jr" | "djnz" => self.pc as i32,
+            _ => 0,
+        };
+
+        let lookup = |name: &str| match self.symbols.get(&self.qualify(name)) {
+            Some(value) => Some(*value),
+            None if self.pass == 1 => Some(placeholder),
+            None => None,
+        };
+
+        eval(text, self.pc as u16, &lookup)
+    }
+
+    /// Evaluate an expression whose symbols must be already defined,
+    /// as it changes the addresses of the following lines.
+    fn defined_expression(&self, text: &str) -> Result<i32, AsmError> {
+        let lookup = |name: &str| self.symbols.get(&self.qualify(name)).cloned();
+        eval(text, self.pc as u16, &lookup)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), AsmError> {
+        if self.pc + bytes.len() as u32 > 0x10000 {
+            return Err(AsmError::new("Code exceeds 64K".to_string()));
+        }
+
+        self.pc += bytes.len() as u32;
+        if self.pass == 2 {
+            self.segments.last_mut().unwrap().bytes.extend(bytes);
+            if let Some(line) = self.lines.last_mut() {
+                line.bytes.extend(bytes);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Split a line in label, mnemonic and operands.
+/// `is_keyword` tells instructions, directives and macros from labels.
+fn parse_line<'a>(
+    text: &'a str,
+    is_keyword: &dyn Fn(&str) -> bool,
+) -> Result<Statement<'a>, AsmError> {
+    let code = strip_comment(text);
+    let starts_in_first_column = !code.starts_with(char::is_whitespace);
+    let mut rest = code.trim();
+
+    let mut label = None;
+
+    let first_word = rest
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .next()
+        .unwrap_or("");
+    let after_word = &rest[first_word.len()..];
+
+    if let Some(after_colon) = after_word.strip_prefix(':') {
+        label = Some(first_word);
+        rest = after_colon.trim();
+    } else if !first_word.is_empty() {
+        let lower = first_word.to_lowercase();
+        let next_word = after_word
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        let named = next_word == "equ" || next_word == "macro";
+
+        if (starts_in_first_column && !is_keyword(&lower)) || named {
+            label = Some(first_word);
+            rest = after_word.trim();
+        }
+    }
+
+    if let Some(name) = label {
+        if !is_symbol(name) {
+            return Err(AsmError::new(format!("Invalid label: {}", name)));
+        }
+    }
+
+    if rest.is_empty() {
+        return Ok(Statement {
+            label,
+            mnemonic: None,
+            operands: "",
+        });
+    }
+
+    let (mnemonic, operands) = match rest.find(char::is_whitespace) {
+        Some(pos) => (&rest[..pos], rest[pos..].trim()),
+        None => (rest, ""),
+    };
+
+    Ok(Statement {
+        label,
+        mnemonic: Some(mnemonic),
+        operands,
+    })
+}
+
+fn is_directive(name: &str) -> bool {
+    matches!(
+        name,
+        "org"
+            | "equ"
+            | "end"
+            | "db"
+            | "defb"
+            | "dm"
+            | "defm"
+            | "dw"
+            | "defw"
+            | "ds"
+            | "defs"
+            | "include"
+            | "incbin"
+            | "if"
+            | "else"
+            | "endif"
+            | "macro"
+            | "endm"
+            | "rept"
+            | "endr"
+    )
+}
+
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '.' || c == '@' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Remove the comment, ignoring semicolons inside quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut previous = String::new();
+
+    for (pos, ch) in text.char_indices() {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some(_) => {}
+            None => match ch {
+                ';' => return &text[..pos],
+                '"' => quote = Some(ch),
+                // AF' is a register, not the start of a character.
+                '\'' if !previous.eq_ignore_ascii_case("af") => quote = Some(ch),
+                _ => {}
+            },
+        }
+
+        if ch.is_alphanumeric() {
+            previous.push(ch);
+        } else {
+            previous.clear();
+        }
+    }
+
+    text
+}
+
+/// Text of a string operand: "text", or 'text' with more than one character.
+fn string_literal(operand: &str) -> Option<&str> {
+    let operand = operand.trim();
+    let quoted = |q: char| operand.len() >= 2 && operand.starts_with(q) && operand.ends_with(q);
+
+    if quoted('"') || (quoted('\'') && operand.chars().count() != 3) {
+        Some(&operand[1..operand.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Replace whole words equal to a parameter name with the argument.
+/// Missing arguments are empty.
+fn substitute(text: &str, params: &[String], arguments: &[String]) -> String {
+    let mut result = String::new();
+    let mut word = String::new();
+
+    let flush = |word: &mut String, result: &mut String| {
+        match params.iter().position(|param| param == word.as_str()) {
+            Some(index) => result.push_str(arguments.get(index).map_or("", |a| a.as_str())),
+            None => result.push_str(word),
+        }
+        word.clear();
+    };
+
+    for ch in text.chars() {
+        if ch.is_alphanumeric() || ch == '_' {
+            word.push(ch);
+        } else {
+            flush(&mut word, &mut result);
+            result.push(ch);
+        }
+    }
+    flush(&mut word, &mut result);
+
+    result
+}
@@ -0,0 +1,479 @@
+use asm::operand::{Condition, Index, Operand, Reg16, Reg8};
+use asm::AsmError;
+
+// === Instruction encoder ===
+//
+// Instructions using IX or IY are encoded as their HL counterpart,
+// then the index prefix and the displacement are inserted:
+//
+//   LD A,(HL)      7e
+//   LD A,(IX+d)    dd 7e d
+//   BIT 0,(HL)     cb 46
+//   BIT 0,(IX+d)   dd cb d 46
+
+/// Register codes as embedded in the object code.
+fn reg_code(reg: Reg8) -> Option<u8> {
+    match reg {
+        Reg8::b => Some(0),
+        Reg8::c => Some(1),
+        Reg8::d => Some(2),
+        Reg8::e => Some(3),
+        Reg8::h | Reg8::ixh | Reg8::iyh => Some(4),
+        Reg8::l | Reg8::ixl | Reg8::iyl => Some(5),
+        Reg8::a => Some(7),
+        Reg8::i | Reg8::r => None,
+    }
+}
+
+fn condition_code(operand: &Operand) -> Option<u8> {
+    match *operand {
+        Operand::Condition(Condition::nz) => Some(0),
+        Operand::Condition(Condition::z) => Some(1),
+        Operand::Condition(Condition::nc) => Some(2),
+        Operand::Condition(Condition::po) => Some(4),
+        Operand::Condition(Condition::pe) => Some(5),
+        Operand::Condition(Condition::p) => Some(6),
+        Operand::Condition(Condition::m) => Some(7),
+        _ => None,
+    }
+}
+
+/// An operand after index registers have been replaced by HL.
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    /// r or (HL), with its 3 bit code
+    R(u8),
+    /// BC, DE, HL, SP with their 2 bit code
+    Ss(u8),
+    Af,
+    Af1,
+    I,
+    R8,
+    Ind(Reg16),
+    Port,
+    Cond(u8),
+    Imm(i32),
+    Mem(i32),
+}
+
+struct Encoding {
+    prefix: Option<u8>,
+    displacement: Option<i32>,
+    index_half: bool,
+    plain_hl: bool,
+    ops: Vec<Op>,
+}
+
+fn normalize(mnemonic: &str, operands: &[Operand]) -> Result<Encoding, AsmError> {
+    // In JP C,nn and friends C is the carry condition, not the register.
+    let conditional = match mnemonic {
+        "jp" | "jr" | "call" => operands.len() == 2,
+        "ret" => operands.len() == 1,
+        _ => false,
+    };
+
+    let mut encoding = Encoding {
+        prefix: None,
+        displacement: None,
+        index_half: false,
+        plain_hl: false,
+        ops: Vec::new(),
+    };
+
+    for (pos, operand) in operands.iter().enumerate() {
+        let use_index = |index: Index, encoding: &mut Encoding| {
+            let prefix = match index {
+                Index::ix => 0xdd,
+                Index::iy => 0xfd,
+            };
+            match encoding.prefix {
+                Some(p) if p != prefix => Err(AsmError::new(
+                    "IX and IY can not be used together".to_string(),
+                )),
+                _ => {
+                    encoding.prefix = Some(prefix);
+                    Ok(())
+                }
+            }
+        };
+
+        let op = match *operand {
+            Operand::Reg8(Reg8::c) if conditional && pos == 0 => Op::Cond(3),
+            Operand::Reg8(Reg8::i) => Op::I,
+            Operand::Reg8(Reg8::r) => Op::R8,
+            Operand::Reg8(reg) => {
+                match reg {
+                    Reg8::ixh | Reg8::ixl => {
+                        use_index(Index::ix, &mut encoding)?;
+                        encoding.index_half = true;
+                    }
+                    Reg8::iyh | Reg8::iyl => {
+                        use_index(Index::iy, &mut encoding)?;
+                        encoding.index_half = true;
+                    }
+                    Reg8::h | Reg8::l => encoding.plain_hl = true,
+                    _ => {}
+                }
+                Op::R(reg_code(reg).unwrap())
+            }
+            Operand::Reg16(Reg16::bc) => Op::Ss(0),
+            Operand::Reg16(Reg16::de) => Op::Ss(1),
+            Operand::Reg16(Reg16::hl) => {
+                encoding.plain_hl = true;
+                Op::Ss(2)
+            }
+            Operand::Reg16(Reg16::sp) => Op::Ss(3),
+            Operand::Reg16(Reg16::af) => Op::Af,
+            Operand::Reg16(Reg16::af1) => Op::Af1,
+            Operand::Reg16(Reg16::ix) => {
+                use_index(Index::ix, &mut encoding)?;
+                Op::Ss(2)
+            }
+            Operand::Reg16(Reg16::iy) => {
+                use_index(Index::iy, &mut encoding)?;
+                Op::Ss(2)
+            }
+            Operand::Indirect(Reg16::hl) => {
+                encoding.plain_hl = true;
+                Op::R(6)
+            }
+            Operand::Indirect(reg) => Op::Ind(reg),
+            Operand::Indexed(index, disp) => {
+                use_index(index, &mut encoding)?;
+                encoding.displacement = Some(disp);
+                Op::R(6)
+            }
+            Operand::Port => Op::Port,
+            Operand::Condition(_) => Op::Cond(condition_code(operand).unwrap()),
+            Operand::Immediate(value) => Op::Imm(value),
+            Operand::Memory(value) => Op::Mem(value),
+        };
+
+        encoding.ops.push(op);
+    }
+
+    // H and L are only allowed next to (IX+d): everywhere else
+    // the prefix turns HL into the index register.
+    if encoding.prefix.is_some() && encoding.plain_hl && encoding.displacement.is_none() {
+        return Err(AsmError::new(
+            "HL, H and L can not be used with index registers".to_string(),
+        ));
+    }
+
+    if encoding.index_half && encoding.displacement.is_some() {
+        return Err(AsmError::new(
+            "Index register halves can not be used with (IX+d) or (IY+d)".to_string(),
+        ));
+    }
+
+    Ok(encoding)
+}
+
+fn byte(value: i32) -> Result<u8, AsmError> {
+    if !(-128..=255).contains(&value) {
+        return Err(AsmError::new(format!("Value out of range: {}", value)));
+    }
+    Ok(value as u8)
+}
+
+fn word(value: i32) -> Result<[u8; 2], AsmError> {
+    if !(-32768..=65535).contains(&value) {
+        return Err(AsmError::new(format!("Value out of range: {}", value)));
+    }
+    Ok([value as u8, (value >> 8) as u8])
+}
+
+fn displacement(value: i32) -> Result<u8, AsmError> {
+    if !(-128..=127).contains(&value) {
+        return Err(AsmError::new(format!("Displacement out of range: {}", value)));
+    }
+    Ok(value as u8)
+}
+
+fn relative(target: i32, pc: u16) -> Result<u8, AsmError> {
+    let offset = target - (i32::from(pc) + 2);
+    if !(-128..=127).contains(&offset) {
+        return Err(AsmError::new(format!("Relative jump out of range: {}", offset)));
+    }
+    Ok(offset as u8)
+}
+
+fn alu_code(mnemonic: &str) -> Option<u8> {
+    match mnemonic {
+        "add" => Some(0),
+        "adc" => Some(1),
+        "sub" => Some(2),
+        "sbc" => Some(3),
+        "and" => Some(4),
+        "xor" => Some(5),
+        "or" => Some(6),
+        "cp" => Some(7),
+        _ => None,
+    }
+}
+
+fn rotate_code(mnemonic: &str) -> Option<u8> {
+    match mnemonic {
+        "rlc" => Some(0),
+        "rrc" => Some(1),
+        "rl" => Some(2),
+        "rr" => Some(3),
+        "sla" => Some(4),
+        "sra" => Some(5),
+        "sll" | "sl1" | "sls" => Some(6),
+        "srl" => Some(7),
+        _ => None,
+    }
+}
+
+fn implied(mnemonic: &str) -> Option<&'static [u8]> {
+    let code: &'static [u8] = match mnemonic {
+        "nop" => &[0x00],
+        "rlca" => &[0x07],
+        "rrca" => &[0x0f],
+        "rla" => &[0x17],
+        "rra" => &[0x1f],
+        "daa" => &[0x27],
+        "cpl" => &[0x2f],
+        "scf" => &[0x37],
+        "ccf" => &[0x3f],
+        "halt" => &[0x76],
+        "exx" => &[0xd9],
+        "di" => &[0xf3],
+        "ei" => &[0xfb],
+        "neg" => &[0xed, 0x44],
+        "retn" => &[0xed, 0x45],
+        "reti" => &[0xed, 0x4d],
+        "rrd" => &[0xed, 0x67],
+        "rld" => &[0xed, 0x6f],
+        "ldi" => &[0xed, 0xa0],
+        "cpi" => &[0xed, 0xa1],
+        "ini" => &[0xed, 0xa2],
+        "outi" => &[0xed, 0xa3],
+        "ldd" => &[0xed, 0xa8],
+        "cpd" => &[0xed, 0xa9],
+        "ind" => &[0xed, 0xaa],
+        "outd" => &[0xed, 0xab],
+        "ldir" => &[0xed, 0xb0],
+        "cpir" => &[0xed, 0xb1],
+        "inir" => &[0xed, 0xb2],
+        "otir" => &[0xed, 0xb3],
+        "lddr" => &[0xed, 0xb8],
+        "cpdr" => &[0xed, 0xb9],
+        "indr" => &[0xed, 0xba],
+        "otdr" => &[0xed, 0xbb],
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// True if the name, in lower case, is an instruction mnemonic.
+pub fn is_mnemonic(mnemonic: &str) -> bool {
+    const OTHERS: [&str; 18] = [
+        "bit", "call", "dec", "djnz", "ex", "im", "in", "inc", "jp", "jr", "ld", "out", "pop",
+        "push", "res", "ret", "rst", "set",
+    ];
+
+    implied(mnemonic).is_some()
+        || alu_code(mnemonic).is_some()
+        || rotate_code(mnemonic).is_some()
+        || OTHERS.contains(&mnemonic)
+}
+
+/// Object code of an instruction without index prefix and displacement.
+enum Code {
+    /// Opcode bytes followed by operand bytes.
+    Plain(Vec<u8>, Vec<u8>),
+    /// A CB prefixed opcode.
+    Cb(u8),
+}
+
+/// Encode an instruction located at address pc.
+/// Mnemonic is case insensitive.
+pub fn encode(mnemonic: &str, operands: &[Operand], pc: u16) -> Result<Vec<u8>, AsmError> {
+    let mnemonic = mnemonic.to_lowercase();
+    let mnemonic = mnemonic.as_str();
+
+    if let Some(code) = implied(mnemonic) {
+        if !operands.is_empty() {
+            return Err(AsmError::new(format!("{} takes no operands", mnemonic)));
+        }
+        return Ok(code.to_vec());
+    }
+
+    let encoding = normalize(mnemonic, operands)?;
+    let indexed = encoding.displacement.is_some();
+    let code = encode_normalized(mnemonic, &encoding.ops, indexed, pc)?;
+
+    let mut bytes = Vec::new();
+    if let Some(prefix) = encoding.prefix {
+        bytes.push(prefix);
+    }
+
+    match code {
+        Code::Plain(opcode, operand) => {
+            // Index prefixes are only valid in front of unprefixed opcodes.
+            if encoding.prefix.is_some() && (opcode.len() > 1 || opcode[0] == 0xeb) {
+                return Err(invalid(mnemonic, operands));
+            }
+            bytes.extend(opcode);
+            if let Some(disp) = encoding.displacement {
+                // JP (IX) has no displacement.
+                if bytes[1] != 0xe9 {
+                    bytes.push(displacement(disp)?);
+                } else if disp != 0 {
+                    return Err(invalid(mnemonic, operands));
+                }
+            }
+            bytes.extend(operand);
+        }
+        Code::Cb(opcode) => {
+            bytes.push(0xcb);
+            if encoding.prefix.is_some() {
+                if encoding.index_half {
+                    return Err(invalid(mnemonic, operands));
+                }
+                bytes.push(displacement(encoding.displacement.unwrap_or(0))?);
+            }
+            bytes.push(opcode);
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn invalid(mnemonic: &str, operands: &[Operand]) -> AsmError {
+    AsmError::new(format!(
+        "Invalid operands for {}: {:?}",
+        mnemonic.to_uppercase(),
+        operands
+    ))
+}
+
+fn encode_normalized(
+    mnemonic: &str,
+    ops: &[Op],
+    indexed: bool,
+    pc: u16,
+) -> Result<Code, AsmError> {
+    use self::Op::*;
+
+    let plain = |opcode: Vec<u8>, operand: Vec<u8>| Ok(Code::Plain(opcode, operand));
+    let fail = || {
+        Err(AsmError::new(format!(
+            "Invalid operands for {}",
+            mnemonic.to_uppercase()
+        )))
+    };
+
+    if let Some(alu) = alu_code(mnemonic) {
+        // ADD A,x and friends; the accumulator may be omitted.
+        // ADD, ADC and SBC also have 16 bit forms.
+        let operand = match ops {
+            [R(7), x] | [x] => x,
+            [Ss(2), Ss(ss)] => {
+                return match mnemonic {
+                    "add" => plain(vec![0x09 | ss << 4], vec![]),
+                    "adc" => plain(vec![0xed, 0x4a | ss << 4], vec![]),
+                    "sbc" => plain(vec![0xed, 0x42 | ss << 4], vec![]),
+                    _ => fail(),
+                };
+            }
+            _ => return fail(),
+        };
+
+        return match *operand {
+            R(r) => plain(vec![0x80 | alu << 3 | r], vec![]),
+            Imm(n) => plain(vec![0xc6 | alu << 3], vec![byte(n)?]),
+            _ => fail(),
+        };
+    }
+
+    if let Some(rot) = rotate_code(mnemonic) {
+        return match ops {
+            [R(r)] => Ok(Code::Cb(rot << 3 | r)),
+            // Undocumented: RLC (IX+d),r also copies the result to r.
+            [R(6), R(r)] if indexed && *r != 6 => Ok(Code::Cb(rot << 3 | r)),
+            _ => fail(),
+        };
+    }
+
+    match (mnemonic, ops) {
+        ("ld", [R(6), R(6)]) => fail(),
+        ("ld", [R(d), R(s)]) => plain(vec![0x40 | d << 3 | s], vec![]),
+        ("ld", [R(d), Imm(n)]) => plain(vec![0x06 | d << 3], vec![byte(*n)?]),
+        ("ld", [R(7), Ind(Reg16::bc)]) => plain(vec![0x0a], vec![]),
+        ("ld", [R(7), Ind(Reg16::de)]) => plain(vec![0x1a], vec![]),
+        ("ld", [R(7), Mem(nn)]) => plain(vec![0x3a], word(*nn)?.to_vec()),
+        ("ld", [Ind(Reg16::bc), R(7)]) => plain(vec![0x02], vec![]),
+        ("ld", [Ind(Reg16::de), R(7)]) => plain(vec![0x12], vec![]),
+        ("ld", [Mem(nn), R(7)]) => plain(vec![0x32], word(*nn)?.to_vec()),
+        ("ld", [R(7), I]) => plain(vec![0xed, 0x57], vec![]),
+        ("ld", [R(7), R8]) => plain(vec![0xed, 0x5f], vec![]),
+        ("ld", [I, R(7)]) => plain(vec![0xed, 0x47], vec![]),
+        ("ld", [R8, R(7)]) => plain(vec![0xed, 0x4f], vec![]),
+        ("ld", [Ss(dd), Imm(nn)]) => plain(vec![0x01 | dd << 4], word(*nn)?.to_vec()),
+        ("ld", [Ss(2), Mem(nn)]) => plain(vec![0x2a], word(*nn)?.to_vec()),
+        ("ld", [Ss(dd), Mem(nn)]) => plain(vec![0xed, 0x4b | dd << 4], word(*nn)?.to_vec()),
+        ("ld", [Mem(nn), Ss(2)]) => plain(vec![0x22], word(*nn)?.to_vec()),
+        ("ld", [Mem(nn), Ss(dd)]) => plain(vec![0xed, 0x43 | dd << 4], word(*nn)?.to_vec()),
+        ("ld", [Ss(3), Ss(2)]) => plain(vec![0xf9], vec![]),
+
+        ("push", [Ss(qq)]) if *qq != 3 => plain(vec![0xc5 | qq << 4], vec![]),
+        ("push", [Af]) => plain(vec![0xf5], vec![]),
+        ("pop", [Ss(qq)]) if *qq != 3 => plain(vec![0xc1 | qq << 4], vec![]),
+        ("pop", [Af]) => plain(vec![0xf1], vec![]),
+
+        ("ex", [Ss(1), Ss(2)]) => plain(vec![0xeb], vec![]),
+        ("ex", [Af, Af1]) => plain(vec![0x08], vec![]),
+        ("ex", [Ind(Reg16::sp), Ss(2)]) => plain(vec![0xe3], vec![]),
+
+        ("inc", [R(r)]) => plain(vec![0x04 | r << 3], vec![]),
+        ("dec", [R(r)]) => plain(vec![0x05 | r << 3], vec![]),
+        ("inc", [Ss(ss)]) => plain(vec![0x03 | ss << 4], vec![]),
+        ("dec", [Ss(ss)]) => plain(vec![0x0b | ss << 4], vec![]),
+
+        ("im", [Imm(0)]) => plain(vec![0xed, 0x46], vec![]),
+        ("im", [Imm(1)]) => plain(vec![0xed, 0x56], vec![]),
+        ("im", [Imm(2)]) => plain(vec![0xed, 0x5e], vec![]),
+
+        ("bit", [Imm(b), R(r)]) if *b >= 0 && *b < 8 => Ok(Code::Cb(0x40 | (*b as u8) << 3 | r)),
+        ("res", [Imm(b), R(r)]) if *b >= 0 && *b < 8 => Ok(Code::Cb(0x80 | (*b as u8) << 3 | r)),
+        ("set", [Imm(b), R(r)]) if *b >= 0 && *b < 8 => Ok(Code::Cb(0xc0 | (*b as u8) << 3 | r)),
+        // Undocumented: RES b,(IX+d),r and SET b,(IX+d),r also copy the result to r.
+        ("res", [Imm(b), R(6), R(r)]) if indexed && *b >= 0 && *b < 8 && *r != 6 => {
+            Ok(Code::Cb(0x80 | (*b as u8) << 3 | r))
+        }
+        ("set", [Imm(b), R(6), R(r)]) if indexed && *b >= 0 && *b < 8 && *r != 6 => {
+            Ok(Code::Cb(0xc0 | (*b as u8) << 3 | r))
+        }
+
+        ("jp", [Imm(nn)]) => plain(vec![0xc3], word(*nn)?.to_vec()),
+        ("jp", [Cond(cc), Imm(nn)]) => plain(vec![0xc2 | cc << 3], word(*nn)?.to_vec()),
+        ("jp", [R(6)]) => plain(vec![0xe9], vec![]),
+        ("jp", [Ss(2)]) => plain(vec![0xe9], vec![]),
+        ("jr", [Imm(e)]) => plain(vec![0x18], vec![relative(*e, pc)?]),
+        ("jr", [Cond(cc), Imm(e)]) if *cc < 4 => {
+            plain(vec![0x20 | cc << 3], vec![relative(*e, pc)?])
+        }
+        ("djnz", [Imm(e)]) => plain(vec![0x10], vec![relative(*e, pc)?]),
+
+        ("call", [Imm(nn)]) => plain(vec![0xcd], word(*nn)?.to_vec()),
+        ("call", [Cond(cc), Imm(nn)]) => plain(vec![0xc4 | cc << 3], word(*nn)?.to_vec()),
+        ("ret", []) => plain(vec![0xc9], vec![]),
+        ("ret", [Cond(cc)]) => plain(vec![0xc0 | cc << 3], vec![]),
+        ("rst", [Imm(p)]) if *p >= 0 && *p <= 0x38 && p % 8 == 0 => {
+            plain(vec![0xc7 | *p as u8], vec![])
+        }
+
+        ("in", [R(7), Mem(n)]) => plain(vec![0xdb], vec![byte(*n)?]),
+        ("in", [R(r), Port]) if *r != 6 => plain(vec![0xed, 0x40 | r << 3], vec![]),
+        ("in", [Port]) => plain(vec![0xed, 0x70], vec![]),
+        ("out", [Mem(n), R(7)]) => plain(vec![0xd3], vec![byte(*n)?]),
+        ("out", [Port, R(r)]) if *r != 6 => plain(vec![0xed, 0x41 | r << 3], vec![]),
+        ("out", [Port, Imm(0)]) => plain(vec![0xed, 0x71], vec![]),
+
+        _ => fail(),
+    }
+}
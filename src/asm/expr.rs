@@ -0,0 +1,342 @@
+use asm::operand::parse_number;
+use asm::AsmError;
+
+// === Expression evaluator ===
+//
+// Precedence, from lowest to highest:
+//   ||
+//   &&
+//   = == != <> < > <= >=
+//   |
+//   ^
+//   &
+//   << >>
+//   + -
+//   * / %
+//   unary - + ~ !
+//
+// `$` is the address of the current instruction.
+// Comparisons and logical operators are -1, all bits set, when true
+// and 0 when false. A symbol followed by `()`, as in `get_z()`, is
+// looked up with the parentheses: the assembler defines none, the
+// debugger uses them for flags.
+
+/// Evaluate an expression. Symbols are resolved by `lookup`,
+/// which returns None for undefined symbols.
+pub fn eval<F>(text: &str, pc: u16, lookup: &F) -> Result<i32, AsmError>
+where
+    F: Fn(&str) -> Option<i32>,
+{
+    let tokens = tokenize(text)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        pc,
+        lookup,
+    };
+
+    let value = parser.logical_or()?;
+
+    if parser.pos != parser.tokens.len() {
+        return Err(AsmError::new(format!("Invalid expression: {}", text)));
+    }
+
+    Ok(value)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i32),
+    Symbol(String),
+    Pc,
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let ch = chars[pos];
+
+        if ch.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        // Character literal
+        if ch == '\'' && pos + 2 < chars.len() && chars[pos + 2] == '\'' {
+            tokens.push(Token::Number(chars[pos + 1] as i32));
+            pos += 3;
+            continue;
+        }
+
+        let two: String = chars[pos..].iter().take(2).collect();
+        let op = match two.as_str() {
+            "<<" => Some("<<"),
+            ">>" => Some(">>"),
+            "<=" => Some("<="),
+            ">=" => Some(">="),
+            "==" => Some("=="),
+            "!=" => Some("!="),
+            "<>" => Some("!="),
+            "&&" => Some("&&"),
+            "||" => Some("||"),
+            _ => None,
+        };
+
+        if let Some(op) = op {
+            tokens.push(Token::Op(op));
+            pos += 2;
+            continue;
+        }
+
+        let op = match ch {
+            '+' => Some("+"),
+            '-' => Some("-"),
+            '*' => Some("*"),
+            '/' => Some("/"),
+            '%' if !next_is_binary_digit(&chars, pos) || follows_operand(&tokens) => Some("%"),
+            '&' => Some("&"),
+            '|' => Some("|"),
+            '^' => Some("^"),
+            '~' => Some("~"),
+            '<' => Some("<"),
+            '>' => Some(">"),
+            '=' => Some("=="),
+            '!' => Some("!"),
+            _ => None,
+        };
+
+        if let Some(op) = op {
+            tokens.push(Token::Op(op));
+            pos += 1;
+            continue;
+        }
+
+        match ch {
+            '(' => {
+                tokens.push(Token::Open);
+                pos += 1;
+                continue;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                pos += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if ch == '$' && !chars.get(pos + 1).is_some_and(|c| c.is_ascii_hexdigit()) {
+            tokens.push(Token::Pc);
+            pos += 1;
+            continue;
+        }
+
+        // Numbers, symbols and prefixed literals ($12, #12, %101).
+        let start = pos;
+        pos += 1;
+        while pos < chars.len()
+            && (chars[pos].is_alphanumeric() || chars[pos] == '_' || chars[pos] == '.')
+        {
+            pos += 1;
+        }
+
+        let mut word: String = chars[start..pos].iter().collect();
+
+        if chars.get(pos) == Some(&'(') && chars.get(pos + 1) == Some(&')') {
+            word.push_str("()");
+            pos += 2;
+        } else if chars.get(pos) == Some(&'\'') && ch.is_alphabetic() {
+            // Shadow registers, as in hl'
+            word.push('\'');
+            pos += 1;
+        }
+
+        if ch.is_ascii_digit() || ch == '$' || ch == '#' || ch == '%' {
+            tokens.push(Token::Number(parse_number(&word)?));
+        } else if ch.is_alphabetic() || ch == '_' || ch == '.' || ch == '@' {
+            tokens.push(Token::Symbol(word));
+        } else {
+            return Err(AsmError::new(format!("Invalid expression: {}", text)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn next_is_binary_digit(chars: &[char], pos: usize) -> bool {
+    chars.get(pos + 1).is_some_and(|c| *c == '0' || *c == '1')
+}
+
+fn follows_operand(tokens: &[Token]) -> bool {
+    matches!(
+        tokens.last(),
+        Some(Token::Number(_)) | Some(Token::Symbol(_)) | Some(Token::Pc) | Some(Token::Close)
+    )
+}
+
+fn overflow() -> AsmError {
+    AsmError::new("Arithmetic overflow".to_string())
+}
+
+struct Parser<'a, F: 'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    pc: u16,
+    lookup: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> Option<i32>,
+{
+    fn next_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        if let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            if ops.contains(op) {
+                self.pos += 1;
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    fn logical_or(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.logical_and()?;
+        while self.next_op(&["||"]).is_some() {
+            let rhs = self.logical_and()?;
+            value = if value != 0 || rhs != 0 { -1 } else { 0 };
+        }
+        Ok(value)
+    }
+
+    fn logical_and(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.comparison()?;
+        while self.next_op(&["&&"]).is_some() {
+            let rhs = self.comparison()?;
+            value = if value != 0 && rhs != 0 { -1 } else { 0 };
+        }
+        Ok(value)
+    }
+
+    fn comparison(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.or()?;
+        while let Some(op) = self.next_op(&["==", "!=", "<", ">", "<=", ">="]) {
+            let rhs = self.or()?;
+            let result = match op {
+                "==" => value == rhs,
+                "!=" => value != rhs,
+                "<" => value < rhs,
+                ">" => value > rhs,
+                "<=" => value <= rhs,
+                _ => value >= rhs,
+            };
+            value = if result { -1 } else { 0 };
+        }
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.xor()?;
+        while self.next_op(&["|"]).is_some() {
+            value |= self.xor()?;
+        }
+        Ok(value)
+    }
+
+    fn xor(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.and()?;
+        while self.next_op(&["^"]).is_some() {
+            value ^= self.and()?;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.shift()?;
+        while self.next_op(&["&"]).is_some() {
+            value &= self.shift()?;
+        }
+        Ok(value)
+    }
+
+    fn shift(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.sum()?;
+        while let Some(op) = self.next_op(&["<<", ">>"]) {
+            let rhs = self.sum()? as u32;
+            let shifted = if op == "<<" {
+                value.checked_shl(rhs)
+            } else {
+                value.checked_shr(rhs)
+            };
+            value = shifted.ok_or_else(overflow)?;
+        }
+        Ok(value)
+    }
+
+    fn sum(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.product()?;
+        while let Some(op) = self.next_op(&["+", "-"]) {
+            let rhs = self.product()?;
+            value = if op == "+" {
+                value.checked_add(rhs)
+            } else {
+                value.checked_sub(rhs)
+            }
+            .ok_or_else(overflow)?;
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<i32, AsmError> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.next_op(&["*", "/", "%"]) {
+            let rhs = self.unary()?;
+            value = match op {
+                "*" => value.checked_mul(rhs),
+                _ if rhs == 0 => return Err(AsmError::new("Division by zero".to_string())),
+                "/" => value.checked_div(rhs),
+                _ => value.checked_rem(rhs),
+            }
+            .ok_or_else(overflow)?;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i32, AsmError> {
+        match self.next_op(&["-", "+", "~", "!"]) {
+            Some("-") => self.unary()?.checked_neg().ok_or_else(overflow),
+            Some("~") => Ok(!self.unary()?),
+            Some("!") => Ok(if self.unary()? == 0 { -1 } else { 0 }),
+            Some(_) => self.unary(),
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i32, AsmError> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Pc) => Ok(i32::from(self.pc)),
+            Some(Token::Symbol(name)) => (self.lookup)(&name)
+                .ok_or_else(|| AsmError::new(format!("Undefined symbol: {}", name))),
+            Some(Token::Open) => {
+                let value = self.logical_or()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err(AsmError::new("Missing ')'".to_string())),
+                }
+            }
+            _ => Err(AsmError::new("Invalid expression".to_string())),
+        }
+    }
+}
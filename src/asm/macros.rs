@@ -0,0 +1,15 @@
+/// Assemble source lines located at address 0, returning the object code.
+/// Panics on errors: meant for tests.
+///
+/// ```ignore
+/// let code = asm!("ld a,(ix-3)", "ret");
+/// ```
+#[macro_export]
+macro_rules! asm {
+    ($($line:expr),+ $(,)*) => {
+        match $crate::asm::assemble(&[$($line),+].join("\n")) {
+            Ok(program) => program.bytes(),
+            Err(error) => panic!("asm!: {}", error),
+        }
+    };
+}
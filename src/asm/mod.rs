@@ -0,0 +1,80 @@
+#[macro_use]
+mod macros;
+#[cfg(test)]
+mod tests;
+
+mod assembler;
+mod encoder;
+mod expr;
+mod operand;
+
+pub use self::assembler::{assemble, assemble_file, ListingLine, Program, Segment};
+pub use self::encoder::encode;
+pub use self::expr::eval;
+pub use self::operand::{parse_number, split_operands, Condition, Index, Operand, Reg16, Reg8};
+
+use std::fmt;
+
+/// An error found while assembling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub message: String,
+    /// Included file, None for the main source.
+    pub file: Option<String>,
+    /// Source line, starting from 1.
+    pub line: Option<usize>,
+}
+
+impl AsmError {
+    pub fn new(message: String) -> AsmError {
+        AsmError {
+            message,
+            file: None,
+            line: None,
+        }
+    }
+
+    /// The same error, found at a source line. Errors already located,
+    /// as in included files and macro expansions, keep the innermost line.
+    pub fn at(self, file: Option<&str>, line: usize) -> AsmError {
+        if self.line.is_some() {
+            return self;
+        }
+
+        AsmError {
+            file: file.map(str::to_string),
+            line: Some(line),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: {}", file, line, self.message),
+            (None, Some(line)) => write!(f, "line {}: {}", line, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Assemble a single instruction, in Zilog syntax, located at address pc.
+/// Operands can only be numeric expressions: there is no symbol table.
+pub fn assemble_instruction(text: &str, pc: u16) -> Result<Vec<u8>, AsmError> {
+    let text = text.trim();
+    let (mnemonic, operands) = match text.find(char::is_whitespace) {
+        Some(pos) => (&text[..pos], &text[pos..]),
+        None => (text, ""),
+    };
+
+    let lookup = |_: &str| None;
+    let mut evaluate = |expr: &str| eval(expr, pc, &lookup);
+
+    let operands = split_operands(operands)
+        .iter()
+        .map(|operand| Operand::parse(operand, &mut evaluate))
+        .collect::<Result<Vec<Operand>, AsmError>>()?;
+
+    encode(mnemonic, &operands, pc)
+}
@@ -0,0 +1,278 @@
+use asm::AsmError;
+
+/// 8 bit registers, including the undocumented index register halves.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reg8 {
+    a,
+    b,
+    c,
+    d,
+    e,
+    h,
+    l,
+    i,
+    r,
+    ixh,
+    ixl,
+    iyh,
+    iyl,
+}
+
+/// 16 bit registers and register pairs.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reg16 {
+    af,
+    af1,
+    bc,
+    de,
+    hl,
+    sp,
+    ix,
+    iy,
+}
+
+/// Index registers usable in (IX+d) and (IY+d) addressing.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Index {
+    ix,
+    iy,
+}
+
+/// Jump, call and return conditions.
+/// The C condition is parsed as `Operand::Reg8(Reg8::c)`.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    nz,
+    z,
+    nc,
+    po,
+    pe,
+    p,
+    m,
+}
+
+/// A parsed instruction operand.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// r
+    Reg8(Reg8),
+    /// rr
+    Reg16(Reg16),
+    /// (BC), (DE), (HL), (SP)
+    Indirect(Reg16),
+    /// (IX+d), (IY+d)
+    Indexed(Index, i32),
+    /// (C), the port addressed by register C
+    Port,
+    /// cc, except C
+    Condition(Condition),
+    /// n, nn, e
+    Immediate(i32),
+    /// (n), (nn)
+    Memory(i32),
+}
+
+impl Operand {
+    /// Parse an operand. Numbers and expressions are evaluated by `eval`.
+    pub fn parse<F>(text: &str, eval: &mut F) -> Result<Operand, AsmError>
+    where
+        F: FnMut(&str) -> Result<i32, AsmError>,
+    {
+        let text = text.trim();
+        let lower = text.to_lowercase();
+
+        if let Some(operand) = Self::keyword(&lower) {
+            return Ok(operand);
+        }
+
+        if lower.starts_with('(') && lower.ends_with(')') && enclosed(&lower) {
+            let inner = lower[1..lower.len() - 1].trim();
+
+            match inner {
+                "bc" => return Ok(Operand::Indirect(Reg16::bc)),
+                "de" => return Ok(Operand::Indirect(Reg16::de)),
+                "hl" => return Ok(Operand::Indirect(Reg16::hl)),
+                "sp" => return Ok(Operand::Indirect(Reg16::sp)),
+                "c" => return Ok(Operand::Port),
+                "ix" => return Ok(Operand::Indexed(Index::ix, 0)),
+                "iy" => return Ok(Operand::Indexed(Index::iy, 0)),
+                _ => {}
+            }
+
+            for &(name, index) in &[("ix", Index::ix), ("iy", Index::iy)] {
+                if let Some(rest) = inner.strip_prefix(name) {
+                    let rest = rest.trim_start();
+
+                    if rest.starts_with('+') || rest.starts_with('-') {
+                        // Keep the original case for symbols in the displacement.
+                        let start = text.len() - 1 - rest.len();
+                        let disp = &text[start..text.len() - 1];
+                        let value = eval(&format!("0{}", disp))?;
+                        return Ok(Operand::Indexed(index, value));
+                    }
+                }
+            }
+
+            let value = eval(&text[1..text.len() - 1])?;
+            return Ok(Operand::Memory(value));
+        }
+
+        Ok(Operand::Immediate(eval(text)?))
+    }
+
+    fn keyword(text: &str) -> Option<Operand> {
+        let operand = match text {
+            "a" => Operand::Reg8(Reg8::a),
+            "b" => Operand::Reg8(Reg8::b),
+            "c" => Operand::Reg8(Reg8::c),
+            "d" => Operand::Reg8(Reg8::d),
+            "e" => Operand::Reg8(Reg8::e),
+            "h" => Operand::Reg8(Reg8::h),
+            "l" => Operand::Reg8(Reg8::l),
+            "i" => Operand::Reg8(Reg8::i),
+            "r" => Operand::Reg8(Reg8::r),
+            "ixh" | "hx" | "xh" => Operand::Reg8(Reg8::ixh),
+            "ixl" | "lx" | "xl" => Operand::Reg8(Reg8::ixl),
+            "iyh" | "hy" | "yh" => Operand::Reg8(Reg8::iyh),
+            "iyl" | "ly" | "yl" => Operand::Reg8(Reg8::iyl),
+            "af" => Operand::Reg16(Reg16::af),
+            "af'" => Operand::Reg16(Reg16::af1),
+            "bc" => Operand::Reg16(Reg16::bc),
+            "de" => Operand::Reg16(Reg16::de),
+            "hl" => Operand::Reg16(Reg16::hl),
+            "sp" => Operand::Reg16(Reg16::sp),
+            "ix" => Operand::Reg16(Reg16::ix),
+            "iy" => Operand::Reg16(Reg16::iy),
+            "nz" => Operand::Condition(Condition::nz),
+            "z" => Operand::Condition(Condition::z),
+            "nc" => Operand::Condition(Condition::nc),
+            "po" => Operand::Condition(Condition::po),
+            "pe" => Operand::Condition(Condition::pe),
+            "p" => Operand::Condition(Condition::p),
+            "m" => Operand::Condition(Condition::m),
+            _ => return None,
+        };
+
+        Some(operand)
+    }
+}
+
+/// Verify that the opening parenthesis at the start of the text
+/// is closed by the one at its end, as in `(1+2)` but not in `(1)+(2)`.
+fn enclosed(text: &str) -> bool {
+    let mut depth = 0;
+
+    for (pos, ch) in text.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 && pos != text.len() - 1 {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    true
+}
+
+/// Split an operand list on commas, ignoring commas inside
+/// parentheses and quotes.
+pub fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+
+    for ch in text.chars() {
+        match quote {
+            Some(q) => {
+                if ch == q {
+                    quote = None;
+                }
+                current.push(ch);
+            }
+            None => match ch {
+                '"' => {
+                    quote = Some(ch);
+                    current.push(ch);
+                }
+                '\'' if !current.trim().eq_ignore_ascii_case("af") => {
+                    quote = Some(ch);
+                    current.push(ch);
+                }
+                '(' => {
+                    depth += 1;
+                    current.push(ch);
+                }
+                ')' => {
+                    depth -= 1;
+                    current.push(ch);
+                }
+                ',' if depth == 0 => {
+                    operands.push(current.trim().to_string());
+                    current.clear();
+                }
+                _ => current.push(ch),
+            },
+        }
+    }
+
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+
+    operands
+}
+
+/// Parse a numeric literal: decimal, hexadecimal (0x12, 12h, $12, #12),
+/// binary (0b101, 101b, %101), octal (17o, 17q) or character ('a').
+pub fn parse_number(text: &str) -> Result<i32, AsmError> {
+    let text = text.trim();
+    let lower = text.to_lowercase();
+    let error = || AsmError::new(format!("Invalid number: {}", text));
+
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() == 3 && chars[0] == '\'' && chars[2] == '\'' {
+        return Ok(chars[1] as i32);
+    }
+
+    let last = lower.len().saturating_sub(1);
+    let (digits, radix) = if let Some(digits) = lower.strip_prefix("0x") {
+        (digits, 16)
+    } else if lower.starts_with('$') || lower.starts_with('#') {
+        (&lower[1..], 16)
+    } else if let Some(digits) = lower.strip_prefix('%') {
+        (digits, 2)
+    } else if lower.starts_with("0b") && lower.len() > 2 && !lower.ends_with('h') {
+        (&lower[2..], 2)
+    } else if lower.ends_with('h') {
+        (&lower[..last], 16)
+    } else if lower.ends_with('b') {
+        (&lower[..last], 2)
+    } else if lower.ends_with('o') || lower.ends_with('q') {
+        (&lower[..last], 8)
+    } else {
+        (&lower[..], 10)
+    };
+
+    let digits = digits.replace('_', "");
+
+    if digits.is_empty() || !lower.chars().next().is_some_and(|c| {
+        c.is_ascii_digit() || c == '$' || c == '#' || c == '%'
+    }) {
+        return Err(error());
+    }
+
+    i64::from_str_radix(&digits, radix)
+        .ok()
+        .filter(|value| *value <= i64::from(i32::MAX))
+        .map(|value| value as i32)
+        .ok_or_else(error)
+}
@@ -0,0 +1,440 @@
+use asm::assemble;
+use asm::assemble_file;
+use asm::assemble_instruction;
+use asm::eval;
+use asm::parse_number;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn asm(text: &str) -> Vec<u8> {
+    assemble_instruction(text, 0).unwrap()
+}
+
+#[test]
+fn numbers() {
+    assert_eq!(parse_number("12"), Ok(12));
+    assert_eq!(parse_number("0x12"), Ok(0x12));
+    assert_eq!(parse_number("12h"), Ok(0x12));
+    assert_eq!(parse_number("0ffh"), Ok(0xff));
+    assert_eq!(parse_number("$12"), Ok(0x12));
+    assert_eq!(parse_number("#12"), Ok(0x12));
+    assert_eq!(parse_number("%0101"), Ok(5));
+    assert_eq!(parse_number("0b0101"), Ok(5));
+    assert_eq!(parse_number("0101b"), Ok(5));
+    assert_eq!(parse_number("17o"), Ok(15));
+    assert_eq!(parse_number("'a'"), Ok(0x61));
+    assert!(parse_number("ffh").is_err());
+    assert!(parse_number("12z").is_err());
+    assert_eq!(parse_number("0x7fffffff"), Ok(i32::MAX));
+    assert!(parse_number("0x80000000").is_err());
+    assert!(parse_number("0xffffffff").is_err());
+}
+
+#[test]
+fn expressions() {
+    let lookup = |name: &str| if name == "label" { Some(0x100) } else { None };
+
+    assert_eq!(eval("1+2*3", 0, &lookup), Ok(7));
+    assert_eq!(eval("(1+2)*3", 0, &lookup), Ok(9));
+    assert_eq!(eval("-3+1", 0, &lookup), Ok(-2));
+    assert_eq!(eval("label+2", 0, &lookup), Ok(0x102));
+    assert_eq!(eval("label >> 8 & 0xff", 0, &lookup), Ok(1));
+    assert_eq!(eval("$+5", 0x10, &lookup), Ok(0x15));
+    assert_eq!(eval("7 % 4", 0, &lookup), Ok(3));
+    assert_eq!(eval("%11 | 4", 0, &lookup), Ok(7));
+    assert_eq!(eval("~0 & 0xff", 0, &lookup), Ok(0xff));
+    assert_eq!(eval("label > 0xff", 0, &lookup), Ok(-1));
+    assert_eq!(eval("1+1 = 3", 0, &lookup), Ok(0));
+    assert_eq!(eval("(2 <> 3) & 1", 0, &lookup), Ok(1));
+    assert_eq!(eval("1 < 2 && label == 0x100", 0, &lookup), Ok(-1));
+    assert_eq!(eval("0 || 2 > 3", 0, &lookup), Ok(0));
+    assert_eq!(eval("!label || !0", 0, &lookup), Ok(-1));
+    assert!(eval("missing", 0, &lookup).is_err());
+    assert!(eval("label()", 0, &lookup).is_err());
+    assert!(eval("1/0", 0, &lookup).is_err());
+    assert_eq!(eval("-0x7fffffff-1", 0, &lookup), Ok(i32::MIN));
+    let overflows = [
+        "(-0x7fffffff-1) / -1",
+        "(-0x7fffffff-1) % -1",
+        "-(-0x7fffffff-1)",
+        "0x7fffffff+1",
+        "65536*65536",
+        "1 << 32",
+        "1 >> -1",
+    ];
+    for overflow in &overflows {
+        assert!(eval(overflow, 0, &lookup).is_err(), "{}", overflow);
+    }
+    assert!(eval("(1", 0, &lookup).is_err());
+}
+
+#[test]
+fn load_8() {
+    assert_eq!(asm("LD A,B"), vec![0x78]);
+    assert_eq!(asm("ld h,e"), vec![0x63]);
+    assert_eq!(asm("LD B,0x12"), vec![0x06, 0x12]);
+    assert_eq!(asm("LD A,(HL)"), vec![0x7e]);
+    assert_eq!(asm("LD (HL),C"), vec![0x71]);
+    assert_eq!(asm("LD (HL),5"), vec![0x36, 0x05]);
+    assert_eq!(asm("LD A,(BC)"), vec![0x0a]);
+    assert_eq!(asm("LD (DE),A"), vec![0x12]);
+    assert_eq!(asm("LD A,(0x1234)"), vec![0x3a, 0x34, 0x12]);
+    assert_eq!(asm("LD (0x1234),A"), vec![0x32, 0x34, 0x12]);
+    assert_eq!(asm("LD A,I"), vec![0xed, 0x57]);
+    assert_eq!(asm("LD R,A"), vec![0xed, 0x4f]);
+    assert!(assemble_instruction("LD (HL),(HL)", 0).is_err());
+}
+
+#[test]
+fn load_indexed() {
+    assert_eq!(asm("LD A,(IX+5)"), vec![0xdd, 0x7e, 0x05]);
+    assert_eq!(asm("ld a,(ix-3)"), vec![0xdd, 0x7e, 0xfd]);
+    assert_eq!(asm("LD H,(IY+1)"), vec![0xfd, 0x66, 0x01]);
+    assert_eq!(asm("LD (IX+2),L"), vec![0xdd, 0x75, 0x02]);
+    assert_eq!(asm("LD (IX),A"), vec![0xdd, 0x77, 0x00]);
+    assert_eq!(asm("LD (IY+2),0xfb"), vec![0xfd, 0x36, 0x02, 0xfb]);
+    assert_eq!(asm("LD IXH,5"), vec![0xdd, 0x26, 0x05]);
+    assert_eq!(asm("LD B,IYL"), vec![0xfd, 0x45]);
+    assert!(assemble_instruction("LD H,IXL", 0).is_err());
+    assert!(assemble_instruction("LD (IX+200),A", 0).is_err());
+    assert!(assemble_instruction("LD IXH,(IX+1)", 0).is_err());
+}
+
+#[test]
+fn load_16() {
+    assert_eq!(asm("LD BC,0x1234"), vec![0x01, 0x34, 0x12]);
+    assert_eq!(asm("LD SP,0xfffe"), vec![0x31, 0xfe, 0xff]);
+    assert_eq!(asm("LD IX,0x1234"), vec![0xdd, 0x21, 0x34, 0x12]);
+    assert_eq!(asm("LD HL,(0x1234)"), vec![0x2a, 0x34, 0x12]);
+    assert_eq!(asm("LD DE,(0x1234)"), vec![0xed, 0x5b, 0x34, 0x12]);
+    assert_eq!(asm("LD (0x1234),HL"), vec![0x22, 0x34, 0x12]);
+    assert_eq!(asm("LD (0x1234),SP"), vec![0xed, 0x73, 0x34, 0x12]);
+    assert_eq!(asm("LD (0x1234),IY"), vec![0xfd, 0x22, 0x34, 0x12]);
+    assert_eq!(asm("LD SP,HL"), vec![0xf9]);
+    assert_eq!(asm("LD SP,IX"), vec![0xdd, 0xf9]);
+    assert_eq!(asm("PUSH AF"), vec![0xf5]);
+    assert_eq!(asm("POP IX"), vec![0xdd, 0xe1]);
+    assert!(assemble_instruction("PUSH SP", 0).is_err());
+}
+
+#[test]
+fn exchange() {
+    assert_eq!(asm("EX DE,HL"), vec![0xeb]);
+    assert_eq!(asm("EX AF,AF'"), vec![0x08]);
+    assert_eq!(asm("EX (SP),IX"), vec![0xdd, 0xe3]);
+    assert_eq!(asm("EXX"), vec![0xd9]);
+    assert_eq!(asm("LDIR"), vec![0xed, 0xb0]);
+    assert!(assemble_instruction("EX DE,IX", 0).is_err());
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(asm("ADD A,B"), vec![0x80]);
+    assert_eq!(asm("ADC A,0x12"), vec![0xce, 0x12]);
+    assert_eq!(asm("SUB (HL)"), vec![0x96]);
+    assert_eq!(asm("SBC A,(IX-1)"), vec![0xdd, 0x9e, 0xff]);
+    assert_eq!(asm("AND 0x0f"), vec![0xe6, 0x0f]);
+    assert_eq!(asm("XOR A"), vec![0xaf]);
+    assert_eq!(asm("OR IXL"), vec![0xdd, 0xb5]);
+    assert_eq!(asm("CP -1"), vec![0xfe, 0xff]);
+    assert_eq!(asm("INC (IY+3)"), vec![0xfd, 0x34, 0x03]);
+    assert_eq!(asm("DEC C"), vec![0x0d]);
+    assert_eq!(asm("INC SP"), vec![0x33]);
+    assert_eq!(asm("ADD HL,DE"), vec![0x19]);
+    assert_eq!(asm("ADD IX,IX"), vec![0xdd, 0x29]);
+    assert_eq!(asm("ADC HL,BC"), vec![0xed, 0x4a]);
+    assert_eq!(asm("SBC HL,SP"), vec![0xed, 0x72]);
+    assert_eq!(asm("NEG"), vec![0xed, 0x44]);
+    assert!(assemble_instruction("ADD IX,HL", 0).is_err());
+    assert!(assemble_instruction("SBC IX,BC", 0).is_err());
+    assert!(assemble_instruction("ADD A,256", 0).is_err());
+}
+
+#[test]
+fn rotate_and_bits() {
+    assert_eq!(asm("RLCA"), vec![0x07]);
+    assert_eq!(asm("RLC B"), vec![0xcb, 0x00]);
+    assert_eq!(asm("RR (HL)"), vec![0xcb, 0x1e]);
+    assert_eq!(asm("SRL (IX+4)"), vec![0xdd, 0xcb, 0x04, 0x3e]);
+    assert_eq!(asm("SLL A"), vec![0xcb, 0x37]);
+    assert_eq!(asm("RLC (IY+1),B"), vec![0xfd, 0xcb, 0x01, 0x00]);
+    assert_eq!(asm("SET 1,(IX+2),C"), vec![0xdd, 0xcb, 0x02, 0xc9]);
+    assert_eq!(asm("BIT 7,A"), vec![0xcb, 0x7f]);
+    assert_eq!(asm("SET 0,(HL)"), vec![0xcb, 0xc6]);
+    assert_eq!(asm("RES 3,(IX-2)"), vec![0xdd, 0xcb, 0xfe, 0x9e]);
+    assert_eq!(asm("RLD"), vec![0xed, 0x6f]);
+    assert!(assemble_instruction("BIT 8,A", 0).is_err());
+    assert!(assemble_instruction("RLC (HL),B", 0).is_err());
+    assert!(assemble_instruction("SET 1,(HL),C", 0).is_err());
+}
+
+#[test]
+fn jumps_and_calls() {
+    assert_eq!(asm("JP 0x1234"), vec![0xc3, 0x34, 0x12]);
+    assert_eq!(asm("JP NZ,0x1234"), vec![0xc2, 0x34, 0x12]);
+    assert_eq!(asm("JP C,0x1234"), vec![0xda, 0x34, 0x12]);
+    assert_eq!(asm("JP M,0x1234"), vec![0xfa, 0x34, 0x12]);
+    assert_eq!(asm("JP (HL)"), vec![0xe9]);
+    assert_eq!(asm("JP (IX)"), vec![0xdd, 0xe9]);
+    assert!(assemble_instruction("JP (IX+3)", 0).is_err());
+    assert!(assemble_instruction("JP (IY-1)", 0).is_err());
+    assert_eq!(asm("JR 0x10"), vec![0x18, 0x0e]);
+    assert_eq!(asm("JR C,$"), vec![0x38, 0xfe]);
+    assert_eq!(assemble_instruction("DJNZ 0x100", 0x110), Ok(vec![0x10, 0xee]));
+    assert_eq!(asm("CALL 0x1234"), vec![0xcd, 0x34, 0x12]);
+    assert_eq!(asm("CALL PE,0x1234"), vec![0xec, 0x34, 0x12]);
+    assert_eq!(asm("RET"), vec![0xc9]);
+    assert_eq!(asm("RET Z"), vec![0xc8]);
+    assert_eq!(asm("RST 0x38"), vec![0xff]);
+    assert_eq!(asm("RETI"), vec![0xed, 0x4d]);
+    assert!(assemble_instruction("JR PO,0", 0).is_err());
+    assert!(assemble_instruction("JR 0x100", 0).is_err());
+    assert!(assemble_instruction("RST 3", 0).is_err());
+}
+
+#[test]
+fn control_and_io() {
+    assert_eq!(asm("NOP"), vec![0x00]);
+    assert_eq!(asm("HALT"), vec![0x76]);
+    assert_eq!(asm("DI"), vec![0xf3]);
+    assert_eq!(asm("IM 2"), vec![0xed, 0x5e]);
+    assert_eq!(asm("IN A,(0xfe)"), vec![0xdb, 0xfe]);
+    assert_eq!(asm("IN D,(C)"), vec![0xed, 0x50]);
+    assert_eq!(asm("IN (C)"), vec![0xed, 0x70]);
+    assert_eq!(asm("OUT (0xfe),A"), vec![0xd3, 0xfe]);
+    assert_eq!(asm("OUT (C),E"), vec![0xed, 0x59]);
+    assert_eq!(asm("OUT (C),0"), vec![0xed, 0x71]);
+    assert_eq!(asm("OTIR"), vec![0xed, 0xb3]);
+    assert!(assemble_instruction("NOP A", 0).is_err());
+    assert!(assemble_instruction("FOO", 0).is_err());
+}
+
+#[test]
+fn labels_and_directives() {
+    let program = assemble(
+        "
+        ; Print a zero terminated string
+size    EQU 3
+        ORG 0x8000
+start:  LD HL,message
+        CALL print
+        HALT
+print:  LD A,(HL)
+        OR A
+        RET Z
+        INC HL
+        JR print
+message DB \"Hi;\",0
+table:  DW start, message+1
+        DS size,0xff
+",
+    )
+    .unwrap();
+
+    assert_eq!(program.origin(), 0x8000);
+    assert_eq!(program.symbol("start"), Some(0x8000));
+    assert_eq!(program.symbol("print"), Some(0x8007));
+    assert_eq!(program.symbol("message"), Some(0x800d));
+    assert_eq!(program.symbol("size"), Some(3));
+    assert_eq!(
+        program.bytes(),
+        vec![
+            0x21, 0x0d, 0x80, 0xcd, 0x07, 0x80, 0x76, 0x7e, 0xb7, 0xc8, 0x23, 0x18, 0xfa, b'H',
+            b'i', b';', 0x00, 0x00, 0x80, 0x0e, 0x80, 0xff, 0xff, 0xff,
+        ]
+    );
+}
+
+#[test]
+fn forward_references() {
+    let program = assemble(
+        "
+        JR NZ,skip
+        LD A,(IX+offset)
+skip:   LD BC,table
+offset  EQU 2
+table   DB 1
+",
+    )
+    .unwrap();
+
+    assert_eq!(
+        program.bytes(),
+        vec![0x20, 0x03, 0xdd, 0x7e, 0x02, 0x01, 0x08, 0x00, 0x01]
+    );
+}
+
+#[test]
+fn local_labels() {
+    let program = assemble(
+        "
+first:  LD B,2
+.loop:  DJNZ .loop
+second: LD B,3
+.loop:  DJNZ .loop
+        JP first.loop
+",
+    )
+    .unwrap();
+
+    assert_eq!(program.symbol("first.loop"), Some(2));
+    assert_eq!(program.symbol("second.loop"), Some(6));
+    assert_eq!(
+        program.bytes(),
+        vec![0x06, 0x02, 0x10, 0xfe, 0x06, 0x03, 0x10, 0xfe, 0xc3, 0x02, 0x00]
+    );
+}
+
+#[test]
+fn segments() {
+    let program = assemble("ORG 2\nDB 1\nORG 5\nDB 2,3\nEND\nDB 4").unwrap();
+
+    assert_eq!(program.segments.len(), 2);
+    assert_eq!(program.bytes(), vec![1, 0, 0, 2, 3]);
+
+    let mut memory = vec![0; 8];
+    program.load(&mut memory);
+    assert_eq!(memory, vec![0, 0, 1, 0, 0, 2, 3, 0]);
+}
+
+#[test]
+fn source_errors() {
+    let error = assemble("NOP\n  LD A,missing").unwrap_err();
+    assert_eq!(error.line, Some(2));
+    assert_eq!(error.to_string(), "line 2: Undefined symbol: missing");
+
+    assert!(assemble("label: NOP\nlabel: NOP").is_err());
+    assert!(assemble("  EQU 3").is_err());
+    assert!(assemble("  FOO A").is_err());
+    assert!(assemble("  ORG later\nlater: NOP").is_err());
+    assert!(assemble("  ORG 0xffff\n  DW 0").is_err());
+}
+
+#[test]
+fn asm_macro() {
+    assert_eq!(asm!("ld a,(ix-3)"), vec![0xdd, 0x7e, 0xfd]);
+    assert_eq!(asm!("ex af,af' ; swap", "loop: jr loop"), vec![0x08, 0x18, 0xfe]);
+}
+
+#[test]
+fn macros() {
+    let program = assemble(
+        "
+add_to  MACRO reg, value
+        LD A,reg
+        ADD A,value
+        LD reg,A
+        ENDM
+
+delay   MACRO count
+        LD B,count
+wait\\@: DJNZ wait\\@
+        ENDM
+
+        add_to B, 2
+        delay 3
+        delay 4
+",
+    )
+    .unwrap();
+
+    assert_eq!(
+        program.bytes(),
+        vec![0x78, 0xc6, 0x02, 0x47, 0x06, 0x03, 0x10, 0xfe, 0x06, 0x04, 0x10, 0xfe]
+    );
+    assert_eq!(program.symbol("wait2"), Some(6));
+    assert_eq!(program.symbol("wait3"), Some(10));
+
+    assert!(assemble("m MACRO a\nNOP\nENDM\n m 1,2").is_err());
+    assert!(assemble("m MACRO\nNOP").is_err());
+    assert!(assemble("  ENDM").is_err());
+}
+
+#[test]
+fn conditional_assembly() {
+    let source = |debug: i32| {
+        format!(
+            "
+debug   EQU {}
+        IF debug
+        LD A,1
+        IF debug > 1
+        LD A,2
+        ENDIF
+        ELSE
+        XOR A
+        ENDIF
+        RET
+",
+            debug
+        )
+    };
+
+    assert_eq!(assemble(&source(0)).unwrap().bytes(), vec![0xaf, 0xc9]);
+    assert_eq!(assemble(&source(1)).unwrap().bytes(), vec![0x3e, 0x01, 0xc9]);
+    assert_eq!(
+        assemble(&source(2)).unwrap().bytes(),
+        vec![0x3e, 0x01, 0x3e, 0x02, 0xc9]
+    );
+
+    assert!(assemble("  IF 1\n  NOP").is_err());
+    assert!(assemble("  ELSE").is_err());
+    assert!(assemble("  ENDIF").is_err());
+    assert!(assemble("  IF later\n  ENDIF\nlater NOP").is_err());
+}
+
+#[test]
+fn repetitions() {
+    let program = assemble("table:\n  REPT 3\n  DB $-table\n  ENDM\n  RET").unwrap();
+    assert_eq!(program.bytes(), vec![0, 1, 2, 0xc9]);
+
+    let nested = assemble("  REPT 2\n  REPT 2\n  NOP\n  ENDM\n  HALT\n  ENDM").unwrap();
+    assert_eq!(nested.bytes(), vec![0, 0, 0x76, 0, 0, 0x76]);
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("z80-asm-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn include_files() {
+    let dir = temp_dir("include");
+    fs::write(dir.join("defs.inc"), "value EQU 5\nshow MACRO\n  LD A,value\n  ENDM\n").unwrap();
+    fs::write(dir.join("data.bin"), [1, 2, 3, 4]).unwrap();
+    fs::write(dir.join("bad.inc"), "\n  LD A,\n").unwrap();
+    fs::write(
+        dir.join("main.asm"),
+        "  INCLUDE \"defs.inc\"\n  show\n  INCBIN \"data.bin\",1,2\n  INCBIN \"data.bin\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("broken.asm"), "  NOP\n  INCLUDE \"bad.inc\"\n").unwrap();
+
+    let program = assemble_file(&dir.join("main.asm")).unwrap();
+    assert_eq!(program.bytes(), vec![0x3e, 0x05, 2, 3, 1, 2, 3, 4]);
+
+    let error = assemble_file(&dir.join("broken.asm")).unwrap_err();
+    assert_eq!(error.line, Some(2));
+    assert!(error.file.unwrap().ends_with("bad.inc"));
+
+    assert!(assemble_file(&dir.join("missing.asm")).is_err());
+    assert!(assemble("  INCBIN \"/nonexistent/file\"").is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn listing() {
+    let program = assemble("start: LD HL,0x1234 ; load\n  DB 1,2,3,4,5\n  IF 0\n  NOP\n  ENDIF").unwrap();
+
+    assert_eq!(
+        program.listing(),
+        "    1  0000  21 34 12     start: LD HL,0x1234 ; load\n\
+         \x20   2  0003  01 02 03 04    DB 1,2,3,4,5\n\
+         \x20      0007  05\n\
+         \x20   3  0008                 IF 0\n\
+         \x20   5  0008                 ENDIF\n"
+    );
+}
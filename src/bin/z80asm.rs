@@ -0,0 +1,92 @@
+extern crate z80;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use z80::asm::assemble_file;
+
+const USAGE: &str = "Usage: z80asm SOURCE [-o OUTPUT] [-s SYMBOLS] [-l LISTING]
+
+Assemble a Zilog syntax source file into a raw binary, starting at the
+lowest address used. OUTPUT defaults to SOURCE with the .bin extension.
+With -s the symbol table is written as `name EQU 0x1234` lines,
+with -l the listing with addresses, object code and source lines.";
+
+struct Options {
+    source: String,
+    output: Option<String>,
+    symbols: Option<String>,
+    listing: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut source = None;
+    let mut output = None;
+    let mut symbols = None;
+    let mut listing = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().ok_or("Missing output file")?.clone()),
+            "-s" => symbols = Some(args.next().ok_or("Missing symbol file")?.clone()),
+            "-l" => listing = Some(args.next().ok_or("Missing listing file")?.clone()),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if source.is_none() => source = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(Options {
+        source: source.ok_or_else(|| USAGE.to_string())?,
+        output,
+        symbols,
+        listing,
+    })
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let program = assemble_file(Path::new(&options.source)).map_err(|e| match e.file {
+        Some(_) => e.to_string(),
+        None => format!("{}: {}", options.source, e),
+    })?;
+
+    let output = match options.output {
+        Some(ref output) => output.clone(),
+        None => Path::new(&options.source)
+            .with_extension("bin")
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    fs::write(&output, program.bytes()).map_err(|e| format!("{}: {}", output, e))?;
+
+    if let Some(ref path) = options.symbols {
+        let table: String = program
+            .symbols
+            .iter()
+            .map(|(name, value)| format!("{} EQU 0x{:04x}\n", name, value & 0xffff))
+            .collect();
+        fs::write(path, table).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    if let Some(ref path) = options.listing {
+        fs::write(path, program.listing()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = parse_args(&args).and_then(|options| run(&options));
+
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,175 @@
+extern crate z80;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use z80::asm::parse_number;
+use z80::tracediff::{compare, parse, report, start_at, Layout};
+
+const USAGE: &str = "Usage: z80tracediff [OPTIONS] TRACE REFERENCE
+
+Compare an execution trace written by rz80 --trace with a reference
+trace, from another run or another emulator, and report the first
+instruction where registers or flags differ.
+
+Options:
+  --layout SPEC      where the fields are on REFERENCE lines, as in
+                     PC=1,AF=af,BC=bc,SP=7: a number is a whitespace
+                     separated column from 1, anything else the name of
+                     a NAME=value or NAME:value token. By default the
+                     format of rz80 traces
+  --fields LIST      compare only these fields, as in PC,AF,SP
+  --ignore LIST      do not compare these fields, as in R,T
+  --start ADDRESS    skip the instructions of both traces before the
+                     first one at ADDRESS
+  --context N        lines before the difference to print, default 5
+
+Fields are PC, AF, BC, DE, HL, IX, IY, SP, AF', BC', DE', HL', I, R,
+IFF, F and T; only those found in both traces are compared. Lines
+without a PC are skipped.
+
+Exit status is 0 when the traces match, 1 when they differ and 2 on
+errors.";
+
+struct Options {
+    trace: String,
+    reference: String,
+    layout: Layout,
+    fields: Option<Vec<String>>,
+    ignore: Vec<String>,
+    start: Option<u16>,
+    context: usize,
+}
+
+fn names(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|name| name.trim().to_uppercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn number(text: &str) -> Result<u32, String> {
+    match parse_number(text) {
+        Ok(value) if value >= 0 => Ok(value as u32),
+        _ => Err(format!("Invalid number: {}", text)),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut files = Vec::new();
+    let mut options = Options {
+        trace: String::new(),
+        reference: String::new(),
+        layout: Layout::native(),
+        fields: None,
+        ignore: Vec::new(),
+        start: None,
+        context: 5,
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(USAGE.to_string());
+        }
+
+        if !arg.starts_with("--") {
+            files.push(arg.clone());
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+
+        match arg.as_str() {
+            "--layout" => options.layout = Layout::parse(value)?,
+            "--fields" => options.fields = Some(names(value)),
+            "--ignore" => options.ignore.extend(names(value)),
+            "--start" => match number(value)? {
+                address if address <= 0xffff => options.start = Some(address as u16),
+                _ => return Err(format!("Address out of range: {}", value)),
+            },
+            "--context" => options.context = number(value)? as usize,
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    match files.len() {
+        2 => {
+            options.reference = files.pop().unwrap();
+            options.trace = files.pop().unwrap();
+            Ok(options)
+        }
+        0 | 1 => Err(USAGE.to_string()),
+        _ => Err(format!("Unexpected argument: {}", files[2])),
+    }
+}
+
+/// True when the traces match.
+fn run(options: &Options) -> Result<bool, String> {
+    let read = |path: &str| fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e));
+
+    let trace = parse(&read(&options.trace)?, &Layout::native());
+    let reference = parse(&read(&options.reference)?, &options.layout);
+
+    let (trace, reference) = match options.start {
+        Some(address) => {
+            let missing = |path: &str| format!("{}: no instruction at 0x{:04x}", path, address);
+            (
+                start_at(&trace, address).ok_or_else(|| missing(&options.trace))?,
+                start_at(&reference, address).ok_or_else(|| missing(&options.reference))?,
+            )
+        }
+        None => (&trace[..], &reference[..]),
+    };
+
+    let fields: Vec<String> = options
+        .fields
+        .clone()
+        .unwrap_or_else(|| {
+            Layout::native()
+                .names()
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        })
+        .into_iter()
+        .filter(|name| !options.ignore.contains(name))
+        .collect();
+
+    match compare(trace, reference, &fields) {
+        Some(divergence) => {
+            print!(
+                "{}",
+                report(
+                    [
+                        (options.trace.as_str(), trace),
+                        (options.reference.as_str(), reference)
+                    ],
+                    &divergence,
+                    options.context
+                )
+            );
+            Ok(false)
+        }
+        None => {
+            println!("{} instructions match", trace.len());
+            Ok(true)
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match parse_args(&args).and_then(|options| run(&options)) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    }
+}
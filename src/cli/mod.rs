@@ -0,0 +1,717 @@
+#[cfg(test)]
+mod tests;
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal};
+use std::path::Path;
+use std::rc::Rc;
+
+use z80::asm::parse_number;
+use z80::cpm::{Cpm, DiskImage, DiskSystem, Exit, Geometry};
+use z80::cpu::{
+    dump_memory, Coverage, Cpu, CpuBuilder, CpuState, Limits, Profile, StopReason, Trace,
+};
+use z80::dap::{DapServer, SourceMap};
+use z80::debugger::Debugger;
+use z80::gdb::GdbStub;
+use z80::loader::{decode, encode, Format, Image};
+use z80::spectrum::{run_fast_load, Snapshot, SnapshotFormat, Tape, TapeFormat, Ula};
+use z80::symbols::SymbolTable;
+
+// === Command line of rz80 ===
+//
+// The options parsed into Options, and the run they set up: a DAP or GDB
+// server, the debugger, a CP/M system booted from disks, or a program
+// run to the end with its traces and reports.
+
+pub const USAGE: &str = "Usage: rz80 [OPTIONS] FILE
+       rz80 --cpm DIR [OPTIONS] FILE.COM [ARGUMENTS]
+       rz80 --disk IMAGE... [--disk-format FORMAT] [OPTIONS]
+       rz80 --dap
+
+Load a program into 64K of memory, run it until HALT or a limit,
+then print the registers.
+
+Options:
+  --format FORMAT         bin, hex, srec or com; by default from the file
+                          extension
+  --load ADDRESS          load address of binary files, default 0
+  --pc ADDRESS            initial PC, default the start address of the file
+  --sp ADDRESS            initial SP, default 0 (0xfffe for .com files)
+  --max-instructions N    stop after N instructions
+  --max-cycles N          stop after N T states
+  --dump START-END        print memory from START to END included, repeatable
+  --save FILE             write the memory of --save-range to FILE after the
+                          run, in the format of its extension: Intel HEX,
+                          S-records, or binary by default
+  --save-range START-END  memory to save, repeatable
+  --save-state FILE       write the state of the CPU and its memory to FILE
+                          after the run
+  --restore FILE          run from a state written by --save-state instead
+                          of a program
+  --rom FILE              ZX Spectrum ROM at 0 for .sna, .z80 and .szx
+                          snapshots, 16K, or 32K with both ROMs of the 128K
+  --save-snapshot FILE    write a ZX Spectrum snapshot to FILE after the run,
+                          .sna, .z80 or .szx by its extension: 48K, or the
+                          model of the snapshot run
+  --tape FILE             play the .tap or .tzx FILE from the start of the
+                          run to the EAR input, bit 6 of the ZX Spectrum
+                          ULA port at the even addresses
+  --fast-load             load the data blocks of --tape at once when the
+                          CPU calls LD-BYTES of the 48K ROM, at 0x0556
+  --trace FILE            write a line per instruction executed to FILE:
+                          address, code, disassembly, registers, flags and
+                          T states before the instruction
+  --trace-range START-END only trace instructions from START to END included,
+                          repeatable
+  --trace-last N          only write the last N instructions traced, at the
+                          end of the run
+  --profile FILE          write the T states spent in each routine and at
+                          each address to FILE
+  --flamegraph FILE       write the T states spent in each stack of routines
+                          to FILE, in the collapsed format of flamegraph tools
+  --symbols FILE          name routines and addresses in traces, profiles and
+                          the debugger with the symbols of FILE: a .sym or
+                          .map file, NAME EQU VALUE lines, or a source to
+                          assemble
+  --coverage FILE         write the disassembly of the program to FILE with
+                          the times each instruction ran, the bytes used as
+                          data and the directions taken by conditional jumps
+  --lcov FILE             write the lines of source run to FILE in the lcov
+                          format, with the line table of --listing
+  --listing FILE          listing of the program written by z80asm -l
+  --source FILE           source file of the listing
+  --debug                 start the monitor instead of running, reading
+                          commands from stdin; type help for the list
+  --gdb [HOST:]PORT       wait for GDB to connect on the port instead of
+                          running, on localhost by default
+  --dap                   serve the Debug Adapter Protocol on stdin and
+                          stdout for an editor, which launches the program
+  --cpm DIR               run the .com file under CP/M 2.2 with its console
+                          on stdin and stdout and drive A in DIR, drives B
+                          to P in its subdirectories b to p; the arguments
+                          after the file are the command tail
+  --disk IMAGE            boot CP/M 2.2 from the raw disk IMAGE in drive A,
+                          its console on stdin and stdout; repeated for
+                          drives B to P. Images written to are saved after
+                          the run
+  --disk-format FORMAT    geometry of the disk images: ibm-3740, the
+                          default, 4mb-hd, or cpmtools parameters changing
+                          ibm-3740, as in seclen=512,sectrk=9,tracks=160,
+                          blocksize=2048,maxdir=128,skew=1,boottrk=2
+
+ZX Spectrum .sna, .z80 and .szx snapshots restore the registers and RAM
+of the 48K or 128K, with the memory paged in at the time seen by the CPU.
+
+CP/M .com files are loaded at 0x100, with HALT at address 0 so that
+returning to the system stops the run. With --cpm, the registers are only
+printed when the CPU stops before the program ends, and with --disk before
+the end of the console input.
+
+Numbers can be decimal or hexadecimal, as in 0x100, $100 or 100h.";
+
+#[derive(Debug)]
+pub struct Options {
+    file: String,
+    format: Option<Format>,
+    load: u16,
+    pc: Option<u16>,
+    sp: Option<u16>,
+    limits: Limits,
+    dumps: Vec<(u16, u16)>,
+    save: Option<String>,
+    save_ranges: Vec<(u16, u16)>,
+    trace: Option<String>,
+    trace_ranges: Vec<(u16, u16)>,
+    trace_last: Option<usize>,
+    profile: Option<String>,
+    flamegraph: Option<String>,
+    symbols: Option<String>,
+    coverage: Option<String>,
+    lcov: Option<String>,
+    listing: Option<String>,
+    source: Option<String>,
+    debug: bool,
+    gdb: Option<String>,
+    dap: bool,
+    cpm: Option<String>,
+    tail: Vec<String>,
+    disks: Vec<String>,
+    disk_format: Option<Geometry>,
+    save_state: Option<String>,
+    restore: Option<String>,
+    rom: Option<String>,
+    save_snapshot: Option<String>,
+    tape: Option<String>,
+    fast_load: bool,
+}
+
+fn number(text: &str) -> Result<u32, String> {
+    match parse_number(text) {
+        Ok(value) if value >= 0 => Ok(value as u32),
+        _ => Err(format!("Invalid number: {}", text)),
+    }
+}
+
+fn address(text: &str) -> Result<u16, String> {
+    let value = number(text)?;
+    if value > 0xffff {
+        return Err(format!("Address out of range: {}", text));
+    }
+    Ok(value as u16)
+}
+
+/// START-END, or a single address.
+fn range(text: &str) -> Result<(u16, u16), String> {
+    let mut range = text.splitn(2, '-');
+    let start = address(range.next().unwrap_or(""))?;
+    let end = match range.next() {
+        Some(end) => address(end)?,
+        None => start,
+    };
+    if end < start {
+        return Err(format!("Invalid range: {}", text));
+    }
+    Ok((start, end))
+}
+
+/// The options of the command line, or None when it asks for help.
+pub fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        file: String::new(),
+        format: None,
+        load: 0,
+        pc: None,
+        sp: None,
+        limits: Limits::default(),
+        dumps: Vec::new(),
+        save: None,
+        save_ranges: Vec::new(),
+        trace: None,
+        trace_ranges: Vec::new(),
+        trace_last: None,
+        profile: None,
+        flamegraph: None,
+        symbols: None,
+        coverage: None,
+        lcov: None,
+        listing: None,
+        source: None,
+        debug: false,
+        gdb: None,
+        dap: false,
+        cpm: None,
+        tail: Vec::new(),
+        disks: Vec::new(),
+        disk_format: None,
+        save_state: None,
+        restore: None,
+        rom: None,
+        save_snapshot: None,
+        tape: None,
+        fast_load: false,
+    };
+    let mut file = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(None);
+        }
+
+        if !arg.starts_with("--") || (file.is_some() && options.cpm.is_some()) {
+            // The arguments of a CP/M program follow it.
+            if file.is_some() && options.cpm.is_some() {
+                options.tail.push(arg.clone());
+                continue;
+            }
+            if file.is_some() {
+                return Err(format!("Unexpected argument: {}", arg));
+            }
+            file = Some(arg.clone());
+            continue;
+        }
+
+        if arg == "--debug" {
+            options.debug = true;
+            continue;
+        }
+
+        if arg == "--dap" {
+            options.dap = true;
+            continue;
+        }
+
+        if arg == "--fast-load" {
+            options.fast_load = true;
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+
+        match arg.as_str() {
+            "--format" => {
+                options.format =
+                    Some(Format::parse(value).ok_or_else(|| format!("Unknown format: {}", value))?)
+            }
+            "--load" => options.load = address(value)?,
+            "--gdb" => {
+                options.gdb = Some(if value.contains(':') {
+                    value.clone()
+                } else {
+                    format!("127.0.0.1:{}", value)
+                })
+            }
+            "--pc" => options.pc = Some(address(value)?),
+            "--sp" => options.sp = Some(address(value)?),
+            "--max-instructions" => options.limits.instructions = Some(u64::from(number(value)?)),
+            "--max-cycles" => options.limits.cycles = Some(u64::from(number(value)?)),
+            "--dump" => options.dumps.push(range(value)?),
+            "--save" => options.save = Some(value.clone()),
+            "--save-range" => options.save_ranges.push(range(value)?),
+            "--save-state" => options.save_state = Some(value.clone()),
+            "--restore" => options.restore = Some(value.clone()),
+            "--rom" => options.rom = Some(value.clone()),
+            "--save-snapshot" => {
+                if SnapshotFormat::from_path(Path::new(value)).is_none() {
+                    return Err(format!("{}: not a .sna, .z80 or .szx snapshot", value));
+                }
+                options.save_snapshot = Some(value.clone())
+            }
+            "--tape" => {
+                if TapeFormat::from_path(Path::new(value)).is_none() {
+                    return Err(format!("{}: not a .tap or .tzx tape", value));
+                }
+                options.tape = Some(value.clone())
+            }
+            "--trace" => options.trace = Some(value.clone()),
+            "--trace-range" => options.trace_ranges.push(range(value)?),
+            "--trace-last" => options.trace_last = Some(number(value)? as usize),
+            "--profile" => options.profile = Some(value.clone()),
+            "--flamegraph" => options.flamegraph = Some(value.clone()),
+            "--symbols" => options.symbols = Some(value.clone()),
+            "--coverage" => options.coverage = Some(value.clone()),
+            "--lcov" => options.lcov = Some(value.clone()),
+            "--listing" => options.listing = Some(value.clone()),
+            "--source" => options.source = Some(value.clone()),
+            "--cpm" => options.cpm = Some(value.clone()),
+            "--disk" => options.disks.push(value.clone()),
+            "--disk-format" => {
+                options.disk_format = Some(Geometry::parse(value).map_err(|e| e.to_string())?)
+            }
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    // The editor names the program to debug.
+    if options.dap {
+        if let Some(file) = file {
+            return Err(format!("Unexpected argument: {}", file));
+        }
+        return Ok(Some(options));
+    }
+
+    if options.save.is_some() == options.save_ranges.is_empty() {
+        return Err("--save and --save-range go together".to_string());
+    }
+
+    if options.cpm.is_some() && (options.debug || options.gdb.is_some()) {
+        return Err("--cpm cannot be used with --debug or --gdb".to_string());
+    }
+
+    if options.cpm.is_some() && options.restore.is_some() {
+        return Err("--restore cannot be used with --cpm".to_string());
+    }
+
+    // The system boots from drive A.
+    if !options.disks.is_empty() {
+        if let Some(file) = file {
+            return Err(format!("Unexpected argument: {}", file));
+        }
+        if options.disks.len() > 16 {
+            return Err("At most 16 disks, A to P".to_string());
+        }
+        if options.cpm.is_some() || options.debug || options.gdb.is_some() {
+            return Err("--disk cannot be used with --cpm, --debug or --gdb".to_string());
+        }
+        return Ok(Some(options));
+    }
+
+    if options.fast_load && options.tape.is_none() {
+        return Err("--fast-load needs --tape".to_string());
+    }
+
+    if options.fast_load && (options.cpm.is_some() || options.debug || options.gdb.is_some()) {
+        return Err("--fast-load cannot be used with --cpm, --debug or --gdb".to_string());
+    }
+
+    if options.lcov.is_some() && (options.listing.is_none() || options.source.is_none()) {
+        return Err("--lcov needs --listing and --source".to_string());
+    }
+
+    // A save state replaces the program.
+    options.file = match (file, options.restore.clone()) {
+        (Some(file), Some(_)) => return Err(format!("Unexpected argument: {}", file)),
+        (None, Some(state)) => state,
+        (file, None) => file.ok_or_else(|| USAGE.to_string())?,
+    };
+    Ok(Some(options))
+}
+
+/// Run the program as the options say.
+pub fn run(options: &Options) -> Result<(), String> {
+    if options.dap {
+        return DapServer::new()
+            .serve(io::stdin(), &mut io::stdout())
+            .map_err(|e| e.to_string());
+    }
+
+    if !options.disks.is_empty() {
+        return boot(options);
+    }
+
+    let (image, mut cpu, snapshot) = program(options)?;
+    let tape = insert_tape(options, &mut cpu)?;
+
+    let mut symbols = SymbolTable::new();
+    if let Some(ref path) = options.symbols {
+        symbols.load(Path::new(path))?;
+    }
+
+    if let Some(ref address) = options.gdb {
+        eprintln!("Waiting for GDB on {}", address);
+        return GdbStub::new(cpu)
+            .listen(address.as_str())
+            .map_err(|e| format!("{}: {}", address, e));
+    }
+
+    if options.debug {
+        return debug(cpu, symbols);
+    }
+
+    instrument(options, &mut cpu, &symbols)?;
+    let (reason, executed) = execute(options, &mut cpu, tape.as_ref())?;
+    report(options, &mut cpu, &image, &symbols)?;
+
+    if let Some(reason) = reason {
+        // The console of a CP/M program is stdout.
+        if options.cpm.is_some() {
+            eprintln!("Stopped by {} after {} instructions", reason, executed);
+            eprint!("{}", cpu.dump_registers());
+        } else {
+            println!("Stopped by {} after {} instructions", reason, executed);
+            print!("{}", cpu.dump_registers());
+        }
+    }
+
+    for &(start, end) in &options.dumps {
+        println!();
+        print!("{}", dump_memory(&cpu.memory, start, end));
+    }
+
+    save(options, &cpu, &image, snapshot)
+}
+
+/// The CPU of the command line: a save state restored, a Spectrum
+/// snapshot with its ROM, or a program loaded.
+fn program(options: &Options) -> Result<(Image, Cpu, Option<Snapshot>), String> {
+    let snapshot = match (options.format, &options.cpm) {
+        (None, &None) if options.restore.is_none() => {
+            SnapshotFormat::from_path(Path::new(&options.file))
+        }
+        _ => None,
+    };
+
+    if options.restore.is_some() {
+        let (image, cpu) = restore(options)?;
+        Ok((image, cpu, None))
+    } else if let Some(format) = snapshot {
+        let (image, cpu, snapshot) = spectrum(options, format)?;
+        Ok((image, cpu, Some(snapshot)))
+    } else {
+        let (image, cpu) = load(options)?;
+        Ok((image, cpu, None))
+    }
+}
+
+/// Connect the tape of the command line to the CPU through a ULA,
+/// playing from now.
+fn insert_tape(options: &Options, cpu: &mut Cpu) -> Result<Option<Rc<RefCell<Tape>>>, String> {
+    let path = match options.tape {
+        Some(ref path) => path,
+        None => return Ok(None),
+    };
+    let format = TapeFormat::from_path(Path::new(path)).unwrap();
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut tape = Tape::read(&data, format).map_err(|e| format!("{}: {}", path, e))?;
+    tape.play(cpu.cycles);
+    let tape = Rc::new(RefCell::new(tape));
+    cpu.ports = Some(Box::new(Ula::new().with_tape(tape.clone())));
+    Ok(Some(tape))
+}
+
+/// Run the debugger on stdin, with a prompt at a terminal.
+fn debug(cpu: Cpu, symbols: SymbolTable) -> Result<(), String> {
+    let stdin = io::stdin();
+    let prompt = if stdin.is_terminal() { Some("z80> ") } else { None };
+    let mut debugger = Debugger::new(cpu);
+    debugger.symbols = symbols;
+    debugger
+        .repl(stdin.lock(), &mut io::stdout(), prompt)
+        .map_err(|e| e.to_string())
+}
+
+/// Attach the trace, profile and coverage asked for.
+fn instrument(options: &Options, cpu: &mut Cpu, symbols: &SymbolTable) -> Result<(), String> {
+    if let Some(ref path) = options.trace {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut trace =
+            Trace::new(Box::new(BufWriter::new(file))).with_symbols(symbols.clone());
+        for &(start, end) in &options.trace_ranges {
+            trace = trace.with_range(start, end);
+        }
+        if let Some(count) = options.trace_last {
+            trace = trace.with_last(count);
+        }
+        cpu.trace = Some(trace);
+    }
+
+    if options.profile.is_some() || options.flamegraph.is_some() {
+        cpu.profile = Some(Profile::new());
+    }
+
+    if options.coverage.is_some() || options.lcov.is_some() {
+        cpu.coverage = Some(Coverage::new());
+    }
+    Ok(())
+}
+
+/// Run the program, under CP/M or on its own, loading the tape fast if
+/// asked. Returns why it stopped, None when a CP/M program exited, and
+/// the instructions executed.
+fn execute(
+    options: &Options,
+    cpu: &mut Cpu,
+    tape: Option<&Rc<RefCell<Tape>>>,
+) -> Result<(Option<StopReason>, u64), String> {
+    if let Some(ref root) = options.cpm {
+        let stdin = io::stdin();
+        let echo = !stdin.is_terminal();
+        let mut cpm =
+            Cpm::new(Path::new(root), Box::new(stdin), Box::new(io::stdout())).with_echo(echo);
+        cpm.start(cpu, &options.tail.join(" "));
+        let exit = cpm.run(cpu, options.limits);
+        return Ok(match exit.map_err(|e| e.to_string())? {
+            (Exit::Stopped(reason), executed) => (Some(reason), executed),
+            _ => (None, 0),
+        });
+    }
+
+    let (reason, executed) = match tape {
+        Some(tape) if options.fast_load => run_fast_load(cpu, tape, options.limits),
+        _ => cpu.run(options.limits),
+    };
+    Ok((Some(reason), executed))
+}
+
+/// Write the trace, profile and coverage reports of the run.
+fn report(
+    options: &Options,
+    cpu: &mut Cpu,
+    image: &Image,
+    symbols: &SymbolTable,
+) -> Result<(), String> {
+    if let Some(mut trace) = cpu.trace.take() {
+        trace
+            .finish()
+            .map_err(|e| format!("{}: {}", options.trace.as_ref().unwrap(), e))?;
+    }
+
+    if let Some(profile) = cpu.profile.take() {
+        if let Some(ref path) = options.profile {
+            fs::write(path, profile.report(&cpu.memory, symbols))
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(ref path) = options.flamegraph {
+            fs::write(path, profile.collapsed(symbols)).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+
+    if let Some(coverage) = cpu.coverage.take() {
+        if let Some(ref path) = options.coverage {
+            let text: String = image
+                .segments
+                .iter()
+                .filter(|segment| !segment.bytes.is_empty())
+                .map(|segment| {
+                    let end = usize::from(segment.address) + segment.bytes.len() - 1;
+                    coverage.annotate(&cpu.memory, segment.address, end.min(0xffff) as u16)
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            fs::write(path, text).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let (Some(path), Some(listing), Some(source)) =
+            (&options.lcov, &options.listing, &options.source)
+        {
+            let text = fs::read_to_string(listing).map_err(|e| format!("{}: {}", listing, e))?;
+            let map = SourceMap::from_listing(&text, Path::new(source));
+            fs::write(path, coverage.lcov(map.lines(), &options.file))
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Save the memory, the save state and the snapshot asked for after the
+/// run.
+fn save(
+    options: &Options,
+    cpu: &Cpu,
+    image: &Image,
+    snapshot: Option<Snapshot>,
+) -> Result<(), String> {
+    if let Some(ref path) = options.save {
+        // Started where the program was.
+        let start = options.pc.unwrap_or(image.start);
+        let saved = Image::from_memory(&cpu.memory, &options.save_ranges, start)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        fs::write(path, encode(&saved, Format::from_path(Path::new(path))))
+            .map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    if let Some(ref path) = options.save_state {
+        fs::write(path, cpu.state().to_bytes()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    if let Some(ref path) = options.save_snapshot {
+        let snapshot = match snapshot {
+            Some(mut snapshot) => {
+                snapshot.update(cpu);
+                snapshot
+            }
+            None => Snapshot::from_cpu(cpu),
+        };
+        let format = SnapshotFormat::from_path(Path::new(path)).unwrap();
+        let data = snapshot
+            .write(format)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        fs::write(path, data).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// Load the program of the command line into a new CPU.
+fn load(options: &Options) -> Result<(Image, Cpu), String> {
+    let data = fs::read(&options.file).map_err(|e| format!("{}: {}", options.file, e))?;
+    let format = match (options.format, &options.cpm) {
+        (Some(format), _) => format,
+        (None, &Some(_)) => Format::Com,
+        (None, &None) => Format::from_path(Path::new(&options.file)),
+    };
+
+    let image =
+        decode(&data, format, options.load).map_err(|e| format!("{}: {}", options.file, e))?;
+
+    let mut memory = vec![0; 0x10000];
+    let mut sp = 0;
+
+    if format == Format::Com {
+        // HALT at the warm boot entry, reached by RET or JP 0.
+        memory[0] = 0x76;
+        sp = 0xfffe;
+    }
+
+    let cpu = CpuBuilder::new()
+        .with_memory(memory)
+        .with_image(&image)
+        .map_err(|e| format!("{}: {}", options.file, e))?
+        .with_pc(options.pc.unwrap_or(image.start))
+        .with_sp(options.sp.unwrap_or(sp))
+        .build();
+
+    Ok((image, cpu))
+}
+
+/// Restore a CPU from the save state of the command line, as the program
+/// started at its PC.
+fn restore(options: &Options) -> Result<(Image, Cpu), String> {
+    let data = fs::read(&options.file).map_err(|e| format!("{}: {}", options.file, e))?;
+    let state = CpuState::from_bytes(&data).map_err(|e| format!("{}: {}", options.file, e))?;
+
+    let mut cpu = CpuBuilder::new().with_memory(Vec::new()).build();
+    cpu.restore(&state);
+    cpu.pc = options.pc.unwrap_or(cpu.pc);
+    cpu.sp = options.sp.unwrap_or(cpu.sp);
+
+    let image = Image {
+        segments: Vec::new(),
+        start: state.pc,
+    };
+    Ok((image, cpu))
+}
+
+/// Load the Spectrum snapshot of the command line, with the ROM.
+fn spectrum(options: &Options, format: SnapshotFormat) -> Result<(Image, Cpu, Snapshot), String> {
+    let data = fs::read(&options.file).map_err(|e| format!("{}: {}", options.file, e))?;
+    let snapshot = Snapshot::read(&data, format).map_err(|e| format!("{}: {}", options.file, e))?;
+    let rom = match options.rom {
+        Some(ref path) => fs::read(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => Vec::new(),
+    };
+
+    let mut cpu = snapshot.cpu(&rom);
+    cpu.pc = options.pc.unwrap_or(cpu.pc);
+    cpu.sp = options.sp.unwrap_or(cpu.sp);
+
+    let image = Image {
+        segments: Vec::new(),
+        start: snapshot.registers.pc,
+    };
+    Ok((image, cpu, snapshot))
+}
+
+/// Boot CP/M from the disk images, saving those written to after the run.
+fn boot(options: &Options) -> Result<(), String> {
+    let geometry = options
+        .disk_format
+        .clone()
+        .unwrap_or_else(Geometry::ibm_3740);
+
+    let stdin = io::stdin();
+    let echo = !stdin.is_terminal();
+    let mut system = DiskSystem::new(Box::new(stdin), Box::new(io::stdout())).with_echo(echo);
+    let mut originals = Vec::new();
+    for (drive, path) in options.disks.iter().enumerate() {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let disk = DiskImage::from_bytes(geometry.clone(), data)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        originals.push(disk.bytes().to_vec());
+        system.insert(drive, disk);
+    }
+
+    let mut cpu = CpuBuilder::new().with_memory(vec![0; 0x10000]).build();
+    system
+        .boot(&mut cpu)
+        .map_err(|e| format!("{}: {}", options.disks[0], e))?;
+    let (exit, executed) = system
+        .run(&mut cpu, options.limits)
+        .map_err(|e| e.to_string())?;
+
+    for (drive, path) in options.disks.iter().enumerate() {
+        if let Some(disk) = system.eject(drive) {
+            if disk.bytes() != &originals[drive][..] {
+                fs::write(path, disk.bytes()).map_err(|e| format!("{}: {}", path, e))?;
+            }
+        }
+    }
+
+    if let Exit::Stopped(reason) = exit {
+        eprintln!("Stopped by {} after {} instructions", reason, executed);
+        eprint!("{}", cpu.dump_registers());
+    }
+    Ok(())
+}
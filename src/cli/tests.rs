@@ -0,0 +1,31 @@
+use cli::{parse_args, USAGE};
+
+fn args(text: &str) -> Vec<String> {
+    text.split_whitespace().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn help() {
+    for text in &["--help", "-h", "prog.bin --help", "--load 0x100 -h"] {
+        match parse_args(&args(text)) {
+            Ok(None) => {}
+            other => panic!("{}: {:?}", text, other),
+        }
+    }
+}
+
+#[test]
+fn options() {
+    let options = match parse_args(&args("--load 0x100 --pc 0x200 prog.bin")) {
+        Ok(Some(options)) => options,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(options.file, "prog.bin");
+    assert_eq!((options.load, options.pc), (0x100, Some(0x200)));
+
+    let error = |text| parse_args(&args(text)).unwrap_err();
+    assert_eq!(error(""), USAGE);
+    assert_eq!(error("--bogus 1 prog.bin"), "Unknown option: --bogus");
+    assert_eq!(error("--load"), "Missing value for --load");
+    assert_eq!(error("--fast-load prog.bin"), "--fast-load needs --tape");
+}
@@ -0,0 +1,379 @@
+use cpm::files::{self, DRIVE, NAME, NEW_NAME, RECORD};
+use cpm::{Cpm, Exit, ALLOCATION, DEFAULT_DMA, DPB};
+use cpu::Cpu;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// === BDOS functions ===
+//
+// The function number is in C and its parameter in E, or the address
+// of a buffer or FCB in DE. Results are in A, 16 bit ones in HL with L
+// also in A and H in B. Failures of the host files are returned as the
+// error codes of the functions, 0xff or 1 to 6 for the records of files,
+// only console output errors end the run.
+
+/// Size of an FCB used with random access.
+const FCB_SIZE: usize = 36;
+
+/// Characters printed at most by function 9 without a '$'.
+const MAX_STRING: usize = 0x10000;
+
+fn read_memory(memory: &[u8], address: u16, size: usize) -> Vec<u8> {
+    (0..size)
+        .map(|offset| memory[usize::from(address.wrapping_add(offset as u16))])
+        .collect()
+}
+
+fn write_memory(memory: &mut [u8], address: u16, bytes: &[u8]) {
+    for (offset, &byte) in bytes.iter().enumerate() {
+        memory[usize::from(address.wrapping_add(offset as u16))] = byte;
+    }
+}
+
+fn name(fcb: &[u8], at: usize) -> [u8; 11] {
+    let mut name = [0; 11];
+    name.copy_from_slice(&fcb[at..at + 11]);
+    name
+}
+
+/// Record of a file, padded with ^Z, or None past its end.
+fn read_record(path: &Path, record: u32) -> io::Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(u64::from(record) * RECORD as u64))?;
+
+    let mut data = Vec::with_capacity(RECORD);
+    file.take(RECORD as u64).read_to_end(&mut data)?;
+    if data.is_empty() {
+        return Ok(None);
+    }
+    data.resize(RECORD, 0x1a);
+    Ok(Some(data))
+}
+
+/// Write a record, the file growing with zeros up to it if needed.
+fn write_record(path: &Path, record: u32, data: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(u64::from(record) * RECORD as u64))?;
+    file.write_all(data)
+}
+
+/// Size of a file in records.
+fn size(path: &Path) -> u32 {
+    fs::metadata(path)
+        .map(|metadata| files::records(metadata.len()))
+        .unwrap_or(0)
+}
+
+/// Directory entry of a file, its last extent showing its size.
+fn directory_entry(user: u8, name: &[u8; 11], size: u32) -> [u8; 32] {
+    let extent = size.saturating_sub(1) / 128;
+    let records = size - extent * 128;
+
+    let mut entry = [0; 32];
+    entry[0] = user;
+    entry[1..12].copy_from_slice(name);
+    entry[12] = (extent & 0x1f) as u8;
+    entry[14] = ((extent >> 5) & 0x3f) as u8;
+    entry[15] = records as u8;
+    // One 2K block per 16 records, as 16 bit block numbers.
+    let blocks = records.div_ceil(16) as usize;
+    for block in 0..blocks {
+        entry[16 + block * 2] = 1;
+    }
+    entry
+}
+
+impl Cpm {
+    /// Serve the call to the BDOS entry.
+    pub(super) fn bdos(&mut self, cpu: &mut Cpu) -> io::Result<Option<Exit>> {
+        let (e, de) = (cpu.e, cpu.read_de());
+
+        let result: u16 = match cpu.c {
+            0 => return Ok(Some(Exit::WarmBoot)),
+            1 => match self.console.read()? {
+                Some(byte) => {
+                    self.console.echo(&[byte])?;
+                    u16::from(byte)
+                }
+                None => return Ok(Some(Exit::EndOfInput)),
+            },
+            2 => {
+                self.console.write(e)?;
+                0
+            }
+            // Reader input at its end, punch and list output dropped.
+            3 => 0x1a,
+            4 | 5 => 0,
+            6 => match e {
+                0xff if self.console.ready() => u16::from(self.console.read()?.unwrap_or(0)),
+                0xff => 0,
+                0xfe => self.status(),
+                0xfd => match self.console.read()? {
+                    Some(byte) => u16::from(byte),
+                    None => return Ok(Some(Exit::EndOfInput)),
+                },
+                _ => {
+                    self.console.write(e)?;
+                    0
+                }
+            },
+            7 => u16::from(cpu.memory[3]),
+            8 => {
+                cpu.memory[3] = e;
+                0
+            }
+            9 => {
+                let mut address = de;
+                for _ in 0..MAX_STRING {
+                    let byte = cpu.memory[usize::from(address)];
+                    if byte == b'$' {
+                        break;
+                    }
+                    self.console.write(byte)?;
+                    address = address.wrapping_add(1);
+                }
+                0
+            }
+            10 => {
+                if !self.read_line(cpu, de)? {
+                    return Ok(Some(Exit::EndOfInput));
+                }
+                0
+            }
+            11 => self.status(),
+            // CP/M 2.2.
+            12 => 0x0022,
+            13 => {
+                self.dma = DEFAULT_DMA;
+                self.select(cpu, 0);
+                0
+            }
+            14 => {
+                self.select(cpu, e & 0x0f);
+                0
+            }
+            15..=23 | 30 | 33..=36 | 40 => u16::from(self.file(cpu, de)),
+            24 => (0..16u8)
+                .filter(|&drive| self.directory(drive).is_dir())
+                .fold(0u16, |vector, drive| vector | 1 << drive),
+            25 => u16::from(self.drive),
+            26 => {
+                self.dma = de;
+                0
+            }
+            27 => ALLOCATION,
+            // Write protection and the read only vector.
+            28 | 29 => 0,
+            31 => DPB,
+            32 => {
+                if e == 0xff {
+                    u16::from(self.user)
+                } else {
+                    self.user = e & 0x0f;
+                    let drive = self.drive;
+                    self.select(cpu, drive);
+                    0
+                }
+            }
+            _ => 0,
+        };
+
+        cpu.write_hl(result);
+        cpu.a = result as u8;
+        cpu.b = (result >> 8) as u8;
+        Ok(None)
+    }
+
+    fn status(&mut self) -> u16 {
+        if self.console.ready() {
+            0xff
+        } else {
+            0
+        }
+    }
+
+    /// Function 10: read a line into the buffer at address, which starts
+    /// with its size. Characters past the size are dropped. False at the
+    /// end of the input.
+    fn read_line(&mut self, cpu: &mut Cpu, buffer: u16) -> io::Result<bool> {
+        let size = usize::from(cpu.memory[usize::from(buffer)]);
+        let mut line = Vec::new();
+
+        loop {
+            match self.console.read()? {
+                Some(b'\r') => break,
+                Some(byte) => line.push(byte),
+                None if line.is_empty() => return Ok(false),
+                None => break,
+            }
+        }
+        self.console.echo(&line)?;
+        self.console.echo(b"\r")?;
+
+        line.truncate(size);
+        write_memory(&mut cpu.memory, buffer.wrapping_add(1), &[line.len() as u8]);
+        write_memory(&mut cpu.memory, buffer.wrapping_add(2), &line);
+        Ok(true)
+    }
+
+    fn select(&mut self, cpu: &mut Cpu, drive: u8) {
+        self.drive = drive;
+        cpu.memory[4] = self.user << 4 | drive;
+    }
+
+    /// Directory of the drive, 0 for A.
+    fn directory(&self, drive: u8) -> PathBuf {
+        match drive {
+            0 => self.root.clone(),
+            _ => self.root.join(char::from(b'a' + drive).to_string()),
+        }
+    }
+
+    /// Functions on the file of the FCB at address.
+    fn file(&mut self, cpu: &mut Cpu, address: u16) -> u8 {
+        let function = cpu.c;
+        let mut fcb = read_memory(&cpu.memory, address, FCB_SIZE);
+        let directory = self.directory(match fcb[DRIVE] {
+            drive @ 1..=16 => drive - 1,
+            _ => self.drive,
+        });
+        let pattern = name(&fcb, NAME);
+        let found = files::find(&directory, &pattern);
+        let path = found.first().map(|(_, path, _)| path.clone());
+
+        let result = match (function, path) {
+            // Open, close and set attributes.
+            (15, Some(path)) => {
+                files::set_record_count(&mut fcb, size(&path));
+                0
+            }
+            (16, Some(_)) | (30, Some(_)) => 0,
+            (17, _) => {
+                self.found = found
+                    .iter()
+                    .map(|&(ref name, _, size)| directory_entry(self.user, name, size))
+                    .collect();
+                self.search_next(cpu)
+            }
+            (18, _) => self.search_next(cpu),
+            (19, Some(_)) => {
+                let deleted = found
+                    .iter()
+                    .filter(|&(_, path, _)| fs::remove_file(path).is_ok());
+                if deleted.count() > 0 {
+                    0
+                } else {
+                    0xff
+                }
+            }
+            (20, Some(path)) => {
+                let record = files::position(&fcb);
+                self.read_file(cpu, &mut fcb, &path, record, 1)
+            }
+            (21, Some(path)) => {
+                let record = files::position(&fcb);
+                self.write_file(cpu, &mut fcb, &path, record, 1)
+            }
+            (22, path) if !pattern.contains(&b'?') => {
+                let path = path.unwrap_or_else(|| directory.join(files::host_name(&pattern)));
+                match File::create(&path) {
+                    Ok(_) => {
+                        files::set_record_count(&mut fcb, 0);
+                        0
+                    }
+                    Err(_) => 0xff,
+                }
+            }
+            (23, Some(path)) => {
+                let new = name(&fcb, NEW_NAME);
+                if new.contains(&b'?') || !files::find(&directory, &new).is_empty() {
+                    0xff
+                } else {
+                    match fs::rename(&path, directory.join(files::host_name(&new))) {
+                        Ok(()) => 0,
+                        Err(_) => 0xff,
+                    }
+                }
+            }
+            (33, Some(path)) | (34, Some(path)) | (40, Some(path)) => {
+                let record = files::random_record(&fcb);
+                if record > 0xffff {
+                    6
+                } else if function == 33 {
+                    self.read_file(cpu, &mut fcb, &path, record, 0)
+                } else {
+                    self.write_file(cpu, &mut fcb, &path, record, 0)
+                }
+            }
+            (35, Some(path)) => {
+                files::set_random_record(&mut fcb, size(&path));
+                0
+            }
+            (36, _) => {
+                let record = files::position(&fcb);
+                files::set_random_record(&mut fcb, record);
+                0
+            }
+            // Reading a file that is not there reads nothing.
+            (20, None) | (33, None) => 1,
+            _ => 0xff,
+        };
+
+        write_memory(&mut cpu.memory, address, &fcb);
+        result
+    }
+
+    fn search_next(&mut self, cpu: &mut Cpu) -> u8 {
+        match self.found.pop_front() {
+            Some(entry) => {
+                write_memory(&mut cpu.memory, self.dma, &entry);
+                0
+            }
+            None => 0xff,
+        }
+    }
+
+    /// Read the record into the DMA buffer and move the FCB to the
+    /// record after it by step. 1 past the end of the file.
+    fn read_file(
+        &mut self,
+        cpu: &mut Cpu,
+        fcb: &mut [u8],
+        path: &Path,
+        record: u32,
+        step: u32,
+    ) -> u8 {
+        let size = size(path);
+        files::set_position(fcb, record, size);
+        match read_record(path, record) {
+            Ok(Some(data)) => {
+                write_memory(&mut cpu.memory, self.dma, &data);
+                files::set_position(fcb, record + step, size);
+                0
+            }
+            Ok(None) | Err(_) => 1,
+        }
+    }
+
+    /// Write the DMA buffer to the record and move the FCB to the record
+    /// after it by step. 2 when the host cannot write the file.
+    fn write_file(
+        &mut self,
+        cpu: &mut Cpu,
+        fcb: &mut [u8],
+        path: &Path,
+        record: u32,
+        step: u32,
+    ) -> u8 {
+        let data = read_memory(&cpu.memory, self.dma, RECORD);
+        match write_record(path, record, &data) {
+            Ok(()) => {
+                let size = size(path);
+                files::set_position(fcb, record + step, size);
+                0
+            }
+            Err(_) => 2,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// The console of a CP/M system on a pair of host streams.
+pub(super) struct Console {
+    /// Input, read by a thread so that the console status can be polled
+    /// without waiting.
+    input: Receiver<Vec<u8>>,
+    pending: VecDeque<u8>,
+    output: Box<dyn Write>,
+    echo: bool,
+}
+
+impl Console {
+    pub(super) fn new(mut input: Box<dyn Read + Send>, output: Box<dyn Write>) -> Console {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 256];
+            loop {
+                match input.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(count) => {
+                        if sender.send(buffer[..count].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Console {
+            input: receiver,
+            pending: VecDeque::new(),
+            output,
+            echo: false,
+        }
+    }
+
+    /// Echo the input read to the output, as a terminal would, for input
+    /// that is not typed.
+    pub(super) fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// True when input can be read without waiting.
+    pub(super) fn ready(&mut self) -> bool {
+        if self.pending.is_empty() {
+            match self.input.try_recv() {
+                Ok(bytes) => self.pending.extend(bytes),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        !self.pending.is_empty()
+    }
+
+    /// Input, waiting for it; new lines read as carriage returns. None
+    /// after the end of the input.
+    pub(super) fn read(&mut self) -> io::Result<Option<u8>> {
+        self.output.flush()?;
+        if self.pending.is_empty() {
+            match self.input.recv() {
+                Ok(bytes) => self.pending.extend(bytes),
+                Err(_) => return Ok(None),
+            }
+        }
+        Ok(self
+            .pending
+            .pop_front()
+            .map(|byte| if byte == b'\n' { b'\r' } else { byte }))
+    }
+
+    /// Output. Lines end with a new line alone.
+    pub(super) fn write(&mut self, byte: u8) -> io::Result<()> {
+        if byte != b'\r' {
+            self.output.write_all(&[byte])?;
+        }
+        Ok(())
+    }
+
+    /// Echo input that was read, when echoing.
+    pub(super) fn echo(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.echo {
+            for &byte in bytes {
+                self.output
+                    .write_all(&[if byte == b'\r' { b'\n' } else { byte }])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
@@ -0,0 +1,562 @@
+use cpm::files::{self, RECORD};
+use std::fmt;
+
+// === Disk images ===
+//
+// A raw disk image holds the sectors of the disk track after track, each
+// track in the order of the physical sector numbers. The geometry is
+// described as in the diskdefs of cpmtools: the first tracks hold the
+// system, and the data tracks hold blocks of 1K to 16K, their records
+// spread over the sectors of a track by the skew. The directory fills the
+// first blocks, in entries of 32 bytes:
+//
+//   0      user number, 0xe5 for a free entry
+//   1-11   name and type, attributes in the high bits of the type
+//   12     EX, extent number, low 5 bits
+//   14     S2, extent number, high 6 bits
+//   15     RC, records in the last extent of the entry
+//   16-31  blocks: 16 of 8 bits, or 8 of 16 bits past 256 blocks
+//
+// An entry holds EXM + 1 extents of 128 records, its extent number being
+// that of the last one.
+
+/// Geometry of a disk and its file system, by the names of cpmtools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geometry {
+    /// Bytes per sector, `seclen`.
+    pub sector_size: usize,
+    /// Sectors per track, `sectrk`.
+    pub sectors: usize,
+    pub tracks: usize,
+    /// Bytes per block, `blocksize`.
+    pub block_size: usize,
+    /// `maxdir`.
+    pub directory_entries: usize,
+    /// Physical sectors between two logical ones, 1 for none.
+    pub skew: usize,
+    /// Tracks holding the system, `boottrk`.
+    pub boot_tracks: usize,
+}
+
+impl Geometry {
+    /// Single sided, single density 8" disk of 250K, the standard CP/M
+    /// distribution format.
+    pub fn ibm_3740() -> Geometry {
+        Geometry {
+            sector_size: 128,
+            sectors: 26,
+            tracks: 77,
+            block_size: 1024,
+            directory_entries: 64,
+            skew: 6,
+            boot_tracks: 2,
+        }
+    }
+
+    /// Geometry of a name, or of a list of cpmtools parameters changing
+    /// those of ibm-3740, as in `seclen=512,sectrk=9,tracks=80,skew=1`.
+    pub fn parse(text: &str) -> Result<Geometry, DiskError> {
+        match text {
+            "ibm-3740" => return Ok(Geometry::ibm_3740()),
+            "4mb-hd" => {
+                return Ok(Geometry {
+                    sector_size: 128,
+                    sectors: 32,
+                    tracks: 1024,
+                    block_size: 2048,
+                    directory_entries: 256,
+                    skew: 1,
+                    boot_tracks: 0,
+                })
+            }
+            _ => {}
+        }
+
+        let mut geometry = Geometry::ibm_3740();
+        for parameter in text.split(',') {
+            let mut parts = parameter.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .ok_or_else(|| DiskError::new(format!("Invalid disk format: {}", text)))?;
+            match name {
+                "seclen" => geometry.sector_size = value,
+                "sectrk" => geometry.sectors = value,
+                "tracks" => geometry.tracks = value,
+                "blocksize" => geometry.block_size = value,
+                "maxdir" => geometry.directory_entries = value,
+                "skew" => geometry.skew = value,
+                "boottrk" => geometry.boot_tracks = value,
+                _ => return Err(DiskError::new(format!("Unknown disk parameter: {}", name))),
+            }
+        }
+        geometry.check()?;
+        Ok(geometry)
+    }
+
+    /// Reject geometries that CP/M 2.2 cannot describe.
+    pub fn check(&self) -> Result<(), DiskError> {
+        let error = |message: &str| Err(DiskError::new(message.to_string()));
+
+        if self.sector_size < RECORD
+            || self.sector_size > 4096
+            || !self.sector_size.is_power_of_two()
+        {
+            return error("Sector size must be a power of 2 from 128 to 4096");
+        }
+        if self.sectors == 0 || self.records_per_track() > 0xffff {
+            return error("Invalid number of sectors per track");
+        }
+        if self.tracks <= self.boot_tracks || self.tracks > 0xffff {
+            return error("No data tracks");
+        }
+        if self.block_size < 1024 || self.block_size > 16384 || !self.block_size.is_power_of_two() {
+            return error("Block size must be a power of 2 from 1K to 16K");
+        }
+        if self.directory_entries == 0
+            || !self.directory_entries.is_multiple_of(4)
+            || self.directory_blocks() > 16
+        {
+            return error("The directory must have a multiple of 4 entries in at most 16 blocks");
+        }
+        if self.blocks() <= self.directory_blocks() || self.blocks() > 0x10000 {
+            return error("Invalid number of blocks");
+        }
+        if self.wide() && self.block_size == 1024 {
+            return error("Disks of more than 256 blocks need blocks of 2K or more");
+        }
+        if self.skew == 0 || self.skew >= self.sectors.max(2) {
+            return error("Skew must be at least 1 and less than the sectors per track");
+        }
+        Ok(())
+    }
+
+    /// Bytes of an image.
+    pub fn size(&self) -> usize {
+        self.tracks * self.sectors * self.sector_size
+    }
+
+    /// 128 byte records per track, SPT.
+    pub fn records_per_track(&self) -> usize {
+        self.sectors * self.sector_size / RECORD
+    }
+
+    /// Blocks of the data tracks, DSM + 1.
+    pub fn blocks(&self) -> usize {
+        (self.tracks - self.boot_tracks) * self.sectors * self.sector_size / self.block_size
+    }
+
+    pub fn directory_blocks(&self) -> usize {
+        (self.directory_entries * 32).div_ceil(self.block_size)
+    }
+
+    /// True when directory entries hold 16 bit block numbers.
+    fn wide(&self) -> bool {
+        self.blocks() > 256
+    }
+
+    /// Extent mask, EXM.
+    fn extent_mask(&self) -> usize {
+        self.block_size / if self.wide() { 2048 } else { 1024 } - 1
+    }
+
+    /// Block numbers in a directory entry.
+    fn pointers(&self) -> usize {
+        if self.wide() {
+            8
+        } else {
+            16
+        }
+    }
+
+    /// Disk parameter block of the geometry, the checksum vector covering
+    /// the whole directory.
+    pub fn parameters(&self) -> [u8; 15] {
+        let shift = (self.block_size / RECORD).trailing_zeros() as u8;
+        let allocation = !(0xffffu32 >> self.directory_blocks()) as u16;
+        let words = [
+            self.records_per_track() as u16,
+            (self.blocks() - 1) as u16,
+            (self.directory_entries - 1) as u16,
+            (self.directory_entries / 4) as u16,
+            self.boot_tracks as u16,
+        ];
+
+        let mut parameters = [0; 15];
+        parameters[0..2].copy_from_slice(&words[0].to_le_bytes());
+        parameters[2] = shift;
+        parameters[3] = (self.block_size / RECORD - 1) as u8;
+        parameters[4] = self.extent_mask() as u8;
+        parameters[5..7].copy_from_slice(&words[1].to_le_bytes());
+        parameters[7..9].copy_from_slice(&words[2].to_le_bytes());
+        parameters[9..11].copy_from_slice(&allocation.to_be_bytes());
+        parameters[11..13].copy_from_slice(&words[3].to_le_bytes());
+        parameters[13..15].copy_from_slice(&words[4].to_le_bytes());
+        parameters
+    }
+
+    /// Physical sector of each logical sector of a track, from 0, as
+    /// cpmtools computes it.
+    fn skew_table(&self) -> Vec<usize> {
+        let mut table: Vec<usize> = Vec::with_capacity(self.sectors);
+        let mut sector = 0;
+        for _ in 0..self.sectors {
+            while table.contains(&sector) {
+                sector = (sector + 1) % self.sectors;
+            }
+            table.push(sector);
+            sector = (sector + self.skew) % self.sectors;
+        }
+        table
+    }
+}
+
+/// An error found in a disk image or its geometry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskError {
+    pub message: String,
+}
+
+impl DiskError {
+    pub fn new(message: String) -> DiskError {
+        DiskError { message }
+    }
+}
+
+impl fmt::Display for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A file of the directory of a disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryEntry {
+    pub user: u8,
+    /// NAME.TYP
+    pub name: String,
+    /// Size in 128 byte records.
+    pub records: u32,
+}
+
+/// A raw disk image and the CP/M file system on it.
+#[derive(Debug, Clone)]
+pub struct DiskImage {
+    geometry: Geometry,
+    skew: Vec<usize>,
+    data: Vec<u8>,
+}
+
+/// Entry of the directory, with its extent number and blocks.
+struct Extent {
+    index: usize,
+    user: u8,
+    name: [u8; 11],
+    extent: usize,
+    records: usize,
+    blocks: Vec<usize>,
+}
+
+impl DiskImage {
+    /// Formatted image, without system or files.
+    pub fn new(geometry: Geometry) -> Result<DiskImage, DiskError> {
+        let size = geometry.size();
+        DiskImage::from_bytes(geometry, vec![0xe5; size])
+    }
+
+    /// Image of the bytes of a file, a short file being the start of the
+    /// disk with the rest unused.
+    pub fn from_bytes(geometry: Geometry, mut data: Vec<u8>) -> Result<DiskImage, DiskError> {
+        geometry.check()?;
+        if data.len() > geometry.size() {
+            return Err(DiskError::new(format!(
+                "Image of {} bytes, larger than the {} of its geometry",
+                data.len(),
+                geometry.size()
+            )));
+        }
+        data.resize(geometry.size(), 0xe5);
+
+        Ok(DiskImage {
+            skew: geometry.skew_table(),
+            geometry,
+            data,
+        })
+    }
+
+    pub fn geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The bytes of the boot tracks.
+    pub fn boot_tracks(&self) -> &[u8] {
+        let geometry = &self.geometry;
+        &self.data[..geometry.boot_tracks * geometry.sectors * geometry.sector_size]
+    }
+
+    /// Offset in the image of a record of a track, as the BIOS numbers
+    /// them: from 0, in logical order.
+    fn offset(&self, track: usize, record: usize) -> Option<usize> {
+        let geometry = &self.geometry;
+        if track >= geometry.tracks || record >= geometry.records_per_track() {
+            return None;
+        }
+        let per_sector = geometry.sector_size / RECORD;
+        let sector = self.skew[record / per_sector];
+        Some(
+            (track * geometry.sectors + sector) * geometry.sector_size
+                + record % per_sector * RECORD,
+        )
+    }
+
+    /// Record of a track, None past the end of the disk.
+    pub fn read_record(&self, track: usize, record: usize) -> Option<&[u8]> {
+        let offset = self.offset(track, record)?;
+        Some(&self.data[offset..offset + RECORD])
+    }
+
+    /// Write a record of a track, false past the end of the disk.
+    pub fn write_record(&mut self, track: usize, record: usize, data: &[u8]) -> bool {
+        match self.offset(track, record) {
+            Some(offset) => {
+                self.data[offset..offset + RECORD].copy_from_slice(&data[..RECORD]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Track and record of a record of a block.
+    fn block_record(&self, block: usize, record: usize) -> (usize, usize) {
+        let geometry = &self.geometry;
+        let record = block * geometry.block_size / RECORD + record;
+        (
+            geometry.boot_tracks + record / geometry.records_per_track(),
+            record % geometry.records_per_track(),
+        )
+    }
+
+    fn read_block(&self, block: usize) -> Vec<u8> {
+        (0..self.geometry.block_size / RECORD)
+            .flat_map(|record| {
+                let (track, record) = self.block_record(block, record);
+                self.read_record(track, record).unwrap_or(&[]).to_vec()
+            })
+            .collect()
+    }
+
+    fn write_block(&mut self, block: usize, data: &[u8]) {
+        for (record, chunk) in data.chunks(RECORD).enumerate() {
+            let (track, record) = self.block_record(block, record);
+            self.write_record(track, record, chunk);
+        }
+    }
+
+    fn directory(&self) -> Vec<u8> {
+        let mut directory: Vec<u8> = (0..self.geometry.directory_blocks())
+            .flat_map(|block| self.read_block(block))
+            .collect();
+        directory.truncate(self.geometry.directory_entries * 32);
+        directory
+    }
+
+    fn write_directory(&mut self, directory: &[u8]) {
+        let mut directory = directory.to_vec();
+        let block_size = self.geometry.block_size;
+        directory.resize(self.geometry.directory_blocks() * block_size, 0xe5);
+        for (block, data) in directory.chunks(block_size).enumerate() {
+            self.write_block(block, data);
+        }
+    }
+
+    /// The entries of files in the directory, users 0 to 15.
+    fn extents(&self, directory: &[u8]) -> Vec<Extent> {
+        let geometry = &self.geometry;
+        directory
+            .chunks(32)
+            .enumerate()
+            .filter(|&(_, entry)| entry[0] < 16)
+            .map(|(index, entry)| {
+                let mut name = [0; 11];
+                for (to, from) in name.iter_mut().zip(&entry[1..12]) {
+                    *to = from & 0x7f;
+                }
+                let blocks = if geometry.wide() {
+                    entry[16..32]
+                        .chunks(2)
+                        .map(|pair| usize::from(u16::from_le_bytes([pair[0], pair[1]])))
+                        .collect()
+                } else {
+                    entry[16..32]
+                        .iter()
+                        .map(|&block| usize::from(block))
+                        .collect()
+                };
+                Extent {
+                    index,
+                    user: entry[0],
+                    name,
+                    extent: usize::from(entry[14] & 0x3f) * 32 + usize::from(entry[12] & 0x1f),
+                    records: usize::from(entry[15]).min(128),
+                    blocks,
+                }
+            })
+            .collect()
+    }
+
+    /// The files of the disk, by user and name.
+    pub fn list(&self) -> Vec<DirectoryEntry> {
+        let mut files: Vec<DirectoryEntry> = Vec::new();
+        for extent in self.extents(&self.directory()) {
+            let name = files::host_name(&extent.name);
+            let records = (extent.extent * 128 + extent.records) as u32;
+            match files
+                .iter_mut()
+                .find(|file| file.user == extent.user && file.name == name)
+            {
+                Some(file) => file.records = file.records.max(records),
+                None => files.push(DirectoryEntry {
+                    user: extent.user,
+                    name,
+                    records,
+                }),
+            }
+        }
+        files.sort_by(|a, b| (a.user, &a.name).cmp(&(b.user, &b.name)));
+        files
+    }
+
+    /// The entries of a file, in order.
+    fn file_extents(
+        &self,
+        directory: &[u8],
+        user: u8,
+        name: &str,
+    ) -> Result<Vec<Extent>, DiskError> {
+        let name = files::cpm_name(name)
+            .ok_or_else(|| DiskError::new(format!("Invalid CP/M file name: {}", name)))?;
+        if user > 15 {
+            return Err(DiskError::new(format!("Invalid user number: {}", user)));
+        }
+        let mut extents: Vec<Extent> = self
+            .extents(directory)
+            .into_iter()
+            .filter(|extent| extent.user == user && extent.name == name)
+            .collect();
+        extents.sort_by_key(|extent| extent.extent);
+        Ok(extents)
+    }
+
+    /// The records of a file.
+    pub fn read_file(&self, user: u8, name: &str) -> Result<Vec<u8>, DiskError> {
+        let extents = self.file_extents(&self.directory(), user, name)?;
+        let last = extents
+            .last()
+            .ok_or_else(|| DiskError::new(format!("{}: no such file", name)))?;
+        let size = (last.extent * 128 + last.records) * RECORD;
+
+        let mut data = Vec::with_capacity(size);
+        for extent in &extents {
+            for &block in &extent.blocks {
+                if block != 0 && block < self.geometry.blocks() {
+                    data.extend(self.read_block(block));
+                }
+            }
+        }
+        data.resize(size, 0x1a);
+        Ok(data)
+    }
+
+    /// Write a file, replacing the one of the same name. Its last record
+    /// is padded with ^Z.
+    pub fn write_file(&mut self, user: u8, name: &str, data: &[u8]) -> Result<(), DiskError> {
+        let mut directory = self.directory();
+        for extent in self.file_extents(&directory, user, name)? {
+            directory[extent.index * 32] = 0xe5;
+        }
+        let name = files::cpm_name(name).unwrap_or([b' '; 11]);
+
+        let geometry = self.geometry.clone();
+        let records = data.len().div_ceil(RECORD);
+        let per_entry = geometry.pointers() * geometry.block_size / RECORD;
+        let blocks_needed = (records * RECORD).div_ceil(geometry.block_size);
+        let entries_needed = records.div_ceil(per_entry).max(1);
+
+        // The directory blocks, and those of the other files.
+        let mut used: Vec<bool> = (0..geometry.blocks())
+            .map(|block| block < geometry.directory_blocks())
+            .collect();
+        for extent in self.extents(&directory) {
+            for block in extent.blocks {
+                if block < used.len() {
+                    used[block] = true;
+                }
+            }
+        }
+        let free: Vec<usize> = (0..used.len()).filter(|&block| !used[block]).collect();
+        let entries: Vec<usize> = (0..geometry.directory_entries)
+            .filter(|&index| directory[index * 32] == 0xe5)
+            .collect();
+        if free.len() < blocks_needed {
+            return Err(DiskError::new("Disk full".to_string()));
+        }
+        if entries.len() < entries_needed {
+            return Err(DiskError::new("Directory full".to_string()));
+        }
+
+        let mut padded = data.to_vec();
+        padded.resize(blocks_needed * geometry.block_size, 0x1a);
+        for (chunk, &block) in padded.chunks(geometry.block_size).zip(&free) {
+            self.write_block(block, chunk);
+        }
+
+        let blocks_per_entry = geometry.pointers();
+        for (number, &index) in entries.iter().take(entries_needed).enumerate() {
+            let in_entry = records.saturating_sub(number * per_entry).min(per_entry);
+            let extent = number * (geometry.extent_mask() + 1) + in_entry.saturating_sub(1) / 128;
+
+            let entry = &mut directory[index * 32..index * 32 + 32];
+            for byte in entry.iter_mut() {
+                *byte = 0;
+            }
+            entry[0] = user;
+            entry[1..12].copy_from_slice(&name);
+            entry[12] = (extent & 0x1f) as u8;
+            entry[14] = (extent >> 5) as u8;
+            entry[15] = (in_entry - in_entry.saturating_sub(1) / 128 * 128) as u8;
+
+            let first = number * blocks_per_entry;
+            let last = blocks_needed.min(first + blocks_per_entry);
+            for (slot, &block) in free[first.min(last)..last].iter().enumerate() {
+                if geometry.wide() {
+                    entry[16 + slot * 2..18 + slot * 2]
+                        .copy_from_slice(&(block as u16).to_le_bytes());
+                } else {
+                    entry[16 + slot] = block as u8;
+                }
+            }
+        }
+
+        self.write_directory(&directory);
+        Ok(())
+    }
+
+    /// Erase a file.
+    pub fn erase(&mut self, user: u8, name: &str) -> Result<(), DiskError> {
+        let mut directory = self.directory();
+        let extents = self.file_extents(&directory, user, name)?;
+        if extents.is_empty() {
+            return Err(DiskError::new(format!("{}: no such file", name)));
+        }
+        for extent in extents {
+            directory[extent.index * 32] = 0xe5;
+        }
+        self.write_directory(&directory);
+        Ok(())
+    }
+}
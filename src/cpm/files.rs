@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// === File names and records ===
+//
+// A file control block (FCB) names a file as a drive, 0 for the current
+// one, and eleven characters of name and type, padded with spaces, in
+// which '?' matches any character. The file is read and written in
+// records of 128 bytes: the current record is cr in the extent ex of the
+// module s2, 128 records per extent and 32 extents per module, and
+// random access uses the record number r0 to r2.
+
+/// Bytes in a record.
+pub const RECORD: usize = 128;
+
+pub const DRIVE: usize = 0;
+pub const NAME: usize = 1;
+pub const EXTENT: usize = 12;
+pub const MODULE: usize = 14;
+pub const RECORD_COUNT: usize = 15;
+/// New name of BDOS function 23.
+pub const NEW_NAME: usize = 17;
+pub const CURRENT_RECORD: usize = 32;
+pub const RANDOM_RECORD: usize = 33;
+
+/// The first 16 bytes of an FCB naming the file of a command argument,
+/// as in `B:NAME.TYP`, `*` standing for as many '?' as fill the field.
+pub fn parse_fcb(argument: &str) -> [u8; 16] {
+    let mut fcb = [0; 16];
+    let mut name = argument;
+
+    let bytes = argument.as_bytes();
+    if bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_uppercase() {
+        fcb[DRIVE] = bytes[0] - b'A' + 1;
+        name = &argument[2..];
+    }
+
+    let (name, extension) = match name.find('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    fill(&mut fcb[NAME..NAME + 8], name);
+    fill(&mut fcb[NAME + 8..NAME + 11], extension);
+    fcb
+}
+
+fn fill(field: &mut [u8], text: &str) {
+    let text = text.as_bytes();
+    let star = text.iter().position(|&byte| byte == b'*');
+    for (index, byte) in field.iter_mut().enumerate() {
+        *byte = match star {
+            Some(star) if index >= star => b'?',
+            _ => text.get(index).cloned().unwrap_or(b' '),
+        };
+    }
+}
+
+/// The name of a file in its CP/M form, when it has one: at most eight
+/// characters and three of type, none of them a space, '.' or a wildcard.
+pub fn cpm_name(file: &str) -> Option<[u8; 11]> {
+    let upper = file.to_uppercase();
+    let (name, extension) = match upper.find('.') {
+        Some(dot) => (&upper[..dot], &upper[dot + 1..]),
+        None => (upper.as_str(), ""),
+    };
+
+    let valid = |text: &str, size| {
+        text.len() <= size
+            && text
+                .bytes()
+                .all(|byte| byte.is_ascii_graphic() && !b".?*:<>,;=[]".contains(&byte))
+    };
+    if name.is_empty() || !valid(name, 8) || !valid(extension, 3) {
+        return None;
+    }
+
+    let mut result = [b' '; 11];
+    result[..name.len()].copy_from_slice(name.as_bytes());
+    result[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+    Some(result)
+}
+
+/// Host name of a CP/M name: NAME.TYP, or NAME without type.
+pub fn host_name(name: &[u8; 11]) -> String {
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).trim_end().to_string();
+    let (base, extension) = (text(&name[..8]), text(&name[8..]));
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+/// True when the name matches the pattern, with '?' matching any
+/// character. Attribute bits, the high bits of the characters, are
+/// ignored.
+pub fn matches(pattern: &[u8; 11], name: &[u8; 11]) -> bool {
+    pattern
+        .iter()
+        .zip(name.iter())
+        .all(|(&p, &n)| p & 0x7f == b'?' || (p & 0x7f).eq_ignore_ascii_case(&(n & 0x7f)))
+}
+
+/// The files of a directory matching the pattern, by name, with their
+/// path and their size in records.
+pub fn find(directory: &Path, pattern: &[u8; 11]) -> Vec<([u8; 11], PathBuf, u32)> {
+    let mut files: Vec<([u8; 11], PathBuf, u32)> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_type()
+                    .map(|kind| kind.is_file())
+                    .unwrap_or(false)
+            })
+            .filter_map(|entry| {
+                let name = cpm_name(&entry.file_name().to_string_lossy())?;
+                let size = entry.metadata().ok()?.len();
+                Some((name, entry.path(), records(size)))
+            })
+            .filter(|(name, _, _)| matches(pattern, name))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    files
+}
+
+/// Records holding size bytes.
+pub fn records(size: u64) -> u32 {
+    size.div_ceil(RECORD as u64).min(u64::from(u32::MAX)) as u32
+}
+
+/// Current record of the FCB for sequential access.
+pub fn position(fcb: &[u8]) -> u32 {
+    let extent = u32::from(fcb[MODULE] & 0x3f) * 32 + u32::from(fcb[EXTENT] & 0x1f);
+    extent * 128 + u32::from(fcb[CURRENT_RECORD] & 0x7f)
+}
+
+/// Move the FCB to a record of a file of the given size in records,
+/// updating the count of records of the extent.
+pub fn set_position(fcb: &mut [u8], record: u32, size: u32) {
+    fcb[CURRENT_RECORD] = (record & 0x7f) as u8;
+    fcb[EXTENT] = ((record >> 7) & 0x1f) as u8;
+    fcb[MODULE] = ((record >> 12) & 0x3f) as u8;
+    set_record_count(fcb, size);
+}
+
+/// Set the count of records of the current extent of the FCB.
+pub fn set_record_count(fcb: &mut [u8], size: u32) {
+    let first = (position(fcb) >> 7) * 128;
+    fcb[RECORD_COUNT] = size.saturating_sub(first).min(128) as u8;
+}
+
+/// Random record number of the FCB.
+pub fn random_record(fcb: &[u8]) -> u32 {
+    u32::from(fcb[RANDOM_RECORD])
+        | u32::from(fcb[RANDOM_RECORD + 1]) << 8
+        | u32::from(fcb[RANDOM_RECORD + 2]) << 16
+}
+
+pub fn set_random_record(fcb: &mut [u8], record: u32) {
+    fcb[RANDOM_RECORD] = record as u8;
+    fcb[RANDOM_RECORD + 1] = (record >> 8) as u8;
+    fcb[RANDOM_RECORD + 2] = (record >> 16) as u8;
+}
@@ -0,0 +1,285 @@
+#[cfg(test)]
+mod tests;
+
+mod bdos;
+mod console;
+mod disk;
+mod files;
+mod system;
+
+pub use self::disk::{DirectoryEntry, DiskError, DiskImage, Geometry};
+pub use self::system::DiskSystem;
+
+use self::console::Console;
+use cpu::{Cpu, Limits, StopReason};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+// === CP/M 2.2 ===
+//
+// A CP/M environment for .com programs, without the operating system
+// code: calls to the BDOS at 0x0005 and to the BIOS jump table are served
+// by `run` before the CPU executes them, then return to the caller. The
+// memory from 0xfe00 holds what programs look at:
+//
+//   0000  JP BIOS+3, warm boot     0005  JP BDOS
+//   0003  IOBYTE                   0004  current drive
+//   005c  FCB of the first argument, 006c of the second
+//   0080  command tail, and the default DMA buffer
+//   0100  the program, up to 0xfe00
+//   fe06  BDOS entry               fe10  disk parameter block
+//   ff00  BIOS jump table, each entry jumping to a HALT at ff40 + n
+//
+// Programs patching the jump table or the jump at 0x0005 work, as calls
+// are caught at the addresses the jumps lead to. The console is a pair of
+// host streams and the disks are host directories: drive A is the root
+// directory, B to P its subdirectories b to p. Files are named NAME.TYP
+// in upper case, and found whatever their case.
+//
+// `DiskSystem` runs the CCP and BDOS of a real CP/M instead, booted from
+// raw disk images that `DiskImage` also reads and writes files on.
+
+/// Address of the BDOS entry, the first address above the programs.
+pub const BDOS: u16 = 0xfe06;
+/// Address of the BIOS jump table.
+pub const BIOS: u16 = 0xff00;
+
+/// Start of the page holding the BDOS entry, where the stack starts.
+const BDOS_PAGE: u16 = 0xfe00;
+/// Disk parameter block, the same for all drives.
+const DPB: u16 = 0xfe10;
+/// Allocation vector, all blocks free.
+const ALLOCATION: u16 = 0xfe40;
+/// HALT reached by each entry of the jump table.
+const BIOS_STUBS: u16 = 0xff40;
+const BIOS_FUNCTIONS: u16 = 17;
+
+const FCB1: u16 = 0x5c;
+const FCB2: u16 = 0x6c;
+const DEFAULT_DMA: u16 = 0x80;
+
+/// 1M disks of 2K blocks, 512 directory entries.
+const DISK_PARAMETERS: [u8; 15] = [
+    64, 0, // SPT, 128 byte records per track
+    4, 15, 0, // BSH, BLM, EXM: 2K blocks
+    0xff, 0x01, // DSM, 512 blocks
+    0xff, 0x01, // DRM, 512 directory entries
+    0xf0, 0x00, // AL0, AL1
+    0x00, 0x00, // CKS, fixed disks
+    0x00, 0x00, // OFF
+];
+
+/// How a CP/M program ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    /// A warm boot, from BDOS function 0, JP 0 or the program returning.
+    WarmBoot,
+    /// A read from the console after the end of its input.
+    EndOfInput,
+    /// The CPU stopped before the program ended.
+    Stopped(StopReason),
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Exit::WarmBoot => write!(f, "warm boot"),
+            Exit::EndOfInput => write!(f, "end of console input"),
+            Exit::Stopped(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// A CP/M environment, serving the BDOS and BIOS calls of a CPU.
+pub struct Cpm {
+    root: PathBuf,
+    console: Console,
+    dma: u16,
+    /// Current drive, 0 for A.
+    drive: u8,
+    user: u8,
+    /// Directory entries left for BDOS function 18.
+    found: VecDeque<[u8; 32]>,
+}
+
+impl Cpm {
+    /// Environment with its disks in root and its console on input and
+    /// output.
+    pub fn new(root: &Path, input: Box<dyn Read + Send>, output: Box<dyn Write>) -> Cpm {
+        Cpm {
+            root: root.to_path_buf(),
+            console: Console::new(input, output),
+            dma: DEFAULT_DMA,
+            drive: 0,
+            user: 0,
+            found: VecDeque::new(),
+        }
+    }
+
+    /// Echo the console input read by the program to the output, as a
+    /// terminal would, for input that is not typed.
+    pub fn with_echo(mut self, echo: bool) -> Cpm {
+        self.console.set_echo(echo);
+        self
+    }
+
+    /// Set up the memory of the CPU as the command processor does before
+    /// running the program loaded at 0x100 with the command tail, as in
+    /// `FILE.TXT /X`.
+    pub fn start(&mut self, cpu: &mut Cpu, tail: &str) {
+        let memory = &mut cpu.memory;
+        memory.resize(0x10000, 0);
+
+        memory[0] = 0xc3;
+        memory[1..3].copy_from_slice(&(BIOS + 3).to_le_bytes());
+        memory[3] = 0;
+        memory[4] = 0;
+        memory[5] = 0xc3;
+        memory[6..8].copy_from_slice(&BDOS.to_le_bytes());
+
+        let tail = tail.trim().to_uppercase();
+        let arguments: Vec<&str> = tail.split_whitespace().collect();
+        for &(fcb, index) in &[(FCB1, 0), (FCB2, 1)] {
+            let fcb = usize::from(fcb);
+            memory[fcb..fcb + 16].copy_from_slice(&files::parse_fcb(
+                arguments.get(index).cloned().unwrap_or(""),
+            ));
+        }
+        for byte in &mut memory[usize::from(FCB1) + 32..usize::from(FCB1) + 36] {
+            *byte = 0;
+        }
+
+        let mut text = Vec::new();
+        if !tail.is_empty() {
+            text.push(b' ');
+            text.extend(tail.bytes().take(126));
+        }
+        memory[0x80] = text.len() as u8;
+        memory[0x81..0x81 + text.len()].copy_from_slice(&text);
+        memory[0x81 + text.len()] = 0;
+
+        for byte in &mut memory[usize::from(BDOS_PAGE)..] {
+            *byte = 0;
+        }
+        memory[usize::from(BDOS)] = 0x76;
+        memory[usize::from(DPB)..usize::from(DPB) + DISK_PARAMETERS.len()]
+            .copy_from_slice(&DISK_PARAMETERS);
+        for function in 0..BIOS_FUNCTIONS {
+            let entry = usize::from(BIOS + function * 3);
+            memory[entry] = 0xc3;
+            memory[entry + 1..entry + 3].copy_from_slice(&(BIOS_STUBS + function).to_le_bytes());
+            memory[usize::from(BIOS_STUBS + function)] = 0x76;
+        }
+
+        self.dma = DEFAULT_DMA;
+        self.drive = 0;
+        self.user = 0;
+
+        // Returning from the program jumps to 0 and warm boots.
+        let sp = BDOS_PAGE - 2;
+        memory[usize::from(sp)..usize::from(BDOS_PAGE)].copy_from_slice(&[0, 0]);
+        cpu.sp = sp;
+        cpu.pc = 0x100;
+    }
+
+    /// Run the program until it ends, or the CPU stops as `Cpu::run` does.
+    /// Returns how, and the number of instructions executed.
+    pub fn run(&mut self, cpu: &mut Cpu, limits: Limits) -> io::Result<(Exit, u64)> {
+        let result = run(cpu, limits, |cpu| {
+            let call = match cpu.pc {
+                BDOS => self.bdos(cpu),
+                pc if (BIOS_STUBS..BIOS_STUBS + BIOS_FUNCTIONS).contains(&pc) => {
+                    self.bios(cpu, (pc - BIOS_STUBS) as u8)
+                }
+                _ => return None,
+            };
+            if let Ok(None) = call {
+                cpu._pop_pc();
+            }
+            Some(call)
+        });
+
+        self.console.flush()?;
+        result
+    }
+
+    /// Serve a call to the BIOS jump table entry.
+    fn bios(&mut self, cpu: &mut Cpu, function: u8) -> io::Result<Option<Exit>> {
+        match function {
+            // BOOT, WBOOT
+            0 | 1 => return Ok(Some(Exit::WarmBoot)),
+            // CONST
+            2 => cpu.a = if self.console.ready() { 0xff } else { 0 },
+            // CONIN
+            3 => match self.console.read()? {
+                Some(byte) => cpu.a = byte,
+                None => return Ok(Some(Exit::EndOfInput)),
+            },
+            // CONOUT
+            4 => self.console.write(cpu.c)?,
+            // LIST, PUNCH
+            5 | 6 => {}
+            // READER, at its end
+            7 => cpu.a = 0x1a,
+            // HOME, SETTRK, SETSEC
+            8 | 10 | 11 => {}
+            // SELDSK: the disks are directories, without sectors.
+            9 => cpu.write_hl(0),
+            // SETDMA
+            12 => self.dma = cpu.read_bc(),
+            // READ, WRITE
+            13 | 14 => cpu.a = 1,
+            // LISTST
+            15 => cpu.a = 0xff,
+            // SECTRAN
+            _ => {
+                let sector = cpu.read_bc();
+                cpu.write_hl(sector);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Run the CPU as `Cpu::run` does, calling trap before each instruction.
+/// The trap serves the calls to the system at the address of the PC,
+/// moving the PC past them, or returns None for instructions to execute.
+fn run<F>(cpu: &mut Cpu, limits: Limits, mut trap: F) -> io::Result<(Exit, u64)>
+where
+    F: FnMut(&mut Cpu) -> Option<io::Result<Option<Exit>>>,
+{
+    let start = cpu.cycles;
+    let mut executed = 0;
+
+    loop {
+        match trap(cpu) {
+            Some(Ok(Some(exit))) => return Ok((exit, executed)),
+            Some(Ok(None)) => continue,
+            Some(Err(e)) => return Err(e),
+            None => {}
+        }
+
+        let stopped = if cpu.halted {
+            Some(StopReason::Halted)
+        } else if limits.instructions.is_some_and(|max| executed >= max) {
+            Some(StopReason::InstructionLimit)
+        } else if limits.cycles.is_some_and(|max| cpu.cycles - start >= max) {
+            Some(StopReason::CycleLimit)
+        } else {
+            None
+        };
+        if let Some(reason) = stopped {
+            return Ok((Exit::Stopped(reason), executed));
+        }
+
+        match cpu.step_checked(executed == 0) {
+            Some(reason @ StopReason::Breakpoint(_)) => {
+                return Ok((Exit::Stopped(reason), executed))
+            }
+            Some(reason) => return Ok((Exit::Stopped(reason), executed + 1)),
+            None => executed += 1,
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use super::console::Console;
+use super::disk::{DiskError, DiskImage};
+use super::{run, Exit};
+use cpm::files::RECORD;
+use cpu::{Cpu, Limits};
+use std::io::{self, Read, Write};
+
+// === Booting from disk images ===
+//
+// The CCP and BDOS of CP/M 2.2 are read from the boot tracks of drive A,
+// found by the JP to the BDOS entry at BDOS+6, and run at the address
+// they were built for. The BIOS follows the BDOS, without code: calls to
+// its jump table are served by `run` as for the `Cpm` environment, the
+// disk functions reading and writing the records of the images. Its data
+// sits above the jump table:
+//
+//   BIOS+0000  jump table            BIOS+0040  a HALT per entry
+//   BIOS+0080  directory buffer      BIOS+0100  disk parameter headers
+//   BIOS+0200  parameter blocks, allocation and checksum vectors
+//
+// The images translate the sectors themselves, so the headers have no
+// translation table.
+
+/// Bytes of the CCP and BDOS.
+const SYSTEM_SIZE: usize = 0x1600;
+/// Offset of the BDOS from the CCP, and of the JP to its entry.
+const BDOS_OFFSET: usize = 0x800;
+const BDOS_JUMP: usize = BDOS_OFFSET + 6;
+const BDOS_ENTRY: usize = BDOS_OFFSET + 0x11;
+
+const BIOS_FUNCTIONS: u16 = 17;
+const STUBS: u16 = 0x40;
+const DIRECTORY_BUFFER: u16 = 0x80;
+const HEADERS: u16 = 0x100;
+const TABLES: u16 = 0x200;
+
+const DRIVES: usize = 16;
+
+/// A CP/M system booted from disk images, on a console.
+pub struct DiskSystem {
+    drives: Vec<Option<DiskImage>>,
+    console: Console,
+    /// Address of the CCP, and where its image starts on the boot tracks.
+    ccp: u16,
+    image: usize,
+    drive: usize,
+    track: usize,
+    record: usize,
+    dma: u16,
+}
+
+impl DiskSystem {
+    /// System without disks, its console on input and output.
+    pub fn new(input: Box<dyn Read + Send>, output: Box<dyn Write>) -> DiskSystem {
+        DiskSystem {
+            drives: vec![None; DRIVES],
+            console: Console::new(input, output),
+            ccp: 0,
+            image: 0,
+            drive: 0,
+            track: 0,
+            record: 0,
+            dma: 0x80,
+        }
+    }
+
+    /// Echo the console input read by the system to the output.
+    pub fn with_echo(mut self, echo: bool) -> DiskSystem {
+        self.console.set_echo(echo);
+        self
+    }
+
+    /// Put a disk in a drive, 0 for A.
+    pub fn insert(&mut self, drive: usize, disk: DiskImage) {
+        self.drives[drive] = Some(disk);
+    }
+
+    /// Take the disk out of a drive, with what the system wrote on it.
+    pub fn eject(&mut self, drive: usize) -> Option<DiskImage> {
+        self.drives.get_mut(drive).and_then(|disk| disk.take())
+    }
+
+    fn bios(&self) -> u16 {
+        self.ccp + SYSTEM_SIZE as u16
+    }
+
+    /// Load the system from drive A and set up the memory for the cold
+    /// start of the CCP.
+    pub fn boot(&mut self, cpu: &mut Cpu) -> Result<(), DiskError> {
+        let disk = self.drives[0]
+            .as_ref()
+            .ok_or_else(|| DiskError::new("No disk in drive A".to_string()))?;
+        let (ccp, image) = find_system(disk.boot_tracks()).ok_or_else(|| {
+            DiskError::new("No CP/M 2.2 system on the boot tracks of drive A".to_string())
+        })?;
+        self.ccp = ccp;
+        self.image = image;
+
+        cpu.memory.resize(0x10000, 0);
+        self.tables(cpu)?;
+        cpu.memory[3] = 0;
+        cpu.memory[4] = 0;
+        self.load(cpu, false)
+    }
+
+    /// Reload the CCP and BDOS, and start the CCP: at its cold start, or
+    /// its warm start clearing the command line.
+    fn load(&mut self, cpu: &mut Cpu, warm: bool) -> Result<(), DiskError> {
+        let disk = self.drives[0]
+            .as_ref()
+            .ok_or_else(|| DiskError::new("No disk in drive A".to_string()))?;
+        let ccp = usize::from(self.ccp);
+        cpu.memory[ccp..ccp + SYSTEM_SIZE]
+            .copy_from_slice(&disk.boot_tracks()[self.image..self.image + SYSTEM_SIZE]);
+
+        let bios = self.bios();
+        cpu.memory[0] = 0xc3;
+        cpu.memory[1..3].copy_from_slice(&(bios + 3).to_le_bytes());
+        cpu.memory[5] = 0xc3;
+        cpu.memory[6..8].copy_from_slice(&(self.ccp + BDOS_JUMP as u16).to_le_bytes());
+
+        self.dma = 0x80;
+        cpu.c = cpu.memory[4];
+        cpu.sp = 0x80;
+        cpu.pc = if warm { self.ccp + 3 } else { self.ccp };
+        Ok(())
+    }
+
+    /// Write the jump table and the disk tables of the drives.
+    fn tables(&self, cpu: &mut Cpu) -> Result<(), DiskError> {
+        let bios = self.bios();
+        let memory = &mut cpu.memory;
+        for byte in &mut memory[usize::from(bios)..] {
+            *byte = 0;
+        }
+        for function in 0..BIOS_FUNCTIONS {
+            let entry = usize::from(bios + function * 3);
+            let stub = bios + STUBS + function;
+            memory[entry] = 0xc3;
+            memory[entry + 1..entry + 3].copy_from_slice(&stub.to_le_bytes());
+            memory[usize::from(stub)] = 0x76;
+        }
+
+        let mut free = usize::from(bios) + usize::from(TABLES);
+        for (drive, disk) in self.drives.iter().enumerate() {
+            let geometry = match *disk {
+                Some(ref disk) => disk.geometry(),
+                None => continue,
+            };
+            let parameters = geometry.parameters();
+            let dpb = free;
+            let checksums = dpb + parameters.len();
+            let allocation = checksums + geometry.directory_entries / 4;
+            free = allocation + geometry.blocks() / 8 + 1;
+            if free > memory.len() {
+                return Err(DiskError::new(
+                    "The disk tables of the BIOS do not fit in memory".to_string(),
+                ));
+            }
+            memory[dpb..checksums].copy_from_slice(&parameters);
+
+            let header = usize::from(bios + HEADERS) + drive * 16;
+            let words = [
+                0,
+                0,
+                0,
+                0,
+                bios + DIRECTORY_BUFFER,
+                dpb as u16,
+                checksums as u16,
+                allocation as u16,
+            ];
+            for (index, word) in words.iter().enumerate() {
+                memory[header + index * 2..header + index * 2 + 2]
+                    .copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the system until the end of the console input, or the CPU
+    /// stops as `Cpu::run` does. Returns how, and the number of
+    /// instructions executed.
+    pub fn run(&mut self, cpu: &mut Cpu, limits: Limits) -> io::Result<(Exit, u64)> {
+        let stubs = self.bios() + STUBS;
+        let result = run(cpu, limits, |cpu| {
+            if cpu.pc < stubs || cpu.pc >= stubs + BIOS_FUNCTIONS {
+                return None;
+            }
+            Some(self.call(cpu, (cpu.pc - stubs) as u8))
+        });
+
+        self.console.flush()?;
+        result
+    }
+
+    /// Serve a call to the BIOS jump table entry.
+    fn call(&mut self, cpu: &mut Cpu, function: u8) -> io::Result<Option<Exit>> {
+        match function {
+            // BOOT, WBOOT
+            0 | 1 => {
+                let loaded = if function == 0 {
+                    self.tables(cpu).and_then(|()| self.load(cpu, false))
+                } else {
+                    self.load(cpu, true)
+                };
+                return loaded
+                    .map(|()| None)
+                    .map_err(|e| io::Error::other(e.message));
+            }
+            // CONST
+            2 => cpu.a = if self.console.ready() { 0xff } else { 0 },
+            // CONIN
+            3 => match self.console.read()? {
+                Some(byte) => cpu.a = byte,
+                None => return Ok(Some(Exit::EndOfInput)),
+            },
+            // CONOUT
+            4 => self.console.write(cpu.c)?,
+            // LIST, PUNCH
+            5 | 6 => {}
+            // READER, at its end
+            7 => cpu.a = 0x1a,
+            // HOME
+            8 => self.track = 0,
+            // SELDSK
+            9 => {
+                let drive = usize::from(cpu.c);
+                if drive < DRIVES && self.drives[drive].is_some() {
+                    self.drive = drive;
+                    let header = self.bios() + HEADERS + drive as u16 * 16;
+                    cpu.write_hl(header);
+                } else {
+                    cpu.write_hl(0);
+                }
+            }
+            // SETTRK, SETSEC, SETDMA
+            10 => self.track = usize::from(cpu.read_bc()),
+            11 => self.record = usize::from(cpu.read_bc()),
+            12 => self.dma = cpu.read_bc(),
+            // READ, WRITE
+            13 => cpu.a = self.read(cpu),
+            14 => cpu.a = self.write(cpu),
+            // LISTST
+            15 => cpu.a = 0xff,
+            // SECTRAN, without translation table
+            _ => {
+                let sector = cpu.read_bc();
+                cpu.write_hl(sector);
+            }
+        }
+        cpu._pop_pc();
+        Ok(None)
+    }
+
+    /// Read the record into the DMA buffer: 0, or 1 for an error.
+    fn read(&mut self, cpu: &mut Cpu) -> u8 {
+        let record = match self.drives[self.drive] {
+            Some(ref disk) => disk.read_record(self.track, self.record),
+            None => None,
+        };
+        match record {
+            Some(data) => {
+                for (offset, &byte) in data.iter().enumerate() {
+                    cpu.memory[usize::from(self.dma.wrapping_add(offset as u16))] = byte;
+                }
+                0
+            }
+            None => 1,
+        }
+    }
+
+    fn write(&mut self, cpu: &mut Cpu) -> u8 {
+        let data: Vec<u8> = (0..RECORD)
+            .map(|offset| cpu.memory[usize::from(self.dma.wrapping_add(offset as u16))])
+            .collect();
+        let written = match self.drives[self.drive] {
+            Some(ref mut disk) => disk.write_record(self.track, self.record, &data),
+            None => false,
+        };
+        if written {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// Address of the CCP and offset of its image in the boot tracks: the
+/// CCP starts with two JPs into it, and the BDOS with a serial number
+/// and a JP to its entry.
+fn find_system(boot: &[u8]) -> Option<(u16, usize)> {
+    let word = |offset: usize| usize::from(boot[offset]) | usize::from(boot[offset + 1]) << 8;
+
+    (0..(boot.len() + 1).saturating_sub(SYSTEM_SIZE))
+        .step_by(RECORD)
+        .find_map(|offset| {
+            if boot[offset] != 0xc3 || boot[offset + 3] != 0xc3 || boot[offset + BDOS_JUMP] != 0xc3
+            {
+                return None;
+            }
+            let ccp = word(offset + BDOS_JUMP + 1).checked_sub(BDOS_ENTRY)?;
+            let inside = |target: usize| target >= ccp && target < ccp + BDOS_OFFSET;
+            let fits = ccp + SYSTEM_SIZE + usize::from(TABLES) <= 0x10000;
+            if ccp & 0xff == 0 && fits && inside(word(offset + 1)) && inside(word(offset + 4)) {
+                Some((ccp as u16, offset))
+            } else {
+                None
+            }
+        })
+}
@@ -0,0 +1,476 @@
+use cpm::files::parse_fcb;
+use cpm::{Cpm, DirectoryEntry, DiskImage, DiskSystem, Exit, Geometry, BDOS, BIOS};
+use cpu::{Cpu, CpuBuilder, Limits};
+use std::env;
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Empty directory for drive A.
+fn disk(name: &str) -> PathBuf {
+    let root = env::temp_dir().join(format!("z80-cpm-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir(&root).unwrap();
+    root
+}
+
+/// Environment on the disk, its console reading input and writing to a
+/// file next to the disk.
+fn cpm(root: &Path, input: &str) -> Cpm {
+    let console = File::create(root.with_extension("out")).unwrap();
+    Cpm::new(
+        root,
+        Box::new(Cursor::new(input.as_bytes().to_vec())),
+        Box::new(console),
+    )
+    .with_echo(true)
+}
+
+fn output(root: &Path) -> String {
+    let text = fs::read_to_string(root.with_extension("out")).unwrap();
+    fs::remove_file(root.with_extension("out")).unwrap();
+    text
+}
+
+/// CPU with the program loaded at 0x100.
+fn cpu(code: Vec<u8>) -> Cpu {
+    let mut memory = vec![0; 0x100];
+    memory.extend(code);
+    memory.resize(0x10000, 0);
+    CpuBuilder::new().with_memory(memory).build()
+}
+
+/// Call a BDOS function, returning A.
+fn call(cpm: &mut Cpm, cpu: &mut Cpu, function: u8, de: u16) -> u8 {
+    cpu.c = function;
+    cpu.write_de(de);
+    assert_eq!(cpm.bdos(cpu).unwrap(), None);
+    cpu.a
+}
+
+/// Write an FCB naming the file at address.
+fn fcb(cpu: &mut Cpu, address: u16, name: &str) {
+    let address = usize::from(address);
+    cpu.memory[address..address + 16].copy_from_slice(&parse_fcb(name));
+    for byte in &mut cpu.memory[address + 16..address + 36] {
+        *byte = 0;
+    }
+}
+
+#[test]
+fn zero_page() {
+    let root = disk("zero-page");
+    let mut cpm = cpm(&root, "");
+    let mut cpu = cpu(vec![]);
+
+    cpm.start(&mut cpu, "b:file.txt *.com");
+    assert_eq!(
+        &cpu.memory[0..8],
+        &[0xc3, 0x03, 0xff, 0, 0, 0xc3, 0x06, 0xfe]
+    );
+    assert_eq!(&cpu.memory[0x5c..0x68], b"\x02FILE    TXT");
+    assert_eq!(&cpu.memory[0x6c..0x78], b"\x00????????COM");
+    assert_eq!(&cpu.memory[0x80..0x92], b"\x11 B:FILE.TXT *.COM");
+    assert_eq!((cpu.pc, cpu.sp), (0x100, 0xfdfe));
+    assert_eq!(&cpu.memory[0xfdfe..0xfe00], &[0, 0]);
+
+    // CONOUT, the fifth entry of the jump table.
+    assert_eq!(
+        &cpu.memory[usize::from(BIOS) + 12..usize::from(BIOS) + 15],
+        &[0xc3, 0x44, 0xff]
+    );
+    assert_eq!(cpu.memory[usize::from(BDOS)], 0x76);
+
+    fs::remove_dir(&root).unwrap();
+    output(&root);
+}
+
+#[test]
+fn console() {
+    let root = disk("console");
+    let mut cpm = cpm(&root, "World\nnext\n");
+    let mut cpu = cpu(asm!(
+        "        ORG 0x100",
+        "        LD C,9",
+        "        LD DE,prompt",
+        "        CALL 5",
+        "        LD C,10",
+        "        LD DE,buffer",
+        "        CALL 5",
+        "        LD HL,(1)",
+        "        LD L,0x0c",
+        "        LD C,'>'",
+        "        LD DE,done",
+        "        PUSH DE",
+        "        JP (HL)",
+        "done:   LD A,(buffer+1)",
+        "        LD (result),A",
+        "        RET",
+        "prompt: DB \"Name? $\"",
+        "        ORG 0x200",
+        "buffer: DB 3",
+        "        DS 4,0",
+        "result: DB 0"
+    ));
+
+    cpm.start(&mut cpu, "");
+    let (exit, _) = cpm.run(&mut cpu, Limits::default()).unwrap();
+    assert_eq!(exit, Exit::WarmBoot);
+    assert_eq!(output(&root), "Name? World\n>");
+    // The line is cut to the size of the buffer.
+    assert_eq!(&cpu.memory[0x200..0x206], b"\x03\x03Wor\x03");
+
+    let mut cpm = self::cpm(&root, "x\r\n");
+    assert_eq!(call(&mut cpm, &mut cpu, 1, 0), b'x');
+    assert_eq!(call(&mut cpm, &mut cpu, 1, 0), b'\r');
+    assert_eq!(call(&mut cpm, &mut cpu, 1, 0), b'\r');
+    cpu.c = 1;
+    assert_eq!(cpm.bdos(&mut cpu).unwrap(), Some(Exit::EndOfInput));
+    assert_eq!(call(&mut cpm, &mut cpu, 6, 0xff), 0);
+    assert_eq!(call(&mut cpm, &mut cpu, 12, 0), 0x22);
+
+    fs::remove_dir(&root).unwrap();
+    assert_eq!(output(&root), "x\n\n");
+}
+
+#[test]
+fn runs_a_program() {
+    // Counts in BCD up to 12 and back down to 8, printing the numbers
+    // without leading zero, then returns to the system.
+    let root = disk("program");
+    let program = asm!(
+        "        ORG 0x100",
+        "        XOR A",
+        "up:     ADD A,1",
+        "        DAA",
+        "        PUSH AF",
+        "        CALL print",
+        "        POP AF",
+        "        CP 0x12",
+        "        JR C,up",
+        "down:   SUB 1",
+        "        DAA",
+        "        PUSH AF",
+        "        CALL print",
+        "        POP AF",
+        "        CP 0x09",
+        "        JR NC,down",
+        "        LD C,9",
+        "        LD DE,done",
+        "        JP 5",
+        "print:  PUSH AF",
+        "        RRCA",
+        "        RRCA",
+        "        RRCA",
+        "        RRCA",
+        "        AND 0x0f",
+        "        CALL NZ,digit",
+        "        POP AF",
+        "        AND 0x0f",
+        "        CALL digit",
+        "        LD E,' '",
+        "        LD C,2",
+        "        JP 5",
+        "digit:  ADD A,'0'",
+        "        LD E,A",
+        "        LD C,2",
+        "        JP 5",
+        "done:   DB \"done$\""
+    );
+    fs::write(root.join("COUNT.COM"), &program).unwrap();
+    let mut cpm = cpm(&root, "");
+    let mut cpu = cpu(fs::read(root.join("COUNT.COM")).unwrap());
+
+    cpm.start(&mut cpu, "");
+    let (exit, _) = cpm.run(&mut cpu, Limits::default()).unwrap();
+
+    assert_eq!(exit, Exit::WarmBoot);
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!(output(&root), "1 2 3 4 5 6 7 8 9 10 11 12 11 10 9 8 done");
+}
+
+#[test]
+fn sequential_files() {
+    let root = disk("sequential");
+    let mut cpm = cpm(&root, "");
+    let mut cpu = cpu(vec![]);
+    cpm.start(&mut cpu, "");
+
+    fcb(&mut cpu, 0x200, "OUT.TXT");
+    assert_eq!(call(&mut cpm, &mut cpu, 15, 0x200), 0xff);
+    assert_eq!(call(&mut cpm, &mut cpu, 22, 0x200), 0);
+    for byte in 0..3 {
+        cpu.memory[0x80..0x100].copy_from_slice(&[b'a' + byte; 128]);
+        assert_eq!(call(&mut cpm, &mut cpu, 21, 0x200), 0);
+    }
+    assert_eq!(call(&mut cpm, &mut cpu, 16, 0x200), 0);
+    let data = fs::read(root.join("OUT.TXT")).unwrap();
+    assert_eq!((data.len(), data[0], data[383]), (384, b'a', b'c'));
+
+    // Host files are found whatever their case.
+    fs::write(root.join("data.bin"), vec![7; 200]).unwrap();
+    fcb(&mut cpu, 0x200, "DATA.BIN");
+    assert_eq!(call(&mut cpm, &mut cpu, 15, 0x200), 0);
+    assert_eq!(cpu.memory[0x20f], 2);
+    assert_eq!(call(&mut cpm, &mut cpu, 26, 0x300), 0);
+    assert_eq!(call(&mut cpm, &mut cpu, 20, 0x200), 0);
+    assert_eq!(call(&mut cpm, &mut cpu, 20, 0x200), 0);
+    assert_eq!(&cpu.memory[0x347..0x34a], &[7, 0x1a, 0x1a]);
+    assert_eq!(call(&mut cpm, &mut cpu, 20, 0x200), 1);
+    assert_eq!(cpu.memory[0x220], 2);
+
+    fs::remove_dir_all(&root).unwrap();
+    output(&root);
+}
+
+#[test]
+fn random_access() {
+    let root = disk("random");
+    let mut cpm = cpm(&root, "");
+    let mut cpu = cpu(vec![]);
+    cpm.start(&mut cpu, "");
+
+    let data: Vec<u8> = (0..3).flat_map(|record| vec![record; 128]).collect();
+    fs::write(root.join("FILE.DAT"), data).unwrap();
+    fcb(&mut cpu, 0x200, "FILE.DAT");
+    assert_eq!(call(&mut cpm, &mut cpu, 15, 0x200), 0);
+
+    cpu.memory[0x221] = 2;
+    assert_eq!(call(&mut cpm, &mut cpu, 33, 0x200), 0);
+    assert_eq!(cpu.memory[0x80], 2);
+    // The next sequential read reads the same record.
+    assert_eq!(cpu.memory[0x220], 2);
+    cpu.memory[0x221] = 3;
+    assert_eq!(call(&mut cpm, &mut cpu, 33, 0x200), 1);
+    cpu.memory[0x223] = 1;
+    assert_eq!(call(&mut cpm, &mut cpu, 33, 0x200), 6);
+
+    // Writing past the end fills the gap with zeros.
+    cpu.memory[0x221..0x224].copy_from_slice(&[0x81, 0, 0]);
+    assert_eq!(call(&mut cpm, &mut cpu, 34, 0x200), 0);
+    assert_eq!((cpu.memory[0x20c], cpu.memory[0x220]), (1, 1));
+    assert_eq!(call(&mut cpm, &mut cpu, 35, 0x200), 0);
+    assert_eq!(&cpu.memory[0x221..0x224], &[0x82, 0, 0]);
+    cpu.memory[0x221] = 0;
+    assert_eq!(call(&mut cpm, &mut cpu, 36, 0x200), 0);
+    assert_eq!(cpu.memory[0x221], 0x81);
+    assert_eq!(
+        fs::metadata(root.join("FILE.DAT")).unwrap().len(),
+        0x82 * 128
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+    output(&root);
+}
+
+#[test]
+fn directory() {
+    let root = disk("directory");
+    let mut cpm = cpm(&root, "");
+    let mut cpu = cpu(vec![]);
+    cpm.start(&mut cpu, "");
+    for name in &["b.com", "A.COM", "c.txt", "too-long-name.com"] {
+        fs::write(root.join(name), vec![0; 300]).unwrap();
+    }
+    fs::create_dir(root.join("b")).unwrap();
+
+    fcb(&mut cpu, 0x200, "*.COM");
+    assert_eq!(call(&mut cpm, &mut cpu, 17, 0x200), 0);
+    assert_eq!(&cpu.memory[0x80..0x90], b"\x00A       COM\x00\x00\x00\x03");
+    assert_eq!(call(&mut cpm, &mut cpu, 18, 0x200), 0);
+    assert_eq!(&cpu.memory[0x81..0x8c], b"B       COM");
+    assert_eq!(call(&mut cpm, &mut cpu, 18, 0x200), 0xff);
+
+    fcb(&mut cpu, 0x200, "C.TXT");
+    cpu.memory[0x211..0x21c].copy_from_slice(b"D       TXT");
+    assert_eq!(call(&mut cpm, &mut cpu, 23, 0x200), 0);
+    assert!(root.join("D.TXT").exists());
+    fcb(&mut cpu, 0x200, "?.COM");
+    assert_eq!(call(&mut cpm, &mut cpu, 19, 0x200), 0);
+    assert_eq!(call(&mut cpm, &mut cpu, 19, 0x200), 0xff);
+    assert!(!root.join("A.COM").exists() && !root.join("b.com").exists());
+
+    // Drive B is the subdirectory b.
+    assert_eq!(call(&mut cpm, &mut cpu, 24, 0), 0b11);
+    assert_eq!(call(&mut cpm, &mut cpu, 14, 1), 0);
+    assert_eq!(cpu.memory[4], 1);
+    fcb(&mut cpu, 0x200, "NEW.TXT");
+    assert_eq!(call(&mut cpm, &mut cpu, 22, 0x200), 0);
+    assert!(root.join("b").join("NEW.TXT").exists());
+    assert_eq!(call(&mut cpm, &mut cpu, 25, 0), 1);
+
+    fs::remove_dir_all(&root).unwrap();
+    output(&root);
+}
+
+#[test]
+fn geometry() {
+    let ibm = Geometry::ibm_3740();
+    assert_eq!(
+        ibm.parameters(),
+        [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xc0, 0, 16, 0, 2, 0]
+    );
+    assert_eq!(Geometry::parse("ibm-3740"), Ok(ibm.clone()));
+
+    let disk =
+        Geometry::parse("seclen=512,sectrk=9,tracks=160,blocksize=2048,maxdir=128,skew=1").unwrap();
+    assert_eq!(
+        (disk.size(), disk.records_per_track(), disk.blocks()),
+        (737280, 36, 355)
+    );
+    assert_eq!(&disk.parameters()[2..7], &[4, 15, 0, 0x62, 0x01]);
+
+    assert!(Geometry::parse("sectrk=26,skew=26").is_err());
+    assert!(Geometry::parse("blocksize=1000").is_err());
+    assert!(Geometry::parse("heads=2").is_err());
+
+    // Logical record 1 of a track is in physical sector 6.
+    let mut image = DiskImage::new(ibm).unwrap();
+    assert!(image.write_record(2, 1, &[0x42; 128]));
+    assert_eq!(image.bytes()[(2 * 26 + 6) * 128], 0x42);
+    assert!(!image.write_record(77, 0, &[0; 128]));
+}
+
+#[test]
+fn disk_files() {
+    let mut image = DiskImage::new(Geometry::ibm_3740()).unwrap();
+    let big: Vec<u8> = (0..20000).map(|index| index as u8).collect();
+    image.write_file(0, "hello.txt", b"Hello").unwrap();
+    image.write_file(1, "BIG.DAT", &big).unwrap();
+    image.write_file(0, "EMPTY", b"").unwrap();
+
+    assert_eq!(
+        image.list(),
+        vec![
+            DirectoryEntry {
+                user: 0,
+                name: "EMPTY".to_string(),
+                records: 0,
+            },
+            DirectoryEntry {
+                user: 0,
+                name: "HELLO.TXT".to_string(),
+                records: 1,
+            },
+            DirectoryEntry {
+                user: 1,
+                name: "BIG.DAT".to_string(),
+                records: 157,
+            },
+        ]
+    );
+    let hello = image.read_file(0, "HELLO.TXT").unwrap();
+    assert_eq!(
+        (&hello[..5], hello.len(), hello[5]),
+        (&b"Hello"[..], 128, 0x1a)
+    );
+    assert_eq!(&image.read_file(1, "big.dat").unwrap()[..20000], &big[..]);
+    assert!(image.read_file(0, "BIG.DAT").is_err());
+
+    // Two directory entries, the second for extent 1 with 29 records.
+    let directory = &image.bytes()[2 * 26 * 128..];
+    assert_eq!(&directory[32..48], b"\x01BIG     DAT\x00\x00\x00\x80");
+    assert_eq!((directory[64 + 12], directory[64 + 15]), (1, 29));
+
+    image.write_file(0, "HELLO.TXT", b"Bye").unwrap();
+    assert_eq!(&image.read_file(0, "HELLO.TXT").unwrap()[..4], b"Bye\x1a");
+    image.erase(1, "BIG.DAT").unwrap();
+    assert_eq!(image.list().len(), 2);
+    assert!(image.erase(1, "BIG.DAT").is_err());
+    assert!(image.write_file(0, "*.COM", b"").is_err());
+
+    assert_eq!(
+        image
+            .write_file(0, "HUGE", &vec![0; 300 * 1024])
+            .unwrap_err()
+            .message,
+        "Disk full"
+    );
+    assert_eq!(image.list().len(), 2);
+}
+
+#[test]
+fn boot() {
+    // A system at 0xe400 whose CCP prints the name of the first file of
+    // drive A and warm boots, then reads the console.
+    let system = ::asm::assemble(
+        &[
+            "bios    EQU 0xfa00",
+            "        ORG 0xe400",
+            "        JP start",
+            "        JP start",
+            "start:  LD A,(0x40)",
+            "        OR A",
+            "        JR NZ,wait",
+            "        INC A",
+            "        LD (0x40),A",
+            "        LD C,0",
+            "        CALL bios+27",
+            "        LD (0x42),HL",
+            "        LD BC,2",
+            "        CALL bios+30",
+            "        LD BC,0",
+            "        CALL bios+33",
+            "        LD BC,0x80",
+            "        CALL bios+36",
+            "        CALL bios+39",
+            "        LD HL,0x81",
+            "        LD B,8",
+            "print:  LD C,(HL)",
+            "        CALL bios+12",
+            "        INC HL",
+            "        DJNZ print",
+            "        LD BC,3",
+            "        CALL bios+30",
+            "        CALL bios+42",
+            "        JP 0",
+            "wait:   CALL bios+9",
+            "        ORG 0xec06",
+            "        JP 0xec11",
+        ]
+        .join("\n"),
+    )
+    .unwrap()
+    .bytes();
+
+    let mut image = DiskImage::new(Geometry::ibm_3740()).unwrap();
+    image.write_file(0, "FIRST.COM", b"").unwrap();
+    let mut data = image.bytes().to_vec();
+    data[128..128 + system.len()].copy_from_slice(&system);
+    let image = DiskImage::from_bytes(Geometry::ibm_3740(), data).unwrap();
+
+    let root = disk("boot");
+    let console = File::create(root.with_extension("out")).unwrap();
+    let mut system = DiskSystem::new(Box::new(Cursor::new(Vec::new())), Box::new(console));
+    system.insert(0, image);
+    let mut cpu = cpu(vec![]);
+    system.boot(&mut cpu).unwrap();
+    assert_eq!(cpu.pc, 0xe400);
+    assert_eq!(
+        &cpu.memory[0..8],
+        &[0xc3, 0x03, 0xfa, 0, 0, 0xc3, 0x06, 0xec]
+    );
+
+    let (exit, _) = system.run(&mut cpu, Limits::default()).unwrap();
+    assert_eq!(exit, Exit::EndOfInput);
+    fs::remove_dir(&root).unwrap();
+    assert_eq!(output(&root), "FIRST   ");
+
+    // The disk parameter header of drive A points to its parameters.
+    assert_eq!(&cpu.memory[0x42..0x44], &[0x00, 0xfb]);
+    let dpb = usize::from(cpu.memory[0xfb0a]) | usize::from(cpu.memory[0xfb0b]) << 8;
+    assert_eq!(
+        &cpu.memory[dpb..dpb + 15],
+        &Geometry::ibm_3740().parameters()
+    );
+
+    // The directory record was written to track 3.
+    let image = system.eject(0).unwrap();
+    assert_eq!(&image.read_record(3, 0).unwrap()[1..12], b"FIRST   COM");
+    assert!(system.eject(0).is_none());
+
+    let mut cpu = self::cpu(vec![]);
+    let mut empty = DiskSystem::new(Box::new(Cursor::new(Vec::new())), Box::new(Vec::new()));
+    empty.insert(0, DiskImage::new(Geometry::ibm_3740()).unwrap());
+    assert!(empty.boot(&mut cpu).is_err());
+}
@@ -1,5 +1,7 @@
 use cpu::Cpu;
+use cpu::CpuState;
 use cpu::RegisterPromote;
+use cpu::diff;
 
 /// An assertor for CPU status modeled as a builder
 
@@ -14,6 +16,26 @@ impl Assertor {
         Assertor { cpu: cpu }
     }
 
+    /// Tests the whole CPU status against the expected snapshot.
+    /// On mismatch all the differences are reported, not only the first one.
+    pub fn expect(&self, expected: CpuState) -> &Assertor {
+        let changes = diff(&expected, &self.cpu.state());
+
+        if !changes.is_empty() {
+            panic!(
+                "CPU status unexpected value (expected -> actual):\n{}",
+                changes
+            );
+        }
+
+        self
+    }
+
+    /// Returns a snapshot of the CPU under test.
+    pub fn state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
     /// Tests if the S flag is false
     #[inline]
     pub fn sign_flag_is_reset(&self) -> &Assertor {
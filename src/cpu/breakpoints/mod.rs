@@ -0,0 +1,281 @@
+#[cfg(test)]
+mod tests;
+
+use asm::eval;
+use cpu::{Access, AccessKind, Cpu, CpuBuilder};
+use std::fmt;
+use std::slice;
+
+// === Breakpoints and watchpoints ===
+//
+// Execution breakpoints are checked by `run` before executing the
+// instruction at their address, watchpoints after each instruction
+// against the data accesses it made on the bus.
+//
+// Conditions are assembler expressions, true when not zero, over the
+// register names (a, hl, af', ...), the flags as get_s(), get_z(),
+// get_h(), get_pv(), get_n(), get_c() and, for watchpoints, the
+// `address` and `value` of the access, as in `a == 0x3f && get_z()`.
+
+/// What a breakpoint stops on. Ranges include both ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// Execution of the instruction at an address.
+    Execute(u16),
+    /// Memory reads and/or writes.
+    Memory {
+        start: u16,
+        end: u16,
+        read: bool,
+        write: bool,
+    },
+    /// IN and/or OUT. Ranges within 0x00-0xff match the low byte of
+    /// the port address only, as most devices decode just that.
+    Port {
+        start: u16,
+        end: u16,
+        input: bool,
+        output: bool,
+    },
+}
+
+impl Kind {
+    fn matches(&self, access: &Access) -> bool {
+        match *self {
+            Kind::Execute(_) => false,
+            Kind::Memory {
+                start,
+                end,
+                read,
+                write,
+            } => {
+                let wanted = match access.kind {
+                    AccessKind::Read => read,
+                    AccessKind::Write => write,
+                    _ => false,
+                };
+                wanted && start <= access.address && access.address <= end
+            }
+            Kind::Port {
+                start,
+                end,
+                input,
+                output,
+            } => {
+                let wanted = match access.kind {
+                    AccessKind::Input => input,
+                    AccessKind::Output => output,
+                    _ => false,
+                };
+                let port = if end <= 0xff {
+                    access.address & 0xff
+                } else {
+                    access.address
+                };
+                wanted && start <= port && port <= end
+            }
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn range(start: u16, end: u16) -> String {
+            if start == end {
+                format!("0x{:04x}", start)
+            } else {
+                format!("0x{:04x}-0x{:04x}", start, end)
+            }
+        }
+
+        match *self {
+            Kind::Execute(address) => write!(f, "break at 0x{:04x}", address),
+            Kind::Memory {
+                start,
+                end,
+                read,
+                write,
+            } => {
+                let what = match (read, write) {
+                    (true, true) => "access",
+                    (true, false) => "read",
+                    _ => "write",
+                };
+                write!(f, "watch {} {}", what, range(start, end))
+            }
+            Kind::Port {
+                start,
+                end,
+                input,
+                output,
+            } => {
+                let what = match (input, output) {
+                    (true, true) => "in/out",
+                    (true, false) => "in",
+                    _ => "out",
+                };
+                write!(f, "watch port {} {}", what, range(start, end))
+            }
+        }
+    }
+}
+
+/// A breakpoint or watchpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    /// Number given when added, never reused.
+    pub id: usize,
+    pub kind: Kind,
+    pub condition: Option<String>,
+    pub enabled: bool,
+}
+
+impl Breakpoint {
+    /// True when the condition holds, or there is none.
+    /// A condition that can not be evaluated, as on a division
+    /// by zero, stops too so that the problem shows up.
+    fn holds(&self, cpu: &Cpu, access: Option<&Access>) -> bool {
+        match self.condition {
+            Some(ref condition) => evaluate(condition, cpu, access) != Ok(0),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.id, self.kind)?;
+        if let Some(ref condition) = self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        if !self.enabled {
+            write!(f, " (disabled)")?;
+        }
+        Ok(())
+    }
+}
+
+fn evaluate(condition: &str, cpu: &Cpu, access: Option<&Access>) -> Result<i32, String> {
+    let lookup = |name: &str| {
+        let flag = match name {
+            "get_s()" => Some(cpu.get_s()),
+            "get_z()" => Some(cpu.get_z()),
+            "get_h()" => Some(cpu.get_h()),
+            "get_pv()" => Some(cpu.get_pv()),
+            "get_n()" => Some(cpu.get_n()),
+            "get_c()" => Some(cpu.get_c()),
+            _ => None,
+        };
+        if let Some(flag) = flag {
+            return Some(if flag { -1 } else { 0 });
+        }
+
+        match (name, access) {
+            ("address", Some(access)) => Some(i32::from(access.address)),
+            ("value", Some(access)) => Some(i32::from(access.value)),
+            _ => cpu.read_named(name).map(i32::from),
+        }
+    };
+
+    eval(condition, cpu.pc, &lookup).map_err(|e| e.message)
+}
+
+/// The breakpoints and watchpoints of a CPU.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    last_id: usize,
+}
+
+impl Breakpoints {
+    /// Add a breakpoint and return its id. The condition is checked
+    /// for syntax and unknown names.
+    pub fn add(&mut self, kind: Kind, condition: Option<&str>) -> Result<usize, String> {
+        if let Some(condition) = condition {
+            let cpu = CpuBuilder::new().with_memory(Vec::new()).build();
+            let access = Access {
+                kind: AccessKind::Read,
+                address: 0,
+                value: 0,
+            };
+            let access = match kind {
+                Kind::Execute(_) => None,
+                _ => Some(&access),
+            };
+            evaluate(condition, &cpu, access)?;
+        }
+
+        self.last_id += 1;
+        self.list.push(Breakpoint {
+            id: self.last_id,
+            kind,
+            condition: condition.map(|c| c.to_string()),
+            enabled: true,
+        });
+        Ok(self.last_id)
+    }
+
+    /// Remove a breakpoint, returning false when there is none with id.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count = self.list.len();
+        self.list.retain(|breakpoint| breakpoint.id != id);
+        self.list.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    /// Enable or disable a breakpoint, returning false when there is none with id.
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        match self.list.iter_mut().find(|breakpoint| breakpoint.id == id) {
+            Some(breakpoint) => {
+                breakpoint.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Breakpoint> {
+        self.list.iter().find(|breakpoint| breakpoint.id == id)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Breakpoint> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// The execution breakpoint stopping the CPU at pc, if any.
+    pub fn execution_hit(&self, cpu: &Cpu) -> Option<usize> {
+        self.list
+            .iter()
+            .filter(|breakpoint| breakpoint.enabled && breakpoint.kind == Kind::Execute(cpu.pc))
+            .find(|breakpoint| breakpoint.holds(cpu, None))
+            .map(|breakpoint| breakpoint.id)
+    }
+
+    /// The first watchpoint hit by the accesses of the last
+    /// instruction, and the access that hit it.
+    pub fn access_hit(&self, cpu: &Cpu) -> Option<(usize, Access)> {
+        if self.list.is_empty() {
+            return None;
+        }
+
+        for access in &cpu.accesses {
+            let hit = self.list.iter().find(|breakpoint| {
+                breakpoint.enabled
+                    && breakpoint.kind.matches(access)
+                    && breakpoint.holds(cpu, Some(access))
+            });
+            if let Some(breakpoint) = hit {
+                return Some((breakpoint.id, *access));
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,126 @@
+use cpu::{Access, AccessKind, CpuBuilder, Kind, Limits, StopReason};
+
+fn cpu(code: Vec<u8>) -> ::cpu::Cpu {
+    let mut memory = code;
+    memory.resize(0x200, 0);
+    CpuBuilder::new().with_memory(memory).with_sp(0x200).build()
+}
+
+#[test]
+fn execution_breakpoints() {
+    // A counts up from 0 in a loop at 0x0001.
+    let mut cpu = cpu(asm!("XOR A", "loop: INC A", "JR loop"));
+
+    let id = cpu.breakpoints.add(Kind::Execute(1), None).unwrap();
+    assert_eq!(cpu.run(Limits::default()), (StopReason::Breakpoint(id), 1));
+    assert_eq!((cpu.pc, cpu.a), (1, 0));
+
+    // Continuing from a breakpoint executes its instruction first.
+    assert_eq!(cpu.run(Limits::default()), (StopReason::Breakpoint(id), 2));
+    assert_eq!(cpu.a, 1);
+
+    cpu.breakpoints.remove(id);
+    let id = cpu
+        .breakpoints
+        .add(Kind::Execute(1), Some("a == 0x3f && !get_z()"))
+        .unwrap();
+    assert_eq!(cpu.run(Limits::default()).0, StopReason::Breakpoint(id));
+    assert_eq!(cpu.a, 0x3f);
+
+    cpu.breakpoints.set_enabled(id, false);
+    let limits = Limits {
+        instructions: Some(10),
+        cycles: None,
+    };
+    assert_eq!(cpu.run(limits).0, StopReason::InstructionLimit);
+}
+
+#[test]
+fn memory_watchpoints() {
+    let mut cpu = cpu(asm!(
+        "LD HL,0x100",
+        "LD (HL),1",
+        "LD A,(0x180)",
+        "LD (0x17f),A",
+        "CALL sub",
+        "HALT",
+        "sub: RET"
+    ));
+
+    let write = Kind::Memory {
+        start: 0x100,
+        end: 0x17f,
+        read: false,
+        write: true,
+    };
+    let id = cpu.breakpoints.add(write, Some("value != 0")).unwrap();
+    let read = Kind::Memory {
+        start: 0x180,
+        end: 0x180,
+        read: true,
+        write: false,
+    };
+    let read_id = cpu.breakpoints.add(read, None).unwrap();
+
+    let access = Access {
+        kind: AccessKind::Write,
+        address: 0x100,
+        value: 1,
+    };
+    assert_eq!(cpu.run(Limits::default()), (StopReason::Watchpoint(id, access), 2));
+    assert_eq!(cpu.pc, 5);
+
+    let access = Access {
+        kind: AccessKind::Read,
+        address: 0x180,
+        value: 0,
+    };
+    assert_eq!(cpu.run(Limits::default()).0, StopReason::Watchpoint(read_id, access));
+
+    // Writing 0 to 0x17f does not meet the condition; the stack
+    // is not watched.
+    assert_eq!(cpu.run(Limits::default()).0, StopReason::Halted);
+}
+
+#[test]
+fn port_watchpoints() {
+    let mut cpu = cpu(asm!("LD A,0x12", "OUT (0xfe),A", "IN A,(0xfe)", "HALT"));
+
+    let kind = Kind::Port {
+        start: 0xfe,
+        end: 0xfe,
+        input: true,
+        output: false,
+    };
+    let id = cpu.breakpoints.add(kind, Some("address == 0x12fe")).unwrap();
+
+    let (reason, _) = cpu.run(Limits::default());
+    assert_eq!(
+        reason,
+        StopReason::Watchpoint(
+            id,
+            Access {
+                kind: AccessKind::Input,
+                address: 0x12fe,
+                value: 0xff,
+            }
+        )
+    );
+    assert_eq!(reason.to_string(), "watchpoint 1, in 0xff from port 0x12fe");
+}
+
+#[test]
+fn conditions_are_checked() {
+    let mut cpu = cpu(vec![]);
+
+    assert!(cpu.breakpoints.add(Kind::Execute(0), Some("a ==")).is_err());
+    assert!(cpu.breakpoints.add(Kind::Execute(0), Some("q == 1")).is_err());
+    assert!(cpu.breakpoints.add(Kind::Execute(0), Some("value == 1")).is_err());
+    assert!(cpu.breakpoints.is_empty());
+
+    let id = cpu.breakpoints.add(Kind::Execute(0x10), Some("hl' > 2")).unwrap();
+    assert_eq!(
+        cpu.breakpoints.get(id).unwrap().to_string(),
+        "1: break at 0x0010 if hl' > 2"
+    );
+}
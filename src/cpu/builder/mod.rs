@@ -1,8 +1,10 @@
 #[cfg(test)]
 mod tests;
 
-use cpu::Cpu;
+use cpu::{Breakpoints, Cpu, Ports};
+use cpu::{C_MASK, H_MASK, N_MASK, PV_MASK, S_MASK, Z_MASK};
 use cpu::RegisterDemote;
+use loader::{Image, LoadError};
 
 #[derive(Debug)]
 pub struct CpuBuilder {
@@ -35,6 +37,9 @@ pub struct CpuBuilder {
     pub iff1: bool,
     pub iff2: bool,
 
+    pub im: u8,
+    pub wz: u16,
+
     pub flag_s: bool,
     pub flag_z: bool,
     pub flag_c: bool,
@@ -43,6 +48,8 @@ pub struct CpuBuilder {
     pub flag_pv: bool,
 
     pub memory: Option<Vec<u8>>,
+
+    pub ports: Option<Box<dyn Ports>>,
 }
 
 #[allow(dead_code)]
@@ -73,6 +80,8 @@ impl CpuBuilder {
             l1: 0,
             iff1: false,
             iff2: false,
+            im: 0,
+            wz: 0,
             flag_s: false,
             flag_z: false,
             flag_c: false,
@@ -80,6 +89,7 @@ impl CpuBuilder {
             flag_n: false,
             flag_pv: false,
             memory: None,
+            ports: None,
         }
     }
 
@@ -158,6 +168,16 @@ impl CpuBuilder {
         self
     }
 
+    pub fn with_im(mut self, value: u8) -> CpuBuilder {
+        self.im = value;
+        self
+    }
+
+    pub fn with_wz(mut self, value: u16) -> CpuBuilder {
+        self.wz = value;
+        self
+    }
+
     pub fn with_bc(mut self, value: u16) -> CpuBuilder {
         self.b = value.high();
         self.c = value.low();
@@ -176,6 +196,48 @@ impl CpuBuilder {
         self
     }
 
+    /// Set F, and the flags from its bits.
+    pub fn with_f(mut self, value: u8) -> CpuBuilder {
+        self.f = value;
+        self.flag_s = value & S_MASK != 0;
+        self.flag_z = value & Z_MASK != 0;
+        self.flag_h = value & H_MASK != 0;
+        self.flag_pv = value & PV_MASK != 0;
+        self.flag_n = value & N_MASK != 0;
+        self.flag_c = value & C_MASK != 0;
+        self
+    }
+
+    pub fn with_af(self, value: u16) -> CpuBuilder {
+        let mut builder = self.with_f(value.low());
+        builder.a = value.high();
+        builder
+    }
+
+    pub fn with_af1(mut self, value: u16) -> CpuBuilder {
+        self.a1 = value.high();
+        self.f1 = value.low();
+        self
+    }
+
+    pub fn with_bc1(mut self, value: u16) -> CpuBuilder {
+        self.b1 = value.high();
+        self.c1 = value.low();
+        self
+    }
+
+    pub fn with_de1(mut self, value: u16) -> CpuBuilder {
+        self.d1 = value.high();
+        self.e1 = value.low();
+        self
+    }
+
+    pub fn with_hl1(mut self, value: u16) -> CpuBuilder {
+        self.h1 = value.high();
+        self.l1 = value.low();
+        self
+    }
+
     pub fn with_flag_s(mut self, value: bool) -> CpuBuilder {
         self.flag_s = value;
         self
@@ -216,6 +278,22 @@ impl CpuBuilder {
         self
     }
 
+    /// Load the segments of an image into the memory, 64K cleared by
+    /// default, and start at its start address.
+    pub fn with_image(mut self, image: &Image) -> Result<CpuBuilder, LoadError> {
+        let mut memory = self.memory.take().unwrap_or_else(|| vec![0; 0x10000]);
+        image.load(&mut memory)?;
+        self.memory = Some(memory);
+        self.pc = image.start;
+        Ok(self)
+    }
+
+    /// Devices on the I/O ports; without them IN reads 0xff.
+    pub fn with_ports(mut self, ports: Box<dyn Ports>) -> CpuBuilder {
+        self.ports = Some(ports);
+        self
+    }
+
     pub fn build(self) -> Cpu {
         let mut cpu = Cpu {
             pc: self.pc,
@@ -232,17 +310,29 @@ impl CpuBuilder {
             f: self.f,
             h: self.h,
             l: self.l,
-            a1: 0,
-            b1: 0,
-            c1: 0,
-            d1: 0,
-            e1: 0,
-            f1: 0,
-            h1: 0,
-            l1: 0,
+            a1: self.a1,
+            b1: self.b1,
+            c1: self.c1,
+            d1: self.d1,
+            e1: self.e1,
+            f1: self.f1,
+            h1: self.h1,
+            l1: self.l1,
             iff1: self.iff1,
             iff2: self.iff2,
+            halted: false,
+            im: self.im,
+            wz: self.wz,
+            cycles: 0,
             memory: self.memory.unwrap(),
+            ports: self.ports,
+            accesses: Vec::new(),
+            breakpoints: Breakpoints::default(),
+            trace: None,
+            history: None,
+            profile: None,
+            coverage: None,
+            call_stack: None,
         };
 
         cpu.set_s(self.flag_s);
@@ -67,3 +67,56 @@ fn create_system_with_preset_memory() {
         .memory_at_address_is(2, 3)
         .memory_at_address_is(3, 4);
 }
+
+#[test]
+fn expect_whole_state() {
+    let cpu = CpuBuilder::new()
+        .with_memory(vec![0; 4])
+        .with_a(0x7f)
+        .build();
+
+    let mut expected = cpu.state();
+    expected.a = 0x7f;
+
+    Assertor::new(cpu).expect(expected);
+}
+
+#[test]
+#[should_panic(expected = "A: 0x80 -> 0x7f")]
+fn expect_reports_differences() {
+    let cpu = CpuBuilder::new()
+        .with_memory(vec![0; 4])
+        .with_a(0x7f)
+        .build();
+
+    let mut expected = cpu.state();
+    expected.a = 0x80;
+    expected.memory[1] = 0x01;
+
+    Assertor::new(cpu).expect(expected);
+}
+
+#[test]
+fn create_system_with_shadow_registers() {
+    let cpu = CpuBuilder::new()
+        .with_memory(vec![0; 4])
+        .with_af(0x12ff)
+        .with_af1(0x3428)
+        .with_bc1(0x5678)
+        .with_de1(0x9abc)
+        .with_hl1(0xdef0)
+        .build();
+
+    Assertor::new(cpu)
+        .register_af_is(0x12ff)
+        .sign_is_negative()
+        .carry_flag_is_set()
+        .register_a1_is(0x34)
+        .register_f1_is(0x28)
+        .register_b1_is(0x56)
+        .register_c1_is(0x78)
+        .register_d1_is(0x9a)
+        .register_e1_is(0xbc)
+        .register_h1_is(0xde)
+        .register_l1_is(0xf0);
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::Cpu;
+use std::fmt;
+
+// === Memory and I/O bus ===
+//
+// Instructions read and write data through these methods, which record
+// each access so that breakpoints, and anything else looking at what an
+// instruction did, can see it. Opcode and operand fetches are not
+// recorded: they read memory at pc directly.
+
+/// Kind of bus access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    /// IN from a port.
+    Input,
+    /// OUT to a port.
+    Output,
+}
+
+/// A data access made by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Access {
+    pub kind: AccessKind,
+    /// Memory address, or the 16 bit port address.
+    pub address: u16,
+    /// Byte read or written.
+    pub value: u8,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            AccessKind::Read => write!(f, "read 0x{:02x} from 0x{:04x}", self.value, self.address),
+            AccessKind::Write => write!(f, "write 0x{:02x} to 0x{:04x}", self.value, self.address),
+            AccessKind::Input => write!(f, "in 0x{:02x} from port 0x{:04x}", self.value, self.address),
+            AccessKind::Output => write!(f, "out 0x{:02x} to port 0x{:04x}", self.value, self.address),
+        }
+    }
+}
+
+/// Devices connected to the I/O ports. The port address has
+/// the 16 bits put on the address bus by IN and OUT.
+pub trait Ports {
+    fn input(&mut self, port: u16) -> u8;
+    fn output(&mut self, port: u16, value: u8);
+
+    /// The T states since reset at the start of the instruction, before
+    /// each input and output, for devices that change with time.
+    fn clock(&mut self, _cycles: u64) {}
+}
+
+impl fmt::Debug for dyn Ports {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Ports")
+    }
+}
+
+impl Cpu {
+    /// Read a byte of data from memory.
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        let value = self.memory[usize::from(address)];
+        self.record(AccessKind::Read, address, value);
+        value
+    }
+
+    /// Write a byte of data to memory.
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.history_write(address);
+        self.memory[usize::from(address)] = value;
+        self.record(AccessKind::Write, address, value);
+    }
+
+    /// Read a port. Without devices the data bus floats high.
+    pub fn input(&mut self, port: u16) -> u8 {
+        let value = match self.ports {
+            Some(ref mut ports) => {
+                ports.clock(self.cycles);
+                ports.input(port)
+            }
+            None => 0xff,
+        };
+        self.record(AccessKind::Input, port, value);
+        value
+    }
+
+    /// Write a port.
+    pub fn output(&mut self, port: u16, value: u8) {
+        if let Some(ref mut ports) = self.ports {
+            ports.clock(self.cycles);
+            ports.output(port, value);
+        }
+        self.record(AccessKind::Output, port, value);
+    }
+
+    fn record(&mut self, kind: AccessKind, address: u16, value: u8) {
+        self.accesses.push(Access {
+            kind,
+            address,
+            value,
+        });
+    }
+}
@@ -0,0 +1,46 @@
+use cpu::{Access, AccessKind, CpuBuilder, Ports};
+
+#[derive(Default)]
+struct Latch {
+    value: u8,
+}
+
+impl Ports for Latch {
+    fn input(&mut self, port: u16) -> u8 {
+        self.value ^ port as u8
+    }
+
+    fn output(&mut self, _port: u16, value: u8) {
+        self.value = value;
+    }
+}
+
+#[test]
+fn records_accesses() {
+    let mut cpu = CpuBuilder::new().with_memory(vec![0; 4]).build();
+
+    cpu.write_byte(2, 0x55);
+    assert_eq!(cpu.read_byte(2), 0x55);
+    assert_eq!(cpu.input(0x1234), 0xff);
+
+    assert_eq!(
+        cpu.accesses,
+        vec![
+            Access { kind: AccessKind::Write, address: 2, value: 0x55 },
+            Access { kind: AccessKind::Read, address: 2, value: 0x55 },
+            Access { kind: AccessKind::Input, address: 0x1234, value: 0xff },
+        ]
+    );
+    assert_eq!(cpu.accesses[0].to_string(), "write 0x55 to 0x0002");
+}
+
+#[test]
+fn devices() {
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0; 4])
+        .with_ports(Box::new(Latch::default()))
+        .build();
+
+    cpu.output(0xfe, 0xf0);
+    assert_eq!(cpu.input(0x0f), 0xff);
+}
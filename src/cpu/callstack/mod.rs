@@ -0,0 +1,259 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::Cpu;
+use std::collections::VecDeque;
+
+// === Shadow call stack ===
+//
+// CALL, RST and interrupts push a frame with the return address they
+// pushed on the stack, and returns pop it. The frames are matched by the
+// address of the return address on the stack, so that code dropping a
+// return address, with POP or by reloading SP, leaves frames that are
+// unwound by the next return above them, or by the next call reusing
+// their stack slot.
+//
+// A return popping a different address than the one pushed means that
+// the stack was overwritten; a return with no frame at SP, that the
+// address was pushed by other means, as in PUSH HL then RET to jump.
+// These, and SP reloaded above frames, are kept as anomalies for the
+// debugger to report.
+
+/// Frames kept at most: the stack holds 32K return addresses.
+const MAX_FRAMES: usize = 0x8000;
+/// Anomalies kept at most, the oldest are dropped.
+const MAX_ANOMALIES: usize = 1000;
+
+/// How a routine was entered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+/// A routine being run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// Address of the CALL or RST, or of the instruction interrupted.
+    pub caller: u16,
+    /// Address jumped to.
+    pub routine: u16,
+    pub return_address: u16,
+    /// SP after the return address was pushed.
+    pub sp: u16,
+}
+
+impl Frame {
+    fn describe(&self, name: &dyn Fn(u16) -> String) -> String {
+        match self.kind {
+            FrameKind::Call => format!("CALL at {}", name(self.caller)),
+            FrameKind::Rst => format!("RST at {}", name(self.caller)),
+            FrameKind::Interrupt => format!("the interrupt at {}", name(self.caller)),
+        }
+    }
+}
+
+/// Something a return or SP reload did to the call stack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    /// A return at pc popped target instead of the return address of
+    /// the frame: the stack was overwritten.
+    Smashed { pc: u16, frame: Frame, target: u16 },
+    /// A return at pc popped target from a stack slot no frame pushed.
+    Unmatched { pc: u16, target: u16 },
+    /// SP was reloaded at pc above the return addresses of frames,
+    /// which were dropped.
+    Reloaded {
+        pc: u16,
+        from: u16,
+        to: u16,
+        dropped: Vec<Frame>,
+    },
+}
+
+impl Anomaly {
+    /// Text of the anomaly, with addresses written by name.
+    pub fn describe(&self, name: &dyn Fn(u16) -> String) -> String {
+        match *self {
+            Anomaly::Smashed { pc, frame, target } => format!(
+                "Return at {} to {}, not to {} pushed by {}",
+                name(pc),
+                name(target),
+                name(frame.return_address),
+                frame.describe(name)
+            ),
+            Anomaly::Unmatched { pc, target } => format!(
+                "Return at {} to {}, pushed by no call",
+                name(pc),
+                name(target)
+            ),
+            Anomaly::Reloaded {
+                pc,
+                from,
+                to,
+                ref dropped,
+            } => format!(
+                "SP reloaded at {} from 0x{:04x} to 0x{:04x}, dropping {} frames",
+                name(pc),
+                from,
+                to,
+                dropped.len()
+            ),
+        }
+    }
+}
+
+/// Frames of the routines being run, kept by the call and return
+/// instructions while set on the CPU.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    /// The outermost first.
+    frames: Vec<Frame>,
+    anomalies: VecDeque<Anomaly>,
+}
+
+/// Distance from b up to a on the stack, which wraps around memory.
+fn distance(a: u16, b: u16) -> i16 {
+    a.wrapping_sub(b) as i16
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack::default()
+    }
+
+    /// The routines being run, the outermost first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// The anomalies found since the last call, the oldest first.
+    pub fn take_anomalies(&mut self) -> Vec<Anomaly> {
+        self.anomalies.drain(..).collect()
+    }
+
+    pub(crate) fn restore(&mut self, frames: Vec<Frame>) {
+        self.frames = frames;
+    }
+
+    fn report(&mut self, anomaly: Anomaly) {
+        if self.anomalies.len() == MAX_ANOMALIES {
+            self.anomalies.pop_front();
+        }
+        self.anomalies.push_back(anomaly);
+    }
+
+    /// Drop the frames whose return address is below sp.
+    fn unwind(&mut self, sp: u16) -> Vec<Frame> {
+        let keep = self
+            .frames
+            .iter()
+            .rposition(|frame| distance(frame.sp, sp) >= 0)
+            .map_or(0, |index| index + 1);
+        self.frames.split_off(keep)
+    }
+
+    fn push(&mut self, frame: Frame) {
+        // Frames whose return address is overwritten by this one.
+        while self
+            .frames
+            .last()
+            .is_some_and(|top| distance(top.sp, frame.sp) <= 0)
+        {
+            self.frames.pop();
+        }
+        if self.frames.len() == MAX_FRAMES {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    /// A return at pc popped target from sp.
+    fn pop(&mut self, pc: u16, sp: u16, target: u16) {
+        self.unwind(sp);
+
+        match self.frames.last().cloned() {
+            Some(frame) if frame.sp == sp => {
+                self.frames.pop();
+                if frame.return_address != target {
+                    self.report(Anomaly::Smashed { pc, frame, target });
+                }
+            }
+            _ => self.report(Anomaly::Unmatched { pc, target }),
+        }
+    }
+
+    /// Backtrace from pc, a line per routine from the innermost: the
+    /// address being run in the routine and its name.
+    pub fn backtrace(&self, pc: u16, name: &dyn Fn(u16) -> String) -> String {
+        let mut text = String::new();
+        let mut address = pc;
+
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            text.push_str(&format!(
+                "#{:<3} {:04x}  {}\n",
+                depth,
+                address,
+                name(address)
+            ));
+            if frame.kind == FrameKind::Interrupt {
+                text.push_str("     <interrupt>\n");
+            }
+            address = frame.caller;
+        }
+        text.push_str(&format!(
+            "#{:<3} {:04x}  {}\n",
+            self.frames.len(),
+            address,
+            name(address)
+        ));
+
+        text
+    }
+}
+
+impl Cpu {
+    /// A CALL, RST or interrupt at caller has pushed pc and jumps to
+    /// routine.
+    pub(crate) fn call_stack_enter(&mut self, kind: FrameKind, caller: u16, routine: u16) {
+        self.history_frames();
+        let frame = Frame {
+            kind,
+            caller,
+            routine,
+            return_address: self.pc,
+            sp: self.sp,
+        };
+        if let Some(ref mut stack) = self.call_stack {
+            stack.push(frame);
+        }
+    }
+
+    /// A return at pc has popped pc from sp.
+    pub(crate) fn call_stack_return(&mut self, pc: u16, sp: u16) {
+        self.history_frames();
+        let target = self.pc;
+        if let Some(ref mut stack) = self.call_stack {
+            stack.pop(pc, sp, target);
+        }
+    }
+
+    /// SP was loaded at pc, from the value from.
+    pub(crate) fn call_stack_reload(&mut self, pc: u16, from: u16) {
+        self.history_frames();
+        let to = self.sp;
+        if let Some(ref mut stack) = self.call_stack {
+            let dropped = stack.unwind(to);
+            if !dropped.is_empty() {
+                stack.report(Anomaly::Reloaded {
+                    pc,
+                    from,
+                    to,
+                    dropped,
+                });
+            }
+        }
+    }
+}
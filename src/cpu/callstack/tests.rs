@@ -0,0 +1,157 @@
+use cpu::{Anomaly, CallStack, Cpu, CpuBuilder, FrameKind, History, Limits};
+
+fn cpu(code: Vec<u8>) -> Cpu {
+    let mut memory = code;
+    memory.resize(0x100, 0);
+    let mut cpu = CpuBuilder::new().with_memory(memory).with_sp(0xf0).build();
+    cpu.call_stack = Some(CallStack::new());
+    cpu
+}
+
+fn name(address: u16) -> String {
+    format!("0x{:04x}", address)
+}
+
+fn stack(cpu: &Cpu) -> Vec<(FrameKind, u16, u16)> {
+    cpu.call_stack
+        .as_ref()
+        .unwrap()
+        .frames()
+        .iter()
+        .map(|frame| (frame.kind, frame.caller, frame.routine))
+        .collect()
+}
+
+fn anomalies(cpu: &mut Cpu) -> Vec<Anomaly> {
+    cpu.call_stack.as_mut().unwrap().take_anomalies()
+}
+
+#[test]
+fn calls_and_returns() {
+    let mut cpu = cpu(asm!(
+        "       CALL outer",
+        "       HALT",
+        "outer: RST 0x38",
+        "       RET",
+        "       ORG 0x38",
+        "       CALL NZ,inner",
+        "       RET",
+        "inner: HALT",
+        "       RET"
+    ));
+
+    cpu.run(Limits::default());
+    assert_eq!(
+        stack(&cpu),
+        vec![
+            (FrameKind::Call, 0x00, 0x04),
+            (FrameKind::Rst, 0x04, 0x38),
+            (FrameKind::Call, 0x38, 0x3c)
+        ]
+    );
+    assert_eq!(
+        cpu.call_stack.as_ref().unwrap().backtrace(0x3c, &name),
+        "#0   003c  0x003c\n#1   0038  0x0038\n#2   0004  0x0004\n#3   0000  0x0000\n"
+    );
+
+    cpu.halted = false;
+    cpu.run(Limits::default());
+    assert_eq!(cpu.pc, 0x04);
+    assert!(stack(&cpu).is_empty());
+    assert!(anomalies(&mut cpu).is_empty());
+}
+
+#[test]
+fn interrupts() {
+    let mut cpu = cpu(asm!(
+        "      LD A,1",
+        "      HALT",
+        "      ORG 0x66",
+        "      RETN"
+    ));
+    cpu.iff1 = true;
+
+    cpu.run(Limits::default());
+    cpu.nmi();
+    assert_eq!((cpu.pc, cpu.iff1, cpu.iff2), (0x66, false, true));
+    assert_eq!(stack(&cpu), vec![(FrameKind::Interrupt, 0x03, 0x66)]);
+    assert!(cpu
+        .call_stack
+        .as_ref()
+        .unwrap()
+        .backtrace(0x66, &name)
+        .contains("     <interrupt>\n#1   0003"));
+
+    cpu.step();
+    assert_eq!((cpu.pc, cpu.iff1), (0x03, true));
+    assert!(stack(&cpu).is_empty());
+}
+
+#[test]
+fn dropped_and_smashed_returns() {
+    let mut cpu = cpu(asm!(
+        "       CALL drop",
+        "       CALL smash",
+        "       LD HL,0x80",
+        "       PUSH HL",
+        "       RET",
+        "drop:  POP HL",
+        "       CALL inner",
+        "       JP (HL)",
+        "inner: RET",
+        "smash: LD HL,0x40",
+        "       EX (SP),HL",
+        "       RET",
+        "       ORG 0x40",
+        "       JP 6",
+        "       ORG 0x80",
+        "       HALT"
+    ));
+
+    cpu.run(Limits::default());
+    let anomalies = anomalies(&mut cpu);
+    assert_eq!(anomalies.len(), 2);
+    match anomalies[0] {
+        Anomaly::Smashed { pc, frame, target } => {
+            assert_eq!((pc, target, frame.return_address), (0x15, 0x40, 0x06));
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        anomalies[1].describe(&name),
+        "Return at 0x000a to 0x0080, pushed by no call"
+    );
+    assert!(stack(&cpu).is_empty());
+}
+
+#[test]
+fn reloaded_stack() {
+    let mut cpu = cpu(asm!(
+        "      CALL sub",
+        "      HALT",
+        "sub:  LD HL,0xf0",
+        "      LD SP,HL",
+        "      HALT"
+    ));
+
+    cpu.run(Limits::default());
+    assert!(stack(&cpu).is_empty());
+    assert_eq!(
+        anomalies(&mut cpu)[0].describe(&name),
+        "SP reloaded at 0x0007 from 0x00ee to 0x00f0, dropping 1 frames"
+    );
+}
+
+#[test]
+fn stepping_back() {
+    let mut cpu = cpu(asm!("CALL sub", "HALT", "sub: RET"));
+    cpu.history = Some(History::new(10));
+
+    cpu.run(Limits::default());
+    assert!(stack(&cpu).is_empty());
+    cpu.step_back();
+    cpu.step_back();
+    assert_eq!(stack(&cpu), vec![(FrameKind::Call, 0x00, 0x04)]);
+    cpu.step_back();
+    assert!(stack(&cpu).is_empty());
+}
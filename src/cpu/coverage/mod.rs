@@ -0,0 +1,227 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::{Access, AccessKind};
+use disasm::{disassemble, listing_line};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// === Code coverage ===
+//
+// Each address remembers how it was used: as the first byte of an
+// instruction executed, how many times, as another byte of one, or as
+// data read or written by instructions. Operands are fetched with the
+// opcode and are not data reads.
+//
+// Conditional instructions count both directions: JP cc, JR cc, CALL cc
+// and RET cc are taken when they jump, DJNZ when it loops. LDIR and the
+// other repeating block instructions run to the end in a single step and
+// are not counted.
+//
+// The lcov report maps addresses to source lines with the line table of
+// the assembler, as kept by `dap::SourceMap`.
+
+const FETCHED: u8 = 1;
+const READ: u8 = 2;
+const WRITTEN: u8 = 4;
+
+/// Times a conditional instruction went each way.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Coverage, collected by `step` while set on the CPU.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    /// Executions of the instruction starting at each address.
+    executions: Vec<u64>,
+    /// FETCHED, READ and WRITTEN flags of each address.
+    uses: Vec<u8>,
+    branches: BTreeMap<u16, Branch>,
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage {
+            executions: vec![0; 0x10000],
+            uses: vec![0; 0x10000],
+            branches: BTreeMap::new(),
+        }
+    }
+}
+
+/// Whether the instruction is a conditional jump, call or return.
+fn conditional(opcode: &[u8; 4]) -> bool {
+    match opcode[0] {
+        0x10 | 0x20 | 0x28 | 0x30 | 0x38 => true,
+        op => op & 0xc7 == 0xc0 || op & 0xc7 == 0xc2 || op & 0xc7 == 0xc4,
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Count the instruction with the given opcode bytes executed at pc,
+    /// which made the accesses, and took its branch when taken.
+    pub fn record(&mut self, pc: u16, opcode: &[u8; 4], taken: bool, accesses: &[Access]) {
+        let length = disassemble(opcode, 0).len() as u16;
+        self.executions[usize::from(pc)] += 1;
+        for offset in 0..length {
+            self.uses[usize::from(pc.wrapping_add(offset))] |= FETCHED;
+        }
+
+        for access in accesses {
+            let address = usize::from(access.address);
+            match access.kind {
+                AccessKind::Read => self.uses[address] |= READ,
+                AccessKind::Write => self.uses[address] |= WRITTEN,
+                AccessKind::Input | AccessKind::Output => {}
+            }
+        }
+
+        if conditional(opcode) {
+            let branch = self.branches.entry(pc).or_default();
+            if taken {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+
+    /// Times the instruction at address was executed.
+    pub fn executions(&self, address: u16) -> u64 {
+        self.executions[usize::from(address)]
+    }
+
+    /// True when address was fetched as part of an instruction.
+    pub fn is_fetched(&self, address: u16) -> bool {
+        self.uses[usize::from(address)] & FETCHED != 0
+    }
+
+    pub fn is_read(&self, address: u16) -> bool {
+        self.uses[usize::from(address)] & READ != 0
+    }
+
+    pub fn is_written(&self, address: u16) -> bool {
+        self.uses[usize::from(address)] & WRITTEN != 0
+    }
+
+    /// Directions of the conditional instruction executed at address.
+    pub fn branch(&self, address: u16) -> Option<Branch> {
+        self.branches.get(&address).cloned()
+    }
+
+    /// Disassembly of memory from start to end included, each instruction
+    /// with its count of executions, - when never executed, and r and w
+    /// when some of its bytes were read or written as data. Conditional
+    /// instructions end with the count of each direction.
+    pub fn annotate(&self, memory: &[u8], start: u16, end: u16) -> String {
+        let mut text = String::new();
+        let mut address = u32::from(start);
+
+        while address <= u32::from(end) {
+            let instruction = disassemble(memory, address as u16);
+            let length = instruction.len() as u32;
+            let addresses = (address..address + length).map(|a| a as u16);
+            let data = format!(
+                "{}{}",
+                if addresses.clone().any(|a| self.is_read(a)) {
+                    'r'
+                } else {
+                    '-'
+                },
+                if addresses.clone().any(|a| self.is_written(a)) {
+                    'w'
+                } else {
+                    '-'
+                }
+            );
+
+            let count = match self.executions(address as u16) {
+                0 => "-".to_string(),
+                count => count.to_string(),
+            };
+            let mut line = format!("{:>10} {}  {}", count, data, listing_line(&instruction));
+            if let Some(branch) = self.branch(address as u16) {
+                line = format!(
+                    "{:<60} ; taken {}, not taken {}",
+                    line, branch.taken, branch.not_taken
+                );
+            }
+            text.push_str(line.trim_end());
+            text.push('\n');
+
+            address += length;
+        }
+
+        text
+    }
+
+    /// Report in the lcov tracefile format of the source lines given by
+    /// file, line, address and length of their object code, as test name.
+    /// Lines whose bytes were only used as data are left out.
+    pub fn lcov(&self, lines: &[(PathBuf, usize, u16, usize)], name: &str) -> String {
+        let mut files: BTreeMap<&PathBuf, Vec<(usize, u16, usize)>> = BTreeMap::new();
+        for (file, line, address, length) in lines {
+            files
+                .entry(file)
+                .or_default()
+                .push((*line, *address, *length));
+        }
+
+        let mut text = String::new();
+        for (file, mut lines) in files {
+            lines.sort();
+            text.push_str(&format!("TN:{}\nSF:{}\n", name, file.display()));
+
+            let (mut found, mut hit) = (0, 0);
+            let (mut branches, mut taken) = (0, 0);
+            for (line, address, length) in lines {
+                let addresses: Vec<u16> = (0..length)
+                    .map(|offset| address.wrapping_add(offset as u16))
+                    .collect();
+                let data = addresses
+                    .iter()
+                    .any(|&a| self.is_read(a) || self.is_written(a));
+                if data && !addresses.iter().any(|&a| self.is_fetched(a)) {
+                    continue;
+                }
+
+                let count = addresses
+                    .iter()
+                    .map(|&a| self.executions(a))
+                    .max()
+                    .unwrap_or(0);
+                text.push_str(&format!("DA:{},{}\n", line, count));
+                found += 1;
+                if count > 0 {
+                    hit += 1;
+                }
+
+                for &a in &addresses {
+                    if let Some(branch) = self.branch(a) {
+                        for (number, times) in [branch.taken, branch.not_taken].iter().enumerate() {
+                            text.push_str(&format!("BRDA:{},{},{},{}\n", line, a, number, times));
+                            branches += 1;
+                            if *times > 0 {
+                                taken += 1;
+                            }
+                        }
+                    }
+                }
+            }
+
+            text.push_str(&format!(
+                "BRF:{}\nBRH:{}\nLF:{}\nLH:{}\nend_of_record\n",
+                branches, taken, found, hit
+            ));
+        }
+
+        text
+    }
+}
@@ -0,0 +1,158 @@
+use cpu::{Branch, Coverage, Cpu, CpuBuilder, Limits};
+use std::path::PathBuf;
+
+fn covered(code: Vec<u8>) -> Cpu {
+    let mut memory = code;
+    memory.resize(0x100, 0);
+    let mut cpu = CpuBuilder::new().with_memory(memory).build();
+    cpu.coverage = Some(Coverage::new());
+    cpu.run(Limits::default());
+    cpu
+}
+
+fn program() -> Vec<u8> {
+    asm!(
+        "       LD B,3",
+        "loop:  LD A,(data)",
+        "       AND 1",
+        "       JR NZ,skip",
+        "       LD (data),A",
+        "skip:  DJNZ loop",
+        "       JP Z,done",
+        "       HALT",
+        "done:  HALT",
+        "data:  DB 1"
+    )
+}
+
+#[test]
+fn uses() {
+    let cpu = covered(program());
+    let coverage = cpu.coverage.as_ref().unwrap();
+
+    assert_eq!(coverage.executions(0x00), 1);
+    assert_eq!(coverage.executions(0x02), 3);
+    // LD (data),A is never reached, HALT at done neither.
+    assert_eq!(coverage.executions(0x09), 0);
+    assert!(!coverage.is_fetched(0x09));
+    assert_eq!(coverage.executions(0x12), 0);
+    assert!(coverage.is_fetched(0x04) && !coverage.is_read(0x04));
+
+    assert!(coverage.is_read(0x13) && !coverage.is_written(0x13));
+    assert!(!coverage.is_fetched(0x13));
+
+    assert_eq!(
+        coverage.branch(0x07),
+        Some(Branch {
+            taken: 3,
+            not_taken: 0
+        })
+    );
+    assert_eq!(
+        coverage.branch(0x0c),
+        Some(Branch {
+            taken: 2,
+            not_taken: 1
+        })
+    );
+    // Z from AND 1 with A = 1 is clear.
+    assert_eq!(
+        coverage.branch(0x0e),
+        Some(Branch {
+            taken: 0,
+            not_taken: 1
+        })
+    );
+    assert_eq!(coverage.branch(0x02), None);
+}
+
+#[test]
+fn calls_and_returns() {
+    let cpu = covered(asm!(
+        "      LD SP,0xf0",
+        "      XOR A",
+        "      CALL Z,sub",
+        "      CALL NZ,sub",
+        "      HALT",
+        "sub:  RET NZ",
+        "      RET"
+    ));
+    let coverage = cpu.coverage.as_ref().unwrap();
+
+    let directions = |address| {
+        let branch = coverage.branch(address).unwrap();
+        (branch.taken, branch.not_taken)
+    };
+    assert_eq!(directions(0x04), (1, 0));
+    assert_eq!(directions(0x07), (0, 1));
+    assert_eq!(directions(0x0b), (0, 1));
+    assert_eq!(coverage.branch(0x0c), None);
+
+    // The return address pushed on the stack.
+    assert!(coverage.is_written(0xee) && coverage.is_read(0xef));
+    assert!(!coverage.is_written(0xf0));
+}
+
+#[test]
+fn branches_to_the_next_instruction() {
+    let cpu = covered(asm!("LD B,2", "JR NZ,$+2", "DJNZ $+2", "JP NZ,$+3", "HALT"));
+    let coverage = cpu.coverage.as_ref().unwrap();
+
+    for &address in &[0x02, 0x04, 0x06] {
+        assert_eq!(
+            coverage.branch(address),
+            Some(Branch {
+                taken: 1,
+                not_taken: 0
+            })
+        );
+    }
+}
+
+#[test]
+fn annotate() {
+    let cpu = covered(program());
+    let text = cpu
+        .coverage
+        .as_ref()
+        .unwrap()
+        .annotate(&cpu.memory, 0, 0x12);
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[0], "         1 --  0000  06 03        LD B,0x03");
+    assert_eq!(lines[4], "         - --  0009  32 13 00     LD (0x0013),A");
+    assert_eq!(
+        lines[5],
+        "         3 --  000c  10 f4        DJNZ 0x0002                ; taken 2, not taken 1"
+    );
+    assert!(lines[7].starts_with("         1 --  0011  76"));
+    assert!(lines[8].starts_with("         - --  0012  76"));
+}
+
+#[test]
+fn lcov() {
+    let cpu = covered(program());
+    let file = PathBuf::from("/src/test.asm");
+    // The lines of the program with object code, but CP and the end.
+    let lines: Vec<(PathBuf, usize, u16, usize)> = [
+        (1, 0x00, 2),
+        (2, 0x02, 3),
+        (4, 0x07, 2),
+        (5, 0x09, 3),
+        (6, 0x0c, 2),
+        (10, 0x13, 1),
+    ]
+    .iter()
+    .map(|&(line, address, length)| (file.clone(), line, address, length))
+    .collect();
+
+    let text = cpu.coverage.as_ref().unwrap().lcov(&lines, "test");
+    assert_eq!(
+        text,
+        "TN:test\nSF:/src/test.asm\n\
+         DA:1,1\nDA:2,3\nDA:4,3\nBRDA:4,7,0,3\nBRDA:4,7,1,0\n\
+         DA:5,0\nDA:6,3\nBRDA:6,12,0,2\nBRDA:6,12,1,1\n\
+         BRF:4\nBRH:3\nLF:5\nLH:4\nend_of_record\n"
+    );
+}
@@ -0,0 +1,446 @@
+use cpu::timing::instruction_time;
+use cpu::Cpu;
+
+// === Instruction decoder ===
+//
+// Handlers in the isa modules read their own opcode and operands
+// starting from pc and advance pc by themselves: decoding an instruction
+// is only a matter of looking at the opcode bytes at pc (prefixes included)
+// and dispatching to the right handler.
+//
+// An index prefix only changes the instructions that use H, L or (HL):
+// in front of any other opcode it is skipped as an instruction of its own,
+// taking 4 T states. The undocumented opcodes on IXH, IXL, IYH and IYL run
+// the unprefixed handler with the index register in place of HL.
+
+/// Whether a DD or FD prefix changes the meaning of the opcode after it.
+pub fn is_indexed(opcode: u8) -> bool {
+    let uses_hl = |r: u8| r == 4 || r == 5 || r == 6;
+
+    match opcode {
+        0x09 | 0x19 | 0x29 | 0x39 => true,
+        0x21..=0x26 | 0x2a..=0x2e | 0x34..=0x36 => true,
+        0x76 => false,
+        0x40..=0x7f => uses_hl(opcode >> 3 & 0b111) || uses_hl(opcode & 0b111),
+        0x80..=0xbf => uses_hl(opcode & 0b111),
+        0xcb | 0xe1 | 0xe3 | 0xe5 | 0xe9 | 0xf9 => true,
+        _ => false,
+    }
+}
+
+/// Unprefixed opcodes working on (HL): with an index prefix
+/// they work on (IX+d) and take longer.
+pub fn uses_hl_indirect(opcode: u8) -> bool {
+    match opcode {
+        0x34..=0x36 => true,
+        0x76 => false,
+        op if op & 0b11_000_000 == 0b01_000_000 => {
+            op & 0b111 == 0b110 || op & 0b111_000 == 0b110_000
+        }
+        op if op & 0b11_000_000 == 0b10_000_000 => op & 0b111 == 0b110,
+        _ => false,
+    }
+}
+
+/// Opcode fetches of an instruction, each incrementing R: two after a
+/// prefix, but one for an index prefix alone.
+pub fn fetches(opcode: &[u8; 4]) -> u8 {
+    match opcode[0] {
+        0xcb | 0xed => 2,
+        0xdd | 0xfd if is_indexed(opcode[1]) => 2,
+        _ => 1,
+    }
+}
+
+#[allow(dead_code)]
+impl Cpu {
+    /// Execute the instruction at pc, counting its T states.
+    /// A halted CPU executes a NOP without moving pc.
+    pub fn step(&mut self) {
+        self.accesses.clear();
+        self.history_begin();
+
+        if self.halted {
+            self.refresh(1);
+            self.cycles += 4;
+            return;
+        }
+
+        if let Some(mut trace) = self.trace.take() {
+            trace.record(self);
+            self.trace = Some(trace);
+        }
+
+        let pc = self.pc;
+        let mut opcode = [0; 4];
+        for (offset, byte) in opcode.iter_mut().enumerate() {
+            *byte = self.memory[usize::from(pc.wrapping_add(offset as u16)) % self.memory.len()];
+        }
+
+        self.refresh(fetches(&opcode));
+        let taken = self.branch_taken(&opcode);
+        self.execute_main(opcode[0]);
+        let repeated = opcode[0] == 0xed && self.pc == pc;
+
+        let time = instruction_time(&opcode, taken || repeated);
+        self.cycles += u64::from(time);
+        if let Some(ref mut profile) = self.profile {
+            profile.record(pc, opcode[0], self.pc, self.sp, time);
+        }
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.record(pc, &opcode, taken, &self.accesses);
+        }
+        self.history_end();
+    }
+
+    /// Count opcode fetches in the low 7 bits of R, as the memory
+    /// refresh does. Bit 7 is only changed by LD R,A.
+    pub fn refresh(&mut self, fetches: u8) {
+        self.r = self.r & 0x80 | self.r.wrapping_add(fetches) & 0x7f;
+    }
+
+    /// Whether the instruction is a conditional jump, call or return
+    /// that is taken, or a DJNZ that loops, checked before executing it.
+    fn branch_taken(&self, opcode: &[u8; 4]) -> bool {
+        match opcode[0] {
+            0x10 => self.b != 1,
+            // JR cc has only the conditions NZ, Z, NC and C
+            0x20 | 0x28 | 0x30 | 0x38 => self.condition(opcode[0] >> 3 & 0b11),
+            // RET cc, JP cc, CALL cc
+            op if op & 0b11_000_111 == 0b11_000_000 => self.condition(op >> 3),
+            op if op & 0b11_000_111 == 0b11_000_010 => self.condition(op >> 3),
+            op if op & 0b11_000_111 == 0b11_000_100 => self.condition(op >> 3),
+            _ => false,
+        }
+    }
+
+    fn execute_main(&mut self, opcode: u8) {
+        match opcode {
+            0x00 => self.nop(),
+            0xcb => self.execute_cb(),
+            0xdd => self.execute_dd(),
+            0xed => self.execute_ed(),
+            0xfd => self.execute_fd(),
+            0x01 | 0x11 | 0x21 | 0x31 => self.ld_dd_nn(),
+            0x02 => self.ld_bc_a(),
+            0x03 | 0x13 | 0x23 | 0x33 => self.inc_ss(),
+            0x07 => self.rlca(),
+            0x08 => self.ex_af_af1(),
+            0x09 | 0x19 | 0x29 | 0x39 => self.add_hl_ss(),
+            0x0a => self.ld_a_bc(),
+            0x0b | 0x1b | 0x2b | 0x3b => self.dec_ss(),
+            0x0f => self.rrca(),
+            0x10 => self.djnz_e(),
+            0x12 => self.ld_de_a(),
+            0x17 => self.rla(),
+            0x18 => self.jr_e(),
+            0x1a => self.ld_a_de(),
+            0x1f => self.rra(),
+            0x20 => self.jr_nz_e(),
+            0x22 => self.ld_nni_hl(),
+            0x27 => self.daa(),
+            0x28 => self.jr_z_e(),
+            0x2a => self.ld_hl_nni(),
+            0x2f => self.cpl(),
+            0x30 => self.jr_nc_e(),
+            0x32 => self.ld_nn_a(),
+            0x34 => self.inc_hli(),
+            0x35 => self.dec_hli(),
+            0x36 => self.ld_hl_n(),
+            0x37 => self.scf(),
+            0x38 => self.jr_c_e(),
+            0x3a => self.ld_a_nn(),
+            0x3f => self.ccf(),
+            0x76 => self.halt(),
+
+            // INC r, DEC r, LD r,n
+            op if op & 0b11_000_111 == 0b00_000_100 => self.inc_r(),
+            op if op & 0b11_000_111 == 0b00_000_101 => self.dec_r(),
+            op if op & 0b11_000_111 == 0b00_000_110 => self.ld_r_n(),
+
+            // LD r,r' LD r,(HL) LD (HL),r
+            op if op & 0b11_000_111 == 0b01_000_110 => self.ld_r_hl(),
+            op if op & 0b11_111_000 == 0b01_110_000 => self.ld_hl_r(),
+            op if op & 0b11_000_000 == 0b01_000_000 => self.ld_r_r1(),
+
+            // 8-bit arithmetic and logic on (HL)
+            0x86 => self.add_a_hli(),
+            0x8e => self.adc_a_hli(),
+            0x96 => self.sub_hli(),
+            0x9e => self.sbc_a_hli(),
+            0xa6 => self.and_hli(),
+            0xae => self.xor_hli(),
+            0xb6 => self.or_hli(),
+            0xbe => self.cp_hli(),
+
+            // 8-bit arithmetic and logic on registers
+            op if op & 0b11_111_000 == 0x80 => self.add_a_r(),
+            op if op & 0b11_111_000 == 0x88 => self.adc_a_r(),
+            op if op & 0b11_111_000 == 0x90 => self.sub_r(),
+            op if op & 0b11_111_000 == 0x98 => self.sbc_a_r(),
+            op if op & 0b11_111_000 == 0xa0 => self.and_r(),
+            op if op & 0b11_111_000 == 0xa8 => self.xor_r(),
+            op if op & 0b11_111_000 == 0xb0 => self.or_r(),
+            op if op & 0b11_111_000 == 0xb8 => self.cp_r(),
+
+            0xc3 => self.jp_nn(),
+            0xc6 => self.add_a_n(),
+            0xc9 => self.ret(),
+            0xcd => self.call_nn(),
+            0xce => self.adc_a_n(),
+            0xd3 => self.out_ni_a(),
+            0xd6 => self.sub_n(),
+            0xd9 => self.exx(),
+            0xdb => self.in_a_ni(),
+            0xde => self.sbc_a_n(),
+            0xe3 => self.ex_spi_hl(),
+            0xe6 => self.and_n(),
+            0xe9 => self.jp_hl(),
+            0xeb => self.ex_de_hl(),
+            0xee => self.xor_n(),
+            0xf3 => self.di(),
+            0xf6 => self.or_n(),
+            0xf9 => self.ld_sp_hl(),
+            0xfb => self.ei(),
+            0xfe => self.cp_n(),
+
+            op if op & 0b11_000_111 == 0b11_000_000 => self.ret_cc(),
+            op if op & 0b11_001_111 == 0b11_000_001 => self.pop_qq(),
+            op if op & 0b11_000_111 == 0b11_000_010 => self.jp_cc_nn(),
+            op if op & 0b11_000_111 == 0b11_000_100 => self.call_cc_nn(),
+            op if op & 0b11_001_111 == 0b11_000_101 => self.push_qq(),
+
+            // RST p, the only opcodes left: 0b11_ppp_111
+            _ => self.rst_p(),
+        }
+    }
+
+    fn execute_cb(&mut self) {
+        let opcode = self.memory_at_pc(1);
+        let hli = opcode & 0b111 == 0b110;
+
+        match (opcode >> 3, hli) {
+            (0, false) => self.rlc_r(),
+            (0, true) => self.rlc_hli(),
+            (1, false) => self.rrc_r(),
+            (1, true) => self.rrc_hli(),
+            (2, false) => self.rl_r(),
+            (2, true) => self.rl_hli(),
+            (3, false) => self.rr_r(),
+            (3, true) => self.rr_hli(),
+            (4, false) => self.sla_r(),
+            (4, true) => self.sla_hli(),
+            (5, false) => self.sra_r(),
+            (5, true) => self.sra_hli(),
+            (6, false) => self.sll_r(),
+            (6, true) => self.sll_hli(),
+            (7, false) => self.srl_r(),
+            (7, true) => self.srl_hli(),
+            (8..=15, false) => self.bit_b_r(),
+            (8..=15, true) => self.bit_b_hli(),
+            (16..=23, false) => self.res_b_r(),
+            (16..=23, true) => self.res_b_hli(),
+            (_, false) => self.set_b_r(),
+            (_, true) => self.set_b_hli(),
+        }
+    }
+
+    fn execute_ed(&mut self) {
+        let opcode = self.memory_at_pc(1);
+
+        match opcode {
+            0x47 => self.ld_i_a(),
+            0x4d => self.reti(),
+            0x4f => self.ld_r_a(),
+            0x57 => self.ld_a_i(),
+            0x5f => self.ld_a_r(),
+            0x67 => self.rrd(),
+            0x6f => self.rld(),
+            0xa0 => self.ldi(),
+            0xa1 => self.cpi(),
+            0xa2 => self.ini(),
+            0xa3 => self.outi(),
+            0xa8 => self.ldd(),
+            0xa9 => self.cpd(),
+            0xaa => self.ind(),
+            0xab => self.outd(),
+            0xb0 => self.ldir(),
+            0xb1 => self.cpir(),
+            0xb2 => self.inir(),
+            0xb3 => self.otir(),
+            0xb8 => self.lddr(),
+            0xb9 => self.cpdr(),
+            0xba => self.indr(),
+            0xbb => self.otdr(),
+
+            op if op & 0b11_000_111 == 0b01_000_000 => self.in_r_ci(),
+            op if op & 0b11_000_111 == 0b01_000_001 => self.out_ci_r(),
+            op if op & 0b11_001_111 == 0b01_000_010 => self.sbc_hl_ss(),
+            op if op & 0b11_001_111 == 0b01_001_010 => self.adc_hl_ss(),
+            op if op & 0b11_001_111 == 0b01_000_011 => self.ld_nni_dd(),
+            op if op & 0b11_001_111 == 0b01_001_011 => self.ld_dd_nni(),
+
+            // Undocumented mirrors of NEG, RETN and IM
+            op if op & 0b11_000_111 == 0b01_000_100 => self.neg(),
+            op if op & 0b11_000_111 == 0b01_000_101 => self.retn(),
+            op if op & 0b11_000_111 == 0b01_000_110 => match op >> 3 & 0b11 {
+                0 | 1 => self.im_0(),
+                2 => self.im_1(),
+                _ => self.im_2(),
+            },
+
+            // Other opcodes are NOPs of two bytes
+            _ => self.pc = self.pc.wrapping_add(2),
+        }
+    }
+
+    fn execute_dd(&mut self) {
+        let opcode = self.memory_at_pc(1);
+
+        // Accesses to (IX+d) leave the address in MEMPTR
+        if opcode == 0xcb || uses_hl_indirect(opcode) {
+            self.wz = self.ix_addr(self.memory_at_pc(2)) as u16;
+        }
+
+        match opcode {
+            // The prefix alone
+            op if !is_indexed(op) => self.pc = self.pc.wrapping_add(1),
+            0x09 | 0x19 | 0x29 | 0x39 => self.add_ix_pp(),
+            0x21 => self.ld_ix_nn(),
+            0x22 => self.ld_nni_ix(),
+            0x23 => self.inc_ix(),
+            0x2a => self.ld_ix_nni(),
+            0x2b => self.dec_ix(),
+            0x34 => self.inc_ixdi(),
+            0x35 => self.dec_ixdi(),
+            0x36 => self.ld_ixd_n(),
+            0x86 => self.add_a_ixdi(),
+            0x8e => self.adc_a_ixdi(),
+            0x96 => self.sub_ixdi(),
+            0x9e => self.sbc_a_ixdi(),
+            0xa6 => self.and_ixdi(),
+            0xae => self.xor_ixdi(),
+            0xb6 => self.or_ixdi(),
+            0xbe => self.cp_ixdi(),
+            0xcb => self.execute_ddcb(),
+            0xe1 => self.pop_ix(),
+            0xe3 => self.ex_spi_ix(),
+            0xe5 => self.push_ix(),
+            0xe9 => self.jp_ix(),
+            0xf9 => self.ld_sp_ix(),
+
+            op if op & 0b11_000_111 == 0b01_000_110 => self.ld_r_ixd(),
+            op if op & 0b11_111_000 == 0b01_110_000 => self.ld_ixd_r(),
+
+            // IXH, IXL in place of H, L
+            op => self.ix = self.execute_index_halves(op, self.ix),
+        }
+    }
+
+    fn execute_fd(&mut self) {
+        let opcode = self.memory_at_pc(1);
+
+        // Accesses to (IX+d) leave the address in MEMPTR
+        if opcode == 0xcb || uses_hl_indirect(opcode) {
+            self.wz = self.iy_addr(self.memory_at_pc(2)) as u16;
+        }
+
+        match opcode {
+            // The prefix alone
+            op if !is_indexed(op) => self.pc = self.pc.wrapping_add(1),
+            0x09 | 0x19 | 0x29 | 0x39 => self.add_iy_rr(),
+            0x21 => self.ld_iy_nn(),
+            0x22 => self.ld_nni_iy(),
+            0x23 => self.inc_iy(),
+            0x2a => self.ld_iy_nni(),
+            0x2b => self.dec_iy(),
+            0x34 => self.inc_iydi(),
+            0x35 => self.dec_iydi(),
+            0x36 => self.ld_iyd_n(),
+            0x86 => self.add_a_iydi(),
+            0x8e => self.adc_a_iydi(),
+            0x96 => self.sub_iydi(),
+            0x9e => self.sbc_a_iydi(),
+            0xa6 => self.and_iydi(),
+            0xae => self.xor_iydi(),
+            0xb6 => self.or_iydi(),
+            0xbe => self.cp_iydi(),
+            0xcb => self.execute_fdcb(),
+            0xe1 => self.pop_iy(),
+            0xe3 => self.ex_spi_iy(),
+            0xe5 => self.push_iy(),
+            0xe9 => self.jp_iy(),
+            0xf9 => self.ld_sp_iy(),
+
+            op if op & 0b11_000_111 == 0b01_000_110 => self.ld_r_iyd(),
+            op if op & 0b11_111_000 == 0b01_110_000 => self.ld_iyd_r(),
+
+            // IXH, IXL in place of H, L
+            op => self.iy = self.execute_index_halves(op, self.iy),
+        }
+    }
+
+    // DD CB d op: the opcode follows the displacement. The undocumented
+    // opcodes naming a register other than (HL) also copy the result to it.
+    fn execute_ddcb(&mut self) {
+        let opcode = self.memory_at_pc(3);
+        let addr = self.ix_addr(self.memory_at_pc(2));
+
+        match opcode >> 3 {
+            0 => self.rlc_ixdi(),
+            1 => self.rrc_ixdi(),
+            2 => self.rl_ixdi(),
+            3 => self.rr_ixdi(),
+            4 => self.sla_ixdi(),
+            5 => self.sra_ixdi(),
+            6 => self.sll_ixdi(),
+            7 => self.srl_ixdi(),
+            8..=15 => self.bit_b_ixdi(),
+            16..=23 => self.res_b_ixdi(),
+            _ => self.set_b_ixdi(),
+        }
+
+        if opcode & 0b111 != 0b110 && opcode >> 6 != 1 {
+            let value = self.memory[addr];
+            self.write(Cpu::select_src(opcode), value);
+        }
+    }
+
+    // FD CB d op: the opcode follows the displacement. The undocumented
+    // opcodes naming a register other than (HL) also copy the result to it.
+    fn execute_fdcb(&mut self) {
+        let opcode = self.memory_at_pc(3);
+        let addr = self.iy_addr(self.memory_at_pc(2));
+
+        match opcode >> 3 {
+            0 => self.rlc_iydi(),
+            1 => self.rrc_iydi(),
+            2 => self.rl_iydi(),
+            3 => self.rr_iydi(),
+            4 => self.sla_iydi(),
+            5 => self.sra_iydi(),
+            6 => self.sll_iydi(),
+            7 => self.srl_iydi(),
+            8..=15 => self.bit_b_iydi(),
+            16..=23 => self.res_b_iydi(),
+            _ => self.set_b_iydi(),
+        }
+
+        if opcode & 0b111 != 0b110 && opcode >> 6 != 1 {
+            let value = self.memory[addr];
+            self.write(Cpu::select_src(opcode), value);
+        }
+    }
+
+    /// Execute the unprefixed opcode after an index prefix with the
+    /// index register in place of HL, returning its new value.
+    fn execute_index_halves(&mut self, opcode: u8, index: u16) -> u16 {
+        let hl = self.read_hl();
+        self.write_hl(index);
+        self.pc = self.pc.wrapping_add(1);
+        self.execute_main(opcode);
+        let index = self.read_hl();
+        self.write_hl(hl);
+        index
+    }
+}
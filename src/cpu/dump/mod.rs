@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::{Cpu, RegisterPromote};
+use cpu::{C_MASK, H_MASK, N_MASK, PV_MASK, S_MASK, Z_MASK};
+
+/// Flags as 6 characters in the order S Z H P/V N C:
+/// the flag letter when set, '-' when reset.
+pub fn flags_text(f: u8) -> String {
+    [
+        (S_MASK, 'S'),
+        (Z_MASK, 'Z'),
+        (H_MASK, 'H'),
+        (PV_MASK, 'P'),
+        (N_MASK, 'N'),
+        (C_MASK, 'C'),
+    ]
+    .iter()
+    .map(|&(mask, name)| if f & mask != 0 { name } else { '-' })
+    .collect()
+}
+
+#[allow(dead_code)]
+impl Cpu {
+    /// Registers, flags and interrupt status, as in:
+    ///
+    /// ```text
+    /// PC=0100 SP=fffe AF=0044 BC=0000 DE=0000 HL=0000 IX=0000 IY=0000
+    /// I=00 R=00 AF'=0000 BC'=0000 DE'=0000 HL'=0000 IFF1=0 IFF2=0
+    /// flags=-Z-P-- cycles=0
+    /// ```
+    pub fn dump_registers(&self) -> String {
+        format!(
+            "PC={:04x} SP={:04x} AF={:04x} BC={:04x} DE={:04x} HL={:04x} IX={:04x} IY={:04x}\n\
+             I={:02x} R={:02x} AF'={:04x} BC'={:04x} DE'={:04x} HL'={:04x} IFF1={} IFF2={}\n\
+             flags={} cycles={}{}\n",
+            self.pc,
+            self.sp,
+            (self.a, self.f).promote(),
+            (self.b, self.c).promote(),
+            (self.d, self.e).promote(),
+            (self.h, self.l).promote(),
+            self.ix,
+            self.iy,
+            self.i,
+            self.r,
+            (self.a1, self.f1).promote(),
+            (self.b1, self.c1).promote(),
+            (self.d1, self.e1).promote(),
+            (self.h1, self.l1).promote(),
+            self.iff1 as u8,
+            self.iff2 as u8,
+            flags_text(self.f),
+            self.cycles,
+            if self.halted { " halted" } else { "" },
+        )
+    }
+}
+
+/// Hex and ASCII dump of memory from start to end included,
+/// 16 bytes per row.
+pub fn dump_memory(memory: &[u8], start: u16, end: u16) -> String {
+    let mut text = String::new();
+    let mut address = usize::from(start);
+    let end = usize::from(end).min(memory.len().saturating_sub(1));
+
+    while address <= end && address < memory.len() {
+        let row = &memory[address..=end.min(address + 15)];
+
+        let hex: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = row
+            .iter()
+            .map(|&byte| {
+                if (0x20..0x7f).contains(&byte) {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        text.push_str(&format!("{:04x}  {:<47}  {}\n", address, hex.join(" "), ascii));
+        address += 16;
+    }
+
+    text
+}
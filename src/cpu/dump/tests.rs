@@ -0,0 +1,44 @@
+use cpu::{dump_memory, flags_text, CpuBuilder};
+
+#[test]
+fn registers() {
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0; 4])
+        .with_pc(0x100)
+        .with_a(0x12)
+        .with_flag_z(true)
+        .with_hl(0xbeef)
+        .build();
+    cpu.halted = true;
+    cpu.iff1 = true;
+
+    assert_eq!(
+        cpu.dump_registers(),
+        "PC=0100 SP=0000 AF=1240 BC=0000 DE=0000 HL=beef IX=0000 IY=0000\n\
+         I=00 R=00 AF'=0000 BC'=0000 DE'=0000 HL'=0000 IFF1=1 IFF2=0\n\
+         flags=-Z---- cycles=0 halted\n"
+    );
+}
+
+#[test]
+fn flags() {
+    assert_eq!(flags_text(0xff), "SZHPNC");
+    assert_eq!(flags_text(0x00), "------");
+    assert_eq!(flags_text(0x81), "S----C");
+}
+
+#[test]
+fn memory() {
+    let memory: Vec<u8> = (0x30..0x58).collect();
+
+    assert_eq!(
+        dump_memory(&memory, 0x0e, 0x12),
+        format!("000e  3e 3f 40 41 42{}  >?@AB\n", " ".repeat(33))
+    );
+    assert_eq!(
+        dump_memory(&memory, 0x20, 0xffff),
+        format!("0020  50 51 52 53 54 55 56 57{}  PQRSTUVW\n", " ".repeat(24))
+    );
+    assert_eq!(dump_memory(&[0x00, 0x41], 0, 1).lines().count(), 1);
+    assert_eq!(dump_memory(&memory, 0, 0x27).lines().count(), 3);
+}
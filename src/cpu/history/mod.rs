@@ -0,0 +1,283 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::{Access, Cpu, Frame, StopReason};
+use std::collections::VecDeque;
+
+// === Execution history ===
+//
+// An undo log: before each instruction `step` saves the registers, and
+// `write_byte` saves the bytes it overwrites, so that the instruction
+// can be undone. The bus accesses of the instruction are kept too, for
+// watchpoints to stop a reverse run as they stop a forward one.
+//
+// Call stack frames are saved before an instruction changes them, and
+// restored with the registers.
+//
+// I/O is not undone: what devices did with OUT stays done, and IN
+// reads them again when the instruction is executed again.
+
+/// What an instruction changed.
+#[derive(Debug, Clone)]
+struct Entry {
+    pc: u16,
+    sp: u16,
+    ix: u16,
+    iy: u16,
+    i: u8,
+    r: u8,
+    main: [u8; 8],
+    alternate: [u8; 8],
+    iff1: bool,
+    iff2: bool,
+    halted: bool,
+    im: u8,
+    wz: u16,
+    cycles: u64,
+    /// Overwritten memory: address and previous value, in write order.
+    writes: Vec<(u16, u8)>,
+    accesses: Vec<Access>,
+    /// Frames of the call stack before the instruction changed them.
+    frames: Option<Vec<Frame>>,
+}
+
+/// The last instructions executed, up to a fixed number.
+#[derive(Debug, Clone)]
+pub struct History {
+    capacity: usize,
+    entries: VecDeque<Entry>,
+    /// Instructions executed since the history was created, less
+    /// those undone.
+    count: u64,
+}
+
+impl History {
+    /// A history of the last capacity instructions.
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            entries: VecDeque::new(),
+            count: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Forget the oldest instructions beyond the capacity.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Forget the instructions executed, which cannot be undone any more
+    /// once memory changed under them. The count goes on.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Instructions that can be undone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Instructions executed since the history was created, less those
+    /// undone: the count `rewind` goes back to.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The oldest count that can be rewound to.
+    pub fn oldest(&self) -> u64 {
+        self.count - self.entries.len() as u64
+    }
+
+    fn push(&mut self, cpu: &Cpu) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(Entry {
+            pc: cpu.pc,
+            sp: cpu.sp,
+            ix: cpu.ix,
+            iy: cpu.iy,
+            i: cpu.i,
+            r: cpu.r,
+            main: [cpu.a, cpu.f, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l],
+            alternate: [
+                cpu.a1, cpu.f1, cpu.b1, cpu.c1, cpu.d1, cpu.e1, cpu.h1, cpu.l1,
+            ],
+            iff1: cpu.iff1,
+            iff2: cpu.iff2,
+            halted: cpu.halted,
+            im: cpu.im,
+            wz: cpu.wz,
+            cycles: cpu.cycles,
+            writes: Vec::new(),
+            accesses: Vec::new(),
+            frames: None,
+        });
+    }
+}
+
+/// The last 100,000 instructions.
+impl Default for History {
+    fn default() -> History {
+        History::new(100_000)
+    }
+}
+
+impl Cpu {
+    /// Start an entry for the instruction about to be executed.
+    pub(crate) fn history_begin(&mut self) {
+        if let Some(mut history) = self.history.take() {
+            history.count += 1;
+            history.push(self);
+            self.history = Some(history);
+        }
+    }
+
+    /// Save the memory byte about to be overwritten at address.
+    pub(crate) fn history_write(&mut self, address: u16) {
+        let previous = self.memory[usize::from(address)];
+        if let Some(entry) = self.history.as_mut().and_then(|h| h.entries.back_mut()) {
+            entry.writes.push((address, previous));
+        }
+    }
+
+    /// Save the call stack frames about to be changed.
+    pub(crate) fn history_frames(&mut self) {
+        let frames = match self.call_stack {
+            Some(ref stack) => stack.frames(),
+            None => return,
+        };
+        if let Some(entry) = self.history.as_mut().and_then(|h| h.entries.back_mut()) {
+            if entry.frames.is_none() {
+                entry.frames = Some(frames.to_vec());
+            }
+        }
+    }
+
+    /// Complete the entry with the accesses of the instruction.
+    pub(crate) fn history_end(&mut self) {
+        if let Some(entry) = self.history.as_mut().and_then(|h| h.entries.back_mut()) {
+            entry.accesses = self.accesses.clone();
+        }
+    }
+
+    /// Undo the last instruction executed. Returns false, doing nothing,
+    /// without history or when it is empty.
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.history.as_mut().and_then(|h| h.entries.pop_back()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if let Some(ref mut history) = self.history {
+            history.count -= 1;
+        }
+
+        for &(address, value) in entry.writes.iter().rev() {
+            self.memory[usize::from(address)] = value;
+        }
+        if let (Some(stack), Some(frames)) = (self.call_stack.as_mut(), entry.frames) {
+            stack.restore(frames);
+        }
+
+        self.pc = entry.pc;
+        self.sp = entry.sp;
+        self.ix = entry.ix;
+        self.iy = entry.iy;
+        self.i = entry.i;
+        self.r = entry.r;
+        let [a, f, b, c, d, e, h, l] = entry.main;
+        self.a = a;
+        self.f = f;
+        self.b = b;
+        self.c = c;
+        self.d = d;
+        self.e = e;
+        self.h = h;
+        self.l = l;
+        let [a, f, b, c, d, e, h, l] = entry.alternate;
+        self.a1 = a;
+        self.f1 = f;
+        self.b1 = b;
+        self.c1 = c;
+        self.d1 = d;
+        self.e1 = e;
+        self.h1 = h;
+        self.l1 = l;
+        self.iff1 = entry.iff1;
+        self.iff2 = entry.iff2;
+        self.halted = entry.halted;
+        self.im = entry.im;
+        self.wz = entry.wz;
+        self.cycles = entry.cycles;
+        self.accesses.clear();
+
+        true
+    }
+
+    /// Undo instructions until one made an access hitting a watchpoint,
+    /// pc reaches a breakpoint, or at most limit instructions have been
+    /// undone. The CPU is left before the instruction that stopped the
+    /// run. Returns the reason, None when the history ran out, and the
+    /// number of instructions undone.
+    pub fn run_back(&mut self, limit: Option<u64>) -> (Option<StopReason>, u64) {
+        let mut undone = 0;
+
+        loop {
+            if limit.is_some_and(|max| undone >= max) {
+                return (Some(StopReason::InstructionLimit), undone);
+            }
+
+            // Watchpoints see the accesses as they were made.
+            self.accesses = match self.history.as_ref().and_then(|h| h.entries.back()) {
+                Some(entry) => entry.accesses.clone(),
+                None => return (None, undone),
+            };
+            let watched = self.breakpoints.access_hit(self);
+
+            self.step_back();
+            undone += 1;
+
+            if let Some((id, access)) = watched {
+                return (Some(StopReason::Watchpoint(id, access)), undone);
+            }
+            if let Some(id) = self.breakpoints.execution_hit(self) {
+                return (Some(StopReason::Breakpoint(id)), undone);
+            }
+        }
+    }
+
+    /// Undo instructions back to the given count of instructions executed.
+    pub fn rewind(&mut self, count: u64) -> Result<(), String> {
+        let (oldest, current) = match self.history {
+            Some(ref history) => (history.oldest(), history.count()),
+            None => return Err("No execution history".to_string()),
+        };
+
+        if count < oldest || count > current {
+            return Err(format!(
+                "Instruction {} out of the history, from {} to {}",
+                count, oldest, current
+            ));
+        }
+
+        for _ in count..current {
+            self.step_back();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use cpu::{CpuBuilder, History, Kind, Limits, StopReason};
+
+fn cpu(code: Vec<u8>) -> ::cpu::Cpu {
+    let mut memory = code;
+    memory.resize(0x100, 0);
+    let mut cpu = CpuBuilder::new().with_memory(memory).with_sp(0xf0).build();
+    cpu.history = Some(History::new(100));
+    cpu
+}
+
+fn program() -> Vec<u8> {
+    asm!(
+        "      LD HL,0x80",
+        "      LD B,3",
+        "loop: INC (HL)",
+        "      EXX",
+        "      PUSH BC",
+        "      EXX",
+        "      DJNZ loop",
+        "      HALT"
+    )
+}
+
+#[test]
+fn step_back() {
+    let mut cpu = cpu(program());
+    let initial = cpu.state();
+
+    cpu.run(Limits::default());
+    let after = cpu.state();
+    assert!(cpu.halted);
+    assert_eq!(cpu.memory[0x80], 3);
+    assert_eq!(cpu.sp, 0xea);
+    assert_eq!(cpu.history.as_ref().unwrap().count(), 18);
+
+    cpu.step_back();
+    assert_eq!(cpu.pc, 0x0b);
+    assert!(!cpu.halted);
+    cpu.step();
+    assert_eq!(cpu.state(), after);
+
+    while cpu.step_back() {}
+    assert_eq!(cpu.state(), initial);
+    assert_eq!(cpu.cycles, 0);
+    assert_eq!(cpu.history.as_ref().unwrap().count(), 0);
+    assert!(!cpu.step_back());
+
+    cpu.run(Limits::default());
+    assert_eq!(cpu.state(), after);
+}
+
+#[test]
+fn window() {
+    let mut cpu = cpu(program());
+    cpu.history.as_mut().unwrap().set_capacity(4);
+    cpu.run(Limits::default());
+
+    let history = cpu.history.as_ref().unwrap();
+    assert_eq!(
+        (history.len(), history.oldest(), history.count()),
+        (4, 14, 18)
+    );
+
+    assert!(cpu.rewind(13).is_err());
+    assert!(cpu.rewind(19).is_err());
+    cpu.rewind(14).unwrap();
+    assert_eq!(cpu.pc, 0x07);
+    assert_eq!(cpu.memory[0x80], 3);
+    assert!(!cpu.step_back());
+
+    let mut plain = CpuBuilder::new().with_memory(vec![0; 4]).build();
+    plain.step();
+    assert!(!plain.step_back());
+    assert!(plain.rewind(0).is_err());
+}
+
+#[test]
+fn run_back() {
+    let mut cpu = cpu(program());
+    cpu.run(Limits::default());
+
+    let inc = cpu.breakpoints.add(Kind::Execute(0x05), None).unwrap();
+    assert_eq!(cpu.run_back(None), (Some(StopReason::Breakpoint(inc)), 6));
+    assert_eq!((cpu.pc, cpu.memory[0x80], cpu.b), (0x05, 2, 1));
+    assert_eq!(
+        cpu.run_back(Some(2)),
+        (Some(StopReason::InstructionLimit), 2)
+    );
+
+    cpu.breakpoints.clear();
+    let watch = cpu
+        .breakpoints
+        .add(
+            Kind::Memory {
+                start: 0xec,
+                end: 0xed,
+                read: false,
+                write: true,
+            },
+            None,
+        )
+        .unwrap();
+    match cpu.run_back(None) {
+        (Some(StopReason::Watchpoint(id, access)), 1) => {
+            assert_eq!(id, watch);
+            assert_eq!(access.address, 0xed);
+        }
+        other => panic!("{:?}", other),
+    }
+    // Before PUSH BC of the second iteration.
+    assert_eq!((cpu.pc, cpu.sp), (0x07, 0xee));
+
+    cpu.breakpoints.clear();
+    assert_eq!(cpu.run_back(None), (None, 9));
+    assert_eq!(cpu.pc, 0);
+}
+
+#[test]
+fn step_back_restores_im() {
+    let mut cpu = cpu(asm!("IM 2"));
+
+    cpu.step();
+    assert_eq!(cpu.im, 2);
+    cpu.step_back();
+    assert_eq!(cpu.im, 0);
+}
@@ -24,10 +24,9 @@ impl Cpu {
         // Z is set if result is 0 (result=0).
         self.set_z_from_byte(result);
 
-        // P/V is set if overflow (overflow in twos complement).
-        // TODO: Verify that implementation complies with Z80 arch.
-        //let (_, overflow) = Cpu::compl2(result).overflowing_add(Cpu::compl2(value));
-        //self.set_pv(overflow);
+        // P/V is set if overflow (overflow in twos complement): the
+        // operands have the same sign, and the result another.
+        self.set_pv((self.a ^ result) & (value ^ result) & 0x80 != 0);
 
         // N is reset (0).
         self.set_n(false);
@@ -57,33 +56,33 @@ impl Cpu {
         let opcode = self.memory_at_pc(0);
         let operand = self.read(Self::select(opcode & 0b111));
         self._add_to_accumulator(operand, 0);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn add_a_n(&mut self) {
         let operand = self.memory_at_pc(1);
         self._add_to_accumulator(operand, 0);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn add_a_hli(&mut self) {
         let operand = self.memory_at_hl();
         self._add_to_accumulator(operand, 0);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn add_a_ixdi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_ix(offset);
         self._add_to_accumulator(operand, 0);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
     pub fn add_a_iydi(&mut self) {
-        let offset = self.memory_at_pc(1);
+        let offset = self.memory_at_pc(2);
         let operand = self.memory_at_iy(offset);
         self._add_to_accumulator(operand, 0);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
     pub fn adc_a_r(&mut self) {
@@ -91,36 +90,36 @@ impl Cpu {
         let operand = self.read(Self::select(opcode & 0b111));
         let c_value = self.get_c_value();
         self._add_to_accumulator(operand, c_value);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn adc_a_n(&mut self) {
         let operand = self.memory_at_pc(1);
         let c_value = self.get_c_value();
         self._add_to_accumulator(operand, c_value);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
-    fn adc_a_hli(&mut self) {
+    pub fn adc_a_hli(&mut self) {
         let operand = self.memory_at_hl();
         let c_value = self.get_c_value();
         self._add_to_accumulator(operand, c_value);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
-    fn adc_a_ixdi(&mut self) {
+    pub fn adc_a_ixdi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_ix(offset);
         let c_value = self.get_c_value();
         self._add_to_accumulator(operand, c_value);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
-    fn adc_a_iydi(&mut self) {
+    pub fn adc_a_iydi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_iy(offset);
         let c_value = self.get_c_value();
         self._add_to_accumulator(operand, c_value);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 }
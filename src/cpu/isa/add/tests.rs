@@ -171,3 +171,17 @@ fn adc_a_iydi() {
         .zero_flag_is_reset()
         .program_counter_is(3);
 }
+
+isa_tests! {
+    add_a_r_half_carry: "ADD A,B" { a: 0x0f, b: 0x01 } => { a: 0x10, flags: "--H---" };
+    add_a_r_zero: "ADD A,C" { a: 0xff, c: 0x01 } => { a: 0x00, flags: "-ZH--C" };
+    add_a_n_decoded: "ADD A,0x22" { a: 0x11 } => { a: 0x33 };
+    add_a_hli_decoded: "ADD A,(HL)" { a: 0x01, hl: 0x100, (0x100): 0x02 } => { a: 0x03 };
+    add_a_ixdi_decoded: "ADD A,(IX+2)" { a: 0x01, ix: 0x100, (0x102): 0x02 } => { a: 0x03 };
+    adc_a_r_decoded: "ADC A,D" { a: 0x01, d: 0x01, flags: "-----C" } => { a: 0x03, flags: "------" };
+    add_a_iydi_negative: "ADD A,(IY-3)" { a: 0x01, iy: 0x1010, (0x100d): 0x02 } => { a: 0x03 };
+    add_a_r_overflow: "ADD A,B" { a: 0x7f, b: 1 } => { a: 0x80, flags: "S-H-V-" };
+    adc_a_n_overflow: "ADC A,0x80" { a: 0x80, flags: "C" } => { a: 0x01, flags: "VC" };
+    add_a_ixl: "ADD A,IXL" { a: 0x01, ix: 0x1202 } => { a: 0x03 };
+    add_a_iyh: "ADD A,IYH" { a: 0x01, iy: 0x0212 } => { a: 0x03 };
+}
@@ -24,32 +24,32 @@ impl Cpu {
         let opcode = self.memory_at_pc(0);
         let operand = self.read(Self::select(opcode & 0b111));
         self._and_with_accumulator(operand);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn and_n(&mut self) {
         let operand = self.memory_at_pc(1);
         self._and_with_accumulator(operand);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn and_hli(&mut self) {
         let operand = self.memory_at_hl();
         self._and_with_accumulator(operand);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn and_ixdi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_ix(offset);
         self._and_with_accumulator(operand);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
     pub fn and_iydi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_iy(offset);
         self._and_with_accumulator(operand);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 }
@@ -40,6 +40,7 @@ impl Cpu {
     pub fn add_hl_ss(&mut self) {
         let opcode = self.memory_at_pc(0);
         let operand = self.read_ss(opcode);
+        self.wz = self.read_hl().wrapping_add(1);
         let (_result, carry) = (&mut self.h, &mut self.l).reg_add(operand);
 
         // TODO: H is set if carry from bit 11
@@ -50,28 +51,36 @@ impl Cpu {
     }
 
     pub fn adc_hl_ss(&mut self) {
-        let operand = self.read_ss(self.memory_at_pc(1)) + self.carry_to_u16();
-        let (result, carry) = (&mut self.h, &mut self.l).reg_add(operand);
+        let hl = self.read_hl();
+        let operand = self.read_ss(self.memory_at_pc(1));
+        self.wz = hl.wrapping_add(1);
+        let sum = u32::from(hl) + u32::from(operand) + u32::from(self.carry_to_u16());
+        let result = sum as u16;
+        self.write_hl(result);
 
         self.set_s_from_msbw(result);
         self.set_z_from_word(result);
-        // TODO: H is set if carry from bit 11
-        self.set_c(carry);
-        //self.set_pv(overflow);
+        self.set_h((hl ^ operand ^ result) & 0x1000 != 0);
+        self.set_c(sum > 0xffff);
+        self.set_pv((hl ^ result) & (operand ^ result) & 0x8000 != 0);
         self.set_n(false);
 
         self.pc.reg_add(2);
     }
 
     pub fn sbc_hl_ss(&mut self) {
-        let operand = self.read_ss(self.memory_at_pc(1)) + self.carry_to_u16();
-        let (result, carry) = (&mut self.h, &mut self.l).reg_sub(operand);
+        let hl = self.read_hl();
+        let operand = self.read_ss(self.memory_at_pc(1));
+        self.wz = hl.wrapping_add(1);
+        let subtrahend = u32::from(operand) + u32::from(self.carry_to_u16());
+        let result = (u32::from(hl).wrapping_sub(subtrahend)) as u16;
+        self.write_hl(result);
 
         self.set_s_from_msbw(result);
         self.set_z_from_word(result);
-        // TODO: H is set if carry from bit 11
-        self.set_c(carry);
-        //self.set_pv(overflow);
+        self.set_h((hl ^ operand ^ result) & 0x1000 != 0);
+        self.set_c(u32::from(hl) < subtrahend);
+        self.set_pv((hl ^ operand) & (hl ^ result) & 0x8000 != 0);
         self.set_n(true);
 
         self.pc.reg_add(2);
@@ -86,6 +95,7 @@ impl Cpu {
             _ => panic!(),
         };
 
+        self.wz = self.ix.wrapping_add(1);
         let (_result, carry) = self.ix.reg_add(operand);
 
         // TODO: H is set if carry from bit 11
@@ -104,6 +114,7 @@ impl Cpu {
             _ => panic!(),
         };
 
+        self.wz = self.iy.wrapping_add(1);
         let (_result, carry) = self.iy.reg_add(operand);
 
         // TODO: H is set if carry from bit 11
@@ -174,3 +174,11 @@ fn dec_iy() {
         .parity_overflow_flag_is_reset()
         .program_counter_is(2);
 }
+
+isa_tests! {
+    adc_hl_ss_carry: "ADC HL,DE" { hl: 0xffff, de: 1, flags: "C" } => { hl: 0x0001, flags: "--H--C" };
+    adc_hl_ss_overflow: "ADC HL,BC" { hl: 0x7fff, bc: 1 } => { hl: 0x8000, flags: "S-HV--" };
+    sbc_hl_ss_borrow: "SBC HL,DE" { hl: 0, de: 0, flags: "C" } => { hl: 0xffff, flags: "S-H-NC" };
+    sbc_hl_ss_overflow: "SBC HL,BC" { hl: 0x8000, bc: 1 } => { hl: 0x7fff, flags: "--HVN-" };
+    add_hl_ss_memptr: "ADD HL,BC" { hl: 0x1000, bc: 0x0001 } => { hl: 0x1001, wz: 0x1001 };
+}
@@ -9,7 +9,34 @@ use cpu::RegisterOperations;
 #[allow(dead_code)]
 impl Cpu {
     pub fn daa(&mut self) {
-        unimplemented!();
+        let a = self.a;
+        let subtract = self.get_n();
+        let mut carry = self.get_c();
+
+        // Add or subtract 6 to the digits above 9, or that had a half carry
+        let mut correction = 0;
+        if self.get_h() || a & 0x0f > 9 {
+            correction |= 0x06;
+        }
+        if carry || a > 0x99 {
+            correction |= 0x60;
+            carry = true;
+        }
+
+        let result = if subtract {
+            self.set_h(self.get_h() && a & 0x0f < 6);
+            a.wrapping_sub(correction)
+        } else {
+            self.set_h(a & 0x0f > 9);
+            a.wrapping_add(correction)
+        };
+
+        self.set_s_from_msb(result);
+        self.set_z_from_byte(result);
+        self.set_pv(result.count_ones() & 1 == 0);
+        self.set_c(carry);
+        self.a = result;
+        self.pc.reg_add(1);
     }
 
     pub fn cpl(&mut self) {
@@ -50,6 +77,6 @@ impl Cpu {
         self.set_c(true);
         self.set_h(false);
         self.set_n(false);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 }
@@ -5,7 +5,17 @@ use cpu::Assertor;
 
 #[test]
 fn daa() {
-    unimplemented!();
+    let mut cpu = CpuBuilder::new().with_memory_size(10).with_a(0x3c).build();
+
+    // 0x15 + 0x27
+    cpu.daa();
+
+    Assertor::new(cpu)
+        .register_a_is(0x42)
+        .half_carry_flag_is_set()
+        .carry_flag_is_reset()
+        .add_subtract_flag_is_reset()
+        .program_counter_is(1);
 }
 
 #[test]
@@ -75,3 +85,10 @@ fn scf() {
         .add_subtract_flag_is_reset()
         .program_counter_is(1);
 }
+
+isa_tests! {
+    neg_mirror: "DB 0xed,0x4c" { a: 0x10 } => { a: 0xf0, flags: "S---NC" };
+    daa_after_sub: "DAA" { a: 0x2d, flags: "--H-N-" } => { a: 0x27, flags: "---PN-" };
+    daa_carry: "DAA" { a: 0x9a } => { a: 0x00, flags: "-ZHP-C" };
+    daa_keeps_carry: "DAA" { a: 0x15, flags: "-----C" } => { a: 0x75, flags: "-----C" };
+}
@@ -48,30 +48,30 @@ impl Cpu {
         let bitmask = Self::operand_b(opcode);
         let data = self.value_r(opcode);
         self.is_zero(bitmask, data);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn bit_b_hli(&mut self) {
         let bitmask = Self::operand_b(self.memory_at_pc(1));
-        let data = self.memory[self.read_hl() as usize];
+        let data = self.read_byte(self.read_hl());
         self.is_zero(bitmask, data);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn bit_b_ixdi(&mut self) {
         let bitmask = Self::operand_b(self.memory_at_pc(3));
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
-        let data = self.memory[addr];
+        let addr = self.ix_addr(self.memory_at_pc(2));
+        let data = self.read_byte(addr as u16);
         self.is_zero(bitmask, data);
-        self.pc += 4;
+        self.pc = self.pc.wrapping_add(4);
     }
 
     pub fn bit_b_iydi(&mut self) {
         let bitmask = Self::operand_b(self.memory_at_pc(3));
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
-        let data = self.memory[addr];
+        let addr = self.iy_addr(self.memory_at_pc(2));
+        let data = self.read_byte(addr as u16);
         self.is_zero(bitmask, data);
-        self.pc += 4;
+        self.pc = self.pc.wrapping_add(4);
     }
 
     pub fn set_b_r(&mut self) {
@@ -89,29 +89,32 @@ impl Cpu {
             _ => panic!(),
         }
         
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn set_b_hli(&mut self) {
         let opcode = self.memory_at_pc(1);
         let bitmask = Self::operand_b(opcode);
         let addr = self.read_hl() as usize;
-        self.memory[addr] |= bitmask;
-        self.pc += 2;
+        let value = self.read_byte(addr as u16) | bitmask;
+        self.write_byte(addr as u16, value);
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn set_b_ixdi(&mut self) {
         let bitmask = Self::operand_b(self.memory_at_pc(3));
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
-        self.memory[addr] |= bitmask;
-        self.pc += 4;
+        let addr = self.ix_addr(self.memory_at_pc(2));
+        let value = self.read_byte(addr as u16) | bitmask;
+        self.write_byte(addr as u16, value);
+        self.pc = self.pc.wrapping_add(4);
     }
 
     pub fn set_b_iydi(&mut self) {
         let bitmask = Self::operand_b(self.memory_at_pc(3));
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
-        self.memory[addr] |= bitmask;
-        self.pc += 4;
+        let addr = self.iy_addr(self.memory_at_pc(2));
+        let value = self.read_byte(addr as u16) | bitmask;
+        self.write_byte(addr as u16, value);
+        self.pc = self.pc.wrapping_add(4);
     }
 
     pub fn res_b_r(&mut self) {
@@ -129,28 +132,31 @@ impl Cpu {
             _ => panic!(),
         }
         
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn res_b_hli(&mut self) {
         let opcode = self.memory_at_pc(1);
         let bitmask = !Self::operand_b(opcode);
         let addr = self.read_hl() as usize;
-        self.memory[addr] &= bitmask;
-        self.pc += 2;
+        let value = self.read_byte(addr as u16) & bitmask;
+        self.write_byte(addr as u16, value);
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn res_b_ixdi(&mut self) {
         let bitmask = !Self::operand_b(self.memory_at_pc(3));
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
-        self.memory[addr] &= bitmask;
-        self.pc += 4;
+        let addr = self.ix_addr(self.memory_at_pc(2));
+        let value = self.read_byte(addr as u16) & bitmask;
+        self.write_byte(addr as u16, value);
+        self.pc = self.pc.wrapping_add(4);
     }
 
     pub fn res_b_iydi(&mut self) {
         let bitmask = !Self::operand_b(self.memory_at_pc(3));
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
-        self.memory[addr] &= bitmask;
-        self.pc += 4;
+        let addr = self.iy_addr(self.memory_at_pc(2));
+        let value = self.read_byte(addr as u16) & bitmask;
+        self.write_byte(addr as u16, value);
+        self.pc = self.pc.wrapping_add(4);
     }
 }
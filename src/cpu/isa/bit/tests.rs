@@ -253,3 +253,9 @@ pub fn res_b_iydi() {
         .memory_at_address_is(7, 0b1011_1111)
         .program_counter_is(4);
 }
+
+isa_tests! {
+    bit_b_ixdi_negative: "BIT 7,(IX-1)" { ix: 0x1010, (0x100f): 0x80 } => { flags: "--H---" };
+    set_b_iydi_negative: "SET 0,(IY-2)" { iy: 0x1010 } => { (0x100e): 0x01 };
+    res_b_ixdi_negative: "RES 7,(IX-128)" { ix: 0x1080, (0x1000): 0xff } => { (0x1000): 0x7f };
+}
@@ -1,4 +1,5 @@
 use cpu::Cpu;
+use cpu::FrameKind;
 use cpu::RegisterDemote;
 use cpu::RegisterPromote;
 use cpu::RegisterOperations;
@@ -11,24 +12,27 @@ mod tests;
 // === Call and Return Group ===
 
 impl Cpu {
-    fn _push_pc(&mut self) {
+    pub(crate) fn _push_pc(&mut self) {
         // (SP – 1) ← PCH
-        self.sp -= 1;
-        self.memory[self.sp as usize] = self.pc.high();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(self.sp, self.pc.high());
         
         // (SP – 2) ← PCL
-        self.sp -= 1;
-        self.memory[self.sp as usize] = self.pc.low();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(self.sp, self.pc.low());
     }
 
     fn _call(&mut self) {
         let addr = (self.memory_at_pc(2), self.memory_at_pc(1)).promote();
+        let caller = self.pc;
 
         self.pc.reg_add(3);
         self._push_pc();
+        self.call_stack_enter(FrameKind::Call, caller, addr);
         
         // PC ← nn
         self.pc = addr;
+        self.wz = addr;
     }
 
     pub fn call_nn(&mut self) {
@@ -39,20 +43,25 @@ impl Cpu {
         if self.condition_at_pc(0) {
             self._call();
         } else {
+            self.wz = (self.memory_at_pc(2), self.memory_at_pc(1)).promote();
             self.pc.reg_add(3);
         }
     }
 
-    fn _pop_pc(&mut self) {
+    pub(crate) fn _pop_pc(&mut self) {
+        let (pc, sp) = (self.pc, self.sp);
+
         // PCL ← (SP)
-        let l = self.memory[self.sp as usize];
-        self.sp += 1;
+        let l = self.read_byte(self.sp);
+        self.sp = self.sp.wrapping_add(1);
 
         // PCH ← (SP+1)
-        let h = self.memory[self.sp as usize];
-        self.sp += 1;
+        let h = self.read_byte(self.sp);
+        self.sp = self.sp.wrapping_add(1);
 
         self.pc = (h, l).promote();
+        self.wz = self.pc;
+        self.call_stack_return(pc, sp);
     }
 
     pub fn ret(&mut self) {
@@ -68,7 +77,7 @@ impl Cpu {
     }
 
     pub fn reti(&mut self) {
-        unimplemented!();
+        self.ret();
     }
 
     pub fn retn(&mut self) {
@@ -78,10 +87,12 @@ impl Cpu {
 
     pub fn rst_p(&mut self) {
         let cc = (self.memory_at_pc(0) & 0b00_111_000) >> 3;
+        let caller = self.pc;
 
-        self.ret();
+        self.pc.reg_add(1);
+        self._push_pc();
 
-        self.pc = match cc {
+        let addr = match cc {
             0b000 => 0x00,
             0b001 => 0x08,
             0b010 => 0x10,
@@ -89,8 +100,13 @@ impl Cpu {
             0b100 => 0x20,
             0b101 => 0x28,
             0b110 => 0x30,
-            0b111 => 0x30,
+            0b111 => 0x38,
             _ => panic!(),
-        }
+        };
+        self.call_stack_enter(FrameKind::Rst, caller, addr);
+
+        // PC ← p
+        self.pc = addr;
+        self.wz = addr;
     }
 }
@@ -59,7 +59,14 @@ fn ret_cc() {
 
 #[test]
 fn reti() {
-    unimplemented!();
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0xed, 0x4d, 0x00, 0x00, 0xaa, 0xbb, 0x04, 0x00])
+        .with_sp(6)
+        .build();
+
+    cpu.reti();
+
+    Assertor::new(cpu).stack_pointer_is(8).program_counter_is(4);
 }
 
 #[test]
@@ -84,9 +91,25 @@ fn retn() {
 fn rst_p() {
     let mut cpu = CpuBuilder::new()
         .with_memory(vec![0b11_001_111, 0, 0, 0])
+        .with_sp(4)
         .build();
 
     cpu.rst_p();
 
-    Assertor::new(cpu).program_counter_is(8);
+    Assertor::new(cpu)
+        .memory_at_address_is(2, 0x01)
+        .memory_at_address_is(3, 0x00)
+        .stack_pointer_is(2)
+        .program_counter_is(8);
+}
+
+isa_tests! {
+    call_nn_decoded: "CALL 0x1234" { sp: 0x100 } => { pc: 0x1234, sp: 0xfe, (0xfe): 0x03 };
+    call_cc_nn_not_taken: "CALL Z,0x1234" { sp: 0x100 } => {};
+    ret_decoded: "RET" { sp: 0xfe, (0xfe): 0x34, (0xff): 0x12 } => { pc: 0x1234, sp: 0x100 };
+    rst_38_decoded: "RST 0x38" { sp: 0x100 } => { pc: 0x38, sp: 0xfe, (0xfe): 0x01 };
+    call_nn_wraps_sp: "CALL 0x1234" { sp: 0 } => { pc: 0x1234, sp: 0xfffe, (0xfffe): 0x03 };
+    ret_wraps_sp: "RET" { sp: 0xffff, (0xffff): 0x34 } => { pc: 0xc934, sp: 1 };
+    call_nn_memptr: "CALL 0x1234" { sp: 0x100 } => { pc: 0x1234, sp: 0xfe, (0xfe): 0x03, wz: 0x1234 };
+    ret_memptr: "RET" { sp: 0xfe, (0xfe): 0x34, (0xff): 0x12 } => { pc: 0x1234, sp: 0x100, wz: 0x1234 };
 }
@@ -0,0 +1,255 @@
+// === Declarative instruction tests ===
+//
+// Each line describes the instruction text, the initial CPU status
+// and the expected one:
+//
+//     isa_tests! {
+//         add_a_b: "ADD A,B" { a: 0x0f, b: 0x01 } => { a: 0x10, flags: "--H---" };
+//         ld_a_hl: "LD A,(HL)" { hl: 0x100, (0x100): 0x12 } => { a: 0x12 };
+//     }
+//
+// The instruction is assembled at pc (0 unless set up) and executed
+// through the decoder. Anything not listed in the expected status must
+// be unchanged, except pc which by default moves past the instruction, R
+// which counts its opcode fetches, and MEMPTR which is only checked when
+// listed.
+//
+// Settings are registers (a, f, bc, ix, a1, ..., pc, sp, i, r, wz), the
+// interrupt mode (im), interrupt flip flops (iff1, iff2), the halted state,
+// memory locations ((addr): value) and flags: a string of the letters of
+// the flags set, S Z H P or V for P/V, N and C, the others being reset.
+// '-' can hold the place of a reset flag, as in "S-H-V-".
+//
+// Opcodes without a mnemonic, as a lone prefix, are given as "DB" and
+// the list of their bytes: "DB 0xdd,0x00".
+
+use asm::{assemble_instruction, parse_number, AsmError};
+use cpu::decoder::fetches;
+use cpu::diff;
+use cpu::CpuBuilder;
+use cpu::CpuState;
+use cpu::RegisterDemote;
+use cpu::{C_MASK, H_MASK, N_MASK, PV_MASK, S_MASK, Z_MASK};
+
+/// Value of a register, flip flop or flags setting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(i64),
+    Bool(bool),
+    Text(&'static str),
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Value {
+        Value::Number(i64::from(value))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+impl From<&'static str> for Value {
+    fn from(value: &'static str) -> Value {
+        Value::Text(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Register(&'static str, Value),
+    Memory(usize, u8),
+}
+
+impl Setting {
+    pub fn register<T: Into<Value>>(name: &'static str, value: T) -> Setting {
+        Setting::Register(name, value.into())
+    }
+
+    pub fn memory(addr: i32, value: i32) -> Setting {
+        Setting::Memory(addr as usize, value as u8)
+    }
+
+    fn apply(&self, state: &mut CpuState) {
+        match *self {
+            Setting::Memory(addr, value) => state.memory[addr] = value,
+            Setting::Register("flags", Value::Text(flags)) => apply_flags(state, flags),
+            Setting::Register(name, Value::Bool(value)) => match name {
+                "iff1" => state.iff1 = value,
+                "iff2" => state.iff2 = value,
+                "halted" => state.halted = value,
+                _ => panic!("Unknown flip flop: {}", name),
+            },
+            Setting::Register(name, Value::Number(value)) => {
+                let byte = value as u8;
+                let word = value as u16;
+
+                match name {
+                    "a" => state.a = byte,
+                    "b" => state.b = byte,
+                    "c" => state.c = byte,
+                    "d" => state.d = byte,
+                    "e" => state.e = byte,
+                    "f" => state.f = byte,
+                    "h" => state.h = byte,
+                    "l" => state.l = byte,
+                    "a1" => state.a1 = byte,
+                    "b1" => state.b1 = byte,
+                    "c1" => state.c1 = byte,
+                    "d1" => state.d1 = byte,
+                    "e1" => state.e1 = byte,
+                    "f1" => state.f1 = byte,
+                    "h1" => state.h1 = byte,
+                    "l1" => state.l1 = byte,
+                    "i" => state.i = byte,
+                    "r" => state.r = byte,
+                    "im" => state.im = byte,
+                    "af" => {
+                        state.a = word.high();
+                        state.f = word.low();
+                    }
+                    "bc" => {
+                        state.b = word.high();
+                        state.c = word.low();
+                    }
+                    "de" => {
+                        state.d = word.high();
+                        state.e = word.low();
+                    }
+                    "hl" => {
+                        state.h = word.high();
+                        state.l = word.low();
+                    }
+                    "pc" => state.pc = word,
+                    "sp" => state.sp = word,
+                    "ix" => state.ix = word,
+                    "iy" => state.iy = word,
+                    "wz" => state.wz = word,
+                    _ => panic!("Unknown register: {}", name),
+                }
+            }
+            Setting::Register(name, ref value) => {
+                panic!("Invalid value for {}: {:?}", name, value)
+            }
+        }
+    }
+}
+
+fn apply_flags(state: &mut CpuState, flags: &str) {
+    state.f &= !(S_MASK | Z_MASK | H_MASK | PV_MASK | N_MASK | C_MASK);
+
+    for ch in flags.chars().filter(|&ch| ch != '-') {
+        state.f |= match ch.to_ascii_uppercase() {
+            'S' => S_MASK,
+            'Z' => Z_MASK,
+            'H' => H_MASK,
+            'P' | 'V' => PV_MASK,
+            'N' => N_MASK,
+            'C' => C_MASK,
+            _ => panic!(
+                "Invalid flag {} in {}, expected S Z H P V N C or -",
+                ch, flags
+            ),
+        };
+    }
+}
+
+/// Object code of the instruction, or the bytes of a DB line.
+fn assemble_code(text: &str, pc: u16) -> Result<Vec<u8>, AsmError> {
+    match text.trim().strip_prefix("DB ") {
+        Some(bytes) => bytes
+            .split(',')
+            .map(|byte| parse_number(byte).map(|value| value as u8))
+            .collect(),
+        None => assemble_instruction(text, pc),
+    }
+}
+
+/// Assemble the instruction, execute it from the initial status
+/// and verify the final status.
+pub fn run(text: &str, setup: Vec<Setting>, expected: Vec<Setting>) {
+    let mut initial = CpuBuilder::new()
+        .with_memory(vec![0; 0x10000])
+        .build()
+        .state();
+
+    for setting in &setup {
+        setting.apply(&mut initial);
+    }
+
+    let code = assemble_code(text, initial.pc)
+        .unwrap_or_else(|e| panic!("Can not assemble {}: {}", text, e));
+
+    for (offset, byte) in code.iter().enumerate() {
+        initial.memory[initial.pc as usize + offset] = *byte;
+    }
+
+    let mut cpu = CpuBuilder::new().with_memory(Vec::new()).build();
+    cpu.restore(&initial);
+    cpu.step();
+
+    let mut opcode = [0; 4];
+    for (byte, &code) in opcode.iter_mut().zip(&code) {
+        *byte = code;
+    }
+
+    let mut wanted = initial.clone();
+    wanted.pc = initial.pc.wrapping_add(code.len() as u16);
+    wanted.r = initial.r & 0x80 | initial.r.wrapping_add(fetches(&opcode)) & 0x7f;
+    wanted.wz = cpu.wz;
+
+    for setting in &expected {
+        setting.apply(&mut wanted);
+    }
+
+    let changes = diff(&wanted, &cpu.state());
+
+    if !changes.is_empty() {
+        panic!(
+            "{}: CPU status unexpected value (expected -> actual):\n{}",
+            text, changes
+        );
+    }
+}
+
+/// Build the list of settings of a test case.
+#[macro_export]
+macro_rules! isa_settings {
+    (@acc [$($out:expr),*]) => {
+        vec![$($out),*]
+    };
+    (@acc [$($out:expr),*] ($addr:expr) : $value:expr, $($rest:tt)*) => {
+        isa_settings!(@acc [$($out,)* $crate::cpu::isa::case::Setting::memory($addr, $value)] $($rest)*)
+    };
+    (@acc [$($out:expr),*] ($addr:expr) : $value:expr) => {
+        isa_settings!(@acc [$($out,)* $crate::cpu::isa::case::Setting::memory($addr, $value)])
+    };
+    (@acc [$($out:expr),*] $name:ident : $value:expr, $($rest:tt)*) => {
+        isa_settings!(@acc [$($out,)* $crate::cpu::isa::case::Setting::register(stringify!($name), $value)] $($rest)*)
+    };
+    (@acc [$($out:expr),*] $name:ident : $value:expr) => {
+        isa_settings!(@acc [$($out,)* $crate::cpu::isa::case::Setting::register(stringify!($name), $value)])
+    };
+    ($($body:tt)*) => {
+        isa_settings!(@acc [] $($body)*)
+    };
+}
+
+/// Define one test per line: `name: "INSTRUCTION" { setup } => { expected };`
+#[macro_export]
+macro_rules! isa_tests {
+    ($($name:ident : $text:tt { $($setup:tt)* } => { $($expected:tt)* };)*) => {
+        $(
+            #[test]
+            fn $name() {
+                $crate::cpu::isa::case::run(
+                    $text,
+                    isa_settings!($($setup)*),
+                    isa_settings!($($expected)*),
+                );
+            }
+        )*
+    };
+}
@@ -7,50 +7,42 @@ mod tests;
 
 impl Cpu {
     fn _cp_with_accumulator(&mut self, value: u8) {
-        let result = self.a - value;
-
-        self.set_s_from_msb(result);
-        self.set_z_from_byte(result);
-        self.set_pv_from_byte(result);
-        self.set_n(true);
-
-        // TODO: C is set if borrow; otherwise, it is reset.
-        self.set_c(false);
-
-        // TODO: H is set if borrow from bit 4; otherwise, it is reset.
-        self.set_h(false);
+        // The flags of SUB, without storing the result.
+        let a = self.a;
+        self._sub_from_accumulator(value, 0);
+        self.a = a;
     }
 
     pub fn cp_r(&mut self) {
         let opcode = self.memory_at_pc(0);
         let operand = self.read(Self::select(opcode & 0b111));
         self._cp_with_accumulator(operand);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn cp_n(&mut self) {
         let operand = self.memory_at_pc(1);
         self._cp_with_accumulator(operand);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn cp_hli(&mut self) {
         let operand = self.memory_at_hl();
         self._cp_with_accumulator(operand);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn cp_ixdi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_ix(offset);
         self._cp_with_accumulator(operand);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
     pub fn cp_iydi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_iy(offset);
         self._cp_with_accumulator(operand);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 }
@@ -101,3 +101,10 @@ fn cp_iydi() {
         // .parity_is_odd()
         .add_subtract_flag_is_set();
 }
+
+isa_tests! {
+    cp_n_below: "CP 0x20" { a: 0x10 } => { flags: "S---NC" };
+    cp_n_equal: "CP 0x10" { a: 0x10 } => { flags: "-Z--N-" };
+    cp_r_half_borrow: "CP B" { a: 0x10, b: 0x01 } => { flags: "--H-N-" };
+    cp_r_overflow: "CP B" { a: 0x80, b: 0x01 } => { flags: "--HVN-" };
+}
@@ -42,15 +42,16 @@ impl Cpu {
         let h = self.h;
         let l = self.l;
 
-        let addrh = (self.sp + 1) as usize;
+        let addrh = self.sp.wrapping_add(1) as usize;
         let addrl = self.sp as usize;
 
-        self.h = self.memory[addrh];
-        self.l = self.memory[addrl];
+        self.h = self.read_byte(addrh as u16);
+        self.l = self.read_byte(addrl as u16);
 
-        self.memory[addrh] = h;
-        self.memory[addrl] = l;
+        self.write_byte(addrh as u16, h);
+        self.write_byte(addrl as u16, l);
 
+        self.wz = (self.h, self.l).promote();
         self.pc.reg_add(1);
     }
 
@@ -58,13 +59,14 @@ impl Cpu {
         let h = self.ix.high();
         let l = self.ix.low();
 
-        let addrh = (self.sp + 1) as usize;
+        let addrh = self.sp.wrapping_add(1) as usize;
         let addrl = self.sp as usize;
 
-        self.ix = (self.memory[addrh], self.memory[addrl]).promote();
-        self.memory[addrh] = h;
-        self.memory[addrl] = l;
+        self.ix = (self.read_byte(addrh as u16), self.read_byte(addrl as u16)).promote();
+        self.write_byte(addrh as u16, h);
+        self.write_byte(addrl as u16, l);
 
+        self.wz = self.ix;
         self.pc.reg_add(2);
     }
 
@@ -72,13 +74,14 @@ impl Cpu {
         let h = self.iy.high();
         let l = self.iy.low();
 
-        let addrh = (self.sp + 1) as usize;
+        let addrh = self.sp.wrapping_add(1) as usize;
         let addrl = self.sp as usize;
 
-        self.iy = (self.memory[addrh], self.memory[addrl]).promote();
-        self.memory[addrh] = h;
-        self.memory[addrl] = l;
+        self.iy = (self.read_byte(addrh as u16), self.read_byte(addrl as u16)).promote();
+        self.write_byte(addrh as u16, h);
+        self.write_byte(addrl as u16, l);
 
+        self.wz = self.iy;
         self.pc.reg_add(2);
     }
 
@@ -86,7 +89,8 @@ impl Cpu {
         // (DE) ← (HL)
         let addr = self.read16(Register16::de) as usize;
         let value_addr = self.read16(Register16::hl) as usize;
-        self.memory[addr] = self.memory[value_addr];
+        let value = self.read_byte(value_addr as u16);
+        self.write_byte(addr as u16, value);
 
         // DE ← DE + 1
         let value = self.read16(Register16::de) as i32 + delta as i32;
@@ -100,8 +104,7 @@ impl Cpu {
         // (self.h, self.l).reg_add(delta);
 
         // BC ← BC – 1
-        let value = self.read16(Register16::bc) - 1;
-        self.write16(Register16::bc, value);
+        self.add_bc(-1);
 
         // P/V is set if BC – 1 ≠ 0; otherwise, it is reset.
         let value = self.read16(Register16::bc) != 0;
@@ -115,15 +118,12 @@ impl Cpu {
     }
 
     pub fn ldir(&mut self) {
-        loop {
-            self.ldi();
-
-            if self.read_bc() == 0 {
-                break;
-            } else {
-                self.pc -= 2;
-                // TODO: Honor interrupts.
-            }
+        self.ldi();
+
+        // Execute the instruction again while BC ≠ 0
+        if self.read_bc() != 0 {
+            self.pc = self.pc.wrapping_sub(2);
+            self.wz = self.pc.wrapping_add(1);
         }
     }
 
@@ -132,27 +132,25 @@ impl Cpu {
     }
 
     pub fn lddr(&mut self) {
-        loop {
-            self.ldd();
-
-            if self.read_bc() == 0 {
-                break;
-            } else {
-                self.pc -= 2;
-                // TODO: Honor interrupts.
-            }
+        self.ldd();
+
+        // Execute the instruction again while BC ≠ 0
+        if self.read_bc() != 0 {
+            self.pc = self.pc.wrapping_sub(2);
+            self.wz = self.pc.wrapping_add(1);
         }
     }
 
     pub fn _cpi(&mut self, step: i8) {
         let addr = self.read_hl() as usize;
-        let diff = self.a - self.memory[addr];
+        let diff = self.a.wrapping_sub(self.read_byte(addr as u16));
 
         // BC ← BC – 1
         self.add_bc(-1);
 
         // HL ← HL +- 1
         self.add_hl(step);
+        self.wz = self.wz.wrapping_add(step as u16);
 
         // Z is set if A is (HL); otherwise, it is reset.
         self.set_z(diff == 0);
@@ -178,16 +176,12 @@ impl Cpu {
     }
 
     pub fn cpir(&mut self) {
-        loop {
-            self.cpi();
-
-            // while (BC ≠ 0)
-            if self.read_bc() == 0 {
-                break;
-            } else {
-                self.pc -= 2;
-                // TODO: Honor interrupts.
-            }
+        self.cpi();
+
+        // Execute the instruction again while BC ≠ 0 and A ≠ (HL)
+        if self.read_bc() != 0 && !self.get_z() {
+            self.pc = self.pc.wrapping_sub(2);
+            self.wz = self.pc.wrapping_add(1);
         }
     }
 
@@ -196,16 +190,12 @@ impl Cpu {
     }
 
     pub fn cpdr(&mut self) {
-        loop {
-            self.cpd();
-
-            // while (BC ≠ 0)
-            if self.read_bc() == 0 {
-                break;
-            } else {
-                self.pc -= 2;
-                // TODO: Honor interrupts.
-            }
+        self.cpd();
+
+        // Execute the instruction again while BC ≠ 0 and A ≠ (HL)
+        if self.read_bc() != 0 && !self.get_z() {
+            self.pc = self.pc.wrapping_sub(2);
+            self.wz = self.pc.wrapping_add(1);
         }
     }
 }
@@ -171,7 +171,12 @@ fn ldir() {
         .with_hl(4)
         .build();
 
+    // One transfer each time, going back to the instruction until BC is 0
     cpu.ldir();
+    assert_eq!(cpu.pc, 0);
+    while cpu.pc == 0 {
+        cpu.ldir();
+    }
 
     Assertor::new(cpu)
         .memory_at_address_is(0, 0x44)      // (DE) ← (HL)
@@ -217,7 +222,12 @@ fn lddr() {
         .with_hl(7)
         .build();
 
+    // One transfer each time, going back to the instruction until BC is 0
     cpu.lddr();
+    assert_eq!(cpu.pc, 0);
+    while cpu.pc == 0 {
+        cpu.lddr();
+    }
 
     Assertor::new(cpu)
         .memory_at_address_is(0, 0x90)      // (DE) ← (HL)
@@ -266,9 +276,9 @@ fn cpir() {
     cpu.cpir();
 
     Assertor::new(cpu)
-        .register_bc_is(0)  // BC ← BC-1
-        .register_hl_is(6)  // HL ← HL+1
-        .zero_flag_is_reset()// Z is set if A is (HL)
+        .register_bc_is(1)  // BC ← BC-1
+        .register_hl_is(5)  // HL ← HL+1
+        .zero_flag_is_set()// Z is set if A is (HL)
         .parity_overflow_flag_is_set()  // P/V is set if BC-1 != 0
         .sign_is_positive()    // S is set if result is negative
         .carry_flag_is_reset()// C is not affected
@@ -306,7 +316,16 @@ fn cpdr() {
     cpu.cpdr();
 
     Assertor::new(cpu)
-        .register_bc_is(0)  // BC ← BC-1
-        .register_hl_is(2)  // HL ← HL-1
+        .register_bc_is(1)  // BC ← BC-1
+        .register_hl_is(3)  // HL ← HL-1
+        .zero_flag_is_set()
         .program_counter_is(2);
 }
+
+isa_tests! {
+    ex_de_hl_decoded: "EX DE,HL" { de: 0x1234, hl: 0x5678 } => { de: 0x5678, hl: 0x1234 };
+    ex_af_af1_decoded: "EX AF,AF'" { a: 0x12, a1: 0x34 } => { a: 0x34, a1: 0x12 };
+    exx_decoded: "EXX" { bc: 0x1234, b1: 0x56 } => { b: 0x56, c: 0x00, b1: 0x12, c1: 0x34 };
+    ldi_bc_zero: "LDI" { hl: 0x100, de: 0x200, bc: 0, (0x100): 0x12 } => { hl: 0x101, de: 0x201, bc: 0xffff, (0x200): 0x12, flags: "---V--" };
+    cpir_repeats: "CPIR" { a: 0x3b, bc: 2, hl: 0x100 } => { bc: 1, hl: 0x101, pc: 0, flags: "---VN-" };
+}
@@ -56,10 +56,11 @@ impl Cpu {
 
     // TODO: work on u8 or i8
     fn _add_addr(&mut self, addr: usize, value: i32) -> (u8, u8) {
-        let old = self.memory[addr];
+        let old = self.read_byte(addr as u16);
         //let (_, carry) = self.memory[addr].reg_add(value);
-        self.memory[addr] = (self.memory[addr] as i32 + value) as u8;
-        (old, self.memory[addr])
+        let new = (old as i32 + value) as u8;
+        self.write_byte(addr as u16, new);
+        (old, new)
     }
 
     fn _evaluate_flags_after_inc(&mut self, old_value: u8, new_value: u8) {
@@ -117,14 +118,14 @@ impl Cpu {
     }
 
     pub fn inc_ixdi(&mut self) {
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         let result = self._add_addr(addr, 1);
         self._evaluate_flags_after_inc(result.0, result.1);
         self.pc.reg_add(3);
     }
 
     pub fn inc_iydi(&mut self) {
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         let result = self._add_addr(addr, 1);
         self._evaluate_flags_after_inc(result.0, result.1);
         self.pc.reg_add(3);
@@ -145,14 +146,14 @@ impl Cpu {
     }
 
     pub fn dec_ixdi(&mut self) {
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         let result = self._add_addr(addr, -1);
         self._evaluate_flags_after_dec(result.0, result.1);
         self.pc.reg_add(3);
     }
 
     pub fn dec_iydi(&mut self) {
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         let result = self._add_addr(addr, -1);
         self._evaluate_flags_after_dec(result.0, result.1);
         self.pc.reg_add(3);
@@ -180,3 +180,10 @@ fn dec_iydi() {
         .carry_flag_is_reset()
         .program_counter_is(3);
 }
+
+isa_tests! {
+    inc_ixdi_negative: "INC (IX-5)" { ix: 0x1010, (0x100b): 0x10 } => { (0x100b): 0x11 };
+    dec_iydi_negative: "DEC (IY-5)" { iy: 0x1010, (0x100b): 0x12 } => { (0x100b): 0x11, flags: "----N-" };
+    inc_iyl: "INC IYL" { iy: 0x1234 } => { iy: 0x1235 };
+    dec_ixh: "DEC IXH" { ix: 0x1234 } => { ix: 0x1134, flags: "----N-" };
+}
@@ -2,6 +2,7 @@
 mod tests;
 
 use cpu::Cpu;
+use cpu::FrameKind;
 
 #[allow(dead_code)]
 
@@ -9,44 +10,63 @@ use cpu::Cpu;
 
 impl Cpu {
     pub fn nop(&mut self) {
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn halt(&mut self) {
         // The HALT instruction suspends CPU operation until 
         // a subsequent interrupt or reset is received.
         // While in the HALT state, the processor executes NOPs 
-        // to maintain memory refresh logic.
-        loop {
-            // TODO: Intercept interrupts or reset and exit
-            // from the loop.
-            // Add a sleep here.
-        }
-
-        // self.pc += 1;
+        // to maintain memory refresh logic: see Cpu::step.
+        self.halted = true;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn di(&mut self) {
         self.iff1 = false;
         self.iff2 = false;
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn ei(&mut self) {
         self.iff1 = true;
         self.iff2 = true;
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
+    }
+
+    /// Accept a non-maskable interrupt between instructions: PC is
+    /// pushed and execution goes on at 0x66, out of HALT. IFF1 is kept
+    /// in IFF2 for RETN to restore, and reset.
+    pub fn nmi(&mut self) {
+        self.accesses.clear();
+        self.history_begin();
+
+        let caller = self.pc;
+        self.halted = false;
+        self._push_pc();
+        self.call_stack_enter(FrameKind::Interrupt, caller, 0x66);
+        self.pc = 0x66;
+        self.wz = 0x66;
+        self.refresh(1);
+
+        self.iff2 = self.iff1;
+        self.iff1 = false;
+        self.cycles += 11;
+        self.history_end();
     }
 
     pub fn im_0(&mut self) {
-        unimplemented!();
+        self.im = 0;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn im_1(&mut self) {
-        unimplemented!();
+        self.im = 1;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn im_2(&mut self) {
-        unimplemented!();
+        self.im = 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 }
@@ -14,7 +14,12 @@ fn nop() {
 
 #[test]
 fn halt() {
-    unimplemented!();
+    let mut cpu = CpuBuilder::new().with_memory(vec![0x76, 0x00]).build();
+
+    cpu.halt();
+
+    assert!(cpu.halted);
+    Assertor::new(cpu).program_counter_is(1);
 }
 
 #[test]
@@ -44,17 +49,55 @@ fn ei() {
 // IM 0
 #[test]
 fn im_0() {
-    unimplemented!();
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0xed, 0x46])
+        .with_im(2)
+        .build();
+
+    cpu.im_0();
+
+    assert_eq!(cpu.im, 0);
+    Assertor::new(cpu).program_counter_is(2);
 }
 
 // IM 1
 #[test]
 fn im_1() {
-    unimplemented!();
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0xed, 0x56])
+        .with_im(0)
+        .build();
+
+    cpu.im_1();
+
+    assert_eq!(cpu.im, 1);
+    Assertor::new(cpu).program_counter_is(2);
 }
 
 // IM 2
 #[test]
 fn im_2() {
-    unimplemented!();
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0xed, 0x5e])
+        .with_im(1)
+        .build();
+
+    cpu.im_2();
+
+    assert_eq!(cpu.im, 2);
+    Assertor::new(cpu).program_counter_is(2);
+}
+
+isa_tests! {
+    dd_before_nop: "DB 0xdd,0x00" {} => { pc: 1 };
+    fd_before_ex_de_hl: "DB 0xfd,0xeb" { de: 0x1234 } => { pc: 1 };
+    ed_invalid_is_nop: "DB 0xed,0x00" {} => {};
+    ed_7f_is_nop: "DB 0xed,0x7f" {} => {};
+    im_1_mirror: "DB 0xed,0x76" {} => { im: 1 };
+    im_0_mirror: "DB 0xed,0x6e" { im: 2 } => { im: 0 };
+    retn_mirror: "DB 0xed,0x55" { sp: 0xfe, (0xfe): 0x34, (0xff): 0x12, iff2: true } => { pc: 0x1234, sp: 0x100, iff1: true };
+    nop_refresh: "NOP" { r: 0x05 } => { r: 0x06 };
+    nop_refresh_keeps_bit_7: "NOP" { r: 0xff } => { r: 0x80 };
+    prefixed_refresh: "IM 1" { r: 0x05 } => { im: 1, r: 0x07 };
+    dd_alone_refresh: "DB 0xdd,0x00" { r: 0x05 } => { pc: 1, r: 0x06 };
 }
@@ -1,4 +1,5 @@
 use cpu::Cpu;
+use cpu::RegisterPromote;
 
 #[cfg(test)]
 mod tests;
@@ -6,66 +7,150 @@ mod tests;
 #[allow(dead_code)]
 
 // === Input and Output Group ===
+//
+// Ports are addressed with 16 bits: IN A,(n) and OUT (n),A put A on the
+// high half of the address bus, the other instructions put BC. The block
+// instructions decrement B before OUT puts it on the bus, after IN does.
 
 impl Cpu {
 
     // IN A, (n)
     pub fn in_a_ni(&mut self) {
-        unimplemented!();
+        let port = (self.a, self.memory_at_pc(1)).promote();
+        self.a = self.input(port);
+        self.wz = port.wrapping_add(1);
+        self.pc = self.pc.wrapping_add(2);
     }
 
-    // IN r (C)
+    // IN r (C), and IN (C) only setting the flags for r = 110
     pub fn in_r_ci(&mut self) {
-        unimplemented!();
+        let opcode = self.memory_at_pc(1);
+        let port = self.read_bc();
+        let value = self.input(port);
+        self.wz = port.wrapping_add(1);
+        if opcode & 0b111_000 != 0b110_000 {
+            self.write(Cpu::select_dest(opcode), value);
+        }
+
+        self.set_s_from_msb(value);
+        self.set_z_from_byte(value);
+        self.set_h(false);
+        self.set_pv(value.count_ones() & 1 == 0);
+        self.set_n(false);
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    fn _in_block(&mut self, delta: i8) {
+        // (HL) ← (C), B ← B – 1, HL ← HL ± 1
+        let value = self.input(self.read_bc());
+        self.wz = self.read_bc().wrapping_add(delta as u16);
+        let addr = self.read_hl();
+        self.write_byte(addr, value);
+        self.b = self.b.wrapping_sub(1);
+        self.add_hl(delta);
+
+        // Z is set if B – 1 = 0
+        let zero = self.b == 0;
+        self.set_z(zero);
+        self.set_n(true);
+        self.pc = self.pc.wrapping_add(2);
     }
 
     // INI
     pub fn ini(&mut self) {
-        unimplemented!();
+        self._in_block(1);
     }
 
     // INIR
     pub fn inir(&mut self) {
-        unimplemented!();
+        self.ini();
+
+        // Execute the instruction again while B ≠ 0
+        if self.b != 0 {
+            self.pc = self.pc.wrapping_sub(2);
+        }
     }
 
     // IND
     pub fn ind(&mut self) {
-        unimplemented!();
+        self._in_block(-1);
     }
 
     // INDR
     pub fn indr(&mut self) {
-        unimplemented!();
+        self.ind();
+
+        // Execute the instruction again while B ≠ 0
+        if self.b != 0 {
+            self.pc = self.pc.wrapping_sub(2);
+        }
     }
 
     // OUT (n), A
     pub fn out_ni_a(&mut self) {
-        unimplemented!();
+        let port = (self.a, self.memory_at_pc(1)).promote();
+        let value = self.a;
+        self.output(port, value);
+        self.wz = self.memptr_after_store(usize::from(port));
+        self.pc = self.pc.wrapping_add(2);
     }
 
-    // OUT (C), r
+    // OUT (C), r, and OUT (C), 0 for r = 110
     pub fn out_ci_r(&mut self) {
-        unimplemented!();
+        let opcode = self.memory_at_pc(1);
+        let value = match opcode & 0b111_000 {
+            0b110_000 => 0,
+            _ => self.read(Cpu::select_dest(opcode)),
+        };
+        let port = self.read_bc();
+        self.output(port, value);
+        self.wz = port.wrapping_add(1);
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    fn _out_block(&mut self, delta: i8) {
+        // B ← B – 1, (C) ← (HL), HL ← HL ± 1
+        let value = self.read_byte(self.read_hl());
+        self.b = self.b.wrapping_sub(1);
+        let port = self.read_bc();
+        self.output(port, value);
+        self.wz = port.wrapping_add(delta as u16);
+        self.add_hl(delta);
+
+        // Z is set if B – 1 = 0
+        let zero = self.b == 0;
+        self.set_z(zero);
+        self.set_n(true);
+        self.pc = self.pc.wrapping_add(2);
     }
 
     // OUTI
     pub fn outi(&mut self) {
-        unimplemented!();
+        self._out_block(1);
     }
 
     // OTIR
     pub fn otir(&mut self) {
-        unimplemented!();
+        self.outi();
+
+        // Execute the instruction again while B ≠ 0
+        if self.b != 0 {
+            self.pc = self.pc.wrapping_sub(2);
+        }
     }
 
     // OUTD
     pub fn outd(&mut self) {
-        unimplemented!();
+        self._out_block(-1);
     }
 
     // OTDR
     pub fn otdr(&mut self) {
-        unimplemented!();
+        self.outd();
+
+        // Execute the instruction again while B ≠ 0
+        if self.b != 0 {
+            self.pc = self.pc.wrapping_sub(2);
+        }
     }
 }
@@ -1,72 +1,284 @@
 // === Input and Output Group ===
 
+use cpu::{Access, AccessKind, Cpu, CpuBuilder, Ports};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Answers each IN with the high byte of the port address.
+struct HighByte;
+
+impl Ports for HighByte {
+    fn input(&mut self, port: u16) -> u8 {
+        (port >> 8) as u8
+    }
+
+    fn output(&mut self, _port: u16, _value: u8) {}
+}
+
+/// Keeps the clock at each output.
+struct Clocked {
+    cycles: u64,
+    outputs: Rc<RefCell<Vec<u64>>>,
+}
+
+impl Ports for Clocked {
+    fn input(&mut self, _port: u16) -> u8 {
+        0xff
+    }
+
+    fn output(&mut self, _port: u16, _value: u8) {
+        self.outputs.borrow_mut().push(self.cycles);
+    }
+
+    fn clock(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+}
+
+fn cpu(code: Vec<u8>) -> Cpu {
+    let mut memory = code;
+    memory.resize(0x200, 0);
+    CpuBuilder::new()
+        .with_memory(memory)
+        .with_ports(Box::new(HighByte))
+        .build()
+}
+
+fn outputs(cpu: &Cpu) -> Vec<(u16, u8)> {
+    cpu.accesses
+        .iter()
+        .filter(|access| access.kind == AccessKind::Output)
+        .map(|access| (access.address, access.value))
+        .collect()
+}
+
+/// Step a block instruction at 0 until it is done, one iteration each
+/// step, returning the outputs of all of them.
+fn repeat(cpu: &mut Cpu) -> Vec<(u16, u8)> {
+    let mut all = Vec::new();
+    loop {
+        cpu.step();
+        all.extend(outputs(cpu));
+        if cpu.pc != 0 {
+            return all;
+        }
+    }
+}
+
 #[test]
 fn in_a_ni() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("IN A,(0xfe)"));
+    cpu.a = 0x7f;
+
+    cpu.step();
+
+    assert_eq!(cpu.a, 0x7f);
+    assert_eq!(
+        cpu.accesses,
+        vec![Access { kind: AccessKind::Input, address: 0x7ffe, value: 0x7f }]
+    );
+    assert_eq!(cpu.pc, 2);
 }
 
 // IN r (C)
 #[test]
 fn in_r_ci() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("IN D,(C)"));
+    cpu.b = 0x80;
+    cpu.c = 0x10;
+    cpu.f = 0xff;
+
+    cpu.step();
+
+    assert_eq!(cpu.d, 0x80);
+    assert!(cpu.get_s() && !cpu.get_z() && !cpu.get_h() && !cpu.get_n());
+    assert!(!cpu.get_pv(), "odd parity");
+    assert!(cpu.get_c(), "carry is not affected");
+    assert_eq!(cpu.pc, 2);
 }
 
 // INI
 #[test]
 fn ini() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("INI"));
+    cpu.b = 0x01;
+    cpu.write_hl(0x100);
+
+    cpu.step();
+
+    assert_eq!(cpu.memory[0x100], 0x01);
+    assert_eq!(cpu.b, 0);
+    assert_eq!(cpu.read_hl(), 0x101);
+    assert!(cpu.get_z() && cpu.get_n());
+    assert_eq!(cpu.pc, 2);
 }
 
 // INIR
 #[test]
 fn inir() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("INIR"));
+    cpu.b = 0x03;
+    cpu.write_hl(0x100);
+
+    cpu.step();
+    assert_eq!(cpu.memory[0x100], 3);
+    assert_eq!(cpu.pc, 0, "repeats while B is not 0");
+    repeat(&mut cpu);
+
+    assert_eq!(&cpu.memory[0x100..0x103], &[3, 2, 1]);
+    assert_eq!(cpu.b, 0);
+    assert_eq!(cpu.read_hl(), 0x103);
+    assert_eq!(cpu.pc, 2);
+    assert_eq!(cpu.cycles, 2 * 21 + 16);
 }
 
 // IND
 #[test]
 fn ind() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("IND"));
+    cpu.b = 0x02;
+    cpu.write_hl(0x100);
+
+    cpu.step();
+
+    assert_eq!(cpu.memory[0x100], 0x02);
+    assert_eq!(cpu.b, 1);
+    assert_eq!(cpu.read_hl(), 0xff);
+    assert!(!cpu.get_z());
 }
 
 // INDR
 #[test]
 fn indr() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("INDR"));
+    cpu.b = 0x02;
+    cpu.write_hl(0x101);
+
+    repeat(&mut cpu);
+
+    assert_eq!(&cpu.memory[0x100..0x102], &[1, 2]);
+    assert_eq!(cpu.read_hl(), 0xff);
+    assert_eq!(cpu.pc, 2);
 }
 
 // OUT (n), A
 #[test]
 fn out_ni_a() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("OUT (0xfe),A"));
+    cpu.a = 0x07;
+
+    cpu.step();
+
+    assert_eq!(outputs(&cpu), vec![(0x07fe, 0x07)]);
+    assert_eq!(cpu.pc, 2);
 }
 
 // OUT (C), r
 #[test]
 fn out_ci_r() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("OUT (C),L"));
+    cpu.write_bc(0x1234);
+    cpu.l = 0x55;
+
+    cpu.step();
+
+    assert_eq!(outputs(&cpu), vec![(0x1234, 0x55)]);
+    assert_eq!(cpu.pc, 2);
+}
+
+// OUT (C), 0
+#[test]
+fn out_ci_0() {
+    let mut cpu = cpu(asm!("OUT (C),0"));
+    cpu.write_bc(0x1234);
+    cpu.h = 0x55;
+
+    cpu.step();
+
+    assert_eq!(outputs(&cpu), vec![(0x1234, 0x00)]);
+    assert_eq!(cpu.pc, 2);
 }
 
 // OUTI
 #[test]
 fn outi() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("OUTI"));
+    cpu.write_bc(0x0198);
+    cpu.write_hl(0x100);
+    cpu.memory[0x100] = 0xaa;
+
+    cpu.step();
+
+    assert_eq!(outputs(&cpu), vec![(0x0098, 0xaa)]);
+    assert_eq!(cpu.read_hl(), 0x101);
+    assert!(cpu.get_z() && cpu.get_n());
 }
 
 // OTIR
 #[test]
 fn otir() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("OTIR"));
+    cpu.write_bc(0x0298);
+    cpu.write_hl(0x100);
+    cpu.memory[0x100] = 0xaa;
+    cpu.memory[0x101] = 0xbb;
+
+    let outputs = repeat(&mut cpu);
+
+    assert_eq!(outputs, vec![(0x0198, 0xaa), (0x0098, 0xbb)]);
+    assert_eq!(cpu.read_hl(), 0x102);
+    assert_eq!(cpu.pc, 2);
+    assert_eq!(cpu.cycles, 21 + 16);
+}
+
+#[test]
+fn otir_clocks_each_output() {
+    let clocks = Rc::new(RefCell::new(Vec::new()));
+    let mut cpu = cpu(asm!("OTIR"));
+    cpu.ports = Some(Box::new(Clocked {
+        cycles: 0,
+        outputs: clocks.clone(),
+    }));
+    cpu.write_bc(0x0398);
+    cpu.write_hl(0x100);
+
+    repeat(&mut cpu);
+
+    assert_eq!(*clocks.borrow(), vec![0, 21, 42]);
 }
 
 // OUTD
 #[test]
 fn outd() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("OUTD"));
+    cpu.write_bc(0x0298);
+    cpu.write_hl(0x100);
+    cpu.memory[0x100] = 0xaa;
+
+    cpu.step();
+
+    assert_eq!(outputs(&cpu), vec![(0x0198, 0xaa)]);
+    assert_eq!(cpu.read_hl(), 0xff);
+    assert!(!cpu.get_z());
 }
 
 // OTDR
 #[test]
 fn otdr() {
-    unimplemented!();
+    let mut cpu = cpu(asm!("OTDR"));
+    cpu.write_bc(0x0298);
+    cpu.write_hl(0x101);
+    cpu.memory[0x100] = 0xaa;
+    cpu.memory[0x101] = 0xbb;
+
+    let outputs = repeat(&mut cpu);
+
+    assert_eq!(outputs, vec![(0x0198, 0xbb), (0x0098, 0xaa)]);
+    assert_eq!(cpu.read_hl(), 0xff);
+}
+
+isa_tests! {
+    in_ci: "IN (C)" { bc: 0x1234 } => { flags: "S--P--" };
+    out_ni_a_memptr: "OUT (0xfe),A" { a: 0x12 } => { wz: 0x12ff };
+    in_r_ci_memptr: "IN B,(C)" { bc: 0x12ff } => { b: 0xff, wz: 0x1300, flags: "S--P--" };
 }
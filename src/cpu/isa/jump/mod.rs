@@ -13,19 +13,24 @@ impl Cpu {
     pub fn jp_nn(&mut self) {
         let addr = (self.memory_at_pc(2), self.memory_at_pc(1)).promote();
         self.pc = addr;
+        self.wz = addr;
     }
 
     pub fn jp_cc_nn(&mut self) {
+        // MEMPTR takes the address, even when not jumping
+        self.wz = (self.memory_at_pc(2), self.memory_at_pc(1)).promote();
         if self.condition_at_pc(0) {
-            self.pc = (self.memory_at_pc(2), self.memory_at_pc(1)).promote();
+            self.pc = self.wz;
         } else {
             self.pc.reg_add(3);
         }
     }
 
     pub fn jr_e(&mut self) {
-        let offset = self.memory_at_pc(1);
-        self.pc.reg_add(u16::from(offset));
+        // The offset is signed and relative to the next instruction.
+        let offset = self.memory_at_pc(1) as i8;
+        self.pc = self.pc.wrapping_add(2).wrapping_add(offset as u16);
+        self.wz = self.pc;
     }
 
     fn jump_on(&mut self, cnd: bool) {
@@ -70,13 +75,12 @@ impl Cpu {
     }
 
     pub fn djnz_e(&mut self) {
-        self.b -= 1;
+        self.b = self.b.wrapping_sub(1);
 
         if self.b == 0 {
             self.pc.reg_add(2);
         } else {
-            let offset = self.memory_at_pc(1);
-            self.pc.reg_add(u16::from(offset));
+            self.jr_e();
         }
     }
 }
@@ -34,7 +34,7 @@ fn jr_e() {
 
     cpu.jr_e();
 
-    Assertor::new(cpu).program_counter_is(4);
+    Assertor::new(cpu).program_counter_is(6);
 }
 
 #[test]
@@ -46,7 +46,7 @@ fn jr_c_e() {
 
     cpu.jr_c_e();
 
-    Assertor::new(cpu).program_counter_is(4);
+    Assertor::new(cpu).program_counter_is(6);
 }
 
 #[test]
@@ -58,7 +58,7 @@ fn jr_nc_e() {
 
     cpu.jr_nc_e();
 
-    Assertor::new(cpu).program_counter_is(4);
+    Assertor::new(cpu).program_counter_is(6);
 }
 
 #[test]
@@ -70,7 +70,7 @@ fn jr_z_e() {
 
     cpu.jr_z_e();
 
-    Assertor::new(cpu).program_counter_is(4);
+    Assertor::new(cpu).program_counter_is(6);
 }
 
 #[test]
@@ -82,7 +82,7 @@ fn jr_nz_e() {
 
     cpu.jr_nz_e();
 
-    Assertor::new(cpu).program_counter_is(4);
+    Assertor::new(cpu).program_counter_is(6);
 }
 
 #[test]
@@ -132,5 +132,23 @@ fn djnz_e() {
         .build();
 
     cpu.djnz_e();
-    Assertor::new(cpu).program_counter_is(4);
+    Assertor::new(cpu).program_counter_is(6);
+}
+
+#[test]
+fn jr_e_backwards() {
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0, 0, 0x18, 0xfc])
+        .with_pc(2)
+        .build();
+
+    cpu.jr_e();
+
+    Assertor::new(cpu).program_counter_is(0);
+}
+
+isa_tests! {
+    jp_nn_memptr: "JP 0x1234" {} => { pc: 0x1234, wz: 0x1234 };
+    jp_cc_nn_memptr: "JP Z,0x1234" {} => { wz: 0x1234 };
+    jr_e_memptr: "JR 0x10" {} => { pc: 0x10, wz: 0x10 };
 }
@@ -31,7 +31,9 @@ impl Cpu {
                 self.h = h;
             },
             Register16::sp => {
+                let sp = self.sp;
                 self.sp = (h, l).promote();
+                self.call_stack_reload(self.pc, sp);
             },
             _ => panic!(),
         }
@@ -55,43 +57,54 @@ impl Cpu {
         let l = self.memory_at_pc(1);
         let h = self.memory_at_pc(2);
         let addr = (h, l).promote() as usize;
-        self.l = self.memory[addr];
-        self.h = self.memory[addr + 1];
+        self.l = self.read_byte(addr as u16);
+        self.h = self.read_byte((addr + 1) as u16);
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(3);
     }
 
     pub fn ld_dd_nni(&mut self) {
         let opcode = self.memory_at_pc(1);
         let addr = self.memory_at_pc(2) as usize + ((self.memory_at_pc(3) as usize) << 8);
-        let value = u16::from(self.memory[addr]) + (u16::from(self.memory[addr + 1]) << 8);
+        let low = self.read_byte(addr as u16);
+        let high = self.read_byte((addr + 1) as u16);
+        let value = (high, low).promote();
 
         match Cpu::select_reg16(opcode) {
             Register16::bc => self.write_bc(value),
             Register16::de => self.write_de(value),
             Register16::hl => self.write_hl(value),
-            Register16::sp => self.sp = value,
+            Register16::sp => {
+                let sp = self.sp;
+                self.sp = value;
+                self.call_stack_reload(self.pc, sp);
+            },
             _ => panic!(),
         }
 
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(4);
     }
 
     pub fn ld_ix_nni(&mut self) {
         let addr = (self.memory_at_pc(3), self.memory_at_pc(2)).promote() as usize;
-        self.ix = (self.memory[addr + 1], self.memory[addr]).promote();
+        self.ix = (self.read_byte((addr + 1) as u16), self.read_byte(addr as u16)).promote();
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(4);
     }
 
     pub fn ld_iy_nni(&mut self) {
         let addr = (self.memory_at_pc(3), self.memory_at_pc(2)).promote() as usize;
-        self.iy = (self.memory[addr + 1], self.memory[addr]).promote();
+        self.iy = (self.read_byte((addr + 1) as u16), self.read_byte(addr as u16)).promote();
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(4);
     }
 
     pub fn ld_nni_hl(&mut self) {
         let addr = (self.memory_at_pc(2), self.memory_at_pc(1)).promote() as usize;
-        self.memory[addr] = self.l;
-        self.memory[addr + 1] = self.h;
+        self.write_byte(addr as u16, self.l);
+        self.write_byte((addr + 1) as u16, self.h);
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(3);
     }
 
@@ -100,52 +113,61 @@ impl Cpu {
         let code = self.memory_at_pc(1);
         match Cpu::select_reg16(code) {
             Register16::bc => {
-                self.memory[addr] = self.c;
-                self.memory[addr + 1] = self.b;
+                self.write_byte(addr as u16, self.c);
+                self.write_byte((addr + 1) as u16, self.b);
             },
             Register16::de => {
-                self.memory[addr] = self.e;
-                self.memory[addr + 1] = self.d;
+                self.write_byte(addr as u16, self.e);
+                self.write_byte((addr + 1) as u16, self.d);
             },
             Register16::hl => {
-                self.memory[addr] = self.l;
-                self.memory[addr + 1] = self.h;
+                self.write_byte(addr as u16, self.l);
+                self.write_byte((addr + 1) as u16, self.h);
             },
             Register16::sp => {
-                self.memory[addr] = self.sp.low();
-                self.memory[addr + 1] = self.sp.high();
+                self.write_byte(addr as u16, self.sp.low());
+                self.write_byte((addr + 1) as u16, self.sp.high());
             },
             _ => panic!(),
         }
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(4);
     }
 
     pub fn ld_nni_ix(&mut self) {
         let addr = (self.memory_at_pc(3), self.memory_at_pc(2)).promote() as usize;
-        self.memory[addr] = self.ix.low();
-        self.memory[addr + 1] = self.ix.high();
+        self.write_byte(addr as u16, self.ix.low());
+        self.write_byte((addr + 1) as u16, self.ix.high());
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(4);
     }
 
     pub fn ld_nni_iy(&mut self) {
         let addr = (self.memory_at_pc(3), self.memory_at_pc(2)).promote() as usize;
-        self.memory[addr] = self.iy.low();
-        self.memory[addr + 1] = self.iy.high();
+        self.write_byte(addr as u16, self.iy.low());
+        self.write_byte((addr + 1) as u16, self.iy.high());
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(4);
     }
 
     pub fn ld_sp_hl(&mut self) {
+        let sp = self.sp;
         self.sp = self.read16(Register16::hl);
+        self.call_stack_reload(self.pc, sp);
         self.pc.reg_add(1);
     }
 
     pub fn ld_sp_ix(&mut self) {
+        let sp = self.sp;
         self.sp = self.ix;
+        self.call_stack_reload(self.pc, sp);
         self.pc.reg_add(2);
     }
 
     pub fn ld_sp_iy(&mut self) {
+        let sp = self.sp;
         self.sp = self.iy;
+        self.call_stack_reload(self.pc, sp);
         self.pc.reg_add(2);
     }
 }
@@ -268,3 +268,8 @@ Assertor::new(cpu)
     .stack_pointer_is(0xcaf3)
     .program_counter_is(2);
 }
+
+isa_tests! {
+    ld_hl_nni_memptr: "LD HL,(0x1234)" {} => { wz: 0x1235 };
+    ld_nni_de_memptr: "LD (0x1234),DE" {} => { wz: 0x1235 };
+}
@@ -31,7 +31,7 @@ impl Cpu {
         let opcode = self.memory_at_pc(0);
         let dest = Cpu::select_dest(opcode);
         let addr = self.read16(Register16::hl) as usize;
-        let value = self.memory[addr];
+        let value = self.read_byte(addr as u16);
         self.write(dest, value);
         self.pc.reg_add(1);
     }
@@ -39,9 +39,9 @@ impl Cpu {
     pub fn ld_r_ixd(&mut self) {
         // memory_at_pc(0) is always 0xdd
         let opcode = self.memory_at_pc(1);
-        let addr = self.ix as usize + self.memory_at_pc(2).two_compl() as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         let dest = Cpu::select_dest(opcode);
-        let value = self.memory[addr];
+        let value = self.read_byte(addr as u16);
         self.write(dest, value);
         self.pc.reg_add(3);
     }
@@ -49,9 +49,9 @@ impl Cpu {
     pub fn ld_r_iyd(&mut self) {
         // memory_at_pc(0) is always 0xfd
         let opcode = self.memory_at_pc(1);
-        let addr = self.iy as usize + self.memory_at_pc(2).two_compl() as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         let dest = Cpu::select_dest(opcode);
-        let value = self.memory[addr];
+        let value = self.read_byte(addr as u16);
         self.write(dest, value);
         self.pc.reg_add(3);
     }
@@ -60,7 +60,7 @@ impl Cpu {
         let opcode = self.memory_at_pc(0);
         let src = Cpu::select_src(opcode);
         let addr = self.read16(Register16::hl) as usize;
-        self.memory[addr] = self.read(src);
+        self.write_byte(addr as u16, self.read(src));
         self.pc.reg_add(1);
     }
 
@@ -68,8 +68,8 @@ impl Cpu {
         // memory_at_pc(0) is always 0xdd
         let opcode = self.memory_at_pc(1);
         let src = Cpu::select_src(opcode);
-        let addr = self.ix as usize + self.memory_at_pc(2).two_compl() as usize;
-        self.memory[addr] = self.read(src);
+        let addr = self.ix_addr(self.memory_at_pc(2));
+        self.write_byte(addr as u16, self.read(src));
         self.pc.reg_add(3);
     }
 
@@ -77,45 +77,69 @@ impl Cpu {
         // memory_at_pc(0) is always 0xfd
         let opcode = self.memory_at_pc(1);
         let src = Cpu::select_src(opcode);
-        let addr = self.iy as usize + self.memory_at_pc(2).two_compl() as usize;
-        self.memory[addr] = self.read(src);
+        let addr = self.iy_addr(self.memory_at_pc(2));
+        self.write_byte(addr as u16, self.read(src));
         self.pc.reg_add(3);
     }
 
     pub fn ld_hl_n(&mut self) {
         let addr = self.read16(Register16::hl) as usize;
         let value = self.memory_at_pc(1);
-        self.memory[addr] = value;
+        self.write_byte(addr as u16, value);
         self.pc.reg_add(2);
     }
 
     pub fn ld_ixd_n(&mut self) {
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
-        self.memory[addr] = self.memory_at_pc(3);
+        let addr = self.ix_addr(self.memory_at_pc(2));
+        self.write_byte(addr as u16, self.memory_at_pc(3));
         self.pc.reg_add(4);
     }
 
     pub fn ld_iyd_n(&mut self) {
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
-        self.memory[addr] = self.memory_at_pc(3);
+        let addr = self.iy_addr(self.memory_at_pc(2));
+        self.write_byte(addr as u16, self.memory_at_pc(3));
         self.pc.reg_add(4);
     }
 
     pub fn ld_a_bc(&mut self) {
         let addr = self.read16(Register16::bc) as usize;
-        self.a = self.memory[addr];
+        self.a = self.read_byte(addr as u16);
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(1);
     }
 
     pub fn ld_a_de(&mut self) {
         let addr = self.read16(Register16::de) as usize;
-        self.a = self.memory[addr];
+        self.a = self.read_byte(addr as u16);
+        self.wz = (addr as u16).wrapping_add(1);
         self.pc.reg_add(1);
     }
 
     pub fn ld_a_nn(&mut self) {
-        let addr = self.memory_at_pc(1) as usize + self.memory_at_pc(2) as usize;
-        self.a = self.memory[addr];
+        let addr = self.addr_at_pc(1);
+        self.a = self.read_byte(addr as u16);
+        self.wz = (addr as u16).wrapping_add(1);
+        self.pc.reg_add(3);
+    }
+
+    pub fn ld_bc_a(&mut self) {
+        let addr = self.read16(Register16::bc) as usize;
+        self.write_byte(addr as u16, self.a);
+        self.wz = self.memptr_after_store(addr);
+        self.pc.reg_add(1);
+    }
+
+    pub fn ld_de_a(&mut self) {
+        let addr = self.read16(Register16::de) as usize;
+        self.write_byte(addr as u16, self.a);
+        self.wz = self.memptr_after_store(addr);
+        self.pc.reg_add(1);
+    }
+
+    pub fn ld_nn_a(&mut self) {
+        let addr = self.addr_at_pc(1);
+        self.write_byte(addr as u16, self.a);
+        self.wz = self.memptr_after_store(addr);
         self.pc.reg_add(3);
     }
 
@@ -99,7 +99,7 @@ fn ld_r_hl() {
 #[test]
 fn ld_r_ixd() {
     let mut cpu = CpuBuilder::new()
-        .with_memory(vec![0xdd, 0b01_000_110, 0b1111_1111, 0, 0, 0xfb, 0, 0])
+        .with_memory(vec![0xdd, 0b01_000_110, 0x01, 0, 0, 0xfb, 0, 0])
         .with_ix(4)
         .build();
 
@@ -113,7 +113,7 @@ fn ld_r_ixd() {
 #[test]
 fn ld_r_iyd() {
     let mut cpu = CpuBuilder::new()
-        .with_memory(vec![0xfd, 0b01_000_110, 0b1111_1111, 0, 0, 0xfb, 0, 0])
+        .with_memory(vec![0xfd, 0b01_000_110, 0x01, 0, 0, 0xfb, 0, 0])
         .with_iy(4)
         .build();
 
@@ -150,7 +150,7 @@ fn ld_ixd_r() {
         .with_memory(vec![
             0xdd, // LD (IX+D), R
             0b01110_111,
-            0xff, // D
+            0x01, // D
             1,
             2,
             3,
@@ -173,7 +173,7 @@ fn ld_iyd_r() {
         .with_memory(vec![
             0xfd, // LD (IY+D), R
             0b01110_111,
-            0xff, // D
+            0x01, // D
             1,
             2,
             3,
@@ -361,3 +361,31 @@ fn ld_r_a() {
         .memory_refresh_register_is(12)
         .program_counter_is(2);
 }
+
+isa_tests! {
+    ld_r_r1_decoded: "LD H,E" { e: 0x1a } => { h: 0x1a };
+    ld_r_n_decoded: "LD B,0xfb" {} => { b: 0xfb };
+    ld_r_hl_decoded: "LD D,(HL)" { hl: 0x100, (0x100): 0x12 } => { d: 0x12 };
+    ld_hl_r_decoded: "LD (HL),A" { a: 0x34, hl: 0x100 } => { (0x100): 0x34 };
+    ld_a_bc_decoded: "LD A,(BC)" { bc: 0x100, (0x100): 0x56 } => { a: 0x56 };
+    ld_bc_a: "LD (BC),A" { a: 0x56, bc: 0x100 } => { (0x100): 0x56 };
+    ld_de_a: "LD (DE),A" { a: 0x78, de: 0x200 } => { (0x200): 0x78 };
+    ld_nn_a: "LD (0x1234),A" { a: 0x9a } => { (0x1234): 0x9a };
+    ld_i_a_decoded: "LD I,A" { a: 0x9a } => { i: 0x9a };
+    ld_r_ixd_negative: "LD A,(IX-3)" { ix: 0x1010, (0x100d): 0x5a } => { a: 0x5a };
+    ld_r_iyd_negative: "LD B,(IY-128)" { iy: 0x1080, (0x1000): 0x77 } => { b: 0x77 };
+    ld_ixd_r_negative: "LD (IX-1),C" { ix: 0x1010, c: 0x33 } => { (0x100f): 0x33 };
+    ld_iyd_n_negative: "LD (IY-2),0x44" { iy: 0x1010 } => { (0x100e): 0x44 };
+    ld_ixd_n_positive: "LD (IX+127),0x45" { ix: 0x1000 } => { (0x107f): 0x45 };
+    ld_r_ixd_wraps: "LD C,(IX-1)" { ix: 0x0000, (0xffff): 0x66 } => { c: 0x66 };
+    ld_ixh_n: "LD IXH,0x05" { ix: 0x1234 } => { ix: 0x0534 };
+    ld_b_iyl: "LD B,IYL" { iy: 0x1234 } => { b: 0x34 };
+    ld_ixh_ixl: "LD IXH,IXL" { ix: 0x1234 } => { ix: 0x3434 };
+    ld_iyl_b_keeps_hl: "LD IYL,B" { iy: 0x1234, b: 0x56, hl: 0x9abc } => { iy: 0x1256 };
+    ld_h_ixd_writes_h: "LD H,(IX+1)" { ix: 0x100, hl: 0x5678, (0x101): 0x12 } => { h: 0x12 };
+    ld_a_nn_memptr: "LD A,(0x1234)" { (0x1234): 0x56 } => { a: 0x56, wz: 0x1235 };
+    ld_nn_a_memptr: "LD (0x12ff),A" { a: 0x56 } => { (0x12ff): 0x56, wz: 0x5600 };
+    ld_r_ixd_memptr: "LD B,(IX-1)" { ix: 0x1010 } => { wz: 0x100f };
+    ld_a_r_refreshed: "LD A,R" { r: 0x7f } => { a: 0x01, r: 0x01 };
+    ld_a_r_keeps_bit_7: "LD A,R" { r: 0xff } => { a: 0x81, r: 0x81, flags: "S-----" };
+}
@@ -1,3 +1,7 @@
+#[cfg(test)]
+#[macro_use]
+pub mod case;
+
 mod add;
 mod and;
 mod ar16;
@@ -23,32 +23,32 @@ impl Cpu {
         let opcode = self.memory_at_pc(0);
         let operand = self.read(Self::select(opcode & 0b111));
         self._or_with_accumulator(operand);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn or_n(&mut self) {
         let operand = self.memory_at_pc(1);
         self._or_with_accumulator(operand);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn or_hli(&mut self) {
         let operand = self.memory_at_hl();
         self._or_with_accumulator(operand);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn or_ixdi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_ix(offset);
         self._or_with_accumulator(operand);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
     pub fn or_iydi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_iy(offset);
         self._or_with_accumulator(operand);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 }
@@ -62,9 +62,10 @@ impl Cpu {
 
     fn rr_mem(&mut self, addr: usize) {
         let msb = if self.get_c() { 0x80 } else { 0 };
-        let carry = self.memory[addr].lsb();
-        let result = (self.memory[addr] >> 1) | msb;
-        self.memory[addr] = result;
+        let value = self.read_byte(addr as u16);
+        let carry = value.lsb();
+        let result = (value >> 1) | msb;
+        self.write_byte(addr as u16, result);
 
         self.set_h(false);
         self.set_n(false);
@@ -97,15 +98,13 @@ impl Cpu {
     }
 
     pub fn rr_ixdi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         self.rr_mem(addr);
         self.pc.reg_add(4);
     }
 
     pub fn rr_iydi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         self.rr_mem(addr);
         self.pc.reg_add(4);
     }
@@ -163,9 +162,10 @@ impl Cpu {
 
     fn rl_mem(&mut self, addr: usize) {
         let lsb = if self.get_c() { 1 } else { 0 };
-        let carry = self.memory[addr].msb();
-        let result = (self.memory[addr] << 1) | lsb;
-        self.memory[addr] = result;
+        let value = self.read_byte(addr as u16);
+        let carry = value.msb();
+        let result = (value << 1) | lsb;
+        self.write_byte(addr as u16, result);
 
         self.set_h(false);
         self.set_n(false);
@@ -198,15 +198,13 @@ impl Cpu {
     }
 
     pub fn rl_ixdi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         self.rl_mem(addr);
         self.pc.reg_add(4);
     }
 
     pub fn rl_iydi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         self.rl_mem(addr);
         self.pc.reg_add(4);
     }
@@ -330,8 +328,8 @@ impl Cpu {
     // === Rotate memory location left ===
     
     fn rlc_memory_location(&mut self, addr: usize) {
-        self.memory[addr] = self.memory[addr].rotate_left(1);
-        let result = self.memory[addr];
+        let result = self.read_byte(addr as u16).rotate_left(1);
+        self.write_byte(addr as u16, result);
         self.set_c(result.lsb());
         self.set_s_from_msb(result);
         self.set_z_from_byte(result);
@@ -347,13 +345,13 @@ impl Cpu {
     }
 
     pub fn rlc_ixdi(&mut self) {
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         self.rlc_memory_location(addr);
         self.pc.reg_add(4);
     }
 
     pub fn rlc_iydi(&mut self) {
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         self.rlc_memory_location(addr);
         self.pc.reg_add(4);
     }
@@ -361,8 +359,8 @@ impl Cpu {
     // === Rotate memory location right ===
     
     fn rrc_memory_location(&mut self, addr: usize) {
-        self.memory[addr] = self.memory[addr].rotate_right(1);
-        let result = self.memory[addr];
+        let result = self.read_byte(addr as u16).rotate_right(1);
+        self.write_byte(addr as u16, result);
         self.set_c(result.msb());
         self.set_s_from_msb(result);
         self.set_z_from_byte(result);
@@ -378,13 +376,13 @@ impl Cpu {
     }
 
     pub fn rrc_ixdi(&mut self) {
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         self.rrc_memory_location(addr);
         self.pc.reg_add(4);
     }
 
     pub fn rrc_iydi(&mut self) {
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         self.rrc_memory_location(addr);
         self.pc.reg_add(4);
     }
@@ -400,8 +398,10 @@ impl Cpu {
     pub fn rld(&mut self) {
         let addr = (self.h, self.l).promote() as usize;
         let a_low_nibble = self.a & 0x0f;
-        self.a = (self.a & 0xf0) | (self.memory[addr] >> 4);
-        self.memory[addr] = (self.memory[addr] << 4) | a_low_nibble;
+        let value = self.read_byte(addr as u16);
+        self.a = (self.a & 0xf0) | (value >> 4);
+        let value = (value << 4) | a_low_nibble;
+        self.write_byte(addr as u16, value);
 
         let result = self.a;
         self.set_s_from_msb(result);
@@ -409,6 +409,7 @@ impl Cpu {
         self.set_pv(!result.lsb());
         self.set_n(false);
         self.set_h(false);
+        self.wz = (addr as u16).wrapping_add(1);
 
         self.pc.reg_add(2);
     }
@@ -416,8 +417,10 @@ impl Cpu {
     pub fn rrd(&mut self) {
         let addr = (self.h, self.l).promote() as usize;
         let a_low_nibble = self.a & 0x0f;
-        self.a = (self.a & 0xf0) | (self.memory[addr] & 0x0f);
-        self.memory[addr] = (a_low_nibble << 4) | (self.memory[addr] >> 4);
+        let value = self.read_byte(addr as u16);
+        self.a = (self.a & 0xf0) | (value & 0x0f);
+        let value = (a_low_nibble << 4) | (value >> 4);
+        self.write_byte(addr as u16, value);
 
         let result = self.a;
         self.set_s_from_msb(result);
@@ -425,6 +428,7 @@ impl Cpu {
         self.set_pv(!result.lsb());
         self.set_n(false);
         self.set_h(false);
+        self.wz = (addr as u16).wrapping_add(1);
 
         self.pc.reg_add(2);
     }
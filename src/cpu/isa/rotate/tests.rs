@@ -517,3 +517,8 @@ fn rrd() {
         .carry_flag_is_reset()
         .program_counter_is(2);
 }
+
+isa_tests! {
+    rlc_iydi_negative: "RLC (IY-2)" { iy: 0x1010, (0x100e): 0x80 } => { (0x100e): 0x01, flags: "-----C" };
+    rlc_iydi_r: "RLC (IY+1),B" { iy: 0x100, (0x101): 0x80 } => { (0x101): 0x01, b: 0x01, flags: "-----C" };
+}
@@ -65,10 +65,10 @@ impl Cpu {
     }
 
     fn sl_mem(&mut self, addr: usize) {
-        let carry = self.memory[addr].msb();
-        self.set_c(carry);
-        let result = self.memory[addr] << 1;
-        self.memory[addr] = result;
+        let value = self.read_byte(addr as u16);
+        self.set_c(value.msb());
+        let result = value << 1;
+        self.write_byte(addr as u16, result);
 
         self.set_s(false);
         self.set_h(false);
@@ -158,12 +158,12 @@ impl Cpu {
     }
 
     fn sra_mem(&mut self, addr: usize) {
-        let carry = self.memory[addr].lsb();
-        self.set_c(carry);
-        let msb = self.memory[addr] & 0x80;
+        let value = self.read_byte(addr as u16);
+        self.set_c(value.lsb());
+        let msb = value & 0x80;
 
-        let result = self.memory[addr] >> 1 | msb;
-        self.memory[addr] = result;
+        let result = value >> 1 | msb;
+        self.write_byte(addr as u16, result);
 
         self.set_s(result.msb());
         self.set_z_from_byte(result);
@@ -179,15 +179,13 @@ impl Cpu {
     }
 
     pub fn sra_ixdi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         self.sra_mem(addr);
         self.pc.reg_add(4);
     }
 
     pub fn sra_iydi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         self.sra_mem(addr);
         self.pc.reg_add(4);
     }
@@ -246,11 +244,11 @@ impl Cpu {
     }
 
     fn srl_mem(&mut self, addr: usize) {
-        let carry = self.memory[addr].lsb();
-        self.set_c(carry);
+        let value = self.read_byte(addr as u16);
+        self.set_c(value.lsb());
 
-        let result = self.memory[addr] >> 1;
-        self.memory[addr] = result;
+        let result = value >> 1;
+        self.write_byte(addr as u16, result);
 
         self.set_s(false);
         self.set_z_from_byte(result);
@@ -266,16 +264,60 @@ impl Cpu {
     }
 
     pub fn srl_ixdi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.ix as usize + self.memory_at_pc(2) as usize;
+        let addr = self.ix_addr(self.memory_at_pc(2));
         self.srl_mem(addr);
         self.pc.reg_add(4);
     }
 
     pub fn srl_iydi(&mut self) {
-        // TODO: Manage negative offset
-        let addr = self.iy as usize + self.memory_at_pc(2) as usize;
+        let addr = self.iy_addr(self.memory_at_pc(2));
         self.srl_mem(addr);
         self.pc.reg_add(4);
     }
+
+    // === Shift registers left setting bit 0 (undocumented SLL) ===
+
+    fn _sll(&mut self, value: u8) -> u8 {
+        let result = value << 1 | 1;
+
+        self.set_c(value.msb());
+        self.set_s_from_msb(result);
+        self.set_z_from_byte(result);
+        self.set_h(false);
+        self.set_pv(result.count_ones() & 1 == 0);
+        self.set_n(false);
+        result
+    }
+
+    pub fn sll_r(&mut self) {
+        let opcode = self.memory_at_pc(1);
+        let value = self.read(Self::select_src(opcode));
+        let result = self._sll(value);
+        self.write(Self::select_src(opcode), result);
+        self.pc.reg_add(2);
+    }
+
+    fn sll_mem(&mut self, addr: usize) {
+        let value = self.read_byte(addr as u16);
+        let result = self._sll(value);
+        self.write_byte(addr as u16, result);
+    }
+
+    pub fn sll_hli(&mut self) {
+        let addr = (self.h, self.l).promote() as usize;
+        self.sll_mem(addr);
+        self.pc.reg_add(2);
+    }
+
+    pub fn sll_ixdi(&mut self) {
+        let addr = self.ix_addr(self.memory_at_pc(2));
+        self.sll_mem(addr);
+        self.pc.reg_add(4);
+    }
+
+    pub fn sll_iydi(&mut self) {
+        let addr = self.iy_addr(self.memory_at_pc(2));
+        self.sll_mem(addr);
+        self.pc.reg_add(4);
+    }
 }
@@ -242,3 +242,10 @@ fn srl_iydi() {
         .add_subtract_flag_is_reset()
         .program_counter_is(4);
 }
+
+isa_tests! {
+    sla_ixdi_negative: "SLA (IX-1)" { ix: 0x1010, (0x100f): 0x06 } => { (0x100f): 0x0c, flags: "---P--" };
+    sll_r: "SLL B" { b: 0x81 } => { b: 0x03, flags: "---P-C" };
+    sll_hli: "SLL (HL)" { hl: 0x100, (0x100): 0x40 } => { (0x100): 0x81, flags: "S--P--" };
+    sll_ixdi: "SLL (IX-1)" { ix: 0x1010, (0x100f): 0x00 } => { (0x100f): 0x01 };
+}
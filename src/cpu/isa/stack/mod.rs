@@ -13,20 +13,20 @@ mod tests;
 
 impl Cpu {
     fn push_byte(&mut self, value: u8) {
-        self.sp -= 1;
-        self.memory[self.sp as usize] = value;
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(self.sp, value);
     }
 
     fn pop_byte(&mut self) -> u8 {
-        let value = self.memory[self.sp as usize];
-        self.sp += 1;
+        let value = self.read_byte(self.sp);
+        self.sp = self.sp.wrapping_add(1);
         value
     }
 
     pub fn push_qq(&mut self) {
         let opcode = self.memory_at_pc(0);
 
-        let h = match Cpu::select_reg16(opcode) {
+        let h = match Cpu::select_push16(opcode) {
             Register16::bc => self.b,
             Register16::de => self.d,
             Register16::hl => self.h,
@@ -34,7 +34,7 @@ impl Cpu {
             _ => panic!(),
         };
 
-        let l = match Cpu::select_reg16(opcode) {
+        let l = match Cpu::select_push16(opcode) {
             Register16::bc => self.c,
             Register16::de => self.e,
             Register16::hl => self.l,
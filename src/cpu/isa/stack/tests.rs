@@ -135,3 +135,12 @@ Assertor::new(cpu)
     .stack_pointer_is(8)
     .program_counter_is(2);
 }
+
+isa_tests! {
+    push_qq_decoded: "PUSH BC" { bc: 0x1234, sp: 0x100 } => { sp: 0xfe, (0xff): 0x12, (0xfe): 0x34 };
+    pop_qq_decoded: "POP DE" { sp: 0xfe, (0xff): 0x12, (0xfe): 0x34 } => { de: 0x1234, sp: 0x100 };
+    push_ix_decoded: "PUSH IX" { ix: 0x1234, sp: 0x100 } => { sp: 0xfe, (0xff): 0x12, (0xfe): 0x34 };
+    push_qq_wraps_sp: "PUSH BC" { bc: 0x1234, sp: 0 } => { sp: 0xfffe, (0xffff): 0x12, (0xfffe): 0x34 };
+    pop_qq_wraps_sp: "POP DE" { sp: 0xffff, (0xffff): 0x34 } => { de: 0xd134, sp: 1 };
+    push_af: "PUSH AF" { af: 0x1234, sp: 0x100 } => { sp: 0xfe, (0xff): 0x12, (0xfe): 0x34 };
+}
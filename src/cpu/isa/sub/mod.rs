@@ -8,7 +8,7 @@ use cpu::Cpu;
 // === 8-Bit Arithmetic Group / ADD ===
 
 impl Cpu {
-    fn _sub_from_accumulator(&mut self, value: u8, value2: u8) {
+    pub fn _sub_from_accumulator(&mut self, value: u8, value2: u8) {
         let (mut result, mut carry) = self.a.overflowing_sub(value);
 
         // If value 2 is meaningful, add it and calculate carry.
@@ -24,10 +24,9 @@ impl Cpu {
         // Z is set if result is 0 (result=0).
         self.set_z_from_byte(result);
 
-        // P/V is set if overflow (overflow in twos complement).
-        // TODO: Verify that implementation complies with Z80 arch.
-        //let (_, overflow) = Cpu::compl2(result).overflowing_sub(Cpu::compl2(value));
-        //self.set_pv(overflow);
+        // P/V is set if overflow (overflow in twos complement): the
+        // operands have different signs, and the result the sign of the subtrahend.
+        self.set_pv((self.a ^ value) & (self.a ^ result) & 0x80 != 0);
 
         // N is reset (1).
         self.set_n(true);
@@ -48,7 +47,7 @@ impl Cpu {
 
         let halfcarry_sub_table = [false, false, true, false, true, false, true, true];
 
-        self.set_h(halfcarry_add_table[lookup & 0x07]);
+        self.set_h(halfcarry_sub_table[(lookup & 0x07) as usize]);
 
         self.a = result;
     }
@@ -57,33 +56,33 @@ impl Cpu {
         let opcode = self.memory_at_pc(0);
         let operand = self.read(Self::select(opcode & 0b111));
         self._sub_from_accumulator(operand, 0);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn sub_n(&mut self) {
         let operand = self.memory_at_pc(1);
         self._sub_from_accumulator(operand, 0);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     pub fn sub_hli(&mut self) {
         let operand = self.memory_at_hl();
         self._sub_from_accumulator(operand, 0);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn sub_ixdi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_ix(offset);
         self._sub_from_accumulator(operand, 0);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
     pub fn sub_iydi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_iy(offset);
         self._sub_from_accumulator(operand, 0);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
     pub fn sbc_a_r(&mut self) {
@@ -91,36 +90,36 @@ impl Cpu {
         let operand = self.read(Self::select(opcode & 0b111));
         let c_value = self.get_c_value();
         self._sub_from_accumulator(operand, c_value);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn sbc_a_n(&mut self) {
         let operand = self.memory_at_pc(1);
         let c_value = self.get_c_value();
         self._sub_from_accumulator(operand, c_value);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
-    fn sbc_a_hli(&mut self) {
+    pub fn sbc_a_hli(&mut self) {
         let operand = self.memory_at_hl();
         let c_value = self.get_c_value();
         self._sub_from_accumulator(operand, c_value);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
-    fn sbc_a_ixdi(&mut self) {
+    pub fn sbc_a_ixdi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_ix(offset);
         let c_value = self.get_c_value();
         self._sub_from_accumulator(operand, c_value);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 
-    fn sbc_a_iydi(&mut self) {
+    pub fn sbc_a_iydi(&mut self) {
         let offset = self.memory_at_pc(2);
         let operand = self.memory_at_iy(offset);
         let c_value = self.get_c_value();
         self._sub_from_accumulator(operand, c_value);
-        self.pc += 3;
+        self.pc = self.pc.wrapping_add(3);
     }
 }
@@ -195,3 +195,8 @@ fn sbc_a_iydi() {
         .add_subtract_flag_is_set()
         .program_counter_is(3);
 }
+
+isa_tests! {
+    sub_r_overflow: "SUB B" { a: 0x80, b: 1 } => { a: 0x7f, flags: "--H-VN" };
+    sbc_a_n_no_overflow: "SBC A,0x01" { a: 0x02, flags: "C" } => { a: 0x00, flags: "ZN" };
+}
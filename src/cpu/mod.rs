@@ -2,7 +2,19 @@ mod registers;
 mod reg8;
 mod reg16;
 mod reg88;
+mod breakpoints;
 mod builder;
+mod bus;
+mod callstack;
+mod coverage;
+mod decoder;
+mod dump;
+mod history;
+mod profile;
+mod runner;
+mod state;
+mod timing;
+mod trace;
 mod isa;
 #[cfg(test)]
 mod assertor;
@@ -12,7 +24,17 @@ mod tests;
 
 pub use self::registers::*;
 pub use self::registers::RegisterOperations;
+pub use self::breakpoints::{Breakpoint, Breakpoints, Kind};
 pub use self::builder::CpuBuilder;
+pub use self::bus::{Access, AccessKind, Ports};
+pub use self::callstack::{Anomaly, CallStack, Frame, FrameKind};
+pub use self::coverage::{Branch, Coverage};
+pub use self::dump::{dump_memory, flags_text};
+pub use self::history::History;
+pub use self::profile::{Counts, Profile, Routine};
+pub use self::runner::{Limits, StopReason};
+pub use self::state::{diff, CpuState, StateDiff};
+pub use self::trace::Trace;
 
 #[cfg(test)]
 pub use self::assertor::Assertor;
@@ -48,14 +70,58 @@ pub struct Cpu {
     pub iff1: bool,
     pub iff2: bool,
 
+    /// Set by HALT: the CPU executes NOPs without moving pc.
+    pub halted: bool,
+
+    /// Interrupt mode set by IM: 0, 1 or 2.
+    pub im: u8,
+
+    /// The internal MEMPTR register, kept in saved states. Jumps, calls
+    /// and returns, 16-bit and indexed memory accesses, ports and block
+    /// instructions update it as the Z80 does.
+    pub wz: u16,
+
+    /// T states elapsed since reset.
+    pub cycles: u64,
+
     pub memory: Vec<u8>,
+
+    /// Devices on the I/O ports.
+    pub ports: Option<Box<dyn Ports>>,
+
+    /// Data accesses of the last instruction executed.
+    pub accesses: Vec<Access>,
+
+    /// Checked by `run` before and after each instruction.
+    pub breakpoints: Breakpoints,
+
+    /// Records each instruction before `step` executes it.
+    pub trace: Option<Trace>,
+
+    /// Undo log of the last instructions, for stepping back.
+    pub history: Option<History>,
+
+    /// Counts instructions and T states as `step` executes them.
+    pub profile: Option<Profile>,
+
+    /// Marks the code executed and the data used as `step` executes them.
+    pub coverage: Option<Coverage>,
+
+    /// Frames of the routines being run, kept by calls and returns.
+    pub call_stack: Option<CallStack>,
 }
 
 #[allow(dead_code)]
 impl Cpu {
     /// Read memory at address: pc + offset
     fn memory_at_pc(&self, offset_from_pc: u16) -> u8 {
-        self.memory[usize::from(self.pc + offset_from_pc)]
+        self.memory[usize::from(self.pc.wrapping_add(offset_from_pc))]
+    }
+
+    /// MEMPTR after storing A at an address or port: the address
+    /// after it in the low byte, and A in the high byte.
+    fn memptr_after_store(&self, address: usize) -> u16 {
+        u16::from(self.a) << 8 | (address as u16).wrapping_add(1) & 0xff
     }
 
     /// Returns the memory address stored in the memory location at pc.
@@ -67,8 +133,13 @@ impl Cpu {
 
     fn condition_at_pc(&self, offset_from_pc: u16) -> bool {
         let data = self.memory_at_pc(offset_from_pc);
+        self.condition((data & 0b00_111_000) >> 3)
+    }
 
-        match (data & 0b00_111_000) >> 3 {
+    /// Whether the condition cc of a jump, call or return is met:
+    /// NZ, Z, NC, C, PO, PE, P, M.
+    fn condition(&self, cc: u8) -> bool {
+        match cc & 0b111 {
             0b000 => !self.get_z(),
             0b001 => self.get_z(),
             0b010 => !self.get_c(),
@@ -76,42 +147,47 @@ impl Cpu {
             0b100 => self.parity_is_odd(),
             0b101 => self.parity_is_even(),
             0b110 => self.sign_is_positive(),
-            0b111 => self.sign_is_negative(),
-            _ => panic!(),
+            _ => self.sign_is_negative(),
         }
     }
 
     /// Read memory at address: hl
-    fn memory_at_hl(&self) -> u8 {
+    fn memory_at_hl(&mut self) -> u8 {
         let addr = (self.h, self.l).promote();
-        self.memory[addr as usize]
+        self.read_byte(addr)
     }
 
     /// Read memory at address: ix + offset
-    fn memory_at_ix(&self, offset: u8) -> u8 {
+    fn memory_at_ix(&mut self, offset: u8) -> u8 {
         let addr = self.ix_addr(offset);
-        self.memory[addr]
+        self.read_byte(addr as u16)
     }
 
     /// Read memory at address: iy + offset
-    fn memory_at_iy(&self, offset: u8) -> u8 {
+    fn memory_at_iy(&mut self, offset: u8) -> u8 {
         let addr = self.iy_addr(offset);
-        self.memory[addr]
+        self.read_byte(addr as u16)
     }
 
+    /// Address of (HL+d), the displacement d being signed. Addresses
+    /// wrap around the 64K.
     fn hl_addr(&self, offset: u8) -> usize {
         let hl = (self.h, self.l).promote();
-        let addr = i32::from(hl) + i32::from(offset);
-        addr as usize
+        usize::from(displace(hl, offset))
     }
 
+    /// Address of (IX+d), the displacement d being signed.
     fn ix_addr(&self, offset: u8) -> usize {
-        let addr = i32::from(self.ix) + i32::from(offset);
-        addr as usize
+        usize::from(displace(self.ix, offset))
     }
 
+    /// Address of (IY+d), the displacement d being signed.
     fn iy_addr(&self, offset: u8) -> usize {
-        let addr = i32::from(self.iy) + i32::from(offset);
-        addr as usize
+        usize::from(displace(self.iy, offset))
     }
 }
+
+/// Add a signed displacement byte to an address.
+fn displace(address: u16, offset: u8) -> u16 {
+    address.wrapping_add(offset as i8 as u16)
+}
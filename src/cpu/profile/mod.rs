@@ -0,0 +1,227 @@
+#[cfg(test)]
+mod tests;
+
+use disasm::disassemble;
+use std::collections::HashMap;
+use symbols::SymbolTable;
+
+// === Profiler ===
+//
+// Instructions and T states are counted per address and per stack of
+// routines. Routines are found by following calls: a CALL or RST that
+// jumps enters the routine at its target, and the routine is left when
+// SP rises above the return address pushed by the call, which covers
+// RET, RETI, RETN and code dropping its return address. An instruction
+// is counted in the routine running it: a CALL in the caller, a RET in
+// the routine returning.
+//
+// The stacks are written in the collapsed format of flamegraph tools,
+// a line per stack as in `start;print;putc 1234`.
+
+/// Instructions executed and T states spent.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Counts {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+}
+
+/// Counts of a routine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Routine {
+    /// Entry address.
+    pub address: u16,
+    /// Times it was called.
+    pub calls: u64,
+    /// Spent in the routine itself.
+    pub own: Counts,
+    /// Spent in the routine and the routines it called.
+    pub total: Counts,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    routine: u16,
+    /// SP after the call, pointing to the return address.
+    sp: u16,
+}
+
+/// Execution profile, collected by `step` while set on the CPU.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    addresses: Vec<Counts>,
+    /// Routines being run, the outermost first: the first one is where
+    /// profiling started, and is never left.
+    frames: Vec<Frame>,
+    stacks: HashMap<Vec<u16>, Counts>,
+    calls: HashMap<u16, u64>,
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile {
+            addresses: vec![Counts::default(); 0x10000],
+            frames: Vec::new(),
+            stacks: HashMap::new(),
+            calls: HashMap::new(),
+        }
+    }
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    /// Count an instruction executed at pc, which left pc at next and
+    /// SP at sp in cycles T states.
+    pub fn record(&mut self, pc: u16, opcode: u8, next: u16, sp: u16, cycles: u8) {
+        let counts = Counts {
+            instructions: 1,
+            cycles: u64::from(cycles),
+        };
+        self.addresses[usize::from(pc)].add(counts);
+
+        if self.frames.is_empty() {
+            self.frames.push(Frame { routine: pc, sp });
+        }
+
+        let stack: Vec<u16> = self.frames.iter().map(|frame| frame.routine).collect();
+        self.stacks.entry(stack).or_default().add(counts);
+
+        while self.frames.len() > 1 {
+            // Wrapping: a call with SP at 0 pushes on 0xfffe.
+            let top = self.frames.last().map_or(0, |frame| frame.sp);
+            if (sp.wrapping_sub(top) as i16) <= 0 {
+                break;
+            }
+            self.frames.pop();
+        }
+
+        let length = match opcode {
+            0xcd | 0xc4 | 0xcc | 0xd4 | 0xdc | 0xe4 | 0xec | 0xf4 | 0xfc => 3,
+            _ if opcode & 0xc7 == 0xc7 => 1,
+            _ => return,
+        };
+        if next != pc.wrapping_add(length) {
+            *self.calls.entry(next).or_insert(0) += 1;
+            self.frames.push(Frame { routine: next, sp });
+        }
+    }
+
+    /// Counts of the instruction at address.
+    pub fn at(&self, address: u16) -> Counts {
+        self.addresses[usize::from(address)]
+    }
+
+    pub fn total(&self) -> Counts {
+        let mut total = Counts::default();
+        for counts in self.stacks.values() {
+            total.add(*counts);
+        }
+        total
+    }
+
+    /// The routines run, the most expensive first.
+    pub fn routines(&self) -> Vec<Routine> {
+        let mut routines: HashMap<u16, Routine> = HashMap::new();
+
+        for (stack, &counts) in &self.stacks {
+            for (depth, &address) in stack.iter().enumerate() {
+                let routine = routines.entry(address).or_insert(Routine {
+                    address,
+                    calls: self.calls.get(&address).cloned().unwrap_or(0),
+                    own: Counts::default(),
+                    total: Counts::default(),
+                });
+                if depth == stack.len() - 1 {
+                    routine.own.add(counts);
+                }
+                // Recursive routines count once per stack.
+                if !stack[..depth].contains(&address) {
+                    routine.total.add(counts);
+                }
+            }
+        }
+
+        let mut routines: Vec<Routine> = routines.into_values().collect();
+        routines.sort_by_key(|routine| (!routine.total.cycles, routine.address));
+        routines
+    }
+
+    /// Text report of the routines, then of the instructions, by T states
+    /// spent. Addresses are written by symbol.
+    pub fn report(&self, memory: &[u8], symbols: &SymbolTable) -> String {
+        let total = self.total().cycles.max(1);
+        let percent = |cycles: u64| cycles as f64 * 100.0 / total as f64;
+
+        let mut text = format!(
+            "{} instructions, {} T states\n\nRoutines:\n{:>12} {:>6} {:>12} {:>6} {:>8}  routine\n",
+            self.total().instructions,
+            self.total().cycles,
+            "total",
+            "%",
+            "own",
+            "%",
+            "calls"
+        );
+        for routine in self.routines() {
+            text.push_str(&format!(
+                "{:>12} {:>6.2} {:>12} {:>6.2} {:>8}  {}\n",
+                routine.total.cycles,
+                percent(routine.total.cycles),
+                routine.own.cycles,
+                percent(routine.own.cycles),
+                routine.calls,
+                symbols.symbolize(routine.address)
+            ));
+        }
+
+        let mut addresses: Vec<(u16, Counts)> = (0..=0xffff)
+            .map(|address| (address, self.at(address)))
+            .filter(|&(_, counts)| counts.instructions > 0)
+            .collect();
+        addresses.sort_by_key(|&(address, counts)| (!counts.cycles, address));
+
+        text.push_str(&format!(
+            "\nInstructions:\n{:>12} {:>6} {:>12}  address\n",
+            "T states", "%", "count"
+        ));
+        for (address, counts) in addresses {
+            text.push_str(&format!(
+                "{:>12} {:>6.2} {:>12}  {:04x} {:<16} {}\n",
+                counts.cycles,
+                percent(counts.cycles),
+                counts.instructions,
+                address,
+                symbols.symbolize(address),
+                disassemble(memory, address).symbolic_text(symbols)
+            ));
+        }
+
+        text
+    }
+
+    /// T states of each stack of routines, in the collapsed format.
+    pub fn collapsed(&self, symbols: &SymbolTable) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, counts)| {
+                let names: Vec<String> = stack
+                    .iter()
+                    .map(|&address| symbols.symbolize(address))
+                    .collect();
+                format!("{} {}\n", names.join(";"), counts.cycles)
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
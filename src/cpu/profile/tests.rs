@@ -0,0 +1,132 @@
+use cpu::{Counts, CpuBuilder, Limits, Profile};
+use symbols::SymbolTable;
+
+fn profiled(code: Vec<u8>) -> Profile {
+    let mut memory = code;
+    memory.resize(0x100, 0);
+    let mut cpu = CpuBuilder::new().with_memory(memory).build();
+    cpu.profile = Some(Profile::new());
+    cpu.run(Limits::default());
+    cpu.profile.take().unwrap()
+}
+
+fn symbols() -> SymbolTable {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("start", 0x00);
+    symbols.insert("twice", 0x0a);
+    symbols.insert("once", 0x11);
+    symbols
+}
+
+fn program() -> Vec<u8> {
+    asm!(
+        "       LD SP,0xf0",
+        "       CALL twice",
+        "       CALL twice",
+        "       HALT",
+        "twice: CALL once",
+        "       CALL once",
+        "       RET",
+        "once:  NOP",
+        "       RET"
+    )
+}
+
+#[test]
+fn counts() {
+    let profile = profiled(program());
+
+    // start: LD SP 10, 2 CALL 17, HALT 4; twice: 2 × (2 CALL 17,
+    // RET 10); once: 4 × (NOP 4, RET 10).
+    assert_eq!(
+        profile.total(),
+        Counts {
+            instructions: 4 + 6 + 8,
+            cycles: 48 + 88 + 56
+        }
+    );
+    assert_eq!(
+        profile.at(0x11),
+        Counts {
+            instructions: 4,
+            cycles: 16
+        }
+    );
+    assert_eq!(profile.at(0x13), Counts::default());
+
+    let routines: Vec<(u16, u64, u64, u64)> = profile
+        .routines()
+        .iter()
+        .map(|r| (r.address, r.calls, r.own.cycles, r.total.cycles))
+        .collect();
+    assert_eq!(
+        routines,
+        vec![(0x00, 0, 48, 192), (0x0a, 2, 88, 144), (0x11, 4, 56, 56)]
+    );
+}
+
+#[test]
+fn collapsed() {
+    let profile = profiled(program());
+
+    assert_eq!(
+        profile.collapsed(&symbols()),
+        "start 48\nstart;twice 88\nstart;twice;once 56\n"
+    );
+}
+
+#[test]
+fn recursion_and_dropped_returns() {
+    // down calls itself three times; skip drops its return address.
+    let profile = profiled(asm!(
+        "      LD SP,0xf0",
+        "      LD B,3",
+        "      CALL down",
+        "      CALL skip",
+        "back: HALT",
+        "down: DJNZ deep",
+        "      RET",
+        "deep: CALL down",
+        "      RET",
+        "skip: POP HL",
+        "      JP back"
+    ));
+
+    let collapsed = profile.collapsed(&SymbolTable::new());
+    assert!(collapsed.contains("0x0000;0x000c;0x000c;0x000c 18\n"));
+    assert!(collapsed.contains("0x0000;0x0013 10\n"));
+    // HALT runs in the program again.
+    assert_eq!(profile.routines()[0].own.cycles, 10 + 7 + 17 + 17 + 10 + 4);
+
+    let down = profile.routines()[1];
+    assert_eq!((down.address, down.calls), (0x0c, 3));
+    assert_eq!(down.total.cycles, 2 * 13 + 8 + 2 * 17 + 3 * 10);
+}
+
+#[test]
+fn report() {
+    let mut memory = program();
+    memory.resize(0x100, 0);
+    let report = profiled(program()).report(&memory, &symbols());
+    let lines: Vec<&str> = report.lines().collect();
+
+    assert_eq!(lines[0], "18 instructions, 192 T states");
+    assert_eq!(lines[2], "Routines:");
+    assert_eq!(
+        lines[4],
+        "         192 100.00           48  25.00        0  start"
+    );
+    assert_eq!(
+        lines[6],
+        "          56  29.17           56  29.17        4  once"
+    );
+    assert_eq!(lines[8], "Instructions:");
+    assert_eq!(
+        lines[10],
+        "          40  20.83            4  0012 once+1           RET"
+    );
+    assert_eq!(
+        lines[11],
+        "          34  17.71            2  000a twice            CALL once"
+    );
+}
@@ -357,6 +357,75 @@ impl Cpu {
         let hl = i32::from(self.read_hl()) + i32::from(value);
         self.write_hl(hl as u16);
     }
+
+    // ===== Registers by name =====
+
+    /// Read a register by name, as in `hl` or `af'`.
+    pub fn read_named(&self, name: &str) -> Option<u16> {
+        let value = match name.to_lowercase().as_str() {
+            "a" => u16::from(self.a),
+            "f" => u16::from(self.f),
+            "b" => u16::from(self.b),
+            "c" => u16::from(self.c),
+            "d" => u16::from(self.d),
+            "e" => u16::from(self.e),
+            "h" => u16::from(self.h),
+            "l" => u16::from(self.l),
+            "i" => u16::from(self.i),
+            "r" => u16::from(self.r),
+            "af" => self.read_af(),
+            "bc" => self.read_bc(),
+            "de" => self.read_de(),
+            "hl" => self.read_hl(),
+            "af'" => (self.a1, self.f1).promote(),
+            "bc'" => (self.b1, self.c1).promote(),
+            "de'" => (self.d1, self.e1).promote(),
+            "hl'" => (self.h1, self.l1).promote(),
+            "ix" => self.ix,
+            "iy" => self.iy,
+            "sp" => self.sp,
+            "pc" => self.pc,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Write a register by name, 8 bit registers taking the low byte
+    /// of value. Setting pc resumes a halted CPU.
+    /// Returns false for unknown names.
+    pub fn write_named(&mut self, name: &str, value: u16) -> bool {
+        let (high, low) = (value.high(), value.low());
+
+        match name.to_lowercase().as_str() {
+            "a" => self.a = low,
+            "f" => self.f = low,
+            "b" => self.b = low,
+            "c" => self.c = low,
+            "d" => self.d = low,
+            "e" => self.e = low,
+            "h" => self.h = low,
+            "l" => self.l = low,
+            "i" => self.i = low,
+            "r" => self.r = low,
+            "af" => self.write_af(value),
+            "bc" => self.write_bc(value),
+            "de" => self.write_de(value),
+            "hl" => self.write_hl(value),
+            "af'" => (self.a1, self.f1) = (high, low),
+            "bc'" => (self.b1, self.c1) = (high, low),
+            "de'" => (self.d1, self.e1) = (high, low),
+            "hl'" => (self.h1, self.l1) = (high, low),
+            "ix" => self.ix = value,
+            "iy" => self.iy = value,
+            "sp" => self.sp = value,
+            "pc" => {
+                self.pc = value;
+                self.halted = false;
+            }
+            _ => return false,
+        }
+        true
+    }
 }
 
 #[cfg(test)]
@@ -594,6 +663,23 @@ mod tests {
         assert_eq!(cpu.read16(Register16::de), 0xabcd as u16);
     }
 
+    #[test]
+    fn registers_by_name() {
+        let mut cpu = CpuBuilder::new().with_memory_size(16).build();
+        cpu.halted = true;
+
+        assert!(cpu.write_named("HL", 0x1234));
+        assert!(cpu.write_named("bc'", 0xabcd));
+        assert!(cpu.write_named("pc", 0x100));
+        assert!(!cpu.write_named("q", 0));
+
+        assert_eq!((cpu.h, cpu.l, cpu.b1, cpu.c1), (0x12, 0x34, 0xab, 0xcd));
+        assert_eq!(cpu.read_named("h"), Some(0x12));
+        assert_eq!(cpu.read_named("pc"), Some(0x100));
+        assert_eq!(cpu.read_named("q"), None);
+        assert!(!cpu.halted);
+    }
+
     #[test]
     fn write_de() {
         let mut cpu = CpuBuilder::new().with_memory_size(16).build();
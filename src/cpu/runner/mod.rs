@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::{Access, Cpu};
+use std::fmt;
+
+/// Limits of a run: None means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Limits {
+    pub instructions: Option<u64>,
+    pub cycles: Option<u64>,
+}
+
+/// Why a run stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// The CPU executed HALT.
+    Halted,
+    /// The given number of instructions has been executed.
+    InstructionLimit,
+    /// The given number of T states has elapsed.
+    CycleLimit,
+    /// pc reached the execution breakpoint with this id.
+    Breakpoint(usize),
+    /// The last instruction made an access watched by the watchpoint
+    /// with this id.
+    Watchpoint(usize, Access),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StopReason::Halted => write!(f, "HALT"),
+            StopReason::InstructionLimit => write!(f, "instruction limit"),
+            StopReason::CycleLimit => write!(f, "cycle limit"),
+            StopReason::Breakpoint(id) => write!(f, "breakpoint {}", id),
+            StopReason::Watchpoint(id, access) => write!(f, "watchpoint {}, {}", id, access),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Cpu {
+    /// Execute instructions until HALT, a breakpoint or one of the limits
+    /// is reached. Returns the reason and the number of instructions
+    /// executed. A breakpoint at pc when the run starts does not stop it,
+    /// so that a run stopped by a breakpoint can be resumed.
+    pub fn run(&mut self, limits: Limits) -> (StopReason, u64) {
+        let start = self.cycles;
+        let mut executed = 0;
+
+        loop {
+            if self.halted {
+                return (StopReason::Halted, executed);
+            }
+
+            if limits.instructions.is_some_and(|max| executed >= max) {
+                return (StopReason::InstructionLimit, executed);
+            }
+
+            if limits.cycles.is_some_and(|max| self.cycles - start >= max) {
+                return (StopReason::CycleLimit, executed);
+            }
+
+            match self.step_checked(executed == 0) {
+                Some(reason @ StopReason::Breakpoint(_)) => return (reason, executed),
+                Some(reason) => return (reason, executed + 1),
+                None => executed += 1,
+            }
+        }
+    }
+
+    /// Execute one instruction, checking breakpoints as `run` does:
+    /// returns the execution breakpoint at pc, without executing the
+    /// instruction unless `resume` is set, or the watchpoint hit by it.
+    pub fn step_checked(&mut self, resume: bool) -> Option<StopReason> {
+        if !resume {
+            if let Some(id) = self.breakpoints.execution_hit(self) {
+                return Some(StopReason::Breakpoint(id));
+            }
+        }
+
+        self.step();
+
+        self.breakpoints
+            .access_hit(self)
+            .map(|(id, access)| StopReason::Watchpoint(id, access))
+    }
+}
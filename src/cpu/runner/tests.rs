@@ -0,0 +1,71 @@
+use cpu::{CpuBuilder, Limits, StopReason};
+
+fn cpu(code: Vec<u8>) -> ::cpu::Cpu {
+    let mut memory = code;
+    memory.resize(0x100, 0);
+    CpuBuilder::new().with_memory(memory).build()
+}
+
+#[test]
+fn runs_until_halt() {
+    // LD B,3; DJNZ $; HALT
+    let mut cpu = cpu(asm!("LD B,3", "DJNZ $", "HALT"));
+
+    let (reason, executed) = cpu.run(Limits::default());
+
+    assert_eq!(reason, StopReason::Halted);
+    assert_eq!(executed, 5);
+    assert_eq!(cpu.pc, 5);
+    assert_eq!(cpu.cycles, 7 + 13 + 13 + 8 + 4);
+}
+
+#[test]
+fn stops_at_limits() {
+    let mut cpu = cpu(asm!("loop: JR loop"));
+
+    let limits = Limits {
+        instructions: Some(10),
+        cycles: None,
+    };
+    assert_eq!(cpu.run(limits), (StopReason::InstructionLimit, 10));
+    assert_eq!(cpu.cycles, 120);
+
+    let limits = Limits {
+        instructions: None,
+        cycles: Some(30),
+    };
+    assert_eq!(cpu.run(limits), (StopReason::CycleLimit, 3));
+    assert_eq!(cpu.cycles, 156);
+}
+
+#[test]
+fn halted_cpu_executes_nops() {
+    let mut cpu = cpu(asm!("HALT"));
+
+    cpu.step();
+    cpu.step();
+
+    assert!(cpu.halted);
+    assert_eq!(cpu.pc, 1);
+    assert_eq!(cpu.cycles, 8);
+}
+
+#[test]
+fn repeats_block_instructions_one_step_at_a_time() {
+    let mut cpu = cpu(asm!("LD BC,100", "LDIR", "HALT"));
+
+    let (reason, executed) = cpu.run(Limits::default());
+
+    assert_eq!(reason, StopReason::Halted);
+    assert_eq!(executed, 1 + 100 + 1);
+    assert_eq!(cpu.cycles, 10 + 99 * 21 + 16 + 4);
+}
+
+#[test]
+fn times_branches_to_the_next_instruction() {
+    let mut cpu = cpu(asm!("LD B,2", "JR NZ,$+2", "DJNZ $+2", "HALT"));
+
+    cpu.run(Limits::default());
+
+    assert_eq!(cpu.cycles, 7 + 12 + 13 + 4);
+}
@@ -0,0 +1,356 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::Cpu;
+use loader::LoadError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A snapshot of the whole CPU status: registers, flags,
+/// interrupt flip flops and memory.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CpuState {
+    pub pc: u16,
+    pub sp: u16,
+    pub ix: u16,
+    pub iy: u16,
+
+    pub i: u8,
+    pub r: u8,
+
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub f: u8,
+    pub h: u8,
+    pub l: u8,
+
+    pub a1: u8,
+    pub b1: u8,
+    pub c1: u8,
+    pub d1: u8,
+    pub e1: u8,
+    pub f1: u8,
+    pub h1: u8,
+    pub l1: u8,
+
+    pub iff1: bool,
+    pub iff2: bool,
+
+    pub halted: bool,
+
+    pub im: u8,
+    pub wz: u16,
+
+    /// T states elapsed since reset.
+    pub cycles: u64,
+
+    pub memory: Vec<u8>,
+}
+
+impl<'a> From<&'a Cpu> for CpuState {
+    fn from(cpu: &'a Cpu) -> CpuState {
+        CpuState {
+            pc: cpu.pc,
+            sp: cpu.sp,
+            ix: cpu.ix,
+            iy: cpu.iy,
+            i: cpu.i,
+            r: cpu.r,
+            a: cpu.a,
+            b: cpu.b,
+            c: cpu.c,
+            d: cpu.d,
+            e: cpu.e,
+            f: cpu.f,
+            h: cpu.h,
+            l: cpu.l,
+            a1: cpu.a1,
+            b1: cpu.b1,
+            c1: cpu.c1,
+            d1: cpu.d1,
+            e1: cpu.e1,
+            f1: cpu.f1,
+            h1: cpu.h1,
+            l1: cpu.l1,
+            iff1: cpu.iff1,
+            iff2: cpu.iff2,
+            halted: cpu.halted,
+            im: cpu.im,
+            wz: cpu.wz,
+            cycles: cpu.cycles,
+            memory: cpu.memory.clone(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Cpu {
+    /// Take a snapshot of the current CPU status.
+    pub fn state(&self) -> CpuState {
+        CpuState::from(self)
+    }
+
+    /// Restore the CPU status from a snapshot.
+    pub fn restore(&mut self, state: &CpuState) {
+        self.pc = state.pc;
+        self.sp = state.sp;
+        self.ix = state.ix;
+        self.iy = state.iy;
+        self.i = state.i;
+        self.r = state.r;
+        self.a = state.a;
+        self.b = state.b;
+        self.c = state.c;
+        self.d = state.d;
+        self.e = state.e;
+        self.f = state.f;
+        self.h = state.h;
+        self.l = state.l;
+        self.a1 = state.a1;
+        self.b1 = state.b1;
+        self.c1 = state.c1;
+        self.d1 = state.d1;
+        self.e1 = state.e1;
+        self.f1 = state.f1;
+        self.h1 = state.h1;
+        self.l1 = state.l1;
+        self.iff1 = state.iff1;
+        self.iff2 = state.iff2;
+        self.halted = state.halted;
+        self.im = state.im;
+        self.wz = state.wz;
+        self.cycles = state.cycles;
+        self.memory = state.memory.clone();
+    }
+}
+
+// === Save states ===
+//
+// A state saved to a file, little endian, so that a session can be
+// restored later or elsewhere:
+//
+//   0   "Z80S"
+//   4   version of the format, 1
+//   6   PC, SP, IX, IY and WZ, 16 bits each
+//   16  I, R, A, F, B, C, D, E, H, L, their shadows A' to L', and IM
+//   35  IFF1 in bit 0, IFF2 in bit 1, HALT in bit 2
+//   36  T states, 64 bits
+//   44  memory size, 32 bits, then the memory
+//
+// Later versions add fields after those of the previous ones and raise
+// the version number; states of newer versions are rejected.
+
+const STATE_MAGIC: &[u8] = b"Z80S";
+const STATE_VERSION: u16 = 1;
+const STATE_HEADER: usize = 48;
+
+impl CpuState {
+    /// The state in the save state format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(STATE_HEADER + self.memory.len());
+        data.extend(STATE_MAGIC);
+        data.extend(&STATE_VERSION.to_le_bytes());
+        for word in &[self.pc, self.sp, self.ix, self.iy, self.wz] {
+            data.extend(&word.to_le_bytes());
+        }
+        data.extend(&[
+            self.i, self.r, self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+            self.a1, self.f1, self.b1, self.c1, self.d1, self.e1, self.h1, self.l1, self.im,
+        ]);
+        data.push(self.iff1 as u8 | (self.iff2 as u8) << 1 | (self.halted as u8) << 2);
+        data.extend(&self.cycles.to_le_bytes());
+        data.extend(&(self.memory.len() as u32).to_le_bytes());
+        data.extend(&self.memory);
+        data
+    }
+
+    /// Read a state saved by `to_bytes`.
+    pub fn from_bytes(data: &[u8]) -> Result<CpuState, LoadError> {
+        if !data.starts_with(STATE_MAGIC) {
+            return Err(LoadError::new("Not a save state".to_string()));
+        }
+        if data.len() < STATE_HEADER {
+            return Err(LoadError::new("Truncated save state".to_string()));
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let version = word(4);
+        if version > STATE_VERSION {
+            return Err(LoadError::new(format!(
+                "Save state version {} is newer than {}",
+                version, STATE_VERSION
+            )));
+        }
+
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&data[36..44]);
+        let size = u32::from_le_bytes([data[44], data[45], data[46], data[47]]) as usize;
+        if data.len() != STATE_HEADER + size {
+            return Err(LoadError::new(format!(
+                "Save state of {} bytes, {} expected",
+                data.len(),
+                STATE_HEADER + size
+            )));
+        }
+
+        let byte = |index: usize| data[16 + index];
+        Ok(CpuState {
+            pc: word(6),
+            sp: word(8),
+            ix: word(10),
+            iy: word(12),
+            wz: word(14),
+            i: byte(0),
+            r: byte(1),
+            a: byte(2),
+            f: byte(3),
+            b: byte(4),
+            c: byte(5),
+            d: byte(6),
+            e: byte(7),
+            h: byte(8),
+            l: byte(9),
+            a1: byte(10),
+            f1: byte(11),
+            b1: byte(12),
+            c1: byte(13),
+            d1: byte(14),
+            e1: byte(15),
+            h1: byte(16),
+            l1: byte(17),
+            im: byte(18),
+            iff1: data[35] & 1 != 0,
+            iff2: data[35] & 2 != 0,
+            halted: data[35] & 4 != 0,
+            cycles: u64::from_le_bytes(cycles),
+            memory: data[STATE_HEADER..].to_vec(),
+        })
+    }
+}
+
+/// Status register bit names, from bit 7 to bit 0.
+/// Undocumented bits 5 and 3 are shown as '-'.
+const FLAG_NAMES: &str = "SZ-H-PNC";
+
+/// The list of differences between two CPU snapshots,
+/// one human readable line per changed item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateDiff {
+    pub changes: Vec<String>,
+}
+
+#[allow(dead_code)]
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    fn byte(&mut self, name: &str, before: u8, after: u8) {
+        if before != after {
+            self.changes
+                .push(format!("{}: 0x{:02x} -> 0x{:02x}", name, before, after));
+        }
+    }
+
+    fn word(&mut self, name: &str, before: u16, after: u16) {
+        if before != after {
+            self.changes
+                .push(format!("{}: 0x{:04x} -> 0x{:04x}", name, before, after));
+        }
+    }
+
+    fn flags(&mut self, name: &str, before: u8, after: u8) {
+        if before != after {
+            self.changes.push(format!(
+                "{}: {} {:08b} -> {:08b}",
+                name, FLAG_NAMES, before, after
+            ));
+        }
+    }
+
+    fn boolean(&mut self, name: &str, before: bool, after: bool) {
+        if before != after {
+            self.changes
+                .push(format!("{}: {} -> {}", name, before, after));
+        }
+    }
+
+    fn memory(&mut self, before: &[u8], after: &[u8]) {
+        if before.len() != after.len() {
+            self.changes.push(format!(
+                "memory size: {} -> {}",
+                before.len(),
+                after.len()
+            ));
+        }
+
+        for (addr, (b, a)) in before.iter().zip(after.iter()).enumerate() {
+            if b != a {
+                self.changes
+                    .push(format!("(0x{:04x}): 0x{:02x} -> 0x{:02x}", addr, b, a));
+            }
+        }
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// List every register, flag and memory byte that changed
+/// between two snapshots.
+pub fn diff(before: &CpuState, after: &CpuState) -> StateDiff {
+    let mut diff = StateDiff {
+        changes: Vec::new(),
+    };
+
+    diff.word("PC", before.pc, after.pc);
+    diff.word("SP", before.sp, after.sp);
+    diff.word("IX", before.ix, after.ix);
+    diff.word("IY", before.iy, after.iy);
+
+    diff.byte("I", before.i, after.i);
+    diff.byte("R", before.r, after.r);
+
+    diff.byte("A", before.a, after.a);
+    diff.flags("F", before.f, after.f);
+    diff.byte("B", before.b, after.b);
+    diff.byte("C", before.c, after.c);
+    diff.byte("D", before.d, after.d);
+    diff.byte("E", before.e, after.e);
+    diff.byte("H", before.h, after.h);
+    diff.byte("L", before.l, after.l);
+
+    diff.byte("A'", before.a1, after.a1);
+    diff.flags("F'", before.f1, after.f1);
+    diff.byte("B'", before.b1, after.b1);
+    diff.byte("C'", before.c1, after.c1);
+    diff.byte("D'", before.d1, after.d1);
+    diff.byte("E'", before.e1, after.e1);
+    diff.byte("H'", before.h1, after.h1);
+    diff.byte("L'", before.l1, after.l1);
+
+    diff.boolean("IFF1", before.iff1, after.iff1);
+    diff.boolean("IFF2", before.iff2, after.iff2);
+    diff.boolean("HALT", before.halted, after.halted);
+    diff.byte("IM", before.im, after.im);
+    diff.word("WZ", before.wz, after.wz);
+
+    diff.memory(&before.memory, &after.memory);
+
+    diff
+}
@@ -0,0 +1,143 @@
+use cpu::CpuBuilder;
+use cpu::state::diff;
+use cpu::CpuState;
+
+#[test]
+fn no_changes() {
+    let cpu = CpuBuilder::new().with_memory(vec![0; 4]).build();
+
+    let before = cpu.state();
+    let after = cpu.state();
+
+    assert!(diff(&before, &after).is_empty());
+}
+
+#[test]
+fn changed_registers() {
+    let cpu = CpuBuilder::new().with_memory(vec![0; 4]).build();
+
+    let before = cpu.state();
+    let mut after = before.clone();
+    after.a = 0x80;
+    after.pc = 0x0001;
+    after.a1 = 0x01;
+    after.iff1 = true;
+
+    let changes = diff(&before, &after).changes;
+
+    assert_eq!(
+        changes,
+        vec![
+            "PC: 0x0000 -> 0x0001",
+            "A: 0x00 -> 0x80",
+            "A': 0x00 -> 0x01",
+            "IFF1: false -> true",
+        ]
+    );
+}
+
+#[test]
+fn changed_flags() {
+    let cpu = CpuBuilder::new()
+        .with_memory(vec![0; 4])
+        .with_flag_z(true)
+        .with_flag_h(true)
+        .with_flag_n(true)
+        .build();
+
+    let before = cpu.state();
+    let mut after = before.clone();
+    after.f = 0b0100_0011;
+
+    assert_eq!(
+        diff(&before, &after).changes,
+        vec!["F: SZ-H-PNC 01010010 -> 01000011"]
+    );
+}
+
+#[test]
+fn restore() {
+    let mut cpu = CpuBuilder::new().with_memory(vec![0; 4]).build();
+
+    let mut state = cpu.state();
+    state.a = 0x12;
+    state.sp = 0x1234;
+    state.iff2 = true;
+    state.memory[3] = 0x56;
+
+    cpu.restore(&state);
+
+    assert_eq!(cpu.state(), state);
+}
+
+#[test]
+fn save_state() {
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![1, 2, 3])
+        .with_pc(0x1234)
+        .with_ix(0xabcd)
+        .with_im(2)
+        .with_wz(0x5678)
+        .with_iff2(true)
+        .build();
+    cpu.h1 = 0x99;
+    cpu.halted = true;
+    cpu.cycles = 0x1_0000_0001;
+
+    let data = cpu.state().to_bytes();
+    assert_eq!(&data[..8], b"Z80S\x01\x00\x34\x12");
+    assert_eq!(data.len(), 48 + 3);
+    assert_eq!(CpuState::from_bytes(&data), Ok(cpu.state()));
+
+    let mut restored = CpuBuilder::new().with_memory(Vec::new()).build();
+    restored.restore(&CpuState::from_bytes(&data).unwrap());
+    assert_eq!(
+        (restored.im, restored.wz, restored.cycles, restored.halted),
+        (2, 0x5678, 0x1_0000_0001, true)
+    );
+}
+
+#[test]
+fn save_state_errors() {
+    let data = CpuBuilder::new()
+        .with_memory(vec![0; 16])
+        .build()
+        .state()
+        .to_bytes();
+    let error = |data: &[u8]| CpuState::from_bytes(data).unwrap_err().message;
+
+    assert_eq!(error(b"Z80X"), "Not a save state");
+    assert_eq!(error(&data[..40]), "Truncated save state");
+    assert_eq!(error(&data[..60]), "Save state of 60 bytes, 64 expected");
+    let mut newer = data.clone();
+    newer[4] = 2;
+    assert_eq!(error(&newer), "Save state version 2 is newer than 1");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+    fn serializable<T: ::serde::Serialize + ::serde::de::DeserializeOwned>() {}
+    serializable::<CpuState>();
+}
+
+#[test]
+fn changed_memory() {
+    let cpu = CpuBuilder::new().with_memory(vec![0; 4]).build();
+
+    let before = cpu.state();
+    let mut after = before.clone();
+    after.memory[2] = 0xff;
+    after.memory.push(0);
+
+    let diff = diff(&before, &after);
+
+    assert_eq!(
+        diff.changes,
+        vec!["memory size: 4 -> 5", "(0x0002): 0x00 -> 0xff"]
+    );
+    assert_eq!(
+        format!("{}", diff),
+        "memory size: 4 -> 5\n(0x0002): 0x00 -> 0xff\n"
+    );
+}
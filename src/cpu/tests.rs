@@ -29,25 +29,85 @@ fn pc_incr() {
 #[test]
 fn hl_addr() {
     // Check regular offset increment
+    let cpu = CpuBuilder::new()
+        .with_memory_size(16)
+        .with_h(0x10)
+        .with_l(0x10)
+        .build();
+    assert_eq!(cpu.hl_addr(3), 0x1013);
+    assert_eq!(cpu.hl_addr(0x7f), 0x108f);
+
+    // Check negative offsets
+    assert_eq!(cpu.hl_addr(0xfd), 0x100d);
+    assert_eq!(cpu.hl_addr(0x80), 0x0f90);
+
     // Check overflowing increment
+    let cpu = CpuBuilder::new()
+        .with_memory_size(16)
+        .with_h(0xff)
+        .with_l(0xfe)
+        .build();
+    assert_eq!(cpu.hl_addr(3), 0x0001);
+
     // Check underflowing decrement
-    unimplemented!();
+    let cpu = CpuBuilder::new()
+        .with_memory_size(16)
+        .with_h(0)
+        .with_l(1)
+        .build();
+    assert_eq!(cpu.hl_addr(0xfe), 0xffff);
 }
 
 #[test]
 fn ix_addr() {
     // Check regular offset increment
+    let cpu = CpuBuilder::new()
+        .with_memory_size(16)
+        .with_ix(0x1010)
+        .build();
+    assert_eq!(cpu.ix_addr(3), 0x1013);
+    assert_eq!(cpu.ix_addr(0x7f), 0x108f);
+
+    // Check negative offsets
+    assert_eq!(cpu.ix_addr(0xfd), 0x100d);
+    assert_eq!(cpu.ix_addr(0x80), 0x0f90);
+
     // Check overflowing increment
+    let cpu = CpuBuilder::new()
+        .with_memory_size(16)
+        .with_ix(0xfffe)
+        .build();
+    assert_eq!(cpu.ix_addr(3), 0x0001);
+
     // Check underflowing decrement
-    unimplemented!();
+    let cpu = CpuBuilder::new().with_memory_size(16).with_ix(1).build();
+    assert_eq!(cpu.ix_addr(0xfe), 0xffff);
 }
 
 #[test]
 fn iy_addr() {
     // Check regular offset increment
+    let cpu = CpuBuilder::new()
+        .with_memory_size(16)
+        .with_iy(0x1010)
+        .build();
+    assert_eq!(cpu.iy_addr(3), 0x1013);
+    assert_eq!(cpu.iy_addr(0x7f), 0x108f);
+
+    // Check negative offsets
+    assert_eq!(cpu.iy_addr(0xfd), 0x100d);
+    assert_eq!(cpu.iy_addr(0x80), 0x0f90);
+
     // Check overflowing increment
+    let cpu = CpuBuilder::new()
+        .with_memory_size(16)
+        .with_iy(0xfffe)
+        .build();
+    assert_eq!(cpu.iy_addr(3), 0x0001);
+
     // Check underflowing decrement
-    unimplemented!();
+    let cpu = CpuBuilder::new().with_memory_size(16).with_iy(1).build();
+    assert_eq!(cpu.iy_addr(0xfe), 0xffff);
 }
 
 #[test]
@@ -66,3 +126,66 @@ fn memory_at_pc() {
         .memory_at_address_is(15, 0x77);
 }
 
+
+#[test]
+fn step_counts_t_states() {
+    // JR $ loops on itself, taking the branch every time
+    let mut cpu = CpuBuilder::new()
+        .with_memory(vec![0x00, 0x18, 0xfe, 0x76])
+        .build();
+
+    cpu.step();
+    assert_eq!(cpu.cycles, 4);
+
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.pc, 1);
+    assert_eq!(cpu.cycles, 4 + 12 + 12);
+}
+
+#[test]
+fn step_while_halted() {
+    let mut cpu = CpuBuilder::new().with_memory(vec![0x76, 0x00]).build();
+
+    cpu.step();
+    cpu.step();
+
+    assert!(cpu.halted);
+    assert_eq!(cpu.pc, 1);
+    assert_eq!(cpu.cycles, 4 + 4);
+}
+
+#[test]
+fn step_runs_every_opcode() {
+    let mut codes = Vec::new();
+    for opcode in 0..=0xff {
+        codes.push(vec![opcode]);
+        codes.push(vec![0xcb, opcode]);
+        codes.push(vec![0xdd, opcode]);
+        codes.push(vec![0xed, opcode]);
+        codes.push(vec![0xfd, opcode]);
+        codes.push(vec![0xdd, 0xcb, 0x80, opcode]);
+        codes.push(vec![0xfd, 0xcb, 0x7f, opcode]);
+    }
+
+    // Registers and pc at both ends of their range, and halfway
+    for code in &codes {
+        for &(pc, fill) in &[(0x0000, 0x00), (0xfffd, 0xff), (0xffff, 0x80)] {
+            let mut cpu = CpuBuilder::new().with_memory(vec![0; 0x10000]).build();
+            cpu.a = fill;
+            cpu.f = fill;
+            cpu.write_bc(u16::from(fill) * 0x101);
+            cpu.write_de(u16::from(fill) * 0x101);
+            cpu.write_hl(u16::from(fill) * 0x101);
+            cpu.ix = u16::from(fill) * 0x101;
+            cpu.iy = u16::from(fill) * 0x101;
+            cpu.sp = u16::from(fill) * 0x101;
+            cpu.pc = pc;
+            for (offset, &byte) in code.iter().enumerate() {
+                cpu.memory[usize::from(pc.wrapping_add(offset as u16))] = byte;
+            }
+
+            cpu.step();
+        }
+    }
+}
@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::decoder::{is_indexed, uses_hl_indirect};
+
+// === Instruction timing ===
+//
+// T states of each instruction, from the Zilog manual. Conditional
+// instructions are listed with the time taken when the condition is false:
+// the extra time of a taken branch, or of a repeated block instruction,
+// is added when the decoder tells it happened.
+
+/// Unprefixed opcodes.
+#[rustfmt::skip]
+const MAIN: [u8; 256] = [
+//  0   1   2   3   4   5   6   7   8   9   a   b   c   d   e   f
+    4, 10,  7,  6,  4,  4,  7,  4,  4, 11,  7,  6,  4,  4,  7,  4, // 0
+    8, 10,  7,  6,  4,  4,  7,  4, 12, 11,  7,  6,  4,  4,  7,  4, // 1
+    7, 10, 16,  6,  4,  4,  7,  4,  7, 11, 16,  6,  4,  4,  7,  4, // 2
+    7, 10, 13,  6, 11, 11, 10,  4,  7, 11, 13,  6,  4,  4,  7,  4, // 3
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 4
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 5
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 6
+    7,  7,  7,  7,  7,  7,  4,  7,  4,  4,  4,  4,  4,  4,  7,  4, // 7
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 8
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 9
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // a
+    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // b
+    5, 10, 10, 10, 10, 11,  7, 11,  5, 10, 10,  0, 10, 17,  7, 11, // c
+    5, 10, 10, 11, 10, 11,  7, 11,  5,  4, 10, 11, 10,  0,  7, 11, // d
+    5, 10, 10, 19, 10, 11,  7, 11,  5,  4, 10,  4, 10,  0,  7, 11, // e
+    5, 10, 10,  4, 10, 11,  7, 11,  5,  6, 10,  4, 10,  0,  7, 11, // f
+];
+
+/// Extra time of a taken branch.
+fn main_extra(opcode: u8) -> u8 {
+    match opcode {
+        // DJNZ, JR cc
+        0x10 | 0x20 | 0x28 | 0x30 | 0x38 => 5,
+        // RET cc
+        op if op & 0b11_000_111 == 0b11_000_000 => 6,
+        // CALL cc
+        op if op & 0b11_000_111 == 0b11_000_100 => 7,
+        _ => 0,
+    }
+}
+
+/// CB prefixed opcodes, prefix included.
+fn cb_time(opcode: u8) -> u8 {
+    match opcode {
+        // BIT b,(HL)
+        op if op & 0b11_000_111 == 0b01_000_110 => 12,
+        op if op & 0b111 == 0b110 => 15,
+        _ => 8,
+    }
+}
+
+/// ED prefixed opcodes, prefix included, and the extra time of a
+/// repeated block instruction.
+fn ed_time(opcode: u8) -> (u8, u8) {
+    let time = match opcode {
+        // IN r,(C), OUT (C),r
+        op if op & 0b11_000_110 == 0b01_000_000 => 12,
+        // SBC HL,ss, ADC HL,ss
+        op if op & 0b11_000_111 == 0b01_000_010 => 15,
+        // LD (nn),dd, LD dd,(nn)
+        op if op & 0b11_000_111 == 0b01_000_011 => 20,
+        // NEG, IM
+        op if op & 0b11_000_111 == 0b01_000_100 => 8,
+        op if op & 0b11_000_111 == 0b01_000_110 => 8,
+        // RETN, RETI
+        op if op & 0b11_000_111 == 0b01_000_101 => 14,
+        0x47 | 0x4f | 0x57 | 0x5f => 9,
+        0x67 | 0x6f => 18,
+        0xa0..=0xa3 | 0xa8..=0xab => 16,
+        0xb0..=0xb3 | 0xb8..=0xbb => return (16, 5),
+        _ => 8,
+    };
+
+    (time, 0)
+}
+
+/// T states taken by an instruction, given its first four bytes and
+/// whether it branched: a conditional jump, call or return taken, a DJNZ
+/// looping, or a block instruction repeating.
+pub fn instruction_time(opcode: &[u8; 4], branched: bool) -> u8 {
+    let (time, extra) = match opcode[0] {
+        0xcb => (cb_time(opcode[1]), 0),
+        0xed => ed_time(opcode[1]),
+        0xdd | 0xfd => match opcode[1] {
+            // DD CB d op
+            0xcb if opcode[3] & 0b11_000_000 == 0b01_000_000 => (20, 0),
+            0xcb => (23, 0),
+            0x34 | 0x35 => (23, 0),
+            op if uses_hl_indirect(op) => (19, 0),
+            // The prefix alone
+            op if !is_indexed(op) => (4, 0),
+            op => (MAIN[op as usize] + 4, 0),
+        },
+        op => (MAIN[op as usize], main_extra(op)),
+    };
+
+    if branched {
+        time + extra
+    } else {
+        time
+    }
+}
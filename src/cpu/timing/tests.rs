@@ -0,0 +1,42 @@
+use cpu::timing::instruction_time;
+
+fn time(code: Vec<u8>, branched: bool) -> u8 {
+    let mut opcode = [0; 4];
+    opcode[..code.len()].copy_from_slice(&code);
+    instruction_time(&opcode, branched)
+}
+
+#[test]
+fn fixed_times() {
+    assert_eq!(time(asm!("NOP"), false), 4);
+    assert_eq!(time(asm!("LD HL,(0x1234)"), false), 16);
+    assert_eq!(time(asm!("ADD IX,BC"), false), 15);
+    assert_eq!(time(asm!("LD A,(IX+1)"), false), 19);
+    assert_eq!(time(asm!("INC (IY-1)"), false), 23);
+    assert_eq!(time(asm!("BIT 0,(HL)"), false), 12);
+    assert_eq!(time(asm!("RES 0,(IX+1)"), false), 23);
+    assert_eq!(time(asm!("BIT 0,(IX+1)"), false), 20);
+    assert_eq!(time(asm!("LD (0x1234),DE"), false), 20);
+    assert_eq!(time(asm!("LD IXH,5"), false), 11);
+    assert_eq!(time(asm!("SLL (IX+1)"), false), 23);
+    assert_eq!(time(vec![0xdd, 0x00], false), 4);
+}
+
+#[test]
+fn conditional_times() {
+    let jr = asm!("JR NZ,0x10");
+    assert_eq!(time(jr.clone(), false), 7);
+    assert_eq!(time(jr, true), 12);
+
+    let call = asm!("CALL C,0x1234");
+    assert_eq!(time(call.clone(), false), 10);
+    assert_eq!(time(call, true), 17);
+
+    assert_eq!(time(asm!("RET Z"), false), 5);
+    assert_eq!(time(asm!("RET Z"), true), 11);
+
+    assert_eq!(time(asm!("JP Z,0x1234"), true), 10);
+
+    assert_eq!(time(asm!("LDIR"), true), 21);
+    assert_eq!(time(asm!("LDIR"), false), 16);
+}
@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::{flags_text, Cpu};
+use disasm::{disassemble, symbolic_listing_line};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use symbols::SymbolTable;
+
+// === Execution trace ===
+//
+// One line per instruction, written before it is executed: address,
+// object code and disassembly as in a listing, then the registers, the
+// flags and the T states elapsed so far:
+//
+//   0003  cd 07 00     CALL 0x0007           AF=0100 BC=0000 ... F=------ T=7
+//
+// Fields have a fixed width and registers are written as NAME=value, so
+// traces of two runs can be compared with diff, or field by field with
+// traces of another emulator. With symbols, the disassembly names the
+// addresses it uses, as in `CALL print`, and the columns move along.
+
+const REGISTERS: [&str; 11] = [
+    "af", "bc", "de", "hl", "ix", "iy", "sp", "af'", "bc'", "de'", "hl'",
+];
+
+/// Where and what to trace: the instructions in the address ranges, all
+/// of them without ranges, written as they run or, with a ring buffer,
+/// only the last ones when the trace is finished or dropped.
+pub struct Trace {
+    output: Box<dyn Write>,
+    ranges: Vec<(u16, u16)>,
+    last: Option<(usize, VecDeque<String>)>,
+    symbols: SymbolTable,
+    /// First write error, reported by `finish`.
+    error: Option<io::Error>,
+}
+
+impl Trace {
+    pub fn new(output: Box<dyn Write>) -> Trace {
+        Trace {
+            output,
+            ranges: Vec::new(),
+            last: None,
+            symbols: SymbolTable::new(),
+            error: None,
+        }
+    }
+
+    /// Trace the instructions from start to end included; repeatable.
+    pub fn with_range(mut self, start: u16, end: u16) -> Trace {
+        self.ranges.push((start, end));
+        self
+    }
+
+    /// Keep only the last count instructions, written by `finish`.
+    pub fn with_last(mut self, count: usize) -> Trace {
+        self.last = Some((count, VecDeque::with_capacity(count)));
+        self
+    }
+
+    /// Name addresses in the disassembly.
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Trace {
+        self.symbols = symbols;
+        self
+    }
+
+    fn traces(&self, address: u16) -> bool {
+        self.ranges.is_empty()
+            || self
+                .ranges
+                .iter()
+                .any(|&(start, end)| (start..=end).contains(&address))
+    }
+
+    /// Add the instruction at pc, if in range.
+    pub fn record(&mut self, cpu: &Cpu) {
+        if !self.traces(cpu.pc) {
+            return;
+        }
+
+        let line = cpu.symbolic_trace_line(&self.symbols);
+        match self.last {
+            Some((count, ref mut lines)) => {
+                if count > 0 {
+                    if lines.len() == count {
+                        lines.pop_front();
+                    }
+                    lines.push_back(line);
+                }
+            }
+            None => self.write(&line),
+        }
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.output, "{}", line) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Write the lines kept by the ring buffer and flush the output.
+    /// Returns the first error met while writing the trace.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let lines = match self.last {
+            Some((_, ref mut lines)) => lines.drain(..).collect(),
+            None => Vec::new(),
+        };
+        for line in lines {
+            self.write(&line);
+        }
+
+        if self.error.is_none() {
+            if let Err(e) = self.output.flush() {
+                self.error = Some(e);
+            }
+        }
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+/// A trace dropped without `finish`, as when the emulator panics,
+/// still writes its last instructions.
+impl Drop for Trace {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Trace")
+            .field("ranges", &self.ranges)
+            .field("last", &self.last.as_ref().map(|&(count, _)| count))
+            .finish()
+    }
+}
+
+impl Cpu {
+    /// The trace line of the instruction at pc, in the current state.
+    pub fn trace_line(&self) -> String {
+        self.symbolic_trace_line(&SymbolTable::new())
+    }
+
+    /// The trace line with the symbolic disassembly of the instruction.
+    pub fn symbolic_trace_line(&self, symbols: &SymbolTable) -> String {
+        let registers: Vec<String> = REGISTERS
+            .iter()
+            .map(|name| {
+                format!(
+                    "{}={:04x}",
+                    name.to_uppercase(),
+                    self.read_named(name).unwrap_or(0)
+                )
+            })
+            .collect();
+
+        format!(
+            "{:<40} {} I={:02x} R={:02x} IFF={}{} F={} T={}",
+            symbolic_listing_line(&disassemble(&self.memory, self.pc), symbols),
+            registers.join(" "),
+            self.i,
+            self.r,
+            self.iff1 as u8,
+            self.iff2 as u8,
+            flags_text(self.f),
+            self.cycles
+        )
+    }
+}
@@ -0,0 +1,103 @@
+use cpu::{CpuBuilder, Limits, Trace};
+use std::env;
+use std::fs::{self, File};
+use symbols::SymbolTable;
+
+fn traced(code: Vec<u8>, name: &str, setup: fn(Trace) -> Trace) -> Vec<String> {
+    let path = env::temp_dir().join(format!("z80-trace-{}-{}", name, ::std::process::id()));
+    let mut memory = code;
+    memory.resize(0x100, 0);
+
+    let mut cpu = CpuBuilder::new().with_memory(memory).with_sp(0xfe).build();
+    cpu.trace = Some(setup(Trace::new(Box::new(File::create(&path).unwrap()))));
+    cpu.run(Limits::default());
+    cpu.trace.take().unwrap().finish().unwrap();
+
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    text.lines().map(str::to_string).collect()
+}
+
+fn program() -> Vec<u8> {
+    asm!(
+        "      LD A,1",
+        "      CALL sub",
+        "      HALT",
+        "sub:  EX AF,AF'",
+        "      LD B,2",
+        "loop: DJNZ loop",
+        "      RET"
+    )
+}
+
+#[test]
+fn lines() {
+    let lines = traced(program(), "lines", |trace| trace);
+
+    assert_eq!(lines.len(), 8);
+    assert_eq!(
+        lines[0],
+        "0000  3e 01        LD A,0x01             AF=0000 BC=0000 DE=0000 HL=0000 \
+         IX=0000 IY=0000 SP=00fe AF'=0000 BC'=0000 DE'=0000 HL'=0000 \
+         I=00 R=00 IFF=00 F=------ T=0"
+    );
+    assert!(lines[1].starts_with("0002  cd 06 00     CALL 0x0006           AF=0100 "));
+    assert!(lines[1].ends_with(" T=7"));
+    assert!(lines[2].contains(" SP=00fc AF'=0000 "));
+    assert!(lines[3].contains(" AF=0000 ") && lines[3].contains(" AF'=0100 "));
+    assert!(lines[6].starts_with("000b  c9           RET "));
+
+    // Fixed columns: all lines are aligned.
+    for line in &lines {
+        assert_eq!(line.find("AF="), Some(41));
+    }
+}
+
+#[test]
+fn ranges() {
+    let lines = traced(program(), "ranges", |trace| {
+        trace.with_range(0, 2).with_range(0x0b, 0x0b)
+    });
+
+    let addresses: Vec<&str> = lines.iter().map(|line| &line[..4]).collect();
+    assert_eq!(addresses, vec!["0000", "0002", "000b"]);
+}
+
+#[test]
+fn last() {
+    let lines = traced(program(), "last", |trace| trace.with_last(3));
+
+    let addresses: Vec<&str> = lines.iter().map(|line| &line[..4]).collect();
+    assert_eq!(addresses, vec!["0009", "000b", "0005"]);
+}
+
+#[test]
+fn symbols() {
+    let lines = traced(program(), "symbols", |trace| {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("sub", 0x06);
+        trace.with_symbols(symbols)
+    });
+
+    assert!(lines[1].starts_with("0002  cd 06 00     CALL sub "));
+    assert_eq!(lines[1].find("AF="), Some(41));
+}
+
+#[test]
+fn dropped() {
+    let path = env::temp_dir().join(format!("z80-trace-dropped-{}", ::std::process::id()));
+    let mut memory = asm!("NOP", "NOP", "NOP");
+    memory.resize(0x100, 0);
+
+    let mut cpu = CpuBuilder::new().with_memory(memory).build();
+    cpu.trace = Some(Trace::new(Box::new(File::create(&path).unwrap())).with_last(2));
+    for _ in 0..3 {
+        cpu.step();
+    }
+    drop(cpu);
+
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(text.lines().count(), 2);
+    assert!(text.ends_with("T=8\n"));
+}
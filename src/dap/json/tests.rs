@@ -0,0 +1,38 @@
+use dap::Json;
+
+#[test]
+fn parse_and_write() {
+    let text = r#" {"seq": 1, "type": "request", "arguments": {"lines": [3, -4.5e1],
+        "name": "a \"b\"\n\u00e9\ud83d\ude00", "ok": true, "none": null}} "#;
+    let json = Json::parse(text).unwrap();
+
+    assert_eq!(json.get("seq").and_then(Json::as_i64), Some(1));
+    let arguments = json.get("arguments").unwrap();
+    assert_eq!(
+        arguments.get("lines"),
+        Some(&Json::Array(vec![Json::Number(3.0), Json::Number(-45.0)]))
+    );
+    assert_eq!(
+        arguments.get("name").and_then(Json::as_str),
+        Some("a \"b\"\n\u{e9}\u{1f600}")
+    );
+    assert_eq!(arguments.get("ok").and_then(Json::as_bool), Some(true));
+    assert_eq!(arguments.get("none"), Some(&Json::Null));
+
+    assert_eq!(
+        json.to_string(),
+        "{\"arguments\":{\"lines\":[3,-45],\"name\":\"a \\\"b\\\"\\n\u{e9}\u{1f600}\",\
+         \"none\":null,\"ok\":true},\"seq\":1,\"type\":\"request\"}"
+    );
+    assert_eq!(Json::parse(&json.to_string()), Ok(json));
+}
+
+#[test]
+fn errors() {
+    assert!(Json::parse("").is_err());
+    assert!(Json::parse("[1,]").is_err());
+    assert!(Json::parse("{\"a\" 1}").is_err());
+    assert!(Json::parse("\"open").is_err());
+    assert!(Json::parse("tru").is_err());
+    assert!(Json::parse("1 2").is_err());
+}
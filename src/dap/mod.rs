@@ -0,0 +1,1145 @@
+#[cfg(test)]
+mod tests;
+
+mod json;
+
+pub use self::json::Json;
+
+use asm::{assemble_file, eval, parse_number, Program};
+use cpu::{flags_text, CallStack, Cpu, CpuBuilder, History, Kind, StopReason};
+use disasm::disassemble;
+use loader::{decode, Format};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use symbols::SymbolTable;
+
+// === Debug Adapter Protocol ===
+//
+// A debug adapter for editors, exchanging JSON messages preceded by a
+// Content-Length header on stdin and stdout. The program launched is
+// an assembler source, assembled on the fly, or a binary with the
+// listing and symbol files written by z80asm. The source map built
+// from them puts breakpoints on source lines and steps line by line.
+//
+// There is a single thread with a single stack frame, at PC. While the
+// program runs, requests are read between slices of instructions so
+// that it can be paused. The execution history lets the editor step
+// and continue backwards, up to its last change of registers or memory.
+
+/// Instructions executed between looks at incoming requests.
+const SLICE: usize = 10_000;
+
+/// Registers shown in the Registers scope, as known by `Cpu::read_named`.
+const REGISTERS: [&str; 14] = [
+    "AF", "BC", "DE", "HL", "IX", "IY", "SP", "PC", "AF'", "BC'", "DE'", "HL'", "I", "R",
+];
+
+/// Largest message body read, well above a writeMemory of the whole 64K.
+const MAX_MESSAGE: usize = 1 << 20;
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_REFERENCE: i64 = 1;
+
+/// Source lines and symbols of the program.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// File, line, address and length of the lines with object code.
+    lines: Vec<(PathBuf, usize, u16, usize)>,
+    pub symbols: SymbolTable,
+}
+
+impl SourceMap {
+    /// Map of a program assembled from the source file main.
+    pub fn from_program(program: &Program, main: &Path) -> SourceMap {
+        let main = normalize(main);
+        let lines = program
+            .lines
+            .iter()
+            .filter(|line| !line.bytes.is_empty())
+            .map(|line| {
+                let file = match line.file {
+                    Some(ref file) => normalize(Path::new(file)),
+                    None => main.clone(),
+                };
+                (file, line.line, line.address, line.bytes.len())
+            })
+            .collect();
+        SourceMap {
+            lines,
+            symbols: SymbolTable::from_program(program),
+        }
+    }
+
+    /// Map from a listing written by `z80asm -l` for source. The listing
+    /// does not name included files: their lines are taken as lines of source.
+    pub fn from_listing(text: &str, source: &Path) -> SourceMap {
+        let source = normalize(source);
+        let mut lines: Vec<(PathBuf, usize, u16, usize)> = Vec::new();
+
+        for row in text.lines() {
+            let address = match row.get(7..11).and_then(|a| u16::from_str_radix(a, 16).ok()) {
+                Some(address) => address,
+                None => continue,
+            };
+            let bytes = row.get(13..).map_or(0, |rest| {
+                rest.chars()
+                    .take(12)
+                    .collect::<String>()
+                    .split_whitespace()
+                    .count()
+            });
+
+            match row.get(..5).and_then(|number| number.trim().parse().ok()) {
+                Some(number) if bytes > 0 => lines.push((source.clone(), number, address, bytes)),
+                Some(_) => {}
+                // Object code continued from the previous line.
+                None => {
+                    if let Some(last) = lines.last_mut() {
+                        last.3 += bytes;
+                    }
+                }
+            }
+        }
+
+        SourceMap {
+            lines,
+            symbols: SymbolTable::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The first line at or after line in file with object code,
+    /// and the addresses of its code: more than one for macros.
+    pub fn line_addresses(&self, file: &Path, line: usize) -> Option<(usize, Vec<u16>)> {
+        let file = normalize(file);
+        let found = self
+            .lines
+            .iter()
+            .filter(|entry| entry.0 == file && entry.1 >= line)
+            .map(|entry| entry.1)
+            .min()?;
+        let addresses = self
+            .lines
+            .iter()
+            .filter(|entry| entry.0 == file && entry.1 == found)
+            .map(|entry| entry.2)
+            .collect();
+        Some((found, addresses))
+    }
+
+    /// File and line of the object code at address.
+    pub fn location(&self, address: u16) -> Option<(&Path, usize)> {
+        self.lines
+            .iter()
+            .find(|entry| {
+                let offset = usize::from(address.wrapping_sub(entry.2));
+                offset < entry.3
+            })
+            .map(|entry| (entry.0.as_path(), entry.1))
+    }
+
+    /// File, line, address and length of the lines with object code.
+    pub fn lines(&self) -> &[(PathBuf, usize, u16, usize)] {
+        &self.lines
+    }
+
+    /// True when the code of a line starts at address.
+    pub fn is_line_start(&self, address: u16) -> bool {
+        self.lines.iter().any(|entry| entry.2 == address)
+    }
+}
+
+/// Canonical path, for comparing the paths of the editor and the assembler.
+fn normalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// How execution goes on until it stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Continue,
+    /// Until the start of a line, or for one instruction.
+    StepIn,
+    /// The same, running the routines called until they return.
+    StepOver,
+    /// Until a return pops an address pushed before SP.
+    StepOut(u16),
+    /// Undoing instructions until the start of a line, or one of them.
+    StepBack,
+    /// Undoing instructions until a breakpoint or watchpoint.
+    ReverseContinue,
+}
+
+#[derive(Debug)]
+struct Running {
+    mode: Mode,
+    /// Step by instruction rather than by line.
+    instruction: bool,
+    /// Return address and SP of a call being stepped over.
+    returning: Option<(u16, u16)>,
+    /// Set before the first instruction: a breakpoint at PC does not stop.
+    resume: bool,
+}
+
+/// Why execution stopped, as told to the editor.
+#[derive(Debug)]
+enum Stop {
+    Entry,
+    Step,
+    Pause,
+    /// Stepping back reached the oldest instruction recorded.
+    HistoryStart,
+    Cpu(StopReason),
+}
+
+/// Debug adapter owning a CPU.
+pub struct DapServer {
+    pub cpu: Cpu,
+    pub map: SourceMap,
+    /// CPU breakpoint ids for each line breakpoint, by source file.
+    line_breakpoints: HashMap<PathBuf, Vec<Vec<usize>>>,
+    instruction_breakpoints: Vec<usize>,
+    running: Option<Running>,
+    launched: bool,
+    configured: bool,
+    stop_on_entry: bool,
+    finished: bool,
+    /// Responses and events to send, numbered when taken.
+    messages: Vec<Json>,
+    seq: i64,
+}
+
+impl Default for DapServer {
+    fn default() -> Self {
+        DapServer::new()
+    }
+}
+
+impl DapServer {
+    pub fn new() -> Self {
+        DapServer {
+            cpu: CpuBuilder::new().with_memory(vec![0; 0x10000]).build(),
+            map: SourceMap::default(),
+            line_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            running: None,
+            launched: false,
+            configured: false,
+            stop_on_entry: false,
+            finished: false,
+            messages: Vec::new(),
+            seq: 0,
+        }
+    }
+
+    /// True after a disconnect request.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// True while the program runs or steps.
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Read requests from input and write responses and events to output
+    /// until disconnected or the end of input.
+    pub fn serve<R, W>(&mut self, input: R, output: &mut W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = BufReader::new(input);
+            loop {
+                let message = read_message(&mut input);
+                let last = !matches!(message, Ok(Some(_)));
+                if sender.send(message).is_err() || last {
+                    break;
+                }
+            }
+        });
+
+        while !self.finished {
+            let message = if self.is_running() {
+                match receiver.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            } else {
+                match receiver.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break,
+                }
+            };
+
+            match message {
+                Some(Ok(Some(message))) => self.handle(&message),
+                Some(Ok(None)) => break,
+                Some(Err(e)) => return Err(e),
+                None => self.poll(),
+            }
+
+            for message in self.take_messages() {
+                write_message(output, &message)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a request, queueing its response and the events it raises.
+    pub fn handle(&mut self, message: &Json) {
+        if message.get("type").and_then(Json::as_str) != Some("request") {
+            return;
+        }
+
+        let command = message.get("command").and_then(Json::as_str).unwrap_or("");
+        let empty = Json::object(vec![]);
+        let arguments = message.get("arguments").unwrap_or(&empty);
+        let request_seq = message.get("seq").and_then(Json::as_i64).unwrap_or(0);
+
+        // Events raised by the request follow its response.
+        let start = self.messages.len();
+        let result = self.request(command, arguments);
+
+        let mut response = vec![
+            ("type", Json::from("response")),
+            ("request_seq", Json::from(request_seq)),
+            ("command", Json::from(command)),
+            ("success", Json::from(result.is_ok())),
+        ];
+        match result {
+            Ok(Json::Null) => {}
+            Ok(body) => response.push(("body", body)),
+            Err(message) => response.push(("message", Json::from(message))),
+        }
+        self.messages.insert(start, Json::object(response));
+    }
+
+    /// Execute a slice of the running program.
+    pub fn poll(&mut self) {
+        let mut running = match self.running.take() {
+            Some(running) => running,
+            None => return,
+        };
+
+        for _ in 0..SLICE {
+            if let Some(stop) = self.step(&mut running) {
+                self.stopped(stop);
+                return;
+            }
+        }
+
+        self.running = Some(running);
+    }
+
+    /// Responses and events queued, with their sequence numbers.
+    pub fn take_messages(&mut self) -> Vec<Json> {
+        let mut messages: Vec<Json> = self.messages.drain(..).collect();
+        for message in &mut messages {
+            self.seq += 1;
+            if let Json::Object(ref mut members) = *message {
+                members.insert("seq".to_string(), Json::from(self.seq));
+            }
+        }
+        messages
+    }
+
+    fn request(&mut self, command: &str, arguments: &Json) -> Result<Json, String> {
+        match command {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsConditionalBreakpoints", true.into()),
+                ("supportsInstructionBreakpoints", true.into()),
+                ("supportsSteppingGranularity", true.into()),
+                ("supportsSetVariable", true.into()),
+                ("supportsEvaluateForHovers", true.into()),
+                ("supportsReadMemoryRequest", true.into()),
+                ("supportsWriteMemoryRequest", true.into()),
+                ("supportsDisassembleRequest", true.into()),
+                ("supportsTerminateRequest", true.into()),
+                ("supportsStepBack", true.into()),
+            ])),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "configurationDone" => {
+                self.configured = true;
+                self.start();
+                Ok(Json::Null)
+            }
+            "threads" => Ok(Json::object(vec![(
+                "threads",
+                vec![Json::object(vec![
+                    ("id", THREAD_ID.into()),
+                    ("name", "Z80".into()),
+                ])]
+                .into(),
+            )])),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(Json::object(vec![(
+                "scopes",
+                vec![Json::object(vec![
+                    ("name", "Registers".into()),
+                    ("presentationHint", "registers".into()),
+                    ("variablesReference", REGISTERS_REFERENCE.into()),
+                    ("expensive", false.into()),
+                ])]
+                .into(),
+            )])),
+            "variables" => Ok(self.variables(arguments)),
+            "setVariable" => self.set_variable(arguments),
+            "continue" => {
+                self.resume(Mode::Continue, arguments)?;
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            }
+            "next" => self.resume(Mode::StepOver, arguments),
+            "stepIn" => self.resume(Mode::StepIn, arguments),
+            "stepOut" => {
+                let sp = self.cpu.sp;
+                self.resume(Mode::StepOut(sp), arguments)
+            }
+            "stepBack" => self.resume(Mode::StepBack, arguments),
+            "reverseContinue" => self.resume(Mode::ReverseContinue, arguments),
+            "pause" => {
+                if self.running.take().is_some() {
+                    self.stopped(Stop::Pause);
+                }
+                Ok(Json::Null)
+            }
+            "evaluate" => {
+                let expression = string_argument(arguments, "expression")?;
+                let value = self.value(expression)?;
+                Ok(Json::object(vec![
+                    (
+                        "result",
+                        format!("0x{:04x} ({})", value as u16, value).into(),
+                    ),
+                    ("variablesReference", 0i64.into()),
+                ]))
+            }
+            "readMemory" => self.read_memory(arguments),
+            "writeMemory" => self.write_memory(arguments),
+            "disassemble" => self.disassemble(arguments),
+            "terminate" => {
+                self.running = None;
+                self.event("terminated", Json::Null);
+                Ok(Json::Null)
+            }
+            "disconnect" => {
+                self.running = None;
+                self.finished = true;
+                Ok(Json::Null)
+            }
+            _ => Err(format!("Unsupported request: {}", command)),
+        }
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        let mut message = vec![("type", Json::from("event")), ("event", Json::from(event))];
+        if body != Json::Null {
+            message.push(("body", body));
+        }
+        self.messages.push(Json::object(message));
+    }
+
+    /// Load the program. Arguments are `program`, an assembler source
+    /// (.asm, .z80, .s) or a binary, Intel HEX or .com file, and
+    /// optionally `listing` and `source` to map the lines of a binary,
+    /// `symbols`, `load`, `pc`, `sp` and `stopOnEntry`.
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let program = string_argument(arguments, "program")?;
+        let path = Path::new(program);
+        let mut memory = vec![0; 0x10000];
+        let mut sp = 0;
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let start = match extension.as_deref() {
+            Some("asm") | Some("z80") | Some("s") => {
+                let assembled = assemble_file(path).map_err(|e| match e.file {
+                    Some(_) => e.to_string(),
+                    None => format!("{}: {}", program, e),
+                })?;
+                assembled.load(&mut memory);
+                self.map = SourceMap::from_program(&assembled, path);
+                assembled.origin()
+            }
+            _ => {
+                let data = fs::read(path).map_err(|e| format!("{}: {}", program, e))?;
+                let format = Format::from_path(path);
+                let load = number_argument(arguments, "load")?.unwrap_or(0);
+                let image =
+                    decode(&data, format, load).map_err(|e| format!("{}: {}", program, e))?;
+                if format == Format::Com {
+                    // HALT at the warm boot entry, as rz80 does.
+                    memory[0] = 0x76;
+                    sp = 0xfffe;
+                }
+                image
+                    .load(&mut memory)
+                    .map_err(|e| format!("{}: {}", program, e))?;
+
+                self.map = match arguments.get("listing").and_then(Json::as_str) {
+                    Some(listing) => {
+                        let source = string_argument(arguments, "source")?;
+                        SourceMap::from_listing(&read_text(listing)?, Path::new(source))
+                    }
+                    None => SourceMap::default(),
+                };
+                image.start
+            }
+        };
+
+        if let Some(symbols) = arguments.get("symbols").and_then(Json::as_str) {
+            self.map.symbols.load(Path::new(symbols))?;
+        }
+
+        self.cpu = CpuBuilder::new()
+            .with_memory(memory)
+            .with_pc(number_argument(arguments, "pc")?.unwrap_or(start))
+            .with_sp(number_argument(arguments, "sp")?.unwrap_or(sp))
+            .build();
+        self.cpu.history = Some(History::default());
+        self.cpu.call_stack = Some(CallStack::new());
+        self.line_breakpoints.clear();
+        self.instruction_breakpoints.clear();
+        self.stop_on_entry = arguments
+            .get("stopOnEntry")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+        self.launched = true;
+
+        // The editor sends breakpoints and configurationDone after this.
+        self.event("initialized", Json::Null);
+        self.start();
+        Ok(Json::Null)
+    }
+
+    /// Start the program once launched and configured.
+    fn start(&mut self) {
+        if !self.launched || !self.configured {
+            return;
+        }
+
+        if self.stop_on_entry {
+            self.stopped(Stop::Entry);
+        } else {
+            self.running = Some(Running {
+                mode: Mode::Continue,
+                instruction: false,
+                returning: None,
+                resume: true,
+            });
+        }
+    }
+
+    fn resume(&mut self, mode: Mode, arguments: &Json) -> Result<Json, String> {
+        if !self.launched {
+            return Err("No program launched".to_string());
+        }
+
+        let granularity = arguments.get("granularity").and_then(Json::as_str);
+        self.running = Some(Running {
+            mode,
+            instruction: granularity == Some("instruction"),
+            returning: None,
+            resume: true,
+        });
+        Ok(Json::Null)
+    }
+
+    /// Execute an instruction, returning why to stop after it, if so.
+    fn step(&mut self, running: &mut Running) -> Option<Stop> {
+        if running.mode == Mode::StepBack || running.mode == Mode::ReverseContinue {
+            return self.step_back(running);
+        }
+
+        if self.cpu.halted {
+            return Some(Stop::Cpu(StopReason::Halted));
+        }
+
+        let instruction = disassemble(&self.cpu.memory, self.cpu.pc);
+        let resume = running.resume;
+        running.resume = false;
+
+        if let Some(reason) = self.cpu.step_checked(resume) {
+            return Some(Stop::Cpu(reason));
+        }
+        if self.cpu.halted {
+            return Some(Stop::Cpu(StopReason::Halted));
+        }
+
+        let (pc, sp) = (self.cpu.pc, self.cpu.sp);
+        let line_start = running.instruction || self.map.is_empty() || self.map.is_line_start(pc);
+
+        let stop = match running.mode {
+            Mode::Continue | Mode::StepBack | Mode::ReverseContinue => false,
+            Mode::StepIn => line_start,
+            Mode::StepOver => {
+                let after = instruction.address.wrapping_add(instruction.len() as u16);
+
+                if let Some((address, call_sp)) = running.returning {
+                    // Recursive calls return to the same address with a lower SP.
+                    if pc == address && stack_distance(sp, call_sp) >= 0 {
+                        running.returning = None;
+                        line_start
+                    } else {
+                        false
+                    }
+                } else if (instruction.mnemonic == "CALL" || instruction.mnemonic == "RST")
+                    && pc != after
+                {
+                    running.returning = Some((after, sp.wrapping_add(2)));
+                    false
+                } else {
+                    line_start
+                }
+            }
+            Mode::StepOut(start) => {
+                instruction.mnemonic.starts_with("RET") && stack_distance(sp, start) > 0
+            }
+        };
+
+        if stop {
+            Some(Stop::Step)
+        } else {
+            None
+        }
+    }
+
+    /// Undo an instruction, returning why to stop before it, if so.
+    fn step_back(&mut self, running: &Running) -> Option<Stop> {
+        match self.cpu.run_back(Some(1)) {
+            (Some(StopReason::InstructionLimit), _) => {}
+            (Some(reason), _) => return Some(Stop::Cpu(reason)),
+            (None, _) => return Some(Stop::HistoryStart),
+        }
+
+        let pc = self.cpu.pc;
+        let line_start = running.instruction || self.map.is_empty() || self.map.is_line_start(pc);
+        if running.mode == Mode::StepBack && line_start {
+            Some(Stop::Step)
+        } else {
+            None
+        }
+    }
+
+    fn stopped(&mut self, stop: Stop) {
+        let (reason, description) = match stop {
+            Stop::Entry => ("entry", None),
+            Stop::Step => ("step", None),
+            Stop::Pause => ("pause", None),
+            Stop::HistoryStart => ("step", Some("start of the history".to_string())),
+            Stop::Cpu(StopReason::Breakpoint(_)) => ("breakpoint", None),
+            Stop::Cpu(StopReason::Watchpoint(_, access)) => {
+                ("data breakpoint", Some(access.to_string()))
+            }
+            Stop::Cpu(StopReason::Halted) => ("halt", Some("HALT".to_string())),
+            Stop::Cpu(reason) => ("pause", Some(reason.to_string())),
+        };
+
+        let mut body = vec![
+            ("reason", Json::from(reason)),
+            ("threadId", Json::from(THREAD_ID)),
+            ("allThreadsStopped", Json::from(true)),
+        ];
+        if let Some(description) = description {
+            body.push(("description", Json::from(description)));
+        }
+        if let Stop::Cpu(StopReason::Breakpoint(id)) = stop {
+            body.push((
+                "hitBreakpointIds",
+                vec![Json::from(self.breakpoint_id(id))].into(),
+            ));
+        }
+        self.event("stopped", Json::object(body));
+    }
+
+    /// Id given to the editor for a CPU breakpoint: line breakpoints
+    /// on several addresses are known by the first one.
+    fn breakpoint_id(&self, id: usize) -> usize {
+        self.line_breakpoints
+            .values()
+            .flat_map(|groups| groups.iter())
+            .find(|group| group.contains(&id))
+            .map_or(id, |group| group[0])
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let path = arguments
+            .get("source")
+            .and_then(|source| source.get("path"))
+            .and_then(Json::as_str)
+            .ok_or("Missing source path")?;
+        let path = normalize(Path::new(path));
+
+        for id in self
+            .line_breakpoints
+            .remove(&path)
+            .unwrap_or_default()
+            .iter()
+            .flatten()
+        {
+            self.cpu.breakpoints.remove(*id);
+        }
+
+        let requested = arguments
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .cloned()
+            .unwrap_or_default();
+        let mut groups = Vec::new();
+        let mut breakpoints = Vec::new();
+
+        for breakpoint in &requested {
+            let line = breakpoint.get("line").and_then(Json::as_i64).unwrap_or(0) as usize;
+            let condition = condition_argument(breakpoint);
+
+            let (found, addresses) = match self.map.line_addresses(&path, line) {
+                Some(found) => found,
+                None => {
+                    breakpoints.push(Json::object(vec![
+                        ("verified", false.into()),
+                        ("line", line.into()),
+                        ("message", "No code at this line".into()),
+                    ]));
+                    continue;
+                }
+            };
+
+            let mut ids = Vec::new();
+            for address in addresses {
+                match self.cpu.breakpoints.add(Kind::Execute(address), condition) {
+                    Ok(id) => ids.push(id),
+                    Err(message) => {
+                        breakpoints.push(Json::object(vec![
+                            ("verified", false.into()),
+                            ("line", line.into()),
+                            ("message", message.into()),
+                        ]));
+                        break;
+                    }
+                }
+            }
+            if ids.is_empty() {
+                continue;
+            }
+
+            breakpoints.push(Json::object(vec![
+                ("id", ids[0].into()),
+                ("verified", true.into()),
+                ("line", found.into()),
+            ]));
+            groups.push(ids);
+        }
+
+        self.line_breakpoints.insert(path, groups);
+        Ok(Json::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        for id in self.instruction_breakpoints.drain(..) {
+            self.cpu.breakpoints.remove(id);
+        }
+
+        let requested = arguments
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .cloned()
+            .unwrap_or_default();
+        let mut breakpoints = Vec::new();
+
+        for breakpoint in &requested {
+            let address = reference_argument(breakpoint, "instructionReference")?;
+            let offset = breakpoint.get("offset").and_then(Json::as_i64).unwrap_or(0);
+            let address = address.wrapping_add(offset as u16);
+
+            let result = self
+                .cpu
+                .breakpoints
+                .add(Kind::Execute(address), condition_argument(breakpoint));
+            breakpoints.push(match result {
+                Ok(id) => {
+                    self.instruction_breakpoints.push(id);
+                    Json::object(vec![
+                        ("id", id.into()),
+                        ("verified", true.into()),
+                        ("instructionReference", format!("0x{:04x}", address).into()),
+                    ])
+                }
+                Err(message) => Json::object(vec![
+                    ("verified", false.into()),
+                    ("message", message.into()),
+                ]),
+            });
+        }
+
+        Ok(Json::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    /// The frame at pc, then a frame per call being run, at the call.
+    fn stack_trace(&self) -> Json {
+        let mut addresses = vec![self.cpu.pc];
+        if let Some(ref stack) = self.cpu.call_stack {
+            addresses.extend(stack.frames().iter().rev().map(|frame| frame.caller));
+        }
+
+        let frames: Vec<Json> = addresses
+            .iter()
+            .enumerate()
+            .map(|(index, &address)| {
+                let mut frame = vec![
+                    ("id", Json::from(index as i64 + 1)),
+                    ("name", Json::from(self.map.symbols.symbolize(address))),
+                    (
+                        "instructionPointerReference",
+                        Json::from(format!("0x{:04x}", address)),
+                    ),
+                ];
+
+                // Without a source line, line and column are 0.
+                match self.map.location(address) {
+                    Some((file, line)) => {
+                        frame.push(("source", source(file)));
+                        frame.push(("line", line.into()));
+                        frame.push(("column", 1i64.into()));
+                    }
+                    None => {
+                        frame.push(("line", 0i64.into()));
+                        frame.push(("column", 0i64.into()));
+                    }
+                }
+                Json::object(frame)
+            })
+            .collect();
+
+        Json::object(vec![
+            ("totalFrames", (frames.len() as i64).into()),
+            ("stackFrames", frames.into()),
+        ])
+    }
+
+    fn variables(&self, arguments: &Json) -> Json {
+        let reference = arguments.get("variablesReference").and_then(Json::as_i64);
+        if reference != Some(REGISTERS_REFERENCE) {
+            return Json::object(vec![("variables", Vec::new().into())]);
+        }
+
+        let mut variables: Vec<Json> = REGISTERS
+            .iter()
+            .map(|&name| {
+                let value = self.cpu.read_named(name).unwrap_or(0);
+                let mut variable = vec![
+                    ("name", Json::from(name)),
+                    ("value", Json::from(self.register_text(name, value))),
+                    ("variablesReference", Json::from(0i64)),
+                ];
+                if name.len() == 2 && !name.starts_with("AF") {
+                    variable.push(("memoryReference", format!("0x{:04x}", value).into()));
+                }
+                Json::object(variable)
+            })
+            .collect();
+
+        variables.push(Json::object(vec![
+            ("name", "Flags".into()),
+            ("value", flags_text(self.cpu.f).into()),
+            ("variablesReference", 0i64.into()),
+        ]));
+
+        Json::object(vec![("variables", variables.into())])
+    }
+
+    fn register_text(&self, name: &str, value: u16) -> String {
+        if name.len() == 1 {
+            format!("0x{:02x}", value)
+        } else {
+            format!("0x{:04x}", value)
+        }
+    }
+
+    fn set_variable(&mut self, arguments: &Json) -> Result<Json, String> {
+        let name = string_argument(arguments, "name")?;
+        let value = self.value(string_argument(arguments, "value")?)?;
+
+        if !REGISTERS.contains(&name) || !self.cpu.write_named(name, value as u16) {
+            return Err(format!("{} can not be set", name));
+        }
+
+        self.forget_history();
+        let value = self.cpu.read_named(name).unwrap_or(0);
+        Ok(Json::object(vec![(
+            "value",
+            self.register_text(name, value).into(),
+        )]))
+    }
+
+    /// Evaluate an expression over the registers and symbols.
+    fn value(&self, text: &str) -> Result<i32, String> {
+        let cpu = &self.cpu;
+        let map = &self.map;
+        let lookup = |name: &str| {
+            cpu.read_named(name)
+                .or_else(|| map.symbols.value(name))
+                .map(i32::from)
+        };
+        eval(text, cpu.pc, &lookup).map_err(|e| e.message)
+    }
+
+    fn read_memory(&self, arguments: &Json) -> Result<Json, String> {
+        let address = reference_argument(arguments, "memoryReference")?;
+        let offset = arguments.get("offset").and_then(Json::as_i64).unwrap_or(0);
+        let count = arguments
+            .get("count")
+            .and_then(Json::as_i64)
+            .unwrap_or(0)
+            .max(0) as usize;
+
+        let start = (i64::from(address) + offset).max(0) as usize;
+        let end = (start + count).min(self.cpu.memory.len());
+        let bytes = self.cpu.memory.get(start..end).unwrap_or(&[]);
+
+        Ok(Json::object(vec![
+            ("address", format!("0x{:04x}", start).into()),
+            ("data", base64_encode(bytes).into()),
+            ("unreadableBytes", (count - bytes.len()).into()),
+        ]))
+    }
+
+    fn write_memory(&mut self, arguments: &Json) -> Result<Json, String> {
+        let address = reference_argument(arguments, "memoryReference")?;
+        let offset = arguments.get("offset").and_then(Json::as_i64).unwrap_or(0);
+        let data =
+            base64_decode(string_argument(arguments, "data")?).ok_or("Invalid base64 data")?;
+
+        let start = (i64::from(address) + offset).max(0) as usize;
+        let memory = self
+            .cpu
+            .memory
+            .get_mut(start..start + data.len())
+            .ok_or_else(|| format!("Address out of range: 0x{:04x}", start + data.len()))?;
+        memory.copy_from_slice(&data);
+        self.forget_history();
+
+        Ok(Json::object(vec![("bytesWritten", data.len().into())]))
+    }
+
+    fn disassemble(&self, arguments: &Json) -> Result<Json, String> {
+        let reference = reference_argument(arguments, "memoryReference")?;
+        let offset = arguments.get("offset").and_then(Json::as_i64).unwrap_or(0);
+        let skip = arguments
+            .get("instructionOffset")
+            .and_then(Json::as_i64)
+            .unwrap_or(0);
+        let count = arguments
+            .get("instructionCount")
+            .and_then(Json::as_i64)
+            .unwrap_or(0)
+            .max(0) as usize;
+        let memory = &self.cpu.memory;
+        let mut address = reference.wrapping_add(offset as u16);
+
+        // Decoding backwards is ambiguous: start far enough before address
+        // and keep the last instructions before it.
+        let mut addresses = Vec::new();
+        if skip < 0 {
+            let before = (-skip) as usize;
+            let mut start = usize::from(address).saturating_sub(before * 4);
+            let mut found = Vec::new();
+            while start < usize::from(address) {
+                found.push(start as u16);
+                start += disassemble(memory, start as u16).len();
+            }
+            addresses.extend(found.iter().skip(found.len().saturating_sub(before)));
+        } else {
+            for _ in 0..skip {
+                address = address.wrapping_add(disassemble(memory, address).len() as u16);
+            }
+        }
+
+        while addresses.len() < count {
+            addresses.push(address);
+            address = address.wrapping_add(disassemble(memory, address).len() as u16);
+        }
+
+        let instructions: Vec<Json> = addresses
+            .iter()
+            .take(count)
+            .map(|&address| {
+                let instruction = disassemble(memory, address);
+                let bytes: Vec<String> = instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+
+                let mut fields = vec![
+                    ("address", Json::from(format!("0x{:04x}", address))),
+                    ("instructionBytes", Json::from(bytes.join(" "))),
+                    (
+                        "instruction",
+                        Json::from(instruction.symbolic_text(&self.map.symbols)),
+                    ),
+                ];
+                if let Some(symbol) = self.map.symbols.name_at(address) {
+                    fields.push(("symbol", symbol.into()));
+                }
+                if let Some((file, line)) = self.map.location(address) {
+                    fields.push(("location", source(file)));
+                    fields.push(("line", line.into()));
+                }
+                Json::object(fields)
+            })
+            .collect();
+
+        Ok(Json::object(vec![("instructions", instructions.into())]))
+    }
+
+    /// Forget the history once the editor changed registers or memory
+    /// under it, as stepping back would restore the values of before.
+    fn forget_history(&mut self) {
+        if let Some(ref mut history) = self.cpu.history {
+            history.clear();
+        }
+    }
+}
+
+/// How far SP moved up from start, taking the wrap around
+/// at 0x0000 into account.
+fn stack_distance(sp: u16, start: u16) -> i16 {
+    sp.wrapping_sub(start) as i16
+}
+
+fn source(path: &Path) -> Json {
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    Json::object(vec![
+        ("name", name.into()),
+        ("path", path.to_string_lossy().into_owned().into()),
+    ])
+}
+
+fn read_text(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn string_argument<'a>(arguments: &'a Json, name: &str) -> Result<&'a str, String> {
+    arguments
+        .get(name)
+        .and_then(Json::as_str)
+        .ok_or_else(|| format!("Missing {}", name))
+}
+
+/// An optional address, as a number or a string like "0x100".
+fn number_argument(arguments: &Json, name: &str) -> Result<Option<u16>, String> {
+    let value = match arguments.get(name) {
+        None | Some(&Json::Null) => return Ok(None),
+        Some(Json::String(text)) => parse_number(text).ok(),
+        Some(value) => value.as_i64().map(|value| value as i32),
+    };
+    match value {
+        Some(value) if (0..=0xffff).contains(&value) => Ok(Some(value as u16)),
+        _ => Err(format!("Invalid {}", name)),
+    }
+}
+
+/// Memory and instruction references are addresses such as "0x0100".
+fn reference_argument(arguments: &Json, name: &str) -> Result<u16, String> {
+    number_argument(arguments, name)?.ok_or_else(|| format!("Missing {}", name))
+}
+
+fn condition_argument(breakpoint: &Json) -> Option<&str> {
+    breakpoint
+        .get("condition")
+        .and_then(Json::as_str)
+        .filter(|condition| !condition.trim().is_empty())
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut group = 0u32;
+    let mut bits = 0;
+
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = BASE64.iter().position(|&d| d == c)? as u32;
+        group = group << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((group >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Read a message, or None at the end of input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.unwrap_or(0);
+    if length > MAX_MESSAGE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Message of {} bytes, above {}", length, MAX_MESSAGE),
+        ));
+    }
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    let text = String::from_utf8(body).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    Json::parse(&text)
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
@@ -0,0 +1,536 @@
+use asm::assemble;
+use dap::{base64_decode, base64_encode, DapServer, Json, SourceMap};
+use std::env;
+use std::fs;
+use std::io::{Cursor, ErrorKind};
+use std::path::{Path, PathBuf};
+
+const SOURCE: &str = "        ORG 0x100
+start:  LD SP,0xff00
+        LD A,1
+        CALL double
+        CALL double
+        LD (0x200),A
+        HALT
+double: ADD A,A
+        RET
+";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("z80-dap-{}-{}", name, ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Send a request and return its response followed by the events it raised.
+fn request(server: &mut DapServer, command: &str, arguments: Json) -> Vec<Json> {
+    server.handle(&Json::object(vec![
+        ("seq", 1i64.into()),
+        ("type", "request".into()),
+        ("command", command.into()),
+        ("arguments", arguments),
+    ]));
+    let messages = server.take_messages();
+    assert_eq!(
+        messages[0].get("success"),
+        Some(&Json::Bool(true)),
+        "{}",
+        messages[0]
+    );
+    messages
+}
+
+fn body(server: &mut DapServer, command: &str, arguments: Json) -> Json {
+    request(server, command, arguments)[0]
+        .get("body")
+        .cloned()
+        .unwrap_or(Json::Null)
+}
+
+/// Run until the program stops and return the stopped event.
+fn run(server: &mut DapServer) -> Json {
+    while server.is_running() {
+        server.poll();
+    }
+    let mut messages = server.take_messages();
+    assert_eq!(messages.len(), 1);
+    messages.remove(0)
+}
+
+fn field<'a>(json: &'a Json, path: &[&str]) -> &'a Json {
+    path.iter().fold(json, |json, key| {
+        json.get(key)
+            .unwrap_or_else(|| panic!("{} in {}", key, json))
+    })
+}
+
+fn text(json: &Json, path: &[&str]) -> String {
+    field(json, path).as_str().unwrap().to_string()
+}
+
+fn stopped_at(server: &mut DapServer) -> (String, i64) {
+    let trace = body(
+        server,
+        "stackTrace",
+        Json::object(vec![("threadId", 1i64.into())]),
+    );
+    let frame = &trace.get("stackFrames").and_then(Json::as_array).unwrap()[0];
+    (
+        text(frame, &["name"]),
+        frame.get("line").and_then(Json::as_i64).unwrap(),
+    )
+}
+
+fn launch(server: &mut DapServer, arguments: Json) {
+    let messages = request(server, "launch", arguments);
+    assert_eq!(text(&messages[1], &["event"]), "initialized");
+}
+
+fn source_breakpoints(server: &mut DapServer, path: &Path, lines: &[i64]) -> Vec<Json> {
+    let breakpoints: Vec<Json> = lines
+        .iter()
+        .map(|&line| Json::object(vec![("line", line.into())]))
+        .collect();
+    let body = body(
+        server,
+        "setBreakpoints",
+        Json::object(vec![
+            (
+                "source",
+                Json::object(vec![("path", path.to_string_lossy().into_owned().into())]),
+            ),
+            ("breakpoints", breakpoints.into()),
+        ]),
+    );
+    body.get("breakpoints")
+        .and_then(Json::as_array)
+        .unwrap()
+        .clone()
+}
+
+fn launched(name: &str) -> (DapServer, PathBuf) {
+    let path = temp_dir(name).join("main.asm");
+    fs::write(&path, SOURCE).unwrap();
+
+    let mut server = DapServer::new();
+    let capabilities = body(&mut server, "initialize", Json::object(vec![]));
+    assert_eq!(
+        capabilities.get("supportsDisassembleRequest"),
+        Some(&Json::Bool(true))
+    );
+
+    launch(
+        &mut server,
+        Json::object(vec![
+            ("program", path.to_string_lossy().into_owned().into()),
+            ("stopOnEntry", true.into()),
+        ]),
+    );
+    (server, path)
+}
+
+fn configure(server: &mut DapServer) {
+    let messages = request(server, "configurationDone", Json::object(vec![]));
+    assert_eq!(text(&messages[1], &["body", "reason"]), "entry");
+}
+
+#[test]
+fn source_debugging() {
+    let (mut server, path) = launched("source");
+
+    let breakpoints = source_breakpoints(&mut server, &path, &[1, 8, 20]);
+    assert_eq!(field(&breakpoints[0], &["line"]), &Json::from(2i64));
+    assert_eq!(field(&breakpoints[1], &["verified"]), &Json::Bool(true));
+    assert_eq!(field(&breakpoints[2], &["verified"]), &Json::Bool(false));
+    let id = field(&breakpoints[1], &["id"]).clone();
+
+    configure(&mut server);
+    assert_eq!(stopped_at(&mut server), ("start".to_string(), 2));
+
+    // The breakpoint at PC when continuing does not stop.
+    request(&mut server, "continue", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(text(&stopped, &["body", "reason"]), "breakpoint");
+    assert_eq!(
+        field(&stopped, &["body", "hitBreakpointIds"]),
+        &Json::from(vec![id])
+    );
+    assert_eq!(stopped_at(&mut server), ("double".to_string(), 8));
+
+    request(&mut server, "stepOut", Json::object(vec![]));
+    assert_eq!(text(&run(&mut server), &["body", "reason"]), "step");
+    assert_eq!(stopped_at(&mut server), ("start+8".to_string(), 5));
+
+    // Stepping over the call stops on the breakpoint in it.
+    request(&mut server, "next", Json::object(vec![]));
+    assert_eq!(text(&run(&mut server), &["body", "reason"]), "breakpoint");
+
+    source_breakpoints(&mut server, &path, &[]);
+    assert!(server.cpu.breakpoints.is_empty());
+    request(&mut server, "stepOut", Json::object(vec![]));
+    run(&mut server);
+    assert_eq!(stopped_at(&mut server), ("start+11".to_string(), 6));
+    assert_eq!(server.cpu.a, 4);
+
+    request(&mut server, "continue", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(text(&stopped, &["body", "reason"]), "halt");
+
+    request(&mut server, "disconnect", Json::object(vec![]));
+    assert!(server.finished());
+}
+
+#[test]
+fn stepping_in() {
+    let (mut server, path) = launched("step");
+    source_breakpoints(&mut server, &path, &[4]);
+    configure(&mut server);
+
+    request(&mut server, "continue", Json::object(vec![]));
+    run(&mut server);
+    assert_eq!(stopped_at(&mut server), ("start+5".to_string(), 4));
+
+    request(&mut server, "stepIn", Json::object(vec![]));
+    run(&mut server);
+    assert_eq!(stopped_at(&mut server), ("double".to_string(), 8));
+
+    // The caller is the second frame, at the call.
+    let trace = body(
+        &mut server,
+        "stackTrace",
+        Json::object(vec![("threadId", 1i64.into())]),
+    );
+    assert_eq!(field(&trace, &["totalFrames"]), &Json::from(2i64));
+    let caller = &field(&trace, &["stackFrames"]).as_array().unwrap()[1];
+    assert_eq!(text(caller, &["name"]), "start+5");
+    assert_eq!(field(caller, &["line"]), &Json::from(4i64));
+
+    // One instruction at a time, SP is set on line 2.
+    server.cpu.pc = 0x100;
+    let granularity = Json::object(vec![("granularity", "instruction".into())]);
+    request(&mut server, "next", granularity);
+    run(&mut server);
+    assert_eq!((server.cpu.pc, server.cpu.sp), (0x103, 0xff00));
+}
+
+#[test]
+fn stepping_back() {
+    let (mut server, path) = launched("back");
+    source_breakpoints(&mut server, &path, &[6]);
+    configure(&mut server);
+    let capabilities = body(&mut server, "initialize", Json::object(vec![]));
+    assert_eq!(
+        capabilities.get("supportsStepBack"),
+        Some(&Json::Bool(true))
+    );
+
+    request(&mut server, "continue", Json::object(vec![]));
+    run(&mut server);
+    assert_eq!(server.cpu.a, 4);
+
+    request(&mut server, "stepBack", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(text(&stopped, &["body", "reason"]), "step");
+    assert_eq!(stopped_at(&mut server), ("double+1".to_string(), 9));
+
+    source_breakpoints(&mut server, &path, &[4]);
+    request(&mut server, "reverseContinue", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(text(&stopped, &["body", "reason"]), "breakpoint");
+    assert_eq!(stopped_at(&mut server), ("start+5".to_string(), 4));
+    assert_eq!(server.cpu.a, 1);
+
+    request(&mut server, "reverseContinue", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(
+        text(&stopped, &["body", "description"]),
+        "start of the history"
+    );
+    assert_eq!((server.cpu.pc, server.cpu.sp), (0x100, 0));
+}
+
+#[test]
+fn edits_forget_the_history() {
+    let (mut server, path) = launched("edits");
+    source_breakpoints(&mut server, &path, &[6]);
+    configure(&mut server);
+    request(&mut server, "continue", Json::object(vec![]));
+    run(&mut server);
+
+    request(
+        &mut server,
+        "writeMemory",
+        Json::object(vec![
+            ("memoryReference", "0x0200".into()),
+            ("data", base64_encode(&[9]).into()),
+        ]),
+    );
+    request(&mut server, "stepBack", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(
+        text(&stopped, &["body", "description"]),
+        "start of the history"
+    );
+    assert_eq!(server.cpu.memory[0x200], 9);
+
+    request(&mut server, "next", Json::object(vec![]));
+    run(&mut server);
+    assert_eq!(server.cpu.memory[0x200], 4);
+    request(
+        &mut server,
+        "setVariable",
+        Json::object(vec![("name", "HL".into()), ("value", "0x1234".into())]),
+    );
+    request(&mut server, "stepBack", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(
+        text(&stopped, &["body", "description"]),
+        "start of the history"
+    );
+    assert_eq!(
+        (server.cpu.read_hl(), server.cpu.memory[0x200]),
+        (0x1234, 4)
+    );
+}
+
+#[test]
+fn registers_and_expressions() {
+    let (mut server, _) = launched("registers");
+    configure(&mut server);
+
+    let scopes = body(
+        &mut server,
+        "scopes",
+        Json::object(vec![("frameId", 1i64.into())]),
+    );
+    let scope = &scopes.get("scopes").and_then(Json::as_array).unwrap()[0];
+    assert_eq!(text(scope, &["name"]), "Registers");
+
+    let reference = field(scope, &["variablesReference"]).clone();
+    let variables = body(
+        &mut server,
+        "variables",
+        Json::object(vec![("variablesReference", reference.clone())]),
+    );
+    let variables = variables.get("variables").and_then(Json::as_array).unwrap();
+    let pc = variables
+        .iter()
+        .find(|variable| text(variable, &["name"]) == "PC")
+        .unwrap();
+    assert_eq!(text(pc, &["value"]), "0x0100");
+    assert_eq!(text(pc, &["memoryReference"]), "0x0100");
+    assert_eq!(text(variables.last().unwrap(), &["name"]), "Flags");
+
+    let set = body(
+        &mut server,
+        "setVariable",
+        Json::object(vec![
+            ("variablesReference", reference),
+            ("name", "HL".into()),
+            ("value", "double + 2".into()),
+        ]),
+    );
+    assert_eq!(text(&set, &["value"]), "0x0111");
+    assert_eq!(server.cpu.read_hl(), 0x111);
+
+    let result = body(
+        &mut server,
+        "evaluate",
+        Json::object(vec![("expression", "hl - start".into())]),
+    );
+    assert_eq!(text(&result, &["result"]), "0x0011 (17)");
+}
+
+#[test]
+fn memory_and_disassembly() {
+    let (mut server, path) = launched("memory");
+    configure(&mut server);
+
+    let read = body(
+        &mut server,
+        "readMemory",
+        Json::object(vec![
+            ("memoryReference", "0x0100".into()),
+            ("offset", 1i64.into()),
+            ("count", 3i64.into()),
+        ]),
+    );
+    assert_eq!(text(&read, &["address"]), "0x0101");
+    assert_eq!(
+        base64_decode(&text(&read, &["data"])),
+        Some(vec![0x00, 0xff, 0x3e])
+    );
+
+    let written = body(
+        &mut server,
+        "writeMemory",
+        Json::object(vec![
+            ("memoryReference", "0x0200".into()),
+            ("data", base64_encode(&[1, 2, 3, 4]).into()),
+        ]),
+    );
+    assert_eq!(field(&written, &["bytesWritten"]), &Json::from(4usize));
+    assert_eq!(&server.cpu.memory[0x200..0x204], &[1, 2, 3, 4]);
+
+    let listing = body(
+        &mut server,
+        "disassemble",
+        Json::object(vec![
+            ("memoryReference", "0x0105".into()),
+            ("instructionOffset", (-2i64).into()),
+            ("instructionCount", 4i64.into()),
+        ]),
+    );
+    let instructions = listing
+        .get("instructions")
+        .and_then(Json::as_array)
+        .unwrap();
+    let rows: Vec<(String, String)> = instructions
+        .iter()
+        .map(|row| (text(row, &["address"]), text(row, &["instruction"])))
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            ("0x0100".to_string(), "LD SP,0xff00".to_string()),
+            ("0x0103".to_string(), "LD A,0x01".to_string()),
+            ("0x0105".to_string(), "CALL double".to_string()),
+            ("0x0108".to_string(), "CALL double".to_string()),
+        ]
+    );
+    assert_eq!(text(&instructions[0], &["symbol"]), "start");
+    assert_eq!(text(&instructions[0], &["instructionBytes"]), "31 00 ff");
+    assert_eq!(field(&instructions[3], &["line"]), &Json::from(5i64));
+    assert_eq!(
+        Path::new(&text(&instructions[3], &["location", "path"])),
+        fs::canonicalize(&path).unwrap()
+    );
+}
+
+#[test]
+fn listing_and_symbols() {
+    let dir = temp_dir("listing");
+    let program = assemble(SOURCE).unwrap();
+    let symbols: String = program
+        .symbols
+        .iter()
+        .map(|(name, value)| format!("{} EQU 0x{:04x}\n", name, value))
+        .collect();
+    fs::write(dir.join("main.asm"), SOURCE).unwrap();
+    fs::write(dir.join("main.bin"), program.bytes()).unwrap();
+    fs::write(dir.join("main.lst"), program.listing()).unwrap();
+    fs::write(dir.join("main.sym"), symbols).unwrap();
+
+    let map = SourceMap::from_listing(&program.listing(), &dir.join("main.asm"));
+    assert_eq!(map.location(0x10a).map(|(_, line)| line), Some(5));
+    assert!(map.is_line_start(0x10f) && !map.is_line_start(0x106));
+
+    let mut server = DapServer::new();
+    let path = |name: &str| Json::from(dir.join(name).to_string_lossy().into_owned());
+    launch(
+        &mut server,
+        Json::object(vec![
+            ("program", path("main.bin")),
+            ("load", "0x100".into()),
+            ("listing", path("main.lst")),
+            ("source", path("main.asm")),
+            ("symbols", path("main.sym")),
+        ]),
+    );
+    let breakpoints = source_breakpoints(&mut server, &dir.join("main.asm"), &[9]);
+    assert_eq!(field(&breakpoints[0], &["verified"]), &Json::Bool(true));
+
+    request(&mut server, "configurationDone", Json::object(vec![]));
+    let stopped = run(&mut server);
+    assert_eq!(text(&stopped, &["body", "reason"]), "breakpoint");
+    assert_eq!(stopped_at(&mut server), ("double+1".to_string(), 9));
+}
+
+#[test]
+fn errors_and_pause() {
+    let mut server = DapServer::new();
+
+    server.handle(&Json::object(vec![
+        ("seq", 7i64.into()),
+        ("type", "request".into()),
+        ("command", "launch".into()),
+        (
+            "arguments",
+            Json::object(vec![("program", "/no/such/file.bin".into())]),
+        ),
+    ]));
+    let response = server.take_messages().remove(0);
+    assert_eq!(response.get("success"), Some(&Json::Bool(false)));
+    assert_eq!(response.get("request_seq"), Some(&Json::from(7i64)));
+    assert!(text(&response, &["message"]).starts_with("/no/such/file.bin: "));
+
+    let path = temp_dir("pause").join("loop.bin");
+    fs::write(&path, asm!("loop: JR loop")).unwrap();
+    let program = path.to_string_lossy().into_owned();
+    launch(&mut server, Json::object(vec![("program", program.into())]));
+    request(&mut server, "configurationDone", Json::object(vec![]));
+    server.poll();
+    assert!(server.is_running());
+
+    let messages = request(&mut server, "pause", Json::object(vec![]));
+    assert_eq!(text(&messages[1], &["body", "reason"]), "pause");
+    assert!(!server.is_running());
+}
+
+#[test]
+fn protocol() {
+    let mut input = String::new();
+    for (seq, command) in ["initialize", "threads", "disconnect"].iter().enumerate() {
+        let message = format!(
+            "{{\"seq\":{},\"type\":\"request\",\"command\":\"{}\"}}",
+            seq + 1,
+            command
+        );
+        input.push_str(&format!(
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        ));
+    }
+
+    let mut output = Vec::new();
+    DapServer::new()
+        .serve(Cursor::new(input.into_bytes()), &mut output)
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let messages: Vec<Json> = output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| Json::parse(&message[message.find("\r\n\r\n").unwrap() + 4..]).unwrap())
+        .collect();
+
+    assert_eq!(messages.len(), 3);
+    assert_eq!(field(&messages[1], &["seq"]), &Json::from(2i64));
+    assert_eq!(
+        messages[1].to_string(),
+        "{\"body\":{\"threads\":[{\"id\":1,\"name\":\"Z80\"}]},\"command\":\"threads\",\
+         \"request_seq\":2,\"seq\":2,\"success\":true,\"type\":\"response\"}"
+    );
+}
+
+#[test]
+fn oversized_message() {
+    let input = "Content-Length: 4294967296\r\n\r\n{}";
+    let error = DapServer::new()
+        .serve(Cursor::new(input.as_bytes().to_vec()), &mut Vec::new())
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn base64() {
+    for bytes in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\xff\x00\x80"].iter() {
+        assert_eq!(
+            base64_decode(&base64_encode(bytes)).as_deref(),
+            Some(*bytes)
+        );
+    }
+    assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+    assert_eq!(base64_decode("!"), None);
+}
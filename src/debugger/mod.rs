@@ -0,0 +1,718 @@
+#[cfg(test)]
+mod tests;
+
+use asm::{eval, Segment};
+use cpu::{dump_memory, flags_text, CallStack, Cpu, CpuState, History, Kind, Limits, StopReason};
+use cpu::{C_MASK, H_MASK, N_MASK, PV_MASK, S_MASK, Z_MASK};
+use disasm::{disassemble, symbolic_listing_line};
+use loader::{decode, encode, Format, Image};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use symbols::SymbolTable;
+
+// === Monitor ===
+//
+// A line oriented debugger: each command is one line of text and its
+// output is returned as text, so that the same commands can be typed
+// at a terminal or read from a script.
+
+const HELP: &str = "Commands:
+  s, step [N]              execute N instructions, default 1
+  n, next                  step over CALL and RST
+  f, finish                run until the current routine returns
+  c, continue [N]          run until HALT or a breakpoint, or at most N instructions
+  sb, step-back [N]        undo the last N instructions, default 1
+  rc, reverse-continue [N] undo instructions until a breakpoint or watchpoint,
+                           the start of the history, or at most N of them
+  rewind COUNT             undo instructions back to COUNT executed
+  history [SIZE]           show the history of executed instructions, or keep
+                           the last SIZE, 0 to stop recording
+  bt, backtrace            show the routines being run, from the innermost
+  symbols FILE             load the symbols of FILE: a .sym or .map file, NAME EQU
+                           VALUE lines, or a source to assemble. Symbols name
+                           addresses in listings and stand for them in commands
+  b, break ADDR [if COND]  stop before executing the instruction at ADDR
+  watch START [END] [if COND]
+                           stop after an instruction writes memory in the range
+  rwatch, awatch ...       the same for reads, and for both reads and writes
+  iowatch [in|out] PORT [END] [if COND]
+                           stop after IN or OUT on the ports, both by default
+  breaks                   list breakpoints and watchpoints
+  delete [ID]              delete a breakpoint, or all of them
+  enable ID, disable ID    enable or disable a breakpoint
+  r, regs [REG VALUE]      show registers, or set one
+  flags [+F|-F ...]        show flags, or set and reset them (S Z H P N C)
+  m, mem [START [END]]     hex dump, 128 bytes by default
+  e, edit ADDR BYTE ...    write bytes
+  fill START END BYTE      fill memory from START to END included
+  d, dis [ADDR [COUNT]]    disassemble, around PC by default
+  load FILE [ADDR]         load a binary, Intel HEX, S-record or .com file
+  save FILE START END      save memory from START to END included, in the
+                           format of the extension of FILE, binary by default
+  snapshot FILE            save the state of the CPU and the memory
+  restore FILE             restore a state saved by snapshot, forgetting the
+                           history
+  q, quit                  leave the monitor
+
+Values are expressions as in the assembler: 0x100, $100, 100h, hl+2.
+Register names stand for their value and $ for PC. Conditions are
+expressions too, true when not zero, as in: a == 0x3f && get_z().
+Flags are get_s(), get_z(), get_h(), get_pv(), get_n() and get_c();
+watchpoint conditions can use the address and value accessed.
+
+Returns to another address than the one pushed by the call, returns
+with no call and SP reloaded above return addresses are reported as
+warnings.
+
+Writing memory with edit, fill and load forgets the history, which
+could not undo the instructions executed before.";
+
+/// Instructions shown before PC when disassembling around it.
+const CONTEXT_BEFORE: usize = 3;
+/// Instructions shown by default when disassembling.
+const DISASSEMBLY_LINES: usize = 10;
+
+/// Interactive debugger owning a CPU.
+pub struct Debugger {
+    pub cpu: Cpu,
+    /// Names of addresses in listings and backtraces, and symbols of
+    /// expressions.
+    pub symbols: SymbolTable,
+    /// Address where `mem` without arguments continues.
+    next_dump: u16,
+    quit: bool,
+}
+
+impl Debugger {
+    pub fn new(mut cpu: Cpu) -> Self {
+        if cpu.history.is_none() {
+            cpu.history = Some(History::default());
+        }
+        if cpu.call_stack.is_none() {
+            cpu.call_stack = Some(CallStack::new());
+        }
+        Debugger {
+            next_dump: cpu.pc,
+            cpu,
+            symbols: SymbolTable::new(),
+            quit: false,
+        }
+    }
+
+    /// True after the quit command.
+    pub fn finished(&self) -> bool {
+        self.quit
+    }
+
+    /// Read commands from input until quit or end of file, writing
+    /// their output, or error, to output. The prompt is shown
+    /// before each command when given.
+    pub fn repl<R, W>(&mut self, input: R, output: &mut W, prompt: Option<&str>) -> io::Result<()>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let mut lines = input.lines();
+
+        while !self.quit {
+            if let Some(prompt) = prompt {
+                write!(output, "{}", prompt)?;
+                output.flush()?;
+            }
+
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+
+            match self.execute(&line) {
+                Ok(text) => write!(output, "{}", text)?,
+                Err(message) => writeln!(output, "Error: {}", message)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Execute one command and return its output.
+    /// Empty lines and comments starting with '#' or ';' do nothing.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            return Ok(String::new());
+        }
+
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("").to_lowercase();
+        let args: Vec<&str> = words.collect();
+
+        let output = match command.as_str() {
+            "h" | "help" | "?" => Ok(format!("{}\n", HELP)),
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(count) => self.count(count)?,
+                    None => 1,
+                };
+                let mut reason = None;
+                for i in 0..count {
+                    reason = self.cpu.step_checked(i == 0);
+                    if reason.is_some() {
+                        break;
+                    }
+                }
+                Ok(self.stopped(reason))
+            }
+            "n" | "next" => {
+                let reason = self.step_over();
+                Ok(self.stopped(reason))
+            }
+            "f" | "finish" => {
+                let reason = self.finish();
+                Ok(self.stopped(reason))
+            }
+            "c" | "continue" => {
+                let limits = Limits {
+                    instructions: match args.first() {
+                        Some(count) => Some(self.count(count)?),
+                        None => None,
+                    },
+                    cycles: None,
+                };
+                let (reason, executed) = self.cpu.run(limits);
+                Ok(format!(
+                    "Stopped by {} after {} instructions\n{}",
+                    reason,
+                    executed,
+                    self.status()
+                ))
+            }
+            "sb" | "step-back" => {
+                let count = match args.first() {
+                    Some(count) => self.count(count)?,
+                    None => 1,
+                };
+                let mut undone = 0;
+                while undone < count && self.cpu.step_back() {
+                    undone += 1;
+                }
+                if undone < count {
+                    Ok(format!(
+                        "Start of the history after {} instructions\n{}",
+                        undone,
+                        self.status()
+                    ))
+                } else {
+                    Ok(self.status())
+                }
+            }
+            "rc" | "reverse-continue" => {
+                let limit = match args.first() {
+                    Some(count) => Some(self.count(count)?),
+                    None => None,
+                };
+                let (reason, undone) = self.cpu.run_back(limit);
+                Ok(format!(
+                    "Stopped by {} after {} instructions back\n{}",
+                    match reason {
+                        Some(reason) => reason.to_string(),
+                        None => "the start of the history".to_string(),
+                    },
+                    undone,
+                    self.status()
+                ))
+            }
+            "rewind" => match args.first() {
+                Some(count) => {
+                    let count = self.unsigned(count)?;
+                    self.cpu.rewind(count)?;
+                    Ok(self.status())
+                }
+                None => Err("Usage: rewind COUNT".to_string()),
+            },
+            "history" => {
+                if let Some(size) = args.first() {
+                    let size = self.unsigned(size)? as usize;
+                    match self.cpu.history {
+                        _ if size == 0 => self.cpu.history = None,
+                        Some(ref mut history) => history.set_capacity(size),
+                        None => self.cpu.history = Some(History::new(size)),
+                    }
+                }
+                Ok(match self.cpu.history {
+                    Some(ref history) => format!(
+                        "{} instructions executed, back to {} can be undone (last {} kept)\n",
+                        history.count(),
+                        history.oldest(),
+                        history.capacity()
+                    ),
+                    None => "No history\n".to_string(),
+                })
+            }
+            "bt" | "backtrace" => match self.cpu.call_stack {
+                Some(ref stack) => {
+                    Ok(stack.backtrace(self.cpu.pc, &|address| self.symbols.symbolize(address)))
+                }
+                None => Err("No call stack".to_string()),
+            },
+            "symbols" => match args.first() {
+                Some(path) => {
+                    let count = self.symbols.load(Path::new(path))?;
+                    Ok(format!("Loaded {} symbols\n", count))
+                }
+                None => Err("Usage: symbols FILE".to_string()),
+            },
+            "r" | "regs" => match args.len() {
+                0 => Ok(self.cpu.dump_registers()),
+                2 => {
+                    let value = self.value(args[1])?;
+                    if !self.cpu.write_named(args[0], value as u16) {
+                        return Err(format!("Unknown register: {}", args[0]));
+                    }
+                    Ok(String::new())
+                }
+                _ => Err("Usage: regs [REG VALUE]".to_string()),
+            },
+            "flags" => {
+                for arg in &args {
+                    self.set_flag(arg)?;
+                }
+                Ok(format!("{}\n", flags_text(self.cpu.f)))
+            }
+            "m" | "mem" => {
+                let start = match args.first() {
+                    Some(start) => self.address(start)?,
+                    None => self.next_dump,
+                };
+                let end = match args.get(1) {
+                    Some(end) => self.address(end)?,
+                    None => start.saturating_add(0x7f),
+                };
+                if end < start {
+                    return Err("Invalid range".to_string());
+                }
+                self.next_dump = end.wrapping_add(1);
+                Ok(dump_memory(&self.cpu.memory, start, end))
+            }
+            "e" | "edit" => {
+                if args.len() < 2 {
+                    return Err("Usage: edit ADDR BYTE ...".to_string());
+                }
+                let address = self.address(args[0])?;
+                let mut bytes = Vec::new();
+                for arg in &args[1..] {
+                    bytes.push(self.value(arg)? as u8);
+                }
+                self.write_memory(address, &bytes)?;
+                Ok(String::new())
+            }
+            "fill" => {
+                if args.len() != 3 {
+                    return Err("Usage: fill START END BYTE".to_string());
+                }
+                let (start, end) = self.range(args[0], args[1])?;
+                let byte = self.value(args[2])? as u8;
+                let bytes = vec![byte; usize::from(end - start) + 1];
+                self.write_memory(start, &bytes)?;
+                Ok(String::new())
+            }
+            "d" | "dis" => {
+                let count = match args.get(1) {
+                    Some(count) => self.count(count)? as usize,
+                    None => DISASSEMBLY_LINES,
+                };
+                let start = match args.first() {
+                    Some(address) => self.address(address)?,
+                    None => self.context_start(),
+                };
+                Ok(self.disassembly(start, count))
+            }
+            "load" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err("Usage: load FILE [ADDR]".to_string());
+                }
+                let address = match args.get(1) {
+                    Some(address) => self.address(address)?,
+                    None => 0,
+                };
+                let data = fs::read(args[0]).map_err(|e| format!("{}: {}", args[0], e))?;
+                let image = decode(&data, Format::from_path(Path::new(args[0])), address)
+                    .map_err(|e| format!("{}: {}", args[0], e))?;
+                image
+                    .load(&mut self.cpu.memory)
+                    .map_err(|e| format!("{}: {}", args[0], e))?;
+                self.forget_history();
+                let size: usize = image.segments.iter().map(|s| s.bytes.len()).sum();
+                Ok(format!("Loaded {} bytes\n", size))
+            }
+            "save" => {
+                if args.len() != 3 {
+                    return Err("Usage: save FILE START END".to_string());
+                }
+                let (start, end) = self.range(args[1], args[2])?;
+                let image = Image {
+                    segments: vec![Segment {
+                        address: start,
+                        bytes: self.memory(start, end)?.to_vec(),
+                    }],
+                    start,
+                };
+                let data = encode(&image, Format::from_path(Path::new(args[0])));
+                fs::write(args[0], data).map_err(|e| format!("{}: {}", args[0], e))?;
+                Ok(format!("Saved {} bytes\n", image.segments[0].bytes.len()))
+            }
+            "snapshot" => {
+                if args.len() != 1 {
+                    return Err("Usage: snapshot FILE".to_string());
+                }
+                fs::write(args[0], self.cpu.state().to_bytes())
+                    .map_err(|e| format!("{}: {}", args[0], e))?;
+                Ok(format!("Saved the state at PC 0x{:04x}\n", self.cpu.pc))
+            }
+            "restore" => {
+                if args.len() != 1 {
+                    return Err("Usage: restore FILE".to_string());
+                }
+                let data = fs::read(args[0]).map_err(|e| format!("{}: {}", args[0], e))?;
+                let state =
+                    CpuState::from_bytes(&data).map_err(|e| format!("{}: {}", args[0], e))?;
+                self.cpu.restore(&state);
+                // The undo log and the frames belong to the run left.
+                if let Some(ref mut history) = self.cpu.history {
+                    *history = History::new(history.capacity());
+                }
+                if self.cpu.call_stack.is_some() {
+                    self.cpu.call_stack = Some(CallStack::new());
+                }
+                self.next_dump = self.cpu.pc;
+                Ok(format!("Restored the state at PC 0x{:04x}\n", self.cpu.pc))
+            }
+            "b" | "break" => {
+                let (args, condition) = split_condition(&args);
+                if args.len() != 1 {
+                    return Err("Usage: break ADDR [if COND]".to_string());
+                }
+                let kind = Kind::Execute(self.address(args[0])?);
+                self.add_breakpoint(kind, condition)
+            }
+            "watch" | "rwatch" | "awatch" => {
+                let (args, condition) = split_condition(&args);
+                if args.is_empty() || args.len() > 2 {
+                    return Err(format!("Usage: {} START [END] [if COND]", command));
+                }
+                let (start, end) = self.range(args[0], args.get(1).unwrap_or(&args[0]))?;
+                let kind = Kind::Memory {
+                    start,
+                    end,
+                    read: command != "watch",
+                    write: command != "rwatch",
+                };
+                self.add_breakpoint(kind, condition)
+            }
+            "iowatch" => {
+                let (mut args, condition) = split_condition(&args);
+                let (input, output) = match args.first().map(|arg| arg.to_lowercase()) {
+                    Some(ref direction) if direction == "in" => (true, false),
+                    Some(ref direction) if direction == "out" => (false, true),
+                    _ => (true, true),
+                };
+                if input != output {
+                    args.remove(0);
+                }
+                if args.is_empty() || args.len() > 2 {
+                    return Err("Usage: iowatch [in|out] PORT [END] [if COND]".to_string());
+                }
+                let (start, end) = self.range(args[0], args.get(1).unwrap_or(&args[0]))?;
+                let kind = Kind::Port {
+                    start,
+                    end,
+                    input,
+                    output,
+                };
+                self.add_breakpoint(kind, condition)
+            }
+            "breaks" => Ok(self
+                .cpu
+                .breakpoints
+                .iter()
+                .map(|breakpoint| format!("{}\n", breakpoint))
+                .collect()),
+            "delete" => {
+                match args.first() {
+                    Some(id) => {
+                        let id = self.value(id)? as usize;
+                        if !self.cpu.breakpoints.remove(id) {
+                            return Err(format!("No breakpoint {}", id));
+                        }
+                    }
+                    None => self.cpu.breakpoints.clear(),
+                }
+                Ok(String::new())
+            }
+            "enable" | "disable" => {
+                if args.len() != 1 {
+                    return Err(format!("Usage: {} ID", command));
+                }
+                let id = self.value(args[0])? as usize;
+                if !self.cpu.breakpoints.set_enabled(id, command == "enable") {
+                    return Err(format!("No breakpoint {}", id));
+                }
+                Ok(String::new())
+            }
+            "q" | "quit" | "exit" => {
+                self.quit = true;
+                Ok(String::new())
+            }
+            _ => Err(format!("Unknown command: {}, try help", command)),
+        };
+
+        Ok(format!("{}{}", self.warnings(), output?))
+    }
+
+    /// Warnings about the call stack anomalies found since the last ones.
+    fn warnings(&mut self) -> String {
+        let anomalies = match self.cpu.call_stack {
+            Some(ref mut stack) => stack.take_anomalies(),
+            None => return String::new(),
+        };
+        let name = |address| self.symbols.symbolize(address);
+        anomalies
+            .iter()
+            .map(|anomaly| format!("Warning: {}\n", anomaly.describe(&name)))
+            .collect()
+    }
+
+    fn add_breakpoint(&mut self, kind: Kind, condition: Option<String>) -> Result<String, String> {
+        let id = self.cpu.breakpoints.add(kind, condition.as_deref())?;
+        Ok(format!("{}\n", self.cpu.breakpoints.get(id).unwrap()))
+    }
+
+    /// Why execution stopped early, if it did, and the status.
+    fn stopped(&self, reason: Option<StopReason>) -> String {
+        match reason {
+            Some(reason) => format!("Stopped by {}\n{}", reason, self.status()),
+            None => self.status(),
+        }
+    }
+
+    /// Registers and the next instruction.
+    fn status(&self) -> String {
+        format!(
+            "{}{}",
+            self.cpu.dump_registers(),
+            self.disassembly(self.cpu.pc, 1)
+        )
+    }
+
+    /// Execute the next instruction; a CALL or RST is run
+    /// until it returns to the following instruction.
+    /// Returns the breakpoint or watchpoint stopping it early.
+    fn step_over(&mut self) -> Option<StopReason> {
+        let instruction = disassemble(&self.cpu.memory, self.cpu.pc);
+        let reason = self.cpu.step_checked(true);
+
+        if reason.is_some() || (instruction.mnemonic != "CALL" && instruction.mnemonic != "RST") {
+            return reason;
+        }
+
+        // Recursive calls return to the same address with a lower SP.
+        let after = instruction.address.wrapping_add(instruction.len() as u16);
+        let sp = self.cpu.sp.wrapping_add(2);
+        while !self.cpu.halted {
+            if self.cpu.pc == after && stack_distance(self.cpu.sp, sp) >= 0 {
+                break;
+            }
+            if let Some(reason) = self.cpu.step_checked(false) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    /// Run until a return instruction leaves the current routine,
+    /// that is until it pops a return address pushed before the start.
+    /// Returns the breakpoint or watchpoint stopping it early.
+    fn finish(&mut self) -> Option<StopReason> {
+        let sp = self.cpu.sp;
+        let mut resume = true;
+
+        while !self.cpu.halted {
+            let mnemonic = disassemble(&self.cpu.memory, self.cpu.pc).mnemonic;
+            if let Some(reason) = self.cpu.step_checked(resume) {
+                return Some(reason);
+            }
+            resume = false;
+
+            if mnemonic.starts_with("RET") && stack_distance(self.cpu.sp, sp) > 0 {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Where to start disassembling so that a few instructions
+    /// before PC are shown. Decoding backwards is ambiguous: take the
+    /// farthest start whose instructions fall exactly on PC.
+    fn context_start(&self) -> u16 {
+        let pc = self.cpu.pc;
+        let memory = &self.cpu.memory;
+
+        for distance in (1..=CONTEXT_BEFORE as u16 * 4).rev() {
+            let start = match pc.checked_sub(distance) {
+                Some(start) => start,
+                None => continue,
+            };
+
+            let mut addresses = vec![start];
+            let mut address = start;
+            while address < pc {
+                address += disassemble(memory, address).len() as u16;
+                addresses.push(address);
+            }
+
+            if address == pc {
+                let before = addresses.len() - 1;
+                return addresses[before.saturating_sub(CONTEXT_BEFORE)];
+            }
+        }
+
+        pc
+    }
+
+    /// Listing of count instructions from start, PC marked with '>' and
+    /// symbols as labels.
+    fn disassembly(&self, start: u16, count: usize) -> String {
+        let mut text = String::new();
+        let mut address = start;
+
+        for _ in 0..count {
+            let instruction = disassemble(&self.cpu.memory, address);
+            if let Some(name) = self.symbols.name_at(address) {
+                text.push_str(&format!("{}:\n", name));
+            }
+            let marker = if address == self.cpu.pc { '>' } else { ' ' };
+            text.push_str(&format!(
+                "{} {}\n",
+                marker,
+                symbolic_listing_line(&instruction, &self.symbols)
+            ));
+            address = address.wrapping_add(instruction.len() as u16);
+        }
+
+        text
+    }
+
+    /// "+Z" sets a flag, "-Z" resets it.
+    fn set_flag(&mut self, arg: &str) -> Result<(), String> {
+        let error = || format!("Invalid flag: {}, use +F or -F with F in SZHPNC", arg);
+
+        let mut chars = arg.chars();
+        let set = match chars.next() {
+            Some('+') => true,
+            Some('-') => false,
+            _ => return Err(error()),
+        };
+        let mask = match chars.as_str().to_lowercase().as_str() {
+            "s" => S_MASK,
+            "z" => Z_MASK,
+            "h" => H_MASK,
+            "p" | "v" | "pv" => PV_MASK,
+            "n" => N_MASK,
+            "c" => C_MASK,
+            _ => return Err(error()),
+        };
+
+        if set {
+            self.cpu.f |= mask;
+        } else {
+            self.cpu.f &= !mask;
+        }
+        Ok(())
+    }
+
+    /// Evaluate an expression, register names and then symbols standing
+    /// for their value.
+    fn value(&self, text: &str) -> Result<i32, String> {
+        let (cpu, symbols) = (&self.cpu, &self.symbols);
+        eval(text, cpu.pc, &|name: &str| {
+            cpu.read_named(name).or_else(|| symbols.value(name)).map(i32::from)
+        })
+        .map_err(|e| e.message)
+    }
+
+    /// A number of times, at least 1.
+    fn count(&self, text: &str) -> Result<u64, String> {
+        match self.value(text)? {
+            value if value > 0 => Ok(value as u64),
+            _ => Err(format!("Invalid count: {}", text)),
+        }
+    }
+
+    /// A count or a size, 0 allowed.
+    fn unsigned(&self, text: &str) -> Result<u64, String> {
+        match self.value(text)? {
+            value if value >= 0 => Ok(value as u64),
+            _ => Err(format!("Invalid count: {}", text)),
+        }
+    }
+
+    fn address(&self, text: &str) -> Result<u16, String> {
+        let value = self.value(text)?;
+        if !(0..=0xffff).contains(&value) {
+            return Err(format!("Address out of range: {}", text));
+        }
+        Ok(value as u16)
+    }
+
+    fn range(&self, start: &str, end: &str) -> Result<(u16, u16), String> {
+        let (start, end) = (self.address(start)?, self.address(end)?);
+        if end < start {
+            return Err("Invalid range".to_string());
+        }
+        Ok((start, end))
+    }
+
+    fn memory(&self, start: u16, end: u16) -> Result<&[u8], String> {
+        self.cpu
+            .memory
+            .get(usize::from(start)..=usize::from(end))
+            .ok_or_else(|| format!("Address out of range: 0x{:04x}", end))
+    }
+
+    fn write_memory(&mut self, address: u16, bytes: &[u8]) -> Result<(), String> {
+        let start = usize::from(address);
+        match self.cpu.memory.get_mut(start..start + bytes.len()) {
+            Some(memory) => {
+                memory.copy_from_slice(bytes);
+                self.forget_history();
+                Ok(())
+            }
+            None => Err(format!(
+                "Address out of range: 0x{:04x}",
+                start + bytes.len() - 1
+            )),
+        }
+    }
+
+    /// Memory written by hand is not in the undo log: undoing the
+    /// instructions before would restore bytes around it, not under it.
+    fn forget_history(&mut self) {
+        if let Some(ref mut history) = self.cpu.history {
+            history.clear();
+        }
+    }
+}
+
+/// How far SP moved up from start, taking the wrap around
+/// at 0x0000 into account.
+fn stack_distance(sp: u16, start: u16) -> i16 {
+    sp.wrapping_sub(start) as i16
+}
+
+/// Split the arguments of a command at `if`, joining the condition.
+fn split_condition<'a>(args: &[&'a str]) -> (Vec<&'a str>, Option<String>) {
+    match args.iter().position(|arg| arg.eq_ignore_ascii_case("if")) {
+        Some(pos) => (args[..pos].to_vec(), Some(args[pos + 1..].join(" "))),
+        None => (args.to_vec(), None),
+    }
+}
@@ -0,0 +1,399 @@
+use cpu::CpuBuilder;
+use debugger::Debugger;
+use std::env;
+use std::fs;
+use std::io::Cursor;
+
+fn debugger(code: Vec<u8>) -> Debugger {
+    let mut memory = code;
+    memory.resize(0x10000, 0);
+    Debugger::new(
+        CpuBuilder::new()
+            .with_memory(memory)
+            .with_sp(0xfffe)
+            .build(),
+    )
+}
+
+fn program() -> Debugger {
+    debugger(asm!(
+        "      LD A,1",
+        "      CALL sub",
+        "      INC A",
+        "      HALT",
+        "sub:  CALL sub2",
+        "      RET",
+        "sub2: ADD A,A",
+        "      RET"
+    ))
+}
+
+#[test]
+fn step_and_step_over() {
+    let mut debugger = program();
+
+    let output = debugger.execute("step").unwrap();
+    assert!(output.starts_with("PC=0002 SP=fffe AF=0100"));
+    assert!(output.ends_with("> 0002  cd 07 00     CALL 0x0007\n"));
+
+    debugger.execute("next").unwrap();
+    assert_eq!(
+        (debugger.cpu.pc, debugger.cpu.sp, debugger.cpu.a),
+        (5, 0xfffe, 2)
+    );
+
+    debugger.execute("s 2").unwrap();
+    assert!(debugger.cpu.halted);
+
+    for command in &["s 0", "step -1", "c 0", "sb -2", "rc 0"] {
+        assert!(debugger.execute(command).is_err(), "{}", command);
+    }
+    for command in &["d 0 0", "rewind -1", "history -1"] {
+        assert!(debugger.execute(command).is_err(), "{}", command);
+    }
+    assert_eq!(debugger.execute("s 0").unwrap_err(), "Invalid count: 0");
+}
+
+#[test]
+fn finish() {
+    let mut debugger = program();
+
+    debugger.execute("s 3").unwrap();
+    assert_eq!(debugger.cpu.pc, 0x0b);
+
+    debugger.execute("finish").unwrap();
+    assert_eq!((debugger.cpu.pc, debugger.cpu.sp), (0x0a, 0xfffc));
+
+    debugger.execute("f").unwrap();
+    assert_eq!((debugger.cpu.pc, debugger.cpu.sp), (0x05, 0xfffe));
+}
+
+#[test]
+fn stack_wrapping_around() {
+    let mut debugger = program();
+    debugger.execute("r sp 0").unwrap();
+
+    debugger.execute("s").unwrap();
+    debugger.execute("n").unwrap();
+    assert_eq!((debugger.cpu.pc, debugger.cpu.sp), (0x05, 0));
+
+    debugger.execute("r pc 2").unwrap();
+    debugger.execute("s").unwrap();
+    debugger.execute("f").unwrap();
+    assert_eq!((debugger.cpu.pc, debugger.cpu.sp), (0x05, 0));
+}
+
+#[test]
+fn continue_until_halt_or_count() {
+    let mut debugger = program();
+
+    let output = debugger.execute("c 2").unwrap();
+    assert!(output.starts_with("Stopped by instruction limit after 2 instructions\n"));
+
+    let output = debugger.execute("continue").unwrap();
+    assert!(output.starts_with("Stopped by HALT after 6 instructions\n"));
+    assert_eq!(debugger.cpu.a, 3);
+}
+
+#[test]
+fn registers_and_flags() {
+    let mut debugger = program();
+
+    debugger.execute("r hl 0x1234").unwrap();
+    debugger.execute("r a h+1").unwrap();
+    debugger.execute("r bc' hl*2").unwrap();
+    assert_eq!(debugger.cpu.a, 0x13);
+    assert_eq!((debugger.cpu.b1, debugger.cpu.c1), (0x24, 0x68));
+
+    assert_eq!(debugger.execute("flags +z +C").unwrap(), "-Z---C\n");
+    assert_eq!(debugger.execute("flags -c").unwrap(), "-Z----\n");
+
+    assert!(debugger.execute("r xx 1").is_err());
+    assert!(debugger.execute("flags z").is_err());
+}
+
+#[test]
+fn breakpoints() {
+    let mut debugger = program();
+
+    assert_eq!(debugger.execute("b sub2").unwrap_err(), "Undefined symbol: sub2");
+    assert_eq!(
+        debugger.execute("break 0x0b if a == 1").unwrap(),
+        "1: break at 0x000b if a == 1\n"
+    );
+    assert_eq!(
+        debugger.execute("watch 0xfff8 0xfff9").unwrap(),
+        "2: watch write 0xfff8-0xfff9\n"
+    );
+    assert_eq!(
+        debugger.execute("iowatch out 0xfe if value & 7").unwrap(),
+        "3: watch port out 0x00fe if value & 7\n"
+    );
+    debugger.execute("disable 2").unwrap();
+
+    let output = debugger.execute("c").unwrap();
+    assert!(output.starts_with("Stopped by breakpoint 1 after 3 instructions\n"));
+
+    // The nested call pushes its return address on 0xfff8.
+    debugger.execute("enable 2").unwrap();
+    debugger.execute("r pc 7").unwrap();
+    let output = debugger.execute("n").unwrap();
+    assert!(output.starts_with("Stopped by watchpoint 2, write 0x00 to 0xfff9\n"));
+
+    debugger.execute("delete 1").unwrap();
+    assert_eq!(
+        debugger.execute("breaks").unwrap(),
+        "2: watch write 0xfff8-0xfff9\n3: watch port out 0x00fe if value & 7\n"
+    );
+    assert!(debugger.execute("delete 1").is_err());
+    debugger.execute("delete").unwrap();
+    assert_eq!(debugger.execute("breaks").unwrap(), "");
+}
+
+#[test]
+fn reverse_execution() {
+    let mut debugger = program();
+    debugger.execute("c").unwrap();
+    assert_eq!(
+        debugger.execute("history").unwrap(),
+        "8 instructions executed, back to 0 can be undone (last 100000 kept)\n"
+    );
+
+    let output = debugger.execute("sb").unwrap();
+    assert!(output.ends_with("> 0006  76           HALT\n"));
+    assert!(!debugger.cpu.halted);
+    debugger.execute("step-back 2").unwrap();
+    assert_eq!((debugger.cpu.pc, debugger.cpu.a), (0x0a, 2));
+
+    debugger.execute("b 0x0b").unwrap();
+    let output = debugger.execute("rc").unwrap();
+    assert!(output.starts_with("Stopped by breakpoint 1 after 2 instructions back\n"));
+    assert_eq!((debugger.cpu.pc, debugger.cpu.a), (0x0b, 1));
+
+    // CALL sub2 pushes its return address on 0xfffa.
+    debugger.execute("delete").unwrap();
+    debugger.execute("watch 0xfffa 0xfffb").unwrap();
+    let output = debugger.execute("reverse-continue").unwrap();
+    assert!(output
+        .starts_with("Stopped by watchpoint 2, write 0x00 to 0xfffb after 1 instructions back\n"));
+    assert_eq!((debugger.cpu.pc, debugger.cpu.sp), (7, 0xfffc));
+
+    assert_eq!(
+        debugger.execute("rewind 8").unwrap_err(),
+        "Instruction 8 out of the history, from 0 to 2"
+    );
+    debugger.execute("rewind 0").unwrap();
+    assert_eq!(debugger.cpu.pc, 0);
+    let output = debugger.execute("rc").unwrap();
+    assert!(output.starts_with("Stopped by the start of the history after 0 instructions back\n"));
+
+    debugger.execute("delete").unwrap();
+    debugger.execute("c").unwrap();
+    assert_eq!((debugger.cpu.pc, debugger.cpu.a), (7, 3));
+
+    debugger.execute("history 2").unwrap();
+    let output = debugger.execute("sb 3").unwrap();
+    assert!(output.starts_with("Start of the history after 2 instructions\n"));
+    assert_eq!(debugger.execute("history 0").unwrap(), "No history\n");
+    assert!(debugger.execute("rewind 0").is_err());
+}
+
+#[test]
+fn memory() {
+    let mut debugger = program();
+
+    debugger.execute("fill 0x100 0x10f 0x41").unwrap();
+    debugger.execute("e 0x102 1 2 3").unwrap();
+
+    assert_eq!(
+        debugger.execute("m 0x100 0x10f").unwrap(),
+        "0100  41 41 01 02 03 41 41 41 41 41 41 41 41 41 41 41  AA...AAAAAAAAAAA\n"
+    );
+    let output = debugger.execute("mem").unwrap();
+    assert!(output.starts_with("0110"));
+    assert_eq!(output.lines().count(), 8);
+    assert!(debugger.execute("m").unwrap().starts_with("0190"));
+
+    assert!(debugger.execute("fill 2 1 0").is_err());
+    assert!(debugger.execute("e 0x10000 0").is_err());
+}
+
+#[test]
+fn writing_memory_forgets_the_history() {
+    let mut debugger = program();
+    debugger.execute("s 3").unwrap();
+    debugger.execute("e 0xfffa 0x55").unwrap();
+    assert_eq!(
+        debugger.execute("history").unwrap(),
+        "3 instructions executed, back to 3 can be undone (last 100000 kept)\n"
+    );
+    let output = debugger.execute("sb").unwrap();
+    assert!(output.starts_with("Start of the history after 0 instructions\n"));
+    assert_eq!(debugger.cpu.memory[0xfffa], 0x55);
+
+    debugger.execute("s").unwrap();
+    debugger.execute("fill 0x100 0x1ff 0").unwrap();
+    assert!(debugger.execute("rewind 3").is_err());
+    assert_eq!(debugger.cpu.pc, 0x0c);
+}
+
+#[test]
+fn disassembly_around_pc() {
+    let mut debugger = program();
+    debugger.execute("s 2").unwrap();
+
+    let output = debugger.execute("d").unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 10);
+    assert_eq!(lines[0], "  0002  cd 07 00     CALL 0x0007");
+    assert_eq!(lines[1], "  0005  3c           INC A");
+    assert_eq!(lines[2], "  0006  76           HALT");
+    assert_eq!(lines[3], "> 0007  cd 0b 00     CALL 0x000b");
+
+    assert_eq!(
+        debugger.execute("dis 5 1").unwrap(),
+        "  0005  3c           INC A\n"
+    );
+}
+
+#[test]
+fn backtrace() {
+    let path = env::temp_dir().join(format!("z80-debugger-{}.sym", ::std::process::id()));
+    fs::write(&path, "sub EQU 7\nsub2 EQU 0x0b\n").unwrap();
+
+    let mut debugger = program();
+    debugger.execute("b 0x0b").unwrap();
+    debugger.execute("c").unwrap();
+    assert_eq!(
+        debugger.execute("bt").unwrap(),
+        "#0   000b  0x000b\n#1   0007  0x0007\n#2   0002  0x0002\n"
+    );
+
+    debugger
+        .execute(&format!("symbols {}", path.to_string_lossy()))
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        debugger.execute("backtrace").unwrap(),
+        "#0   000b  sub2\n#1   0007  sub\n#2   0002  0x0002\n"
+    );
+
+    // Return from sub2 to the HALT instead of sub.
+    debugger.execute("e 0xfffa 6").unwrap();
+    let output = debugger.execute("c").unwrap();
+    assert!(output.starts_with(
+        "Warning: Return at sub2+1 to 0x0006, not to sub+3 pushed by CALL at sub\nStopped by"
+    ));
+    assert_eq!(debugger.execute("bt").unwrap(), "#0   0007  sub\n#1   0002  0x0002\n");
+}
+
+#[test]
+fn symbols() {
+    let path = env::temp_dir().join(format!("z80-debugger-{}.map", ::std::process::id()));
+    fs::write(
+        &path,
+        "sub  = $0007 ; addr, public, , main, code, main.asm:5\n\
+         sub2 = $000B ; addr, public, , main, code, main.asm:7\n",
+    )
+    .unwrap();
+
+    let mut debugger = program();
+    assert_eq!(
+        debugger
+            .execute(&format!("symbols {}", path.to_string_lossy()))
+            .unwrap(),
+        "Loaded 2 symbols\n"
+    );
+    fs::remove_file(&path).unwrap();
+
+    debugger.execute("b sub2").unwrap();
+    debugger.execute("c").unwrap();
+    assert_eq!(debugger.execute("r pc sub+3").unwrap(), "");
+    assert_eq!(
+        debugger.execute("dis sub 3").unwrap(),
+        "sub:\n  0007  cd 0b 00     CALL sub2\n> 000a  c9           RET\n\
+         sub2:\n  000b  87           ADD A,A\n"
+    );
+    assert!(debugger.execute("b nowhere").is_err());
+}
+
+#[test]
+fn load_and_save() {
+    let path = env::temp_dir().join(format!("z80-debugger-{}.bin", ::std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+
+    let mut debugger = program();
+    assert_eq!(
+        debugger.execute(&format!("save {} 0 3", path)).unwrap(),
+        "Saved 4 bytes\n"
+    );
+    assert_eq!(
+        debugger.execute(&format!("load {} 0x8000", path)).unwrap(),
+        "Loaded 4 bytes\n"
+    );
+    assert_eq!(
+        &debugger.cpu.memory[0x8000..0x8004],
+        &[0x3e, 0x01, 0xcd, 0x07]
+    );
+
+    fs::remove_file(&path).unwrap();
+    assert!(debugger.execute(&format!("load {}", path)).is_err());
+
+    let path = env::temp_dir().join(format!("z80-debugger-{}.s19", ::std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    debugger.execute(&format!("save {} 7 0x0b", path)).unwrap();
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .contains("S1080007CD0B00C987C8\n"));
+    assert_eq!(
+        debugger.execute(&format!("load {}", path)).unwrap(),
+        "Loaded 5 bytes\n"
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let path = env::temp_dir().join(format!("z80-debugger-{}.state", ::std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+
+    let mut debugger = program();
+    debugger.execute("s 2").unwrap();
+    let state = debugger.cpu.state();
+    assert_eq!(
+        debugger.execute(&format!("snapshot {}", path)).unwrap(),
+        format!("Saved the state at PC 0x{:04x}\n", state.pc)
+    );
+
+    debugger.execute("c").unwrap();
+    debugger.execute("e 0 0xff").unwrap();
+    assert_eq!(
+        debugger.execute(&format!("restore {}", path)).unwrap(),
+        format!("Restored the state at PC 0x{:04x}\n", state.pc)
+    );
+    assert_eq!(debugger.cpu.state(), state);
+    assert!(debugger
+        .execute("sb")
+        .unwrap()
+        .starts_with("Start of the history after 0 instructions"));
+
+    fs::remove_file(&path).unwrap();
+    assert!(debugger.execute(&format!("restore {}", path)).is_err());
+}
+
+#[test]
+fn script() {
+    let mut debugger = program();
+    let script = "# comment\n\nbogus\nr a 0x10\nc\nquit\nstep\n";
+    let mut output = Vec::new();
+
+    debugger
+        .repl(Cursor::new(script), &mut output, None)
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("Error: Unknown command: bogus, try help\n"));
+    assert!(output.contains("Stopped by HALT after 8 instructions\n"));
+    assert!(output.ends_with("cycles=73 halted\n> 0007  cd 0b 00     CALL 0x000b\n"));
+    assert!(debugger.finished());
+}
@@ -0,0 +1,694 @@
+#[cfg(test)]
+mod tests;
+
+use asm::{Condition, Index, Operand, Reg16, Reg8};
+use std::fmt;
+use symbols::SymbolTable;
+
+// === Disassembler ===
+//
+// Opcodes are decoded by splitting them in the bit fields
+//
+//   x x y y y z z z
+//       p p q
+//
+// as in the Zilog manual tables. With a DD or FD prefix HL becomes IX or IY,
+// (HL) becomes (IX+d) or (IY+d) and, unless (IX+d) is used, H and L become
+// the undocumented IXH and IXL.
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// Address of the first byte.
+    pub address: u16,
+    /// Object code, prefixes included.
+    pub bytes: Vec<u8>,
+    /// Upper case mnemonic, as in the Zilog manual.
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    /// True for undocumented opcodes and undocumented aliases
+    /// of documented ones.
+    pub undocumented: bool,
+}
+
+impl Instruction {
+    /// Length in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Zilog syntax text, as in `LD A,(IX-3)`.
+    pub fn text(&self) -> String {
+        self.format(None)
+    }
+
+    /// Text with the addresses of jumps, calls, 16 bit loads and memory
+    /// operands written as the symbol defined there, if any, as in
+    /// `CALL print`.
+    pub fn symbolic_text(&self, symbols: &SymbolTable) -> String {
+        self.format(Some(symbols))
+    }
+
+    fn format(&self, symbols: Option<&SymbolTable>) -> String {
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .map(|operand| self.format_operand(operand, symbols))
+            .collect();
+
+        if operands.is_empty() {
+            self.mnemonic.to_string()
+        } else {
+            format!("{} {}", self.mnemonic, operands.join(","))
+        }
+    }
+
+    fn format_operand(&self, operand: &Operand, symbols: Option<&SymbolTable>) -> String {
+        let address = |value: u16| match symbols.and_then(|symbols| symbols.name_at(value)) {
+            Some(name) => name.to_string(),
+            None => format!("0x{:04x}", value),
+        };
+
+        match *operand {
+            Operand::Reg8(reg) => reg8_name(reg).to_string(),
+            Operand::Reg16(reg) => reg16_name(reg).to_string(),
+            Operand::Indirect(reg) => format!("({})", reg16_name(reg)),
+            Operand::Indexed(index, disp) => {
+                let name = match index {
+                    Index::ix => "IX",
+                    Index::iy => "IY",
+                };
+                if self.mnemonic == "JP" {
+                    format!("({})", name)
+                } else if disp < 0 {
+                    format!("({}-0x{:02x})", name, -disp)
+                } else {
+                    format!("({}+0x{:02x})", name, disp)
+                }
+            }
+            Operand::Port => "(C)".to_string(),
+            Operand::Condition(cc) => condition_name(cc).to_string(),
+            Operand::Immediate(value) => match self.mnemonic {
+                "BIT" | "RES" | "SET" | "IM" | "OUT" => format!("{}", value),
+                "JP" | "JR" | "DJNZ" | "CALL" => address(value as u16),
+                "LD" => match self.operands[0] {
+                    Operand::Reg16(_) => address(value as u16),
+                    _ => format!("0x{:02x}", value),
+                },
+                _ => format!("0x{:02x}", value),
+            },
+            Operand::Memory(value) => match self.mnemonic {
+                "IN" | "OUT" => format!("(0x{:02x})", value),
+                _ => format!("({})", address(value as u16)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+fn reg8_name(reg: Reg8) -> &'static str {
+    match reg {
+        Reg8::a => "A",
+        Reg8::b => "B",
+        Reg8::c => "C",
+        Reg8::d => "D",
+        Reg8::e => "E",
+        Reg8::h => "H",
+        Reg8::l => "L",
+        Reg8::i => "I",
+        Reg8::r => "R",
+        Reg8::ixh => "IXH",
+        Reg8::ixl => "IXL",
+        Reg8::iyh => "IYH",
+        Reg8::iyl => "IYL",
+    }
+}
+
+fn reg16_name(reg: Reg16) -> &'static str {
+    match reg {
+        Reg16::af => "AF",
+        Reg16::af1 => "AF'",
+        Reg16::bc => "BC",
+        Reg16::de => "DE",
+        Reg16::hl => "HL",
+        Reg16::sp => "SP",
+        Reg16::ix => "IX",
+        Reg16::iy => "IY",
+    }
+}
+
+fn condition_name(cc: Condition) -> &'static str {
+    match cc {
+        Condition::nz => "NZ",
+        Condition::z => "Z",
+        Condition::nc => "NC",
+        Condition::po => "PO",
+        Condition::pe => "PE",
+        Condition::p => "P",
+        Condition::m => "M",
+    }
+}
+
+const ALU: [&str; 8] = ["ADD", "ADC", "SUB", "SBC", "AND", "XOR", "OR", "CP"];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SLL", "SRL"];
+const BLOCK: [[&str; 4]; 4] = [
+    ["LDI", "CPI", "INI", "OUTI"],
+    ["LDD", "CPD", "IND", "OUTD"],
+    ["LDIR", "CPIR", "INIR", "OTIR"],
+    ["LDDR", "CPDR", "INDR", "OTDR"],
+];
+
+/// Reads the bytes of an instruction, wrapping around at the end of memory.
+struct Reader<'a> {
+    memory: &'a [u8],
+    address: u16,
+    bytes: Vec<u8>,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> u8 {
+        let addr = self.address.wrapping_add(self.bytes.len() as u16) as usize;
+        let value = if addr < self.memory.len() {
+            self.memory[addr]
+        } else {
+            0
+        };
+        self.bytes.push(value);
+        value
+    }
+
+    fn word(&mut self) -> i32 {
+        let low = i32::from(self.byte());
+        let high = i32::from(self.byte());
+        high << 8 | low
+    }
+
+    fn displacement(&mut self) -> i32 {
+        i32::from(self.byte() as i8)
+    }
+}
+
+/// The instruction being decoded.
+struct Decoder<'a> {
+    reader: Reader<'a>,
+    index: Option<Index>,
+    displacement: Option<i32>,
+    /// Set when the index prefix changed the meaning of the instruction.
+    index_used: bool,
+    undocumented: bool,
+}
+
+impl<'a> Decoder<'a> {
+    /// Register r[code], with H and L replaced by the index register
+    /// halves unless the instruction also uses (IX+d).
+    fn r(&mut self, code: u8, with_memory: bool) -> Operand {
+        match code {
+            0 => Operand::Reg8(Reg8::b),
+            1 => Operand::Reg8(Reg8::c),
+            2 => Operand::Reg8(Reg8::d),
+            3 => Operand::Reg8(Reg8::e),
+            4 | 5 if self.index.is_some() && !with_memory => {
+                self.index_used = true;
+                self.undocumented = true;
+                let high = code == 4;
+                Operand::Reg8(match (self.index, high) {
+                    (Some(Index::ix), true) => Reg8::ixh,
+                    (Some(Index::ix), false) => Reg8::ixl,
+                    (_, true) => Reg8::iyh,
+                    (_, false) => Reg8::iyl,
+                })
+            }
+            4 => Operand::Reg8(Reg8::h),
+            5 => Operand::Reg8(Reg8::l),
+            6 => self.hl_indirect(),
+            _ => Operand::Reg8(Reg8::a),
+        }
+    }
+
+    /// (HL), or (IX+d) with the displacement read on first use.
+    fn hl_indirect(&mut self) -> Operand {
+        match self.index {
+            Some(index) => {
+                self.index_used = true;
+                let disp = match self.displacement {
+                    Some(disp) => disp,
+                    None => {
+                        let disp = self.reader.displacement();
+                        self.displacement = Some(disp);
+                        disp
+                    }
+                };
+                Operand::Indexed(index, disp)
+            }
+            None => Operand::Indirect(Reg16::hl),
+        }
+    }
+
+    fn hl(&mut self) -> Reg16 {
+        match self.index {
+            Some(Index::ix) => {
+                self.index_used = true;
+                Reg16::ix
+            }
+            Some(Index::iy) => {
+                self.index_used = true;
+                Reg16::iy
+            }
+            None => Reg16::hl,
+        }
+    }
+
+    fn rp(&mut self, p: u8) -> Operand {
+        Operand::Reg16(match p {
+            0 => Reg16::bc,
+            1 => Reg16::de,
+            2 => self.hl(),
+            _ => Reg16::sp,
+        })
+    }
+
+    fn rp2(&mut self, p: u8) -> Operand {
+        match p {
+            3 => Operand::Reg16(Reg16::af),
+            _ => self.rp(p),
+        }
+    }
+
+    fn cc(y: u8) -> Operand {
+        match y {
+            0 => Operand::Condition(Condition::nz),
+            1 => Operand::Condition(Condition::z),
+            2 => Operand::Condition(Condition::nc),
+            3 => Operand::Reg8(Reg8::c),
+            4 => Operand::Condition(Condition::po),
+            5 => Operand::Condition(Condition::pe),
+            6 => Operand::Condition(Condition::p),
+            _ => Operand::Condition(Condition::m),
+        }
+    }
+
+    fn relative(&mut self) -> Operand {
+        let disp = self.reader.displacement();
+        let target = i32::from(self.reader.address) + 2 + disp;
+        Operand::Immediate(target & 0xffff)
+    }
+
+    fn main(&mut self, opcode: u8) -> (&'static str, Vec<Operand>) {
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 7;
+        let z = opcode & 7;
+        let p = y >> 1;
+        let q = y & 1;
+
+        let a = Operand::Reg8(Reg8::a);
+
+        match (x, z) {
+            (0, 0) => match y {
+                0 => ("NOP", vec![]),
+                1 => ("EX", vec![Operand::Reg16(Reg16::af), Operand::Reg16(Reg16::af1)]),
+                2 => {
+                    let target = self.relative();
+                    ("DJNZ", vec![target])
+                }
+                3 => {
+                    let target = self.relative();
+                    ("JR", vec![target])
+                }
+                _ => {
+                    let target = self.relative();
+                    ("JR", vec![Self::cc(y - 4), target])
+                }
+            },
+            (0, 1) => {
+                let rp = self.rp(p);
+                if q == 0 {
+                    let nn = self.reader.word();
+                    ("LD", vec![rp, Operand::Immediate(nn)])
+                } else {
+                    let hl = self.rp(2);
+                    ("ADD", vec![hl, rp])
+                }
+            }
+            (0, 2) => match (q, p) {
+                (0, 0) => ("LD", vec![Operand::Indirect(Reg16::bc), a]),
+                (0, 1) => ("LD", vec![Operand::Indirect(Reg16::de), a]),
+                (0, 2) => {
+                    let nn = self.reader.word();
+                    let hl = self.rp(2);
+                    ("LD", vec![Operand::Memory(nn), hl])
+                }
+                (0, _) => {
+                    let nn = self.reader.word();
+                    ("LD", vec![Operand::Memory(nn), a])
+                }
+                (_, 0) => ("LD", vec![a, Operand::Indirect(Reg16::bc)]),
+                (_, 1) => ("LD", vec![a, Operand::Indirect(Reg16::de)]),
+                (_, 2) => {
+                    let nn = self.reader.word();
+                    let hl = self.rp(2);
+                    ("LD", vec![hl, Operand::Memory(nn)])
+                }
+                (_, _) => {
+                    let nn = self.reader.word();
+                    ("LD", vec![a, Operand::Memory(nn)])
+                }
+            },
+            (0, 3) => {
+                let rp = self.rp(p);
+                (if q == 0 { "INC" } else { "DEC" }, vec![rp])
+            }
+            (0, 4) => {
+                let r = self.r(y, false);
+                ("INC", vec![r])
+            }
+            (0, 5) => {
+                let r = self.r(y, false);
+                ("DEC", vec![r])
+            }
+            (0, 6) => {
+                let r = self.r(y, false);
+                let n = self.reader.byte();
+                ("LD", vec![r, Operand::Immediate(i32::from(n))])
+            }
+            (0, _) => (
+                ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"][y as usize],
+                vec![],
+            ),
+            (1, 6) if y == 6 => ("HALT", vec![]),
+            (1, _) => {
+                let with_memory = y == 6 || z == 6;
+                let dest = self.r(y, with_memory);
+                let src = self.r(z, with_memory);
+                ("LD", vec![dest, src])
+            }
+            (2, _) => {
+                let r = self.r(z, false);
+                Self::alu(y, r)
+            }
+            (_, 0) => ("RET", vec![Self::cc(y)]),
+            (_, 1) => match (q, p) {
+                (0, _) => {
+                    let rp = self.rp2(p);
+                    ("POP", vec![rp])
+                }
+                (_, 0) => ("RET", vec![]),
+                (_, 1) => ("EXX", vec![]),
+                (_, 2) => {
+                    let operand = match self.hl() {
+                        Reg16::hl => Operand::Indirect(Reg16::hl),
+                        Reg16::ix => Operand::Indexed(Index::ix, 0),
+                        _ => Operand::Indexed(Index::iy, 0),
+                    };
+                    ("JP", vec![operand])
+                }
+                (_, _) => {
+                    let hl = self.rp(2);
+                    ("LD", vec![Operand::Reg16(Reg16::sp), hl])
+                }
+            },
+            (_, 2) => {
+                let nn = self.reader.word();
+                ("JP", vec![Self::cc(y), Operand::Immediate(nn)])
+            }
+            (_, 3) => match y {
+                0 => {
+                    let nn = self.reader.word();
+                    ("JP", vec![Operand::Immediate(nn)])
+                }
+                2 => {
+                    let n = self.reader.byte();
+                    ("OUT", vec![Operand::Memory(i32::from(n)), a])
+                }
+                3 => {
+                    let n = self.reader.byte();
+                    ("IN", vec![a, Operand::Memory(i32::from(n))])
+                }
+                4 => {
+                    let hl = self.rp(2);
+                    ("EX", vec![Operand::Indirect(Reg16::sp), hl])
+                }
+                5 => ("EX", vec![Operand::Reg16(Reg16::de), Operand::Reg16(Reg16::hl)]),
+                6 => ("DI", vec![]),
+                _ => ("EI", vec![]),
+            },
+            (_, 4) => {
+                let nn = self.reader.word();
+                ("CALL", vec![Self::cc(y), Operand::Immediate(nn)])
+            }
+            (_, 5) => match (q, p) {
+                (0, _) => {
+                    let rp = self.rp2(p);
+                    ("PUSH", vec![rp])
+                }
+                _ => {
+                    let nn = self.reader.word();
+                    ("CALL", vec![Operand::Immediate(nn)])
+                }
+            },
+            (_, 6) => {
+                let n = self.reader.byte();
+                Self::alu(y, Operand::Immediate(i32::from(n)))
+            }
+            (_, _) => ("RST", vec![Operand::Immediate(i32::from(y) * 8)]),
+        }
+    }
+
+    fn alu(y: u8, operand: Operand) -> (&'static str, Vec<Operand>) {
+        let mnemonic = ALU[y as usize];
+        match y {
+            0 | 1 | 3 => (mnemonic, vec![Operand::Reg8(Reg8::a), operand]),
+            _ => (mnemonic, vec![operand]),
+        }
+    }
+
+    fn cb(&mut self, opcode: u8) -> (&'static str, Vec<Operand>) {
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 7;
+        let z = opcode & 7;
+
+        if y == 6 && x == 0 {
+            self.undocumented = true;
+        }
+
+        // With an index prefix the operand is always (IX+d): a register
+        // code other than (HL) also copies the result to that register.
+        let (operand, copy) = if self.index.is_some() {
+            let operand = self.hl_indirect();
+            if z == 6 {
+                (operand, None)
+            } else {
+                self.undocumented = true;
+                (operand, Some(self.r(z, true)))
+            }
+        } else {
+            (self.r(z, false), None)
+        };
+
+        let bit = Operand::Immediate(i32::from(y));
+
+        match (x, copy) {
+            (0, None) => (ROT[y as usize], vec![operand]),
+            (0, Some(reg)) => (ROT[y as usize], vec![operand, reg]),
+            (1, _) => ("BIT", vec![bit, operand]),
+            (2, None) => ("RES", vec![bit, operand]),
+            (2, Some(reg)) => ("RES", vec![bit, operand, reg]),
+            (_, None) => ("SET", vec![bit, operand]),
+            (_, Some(reg)) => ("SET", vec![bit, operand, reg]),
+        }
+    }
+
+    fn ed(&mut self, opcode: u8) -> (&'static str, Vec<Operand>) {
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 7;
+        let z = opcode & 7;
+        let p = y >> 1;
+        let q = y & 1;
+
+        let a = Operand::Reg8(Reg8::a);
+
+        match (x, z) {
+            (1, 0) if y == 6 => {
+                self.undocumented = true;
+                ("IN", vec![Operand::Port])
+            }
+            (1, 0) => {
+                let r = self.r(y, false);
+                ("IN", vec![r, Operand::Port])
+            }
+            (1, 1) if y == 6 => {
+                self.undocumented = true;
+                ("OUT", vec![Operand::Port, Operand::Immediate(0)])
+            }
+            (1, 1) => {
+                let r = self.r(y, false);
+                ("OUT", vec![Operand::Port, r])
+            }
+            (1, 2) => {
+                let rp = self.rp(p);
+                let hl = Operand::Reg16(Reg16::hl);
+                (if q == 0 { "SBC" } else { "ADC" }, vec![hl, rp])
+            }
+            (1, 3) => {
+                let nn = Operand::Memory(self.reader.word());
+                let rp = self.rp(p);
+                if q == 0 {
+                    ("LD", vec![nn, rp])
+                } else {
+                    ("LD", vec![rp, nn])
+                }
+            }
+            (1, 4) => {
+                self.undocumented = y != 0;
+                ("NEG", vec![])
+            }
+            (1, 5) => {
+                self.undocumented = y > 1;
+                (if y == 1 { "RETI" } else { "RETN" }, vec![])
+            }
+            (1, 6) => {
+                self.undocumented = !(y == 0 || y == 2 || y == 3);
+                let mode = [0, 0, 1, 2, 0, 0, 1, 2][y as usize];
+                ("IM", vec![Operand::Immediate(mode)])
+            }
+            (1, 7) => match y {
+                0 => ("LD", vec![Operand::Reg8(Reg8::i), a]),
+                1 => ("LD", vec![Operand::Reg8(Reg8::r), a]),
+                2 => ("LD", vec![a, Operand::Reg8(Reg8::i)]),
+                3 => ("LD", vec![a, Operand::Reg8(Reg8::r)]),
+                4 => ("RRD", vec![]),
+                5 => ("RLD", vec![]),
+                _ => self.invalid_ed(opcode),
+            },
+            (2, _) if z <= 3 && y >= 4 => (BLOCK[(y - 4) as usize][z as usize], vec![]),
+            _ => self.invalid_ed(opcode),
+        }
+    }
+
+    /// Invalid ED opcodes execute as a two byte NOP.
+    fn invalid_ed(&mut self, opcode: u8) -> (&'static str, Vec<Operand>) {
+        self.undocumented = true;
+        (
+            "DB",
+            vec![Operand::Immediate(0xed), Operand::Immediate(i32::from(opcode))],
+        )
+    }
+}
+
+/// Decode the instruction at address.
+pub fn disassemble(memory: &[u8], address: u16) -> Instruction {
+    let mut decoder = Decoder {
+        reader: Reader {
+            memory,
+            address,
+            bytes: Vec::new(),
+        },
+        index: None,
+        displacement: None,
+        index_used: false,
+        undocumented: false,
+    };
+
+    let mut opcode = decoder.reader.byte();
+
+    let prefix = opcode;
+    if prefix == 0xdd || prefix == 0xfd {
+        decoder.index = Some(if prefix == 0xdd { Index::ix } else { Index::iy });
+        opcode = decoder.reader.byte();
+    }
+
+    let (mnemonic, operands) = match opcode {
+        0xcb if decoder.index.is_some() => {
+            // DD CB d op: the displacement comes before the opcode.
+            decoder.hl_indirect();
+            let opcode = decoder.reader.byte();
+            decoder.cb(opcode)
+        }
+        0xcb => {
+            let opcode = decoder.reader.byte();
+            decoder.cb(opcode)
+        }
+        0xed if decoder.index.is_none() => {
+            let opcode = decoder.reader.byte();
+            decoder.ed(opcode)
+        }
+        0xdd | 0xed | 0xfd => ("", vec![]),
+        _ => decoder.main(opcode),
+    };
+
+    // A prefix that does not change the following instruction
+    // only wastes time: show it on its own.
+    if decoder.index.is_some() && (!decoder.index_used || mnemonic.is_empty()) {
+        return Instruction {
+            address,
+            bytes: vec![prefix],
+            mnemonic: "DB",
+            operands: vec![Operand::Immediate(i32::from(prefix))],
+            undocumented: true,
+        };
+    }
+
+    Instruction {
+        address,
+        bytes: decoder.reader.bytes,
+        mnemonic,
+        operands,
+        undocumented: decoder.undocumented,
+    }
+}
+
+/// Decode the instructions starting from start to end included, so
+/// that a range can reach 0xffff.
+pub fn disassemble_range(memory: &[u8], start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = u32::from(start);
+
+    while address <= u32::from(end) {
+        let instruction = disassemble(memory, address as u16);
+        address += instruction.len() as u32;
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+/// Format an instruction as a listing line: address, object code and text.
+pub fn listing_line(instruction: &Instruction) -> String {
+    format_line(instruction, instruction.text())
+}
+
+/// Listing line with the symbolic text of the instruction.
+pub fn symbolic_listing_line(instruction: &Instruction, symbols: &SymbolTable) -> String {
+    format_line(instruction, instruction.symbolic_text(symbols))
+}
+
+fn format_line(instruction: &Instruction, text: String) -> String {
+    let bytes: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    format!(
+        "{:04x}  {:<12} {}",
+        instruction.address,
+        bytes.join(" "),
+        text
+    )
+}
+
+/// Listing of the instructions starting from start to end included.
+pub fn listing(memory: &[u8], start: u16, end: u16) -> String {
+    disassemble_range(memory, start, end)
+        .iter()
+        .map(|instruction| listing_line(instruction) + "\n")
+        .collect()
+}
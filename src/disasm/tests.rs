@@ -0,0 +1,180 @@
+use asm::assemble_instruction;
+use disasm::{disassemble, disassemble_range, listing, symbolic_listing_line};
+use symbols::SymbolTable;
+
+fn text(bytes: &[u8]) -> String {
+    disassemble(bytes, 0).text()
+}
+
+#[test]
+fn main_opcodes() {
+    assert_eq!(text(&[0x00]), "NOP");
+    assert_eq!(text(&[0x78]), "LD A,B");
+    assert_eq!(text(&[0x06, 0x12]), "LD B,0x12");
+    assert_eq!(text(&[0x01, 0x34, 0x12]), "LD BC,0x1234");
+    assert_eq!(text(&[0x3a, 0x34, 0x12]), "LD A,(0x1234)");
+    assert_eq!(text(&[0x22, 0x34, 0x12]), "LD (0x1234),HL");
+    assert_eq!(text(&[0x08]), "EX AF,AF'");
+    assert_eq!(text(&[0x76]), "HALT");
+    assert_eq!(text(&[0x80]), "ADD A,B");
+    assert_eq!(text(&[0x96]), "SUB (HL)");
+    assert_eq!(text(&[0xfe, 0xff]), "CP 0xff");
+    assert_eq!(text(&[0xda, 0x34, 0x12]), "JP C,0x1234");
+    assert_eq!(text(&[0xc8]), "RET Z");
+    assert_eq!(text(&[0xff]), "RST 0x38");
+    assert_eq!(text(&[0xdb, 0xfe]), "IN A,(0xfe)");
+}
+
+#[test]
+fn relative_jumps() {
+    assert_eq!(text(&[0x18, 0x0e]), "JR 0x0010");
+    assert_eq!(text(&[0x38, 0xfe]), "JR C,0x0000");
+
+    let mut memory = vec![0; 0x200];
+    memory[0x110] = 0x10;
+    memory[0x111] = 0xee;
+    assert_eq!(disassemble(&memory, 0x110).text(), "DJNZ 0x0100");
+}
+
+#[test]
+fn prefixed_opcodes() {
+    assert_eq!(text(&[0xcb, 0x00]), "RLC B");
+    assert_eq!(text(&[0xcb, 0x7f]), "BIT 7,A");
+    assert_eq!(text(&[0xed, 0x5b, 0x34, 0x12]), "LD DE,(0x1234)");
+    assert_eq!(text(&[0xed, 0x5e]), "IM 2");
+    assert_eq!(text(&[0xed, 0xb0]), "LDIR");
+    assert_eq!(text(&[0xdd, 0x7e, 0xfd]), "LD A,(IX-0x03)");
+    assert_eq!(text(&[0xfd, 0x36, 0x02, 0xfb]), "LD (IY+0x02),0xfb");
+    assert_eq!(text(&[0xfd, 0x66, 0x01]), "LD H,(IY+0x01)");
+    assert_eq!(text(&[0xdd, 0x21, 0x34, 0x12]), "LD IX,0x1234");
+    assert_eq!(text(&[0xdd, 0xe9]), "JP (IX)");
+    assert_eq!(text(&[0xdd, 0xcb, 0xfe, 0x9e]), "RES 3,(IX-0x02)");
+}
+
+#[test]
+fn undocumented_opcodes() {
+    let ixh = disassemble(&[0xdd, 0x26, 0x05], 0);
+    assert_eq!(ixh.text(), "LD IXH,0x05");
+    assert!(ixh.undocumented);
+
+    assert!(!disassemble(&[0xdd, 0x7e, 0x00], 0).undocumented);
+    assert_eq!(text(&[0xcb, 0x37]), "SLL A");
+    assert_eq!(text(&[0xfd, 0xcb, 0x01, 0x00]), "RLC (IY+0x01),B");
+    assert_eq!(text(&[0xdd, 0xcb, 0x02, 0xc9]), "SET 1,(IX+0x02),C");
+    assert_eq!(text(&[0xdd, 0xcb, 0x02, 0x48]), "BIT 1,(IX+0x02)");
+    assert_eq!(text(&[0xed, 0x70]), "IN (C)");
+    assert_eq!(text(&[0xed, 0x71]), "OUT (C),0");
+    assert_eq!(text(&[0xed, 0x4c]), "NEG");
+    assert!(disassemble(&[0xed, 0x4c], 0).undocumented);
+}
+
+#[test]
+fn ignored_prefixes() {
+    let prefix = disassemble(&[0xdd, 0x00], 0);
+    assert_eq!(prefix.text(), "DB 0xdd");
+    assert_eq!(prefix.len(), 1);
+
+    assert_eq!(text(&[0xfd, 0xdd, 0x21, 0, 0]), "DB 0xfd");
+    assert_eq!(text(&[0xdd, 0xeb]), "DB 0xdd");
+
+    let invalid = disassemble(&[0xed, 0x00], 0);
+    assert_eq!(invalid.text(), "DB 0xed,0x00");
+    assert_eq!(invalid.len(), 2);
+}
+
+#[test]
+fn wraps_at_end_of_memory() {
+    let mut memory = vec![0; 0x10000];
+    memory[0xffff] = 0xc3;
+    memory[0] = 0x34;
+    memory[1] = 0x12;
+
+    let instruction = disassemble(&memory, 0xffff);
+    assert_eq!(instruction.text(), "JP 0x1234");
+    assert_eq!(instruction.bytes, vec![0xc3, 0x34, 0x12]);
+}
+
+#[test]
+fn range_and_listing() {
+    let memory = [0x3e, 0x01, 0xdd, 0x7e, 0xfd, 0xc9];
+
+    let instructions = disassemble_range(&memory, 0, 5);
+    assert_eq!(instructions.len(), 3);
+    assert_eq!(instructions[2].address, 5);
+    assert_eq!(disassemble_range(&memory, 0, 4).len(), 2);
+    assert_eq!(disassemble_range(&memory, 5, 5).len(), 1);
+
+    let mut memory = vec![0; 0x10000];
+    memory[0xffff] = 0xc9;
+    let instructions = disassemble_range(&memory, 0xfffe, 0xffff);
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(instructions[1].text(), "RET");
+
+    let memory = [0x3e, 0x01, 0xdd, 0x7e, 0xfd, 0xc9];
+    assert_eq!(
+        listing(&memory, 0, 5),
+        "0000  3e 01        LD A,0x01\n\
+         0002  dd 7e fd     LD A,(IX-0x03)\n\
+         0005  c9           RET\n"
+    );
+}
+
+#[test]
+fn symbolic_text() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("print", 0x1234);
+    symbols.insert("count", 0x12);
+    let symbolic = |bytes: &[u8]| disassemble(bytes, 0).symbolic_text(&symbols);
+
+    assert_eq!(symbolic(&[0xcd, 0x34, 0x12]), "CALL print");
+    assert_eq!(symbolic(&[0xc2, 0x34, 0x12]), "JP NZ,print");
+    assert_eq!(symbolic(&[0x21, 0x34, 0x12]), "LD HL,print");
+    assert_eq!(symbolic(&[0x3a, 0x34, 0x12]), "LD A,(print)");
+    assert_eq!(symbolic(&[0xed, 0x43, 0x34, 0x12]), "LD (print),BC");
+    // Only addresses with a symbol, and only 16 bit ones.
+    assert_eq!(symbolic(&[0xcd, 0x35, 0x12]), "CALL 0x1235");
+    assert_eq!(symbolic(&[0x3e, 0x12]), "LD A,0x12");
+    assert_eq!(symbolic(&[0xdb, 0x12]), "IN A,(0x12)");
+
+    let mut memory = vec![0; 0x1222];
+    memory.extend_from_slice(&[0x18, 0x10]);
+    assert_eq!(
+        symbolic_listing_line(&disassemble(&memory, 0x1222), &symbols),
+        "1222  18 10        JR print"
+    );
+}
+
+// Every instruction must assemble back to the same object code.
+fn round_trip(bytes: &[u8]) {
+    let instruction = disassemble(bytes, 0x100);
+
+    if instruction.mnemonic == "DB" {
+        return;
+    }
+
+    let text = instruction.text();
+    let code = assemble_instruction(&text, 0x100)
+        .unwrap_or_else(|e| panic!("{:02x?} {}: {}", bytes, text, e));
+
+    // Undocumented aliases assemble to the documented opcode.
+    if code != instruction.bytes {
+        assert!(
+            instruction.undocumented,
+            "{:02x?} {} -> {:02x?}",
+            bytes, text, code
+        );
+    }
+}
+
+#[test]
+fn round_trip_all_opcodes() {
+    for opcode in 0..=0xff {
+        round_trip(&[opcode, 0x12, 0x34, 0x56]);
+        round_trip(&[0xcb, opcode, 0x12, 0x34]);
+        round_trip(&[0xed, opcode, 0x12, 0x34]);
+        round_trip(&[0xdd, opcode, 0x85, 0x34]);
+        round_trip(&[0xfd, opcode, 0x12, 0x34]);
+        round_trip(&[0xdd, 0xcb, 0x85, opcode]);
+        round_trip(&[0xfd, 0xcb, 0x12, opcode]);
+    }
+}
@@ -0,0 +1,524 @@
+#[cfg(test)]
+mod tests;
+
+use cpu::{Cpu, History, Kind, Limits, StopReason};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+// === GDB remote serial protocol ===
+//
+// A stub serving one GDB connection over TCP. Packets are `$data#cs`
+// with a two digit hex checksum, acknowledged by `+`. Registers are
+// sent little endian in the order of REGISTERS, which the target
+// description gives to GDB. While the CPU runs, a 0x03 byte from GDB
+// interrupts it. With the execution history, GDB can also step and
+// continue backwards, up to its last change of registers or memory.
+
+/// Register names, as known by `Cpu::read_named`, and sizes in bytes.
+/// The first twelve follow the numbering of GDB's own Z80 target.
+pub const REGISTERS: [(&str, usize); 14] = [
+    ("af", 2),
+    ("bc", 2),
+    ("de", 2),
+    ("hl", 2),
+    ("sp", 2),
+    ("pc", 2),
+    ("ix", 2),
+    ("iy", 2),
+    ("af'", 2),
+    ("bc'", 2),
+    ("de'", 2),
+    ("hl'", 2),
+    ("i", 1),
+    ("r", 1),
+];
+
+/// Instructions run between checks for an interrupt from GDB.
+const SLICE: u64 = 10_000;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// What to do after a packet.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Reply(String),
+    /// Execute one instruction, or run, then send a stop reply.
+    Resume {
+        step: bool,
+    },
+    /// The same backwards, undoing instructions.
+    Reverse {
+        step: bool,
+    },
+    /// Close the connection after sending the reply, if any.
+    Close(Option<String>),
+}
+
+/// GDB stub owning a CPU.
+pub struct GdbStub {
+    pub cpu: Cpu,
+    /// Breakpoints inserted by Z packets, by type, address and kind,
+    /// with their id in the CPU breakpoints.
+    inserted: HashMap<(u8, u16, u16), usize>,
+}
+
+impl GdbStub {
+    pub fn new(mut cpu: Cpu) -> Self {
+        if cpu.history.is_none() {
+            cpu.history = Some(History::default());
+        }
+        GdbStub {
+            cpu,
+            inserted: HashMap::new(),
+        }
+    }
+
+    /// Wait for one connection on address and serve it.
+    pub fn listen<A: ToSocketAddrs>(&mut self, address: A) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    /// Serve a connection until GDB detaches, kills or disconnects.
+    /// Breakpoints inserted by GDB are removed when it leaves.
+    pub fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let result = self.session(&mut stream);
+
+        for (_, id) in self.inserted.drain() {
+            self.cpu.breakpoints.remove(id);
+        }
+        result
+    }
+
+    fn session(&mut self, stream: &mut TcpStream) -> io::Result<()> {
+        while let Some(packet) = read_packet(stream)? {
+            match self.packet(&packet) {
+                Action::Reply(reply) => send_packet(stream, &reply)?,
+                Action::Resume { step } => {
+                    let reply = self.resume(stream, step)?;
+                    send_packet(stream, &reply)?;
+                }
+                Action::Reverse { step } => {
+                    let reply = self.reverse(stream, step)?;
+                    send_packet(stream, &reply)?;
+                }
+                Action::Close(reply) => {
+                    if let Some(reply) = reply {
+                        send_packet(stream, &reply)?;
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle the data of a packet.
+    pub fn packet(&mut self, data: &str) -> Action {
+        let command = data.get(..1).unwrap_or("");
+        let args = data.get(1..).unwrap_or("");
+
+        let reply = match command {
+            "?" => Some(format!("S{:02x}", SIGTRAP)),
+            "g" => Some(self.read_registers()),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" | "c" => {
+                // An address to resume at may follow.
+                if !args.is_empty() {
+                    let pc = match u16::from_str_radix(args, 16) {
+                        Ok(pc) => pc,
+                        Err(_) => return Action::Reply("E01".to_string()),
+                    };
+                    self.cpu.write_named("pc", pc);
+                }
+                return Action::Resume {
+                    step: command == "s",
+                };
+            }
+            "b" if args == "s" || args == "c" => return Action::Reverse { step: args == "s" },
+            "Z" => self.insert(args),
+            "z" => self.remove(args),
+            "H" => Some("OK".to_string()),
+            "q" => Some(query(args)),
+            "D" => return Action::Close(Some("OK".to_string())),
+            "k" => return Action::Close(None),
+            // Unsupported packets get an empty reply.
+            _ => Some(String::new()),
+        };
+
+        Action::Reply(reply.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    /// Run or step the CPU and return the stop reply.
+    fn resume(&mut self, stream: &mut TcpStream, step: bool) -> io::Result<String> {
+        if step {
+            let reason = self.cpu.step_checked(true);
+            return Ok(self.stop_reply(reason));
+        }
+
+        let limits = Limits {
+            instructions: Some(SLICE),
+            cycles: None,
+        };
+        let mut first = true;
+
+        loop {
+            // run() resumes over a breakpoint at pc, wanted only at the start.
+            if !first {
+                if let Some(id) = self.cpu.breakpoints.execution_hit(&self.cpu) {
+                    return Ok(self.stop_reply(Some(StopReason::Breakpoint(id))));
+                }
+            }
+            first = false;
+
+            match self.cpu.run(limits) {
+                (StopReason::InstructionLimit, _) => {}
+                (reason, _) => return Ok(self.stop_reply(Some(reason))),
+            }
+
+            if interrupted(stream)? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    /// Undo one instruction, or run backwards, and return the stop reply.
+    fn reverse(&mut self, stream: &mut TcpStream, step: bool) -> io::Result<String> {
+        // The start of the history, as GDB knows it from record targets.
+        let begin = format!("T{:02x}replaylog:begin;", SIGTRAP);
+
+        if step {
+            return Ok(if self.cpu.step_back() {
+                self.stop_reply(None)
+            } else {
+                begin
+            });
+        }
+
+        loop {
+            match self.cpu.run_back(Some(SLICE)) {
+                (Some(StopReason::InstructionLimit), _) => {}
+                (Some(reason), _) => return Ok(self.stop_reply(Some(reason))),
+                (None, _) => return Ok(begin),
+            }
+
+            if interrupted(stream)? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    fn stop_reply(&self, reason: Option<StopReason>) -> String {
+        if let Some(StopReason::Watchpoint(id, access)) = reason {
+            let kind = self
+                .inserted
+                .iter()
+                .find(|&(_, &inserted)| inserted == id)
+                .map(|(&(kind, _, _), _)| kind);
+            let name = match kind {
+                Some(2) => "watch",
+                Some(3) => "rwatch",
+                Some(4) => "awatch",
+                _ => return format!("S{:02x}", SIGTRAP),
+            };
+            return format!("T{:02x}{}:{:04x};", SIGTRAP, name, access.address);
+        }
+
+        format!("S{:02x}", SIGTRAP)
+    }
+
+    fn read_registers(&self) -> String {
+        REGISTERS
+            .iter()
+            .map(|&(name, size)| register_hex(&self.cpu, name, size))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        let bytes = decode_hex(args)?;
+        let size: usize = REGISTERS.iter().map(|&(_, size)| size).sum();
+        if bytes.len() != size {
+            return None;
+        }
+
+        let mut offset = 0;
+        for &(name, size) in REGISTERS.iter() {
+            self.cpu
+                .write_named(name, little_endian(&bytes[offset..offset + size]));
+            offset += size;
+        }
+        self.forget_history();
+        Some("OK".to_string())
+    }
+
+    fn read_register(&self, args: &str) -> Option<String> {
+        let number = usize::from_str_radix(args, 16).ok()?;
+        let &(name, size) = REGISTERS.get(number)?;
+        Some(register_hex(&self.cpu, name, size))
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<String> {
+        let mut parts = args.splitn(2, '=');
+        let number = usize::from_str_radix(parts.next()?, 16).ok()?;
+        let &(name, size) = REGISTERS.get(number)?;
+        let bytes = decode_hex(parts.next()?)?;
+        if bytes.len() != size {
+            return None;
+        }
+
+        self.cpu.write_named(name, little_endian(&bytes));
+        self.forget_history();
+        Some("OK".to_string())
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (start, length) = address_length(args)?;
+        let start = usize::from(start);
+        if start >= self.cpu.memory.len() {
+            return None;
+        }
+
+        // A read past the end of memory returns the bytes up to it.
+        let end = (start + usize::from(length)).min(self.cpu.memory.len());
+        Some(encode_hex(&self.cpu.memory[start..end]))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let mut parts = args.splitn(2, ':');
+        let (start, length) = address_length(parts.next()?)?;
+        let bytes = decode_hex(parts.next()?)?;
+        let start = usize::from(start);
+        let end = start + usize::from(length);
+        if bytes.len() != usize::from(length) || end > self.cpu.memory.len() {
+            return None;
+        }
+
+        self.cpu.memory[start..end].copy_from_slice(&bytes);
+        self.forget_history();
+        Some("OK".to_string())
+    }
+
+    /// Z type,addr,kind: insert a breakpoint (0, 1) or a write (2),
+    /// read (3) or access (4) watchpoint on kind bytes.
+    fn insert(&mut self, args: &str) -> Option<String> {
+        let (kind, address, length) = breakpoint_args(args)?;
+        if self.inserted.contains_key(&(kind, address, length)) {
+            return Some("OK".to_string());
+        }
+
+        let breakpoint = match kind {
+            0 | 1 => Kind::Execute(address),
+            2..=4 => Kind::Memory {
+                start: address,
+                end: address.wrapping_add(length.max(1) - 1),
+                read: kind != 2,
+                write: kind != 3,
+            },
+            _ => return Some(String::new()),
+        };
+        let id = self.cpu.breakpoints.add(breakpoint, None).ok()?;
+        self.inserted.insert((kind, address, length), id);
+        Some("OK".to_string())
+    }
+
+    fn remove(&mut self, args: &str) -> Option<String> {
+        let key = breakpoint_args(args)?;
+        if let Some(id) = self.inserted.remove(&key) {
+            self.cpu.breakpoints.remove(id);
+        }
+        Some("OK".to_string())
+    }
+
+    /// Forget the history once GDB changed registers or memory under
+    /// it: stepping back would mix the old and new values.
+    fn forget_history(&mut self) {
+        if let Some(ref mut history) = self.cpu.history {
+            history.clear();
+        }
+    }
+}
+
+/// Target description: the Z80 registers in the order of REGISTERS.
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n  \
+         <architecture>z80</architecture>\n  \
+         <feature name=\"org.gnu.gdb.z80.cpu\">\n",
+    );
+    for &(name, size) in REGISTERS.iter() {
+        let kind = match name {
+            "pc" => "code_ptr",
+            "sp" => "data_ptr",
+            _ => "int",
+        };
+        xml.push_str(&format!(
+            "    <reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>\n",
+            name,
+            size * 8,
+            kind
+        ));
+    }
+    xml.push_str("  </feature>\n</target>\n");
+    xml
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        "PacketSize=1000;qXfer:features:read+;ReverseStep+;ReverseContinue+".to_string()
+    } else if args == "Attached" {
+        "1".to_string()
+    } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        match address_length(range) {
+            Some((offset, length)) => {
+                let xml = target_xml();
+                let start = usize::from(offset).min(xml.len());
+                let end = (start + usize::from(length)).min(xml.len());
+                let more = if end < xml.len() { "m" } else { "l" };
+                format!("{}{}", more, &xml[start..end])
+            }
+            None => "E01".to_string(),
+        }
+    } else {
+        String::new()
+    }
+}
+
+fn register_hex(cpu: &Cpu, name: &str, size: usize) -> String {
+    let value = cpu.read_named(name).unwrap_or(0);
+    encode_hex(&value.to_le_bytes()[..size])
+}
+
+fn little_endian(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | u16::from(byte))
+}
+
+/// Parse `addr,length` in hex.
+fn address_length(args: &str) -> Option<(u16, u16)> {
+    let mut parts = args.splitn(2, ',');
+    let address = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let length = u16::from_str_radix(parts.next()?, 16).ok()?;
+    Some((address, length))
+}
+
+/// Parse `type,addr,kind` of Z and z packets.
+fn breakpoint_args(args: &str) -> Option<(u8, u16, u16)> {
+    let mut parts = args.splitn(2, ',');
+    let kind = parts.next()?.parse().ok()?;
+    let (address, length) = address_length(parts.next()?)?;
+    Some((kind, address, length))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match std::str::from_utf8(pair) {
+            Ok(pair) if pair.len() == 2 => u8::from_str_radix(pair, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &byte| sum.wrapping_add(byte))
+}
+
+/// Read the next packet, acknowledging it, or None at end of stream.
+/// Acknowledgements and interrupts outside of a run are skipped,
+/// and packets with a bad checksum are asked again.
+fn read_packet<S: Read + Write>(stream: &mut S) -> io::Result<Option<String>> {
+    let mut data = Vec::new();
+
+    loop {
+        let mut byte = [0];
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] != b'$' {
+            continue;
+        }
+
+        data.clear();
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+
+        let mut sum = [0; 2];
+        stream.read_exact(&mut sum)?;
+        let expected = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+
+        if expected == Some(checksum(&data)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&unescape(&data)).into_owned()));
+        }
+        stream.write_all(b"-")?;
+    }
+}
+
+/// Undo the `}` escapes of binary data.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = data.iter();
+    let mut unescaped = Vec::with_capacity(data.len());
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => unescaped.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            _ => unescaped.push(byte),
+        }
+    }
+    unescaped
+}
+
+/// Send a packet, escaping the characters of the framing.
+fn send_packet<W: Write>(stream: &mut W, data: &str) -> io::Result<()> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data.as_bytes() {
+        match byte {
+            b'$' | b'#' | b'}' | b'*' => escaped.extend_from_slice(&[b'}', byte ^ 0x20]),
+            _ => escaped.push(byte),
+        }
+    }
+
+    let mut packet = vec![b'$'];
+    packet.extend_from_slice(&escaped);
+    packet.extend_from_slice(format!("#{:02x}", checksum(&escaped)).as_bytes());
+    stream.write_all(&packet)?;
+    stream.flush()
+}
+
+/// True when GDB sent an interrupt, without waiting for it.
+fn interrupted(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0];
+    let result = match stream.read(&mut byte) {
+        Ok(0) => Err(io::Error::new(ErrorKind::UnexpectedEof, "GDB disconnected")),
+        Ok(_) => Ok(byte[0] == 0x03),
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    };
+    stream.set_nonblocking(false)?;
+    result
+}
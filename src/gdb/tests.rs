@@ -0,0 +1,314 @@
+use cpu::CpuBuilder;
+use gdb::{target_xml, Action, GdbStub};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+fn stub(code: Vec<u8>) -> GdbStub {
+    let mut memory = code;
+    memory.resize(0x10000, 0);
+    GdbStub::new(
+        CpuBuilder::new()
+            .with_memory(memory)
+            .with_sp(0xfffe)
+            .build(),
+    )
+}
+
+fn reply(stub: &mut GdbStub, packet: &str) -> String {
+    match stub.packet(packet) {
+        Action::Reply(reply) => reply,
+        action => panic!("{:?} for {}", action, packet),
+    }
+}
+
+/// Client side of a connection: sends packets, returns the replies.
+struct Client(TcpStream);
+
+impl Client {
+    fn send(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.0, "${}#{:02x}", data, sum).unwrap();
+        self.expect(b'+');
+    }
+
+    fn expect(&mut self, expected: u8) {
+        let mut byte = [0];
+        self.0.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], expected);
+    }
+
+    fn receive(&mut self) -> String {
+        self.expect(b'$');
+        let mut data = Vec::new();
+        let mut byte = [0];
+        loop {
+            self.0.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut sum = [0; 2];
+        self.0.read_exact(&mut sum).unwrap();
+        self.0.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+}
+
+/// Serve stub on a local port for the session run by client.
+fn session<F>(stub: &mut GdbStub, client: F) -> Vec<String>
+where
+    F: FnOnce(&mut Client) -> Vec<String> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || client(&mut Client(TcpStream::connect(address).unwrap())));
+
+    let (stream, _) = listener.accept().unwrap();
+    stub.serve(stream).unwrap();
+    client.join().unwrap()
+}
+
+#[test]
+fn registers() {
+    let mut stub = stub(vec![]);
+    stub.cpu.write_named("af", 0x1234);
+    stub.cpu.write_named("hl'", 0xbeef);
+    stub.cpu.pc = 0x0100;
+    stub.cpu.i = 0x3f;
+
+    assert_eq!(
+        reply(&mut stub, "g"),
+        "3412000000000000feff000100000000000000000000efbe3f00"
+    );
+    assert_eq!(reply(&mut stub, "pb"), "efbe");
+    assert_eq!(reply(&mut stub, "pc"), "3f");
+
+    assert_eq!(reply(&mut stub, "P2=3412"), "OK");
+    assert_eq!(stub.cpu.read_named("de"), Some(0x1234));
+    assert_eq!(reply(&mut stub, "Pd=80"), "OK");
+    assert_eq!(stub.cpu.r, 0x80);
+
+    let registers = "0100020003000400050006000700080009000a000b000c000d0e";
+    assert_eq!(reply(&mut stub, &format!("G{}", registers)), "OK");
+    assert_eq!(reply(&mut stub, "g"), registers);
+    assert_eq!((stub.cpu.sp, stub.cpu.pc, stub.cpu.r), (5, 6, 0x0e));
+
+    assert_eq!(reply(&mut stub, "G0100"), "E01");
+    assert_eq!(reply(&mut stub, "p20"), "E01");
+}
+
+#[test]
+fn memory() {
+    let mut stub = stub(asm!("LD A,1", "HALT"));
+
+    assert_eq!(reply(&mut stub, "m0,3"), "3e0176");
+    assert_eq!(reply(&mut stub, "M100,2:abcd"), "OK");
+    assert_eq!(&stub.cpu.memory[0x100..0x102], &[0xab, 0xcd]);
+    assert_eq!(reply(&mut stub, "mfffe,4"), "0000");
+
+    assert_eq!(reply(&mut stub, "M100,2:ab"), "E01");
+    assert_eq!(reply(&mut stub, "mfffe"), "E01");
+
+    let mut small = GdbStub::new(CpuBuilder::new().with_memory(vec![0; 4]).build());
+    assert_eq!(reply(&mut small, "m10,1"), "E01");
+    assert_eq!(reply(&mut small, "M3,2:0000"), "E01");
+}
+
+#[test]
+fn queries() {
+    let mut stub = stub(vec![]);
+
+    assert!(reply(&mut stub, "qSupported:multiprocess+;swbreak+").contains("qXfer:features:read+"));
+    assert_eq!(reply(&mut stub, "?"), "S05");
+    assert_eq!(reply(&mut stub, "Hg0"), "OK");
+    assert_eq!(reply(&mut stub, "vMustReplyEmpty"), "");
+
+    let xml = target_xml();
+    assert!(xml.contains("<reg name=\"hl'\" bitsize=\"16\" type=\"int\"/>"));
+    assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>"));
+    assert!(xml.contains("<reg name=\"r\" bitsize=\"8\" type=\"int\"/>"));
+
+    let first = reply(&mut stub, "qXfer:features:read:target.xml:0,10");
+    assert_eq!(first, format!("m{}", &xml[..0x10]));
+    let rest = reply(&mut stub, "qXfer:features:read:target.xml:10,1000");
+    assert_eq!(rest, format!("l{}", &xml[0x10..]));
+}
+
+#[test]
+fn breakpoints() {
+    let mut stub = stub(asm!(
+        "      LD A,1",
+        "      CALL sub",
+        "      HALT",
+        "sub:  LD (0x100),A",
+        "      RET"
+    ));
+
+    assert_eq!(reply(&mut stub, "Z0,6,1"), "OK");
+    assert_eq!(reply(&mut stub, "Z0,6,1"), "OK");
+    assert_eq!(reply(&mut stub, "Z2,100,2"), "OK");
+    assert_eq!(stub.cpu.breakpoints.iter().count(), 2);
+
+    assert_eq!(reply(&mut stub, "z0,6,1"), "OK");
+    assert_eq!(reply(&mut stub, "z0,6,1"), "OK");
+    assert_eq!(stub.cpu.breakpoints.iter().count(), 1);
+
+    assert_eq!(stub.packet("c"), Action::Resume { step: false });
+    assert_eq!(stub.packet("s10"), Action::Resume { step: true });
+    assert_eq!(stub.cpu.pc, 0x10);
+    assert_eq!(reply(&mut stub, "cxyz"), "E01");
+
+    assert_eq!(stub.packet("D"), Action::Close(Some("OK".to_string())));
+    assert_eq!(stub.packet("k"), Action::Close(None));
+}
+
+#[test]
+fn debugging_session() {
+    let mut stub = stub(asm!(
+        "      LD A,1",
+        "      CALL sub",
+        "      HALT",
+        "sub:  LD (0x100),A",
+        "      RET"
+    ));
+
+    let replies = session(&mut stub, |client| {
+        // A stray acknowledgement and a corrupted packet are skipped.
+        client.0.write_all(b"+$?#00").unwrap();
+        client.expect(b'-');
+
+        vec![
+            client.request("?"),
+            client.request("Z0,6,1"),
+            client.request("Z2,100,1"),
+            client.request("c"),
+            client.request("p5"),
+            client.request("c"),
+            client.request("s"),
+            client.request("c"),
+            client.request("D"),
+        ]
+    });
+
+    assert_eq!(
+        replies,
+        vec![
+            "S05",
+            "OK",
+            "OK",
+            "S05",
+            "0600",
+            "T05watch:0100;",
+            "S05",
+            "S05",
+            "OK"
+        ]
+    );
+    assert!(stub.cpu.halted);
+    assert_eq!(stub.cpu.memory[0x100], 1);
+    // GDB's breakpoints are gone with it.
+    assert!(stub.cpu.breakpoints.is_empty());
+}
+
+#[test]
+fn reverse_execution() {
+    let mut stub = stub(asm!(
+        "      LD A,1",
+        "      CALL sub",
+        "      HALT",
+        "sub:  LD (0x100),A",
+        "      RET"
+    ));
+    assert!(reply(&mut stub, "qSupported").contains("ReverseContinue+"));
+
+    let replies = session(&mut stub, |client| {
+        let replies = vec![
+            client.request("c"),
+            client.request("bs"),
+            client.request("p5"),
+            client.request("Z2,100,1"),
+            client.request("bc"),
+            client.request("p5"),
+            client.request("z2,100,1"),
+            client.request("bc"),
+            client.request("bs"),
+        ];
+        client.send("k");
+        replies
+    });
+
+    assert_eq!(
+        replies,
+        vec![
+            "S05",
+            "S05",
+            "0500",
+            "OK",
+            "T05watch:0100;",
+            "0600",
+            "OK",
+            "T05replaylog:begin;",
+            "T05replaylog:begin;",
+        ]
+    );
+    assert_eq!(stub.cpu.pc, 0);
+    assert_eq!(stub.cpu.memory[0x100], 0);
+}
+
+#[test]
+fn writes_forget_the_history() {
+    let mut stub = stub(asm!("LD A,1", "LD (0x100),A", "HALT"));
+
+    let replies = session(&mut stub, |client| {
+        let replies = vec![
+            client.request("s"),
+            client.request("s"),
+            client.request("M100,1:ff"),
+            client.request("bs"),
+            client.request("s"),
+            client.request("Pd=80"),
+            client.request("bs"),
+        ];
+        client.send("k");
+        replies
+    });
+
+    assert_eq!(
+        replies,
+        vec![
+            "S05",
+            "S05",
+            "OK",
+            "T05replaylog:begin;",
+            "S05",
+            "OK",
+            "T05replaylog:begin;",
+        ]
+    );
+    assert_eq!(stub.cpu.pc, 6);
+    assert_eq!(stub.cpu.memory[0x100], 0xff);
+}
+
+#[test]
+fn interrupt() {
+    let mut stub = stub(asm!("loop: JR loop"));
+
+    let replies = session(&mut stub, |client| {
+        client.send("c");
+        client.0.write_all(&[0x03]).unwrap();
+        let stop = client.receive();
+        client.send("k");
+        vec![stop]
+    });
+
+    assert_eq!(replies, vec!["S02"]);
+    assert_eq!(stub.cpu.pc, 0);
+}
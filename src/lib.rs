@@ -1,4 +1,18 @@
-mod cpu;
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[macro_use]
+pub mod asm;
+pub mod cpm;
+pub mod cpu;
+pub mod dap;
+pub mod debugger;
+pub mod disasm;
+pub mod gdb;
+pub mod loader;
+pub mod spectrum;
+pub mod symbols;
+pub mod tracediff;
 
 #[cfg(test)]
 mod tests {
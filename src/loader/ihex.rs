@@ -0,0 +1,161 @@
+use asm::Segment;
+use loader::LoadError;
+
+// === Intel HEX ===
+//
+// One record per line:
+//
+//   :LLAAAATTDD...CC
+//
+// LL data length, AAAA address, TT record type, DD data bytes and
+// CC the two's complement of the sum of all the other bytes.
+// Addresses beyond 64K, set by extended address records, are rejected.
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Data bytes written per record.
+const RECORD_SIZE: usize = 16;
+
+/// Parse Intel HEX records into segments, merging contiguous data,
+/// and the start address if present.
+pub fn parse_intel_hex(text: &str) -> Result<(Vec<Segment>, Option<u16>), LoadError> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut start = None;
+    let mut base: u32 = 0;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |message: &str| LoadError::new(format!("line {}: {}", number + 1, message));
+
+        let record = parse_record(line).map_err(|message| error(&message))?;
+        let address = u16::from(record[1]) << 8 | u16::from(record[2]);
+        let kind = record[3];
+        let data = &record[4..];
+
+        match kind {
+            DATA => {
+                let address = base + u32::from(address);
+                match address.checked_add(data.len() as u32) {
+                    Some(end) if end <= 0x10000 => {}
+                    _ => return Err(error("Address beyond 64K")),
+                }
+
+                let address = address as u16;
+                match segments.last_mut() {
+                    Some(ref mut last)
+                        if u32::from(last.address) + last.bytes.len() as u32
+                            == u32::from(address) =>
+                    {
+                        last.bytes.extend(data);
+                    }
+                    _ => segments.push(Segment {
+                        address,
+                        bytes: data.to_vec(),
+                    }),
+                }
+            }
+            END_OF_FILE => break,
+            EXTENDED_SEGMENT_ADDRESS | EXTENDED_LINEAR_ADDRESS => {
+                if data.len() != 2 {
+                    return Err(error("Invalid extended address record"));
+                }
+                let value = u32::from(data[0]) << 8 | u32::from(data[1]);
+                base = if kind == EXTENDED_SEGMENT_ADDRESS {
+                    value << 4
+                } else {
+                    value << 16
+                };
+            }
+            START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS => {
+                if data.len() != 4 {
+                    return Err(error("Invalid start address record"));
+                }
+                let high = u32::from(data[0]) << 8 | u32::from(data[1]);
+                let low = u32::from(data[2]) << 8 | u32::from(data[3]);
+                let address = if kind == START_SEGMENT_ADDRESS {
+                    (high << 4) + low
+                } else {
+                    high << 16 | low
+                };
+                if address > 0xffff {
+                    return Err(error("Start address beyond 64K"));
+                }
+                start = Some(address as u16);
+            }
+            _ => return Err(error(&format!("Unknown record type {:02x}", kind))),
+        }
+    }
+
+    Ok((segments, start))
+}
+
+/// Decode and verify a record: length, address, type, data.
+fn parse_record(line: &str) -> Result<Vec<u8>, String> {
+    if !line.starts_with(':') {
+        return Err("Missing ':' at the start of the record".to_string());
+    }
+
+    let digits = &line[1..];
+    if !digits.len().is_multiple_of(2) || digits.len() < 10 {
+        return Err("Truncated record".to_string());
+    }
+
+    let bytes = (0..digits.len())
+        .step_by(2)
+        .map(|pos| u8::from_str_radix(&digits[pos..pos + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| "Invalid hex digit".to_string())?;
+
+    if bytes.len() != usize::from(bytes[0]) + 5 {
+        return Err("Record length mismatch".to_string());
+    }
+
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    if sum != 0 {
+        return Err("Checksum mismatch".to_string());
+    }
+
+    Ok(bytes[..bytes.len() - 1].to_vec())
+}
+
+/// Write segments as data records, a start linear address record when
+/// start is given, and the end of file record.
+pub fn write_intel_hex(segments: &[Segment], start: Option<u16>) -> String {
+    let mut text = String::new();
+
+    for segment in segments {
+        for (index, chunk) in segment.bytes.chunks(RECORD_SIZE).enumerate() {
+            let address = usize::from(segment.address) + index * RECORD_SIZE;
+            text.push_str(&record(DATA, address as u16, chunk));
+        }
+    }
+    if let Some(start) = start {
+        text.push_str(&record(
+            START_LINEAR_ADDRESS,
+            0,
+            &[0, 0, (start >> 8) as u8, start as u8],
+        ));
+    }
+    text.push_str(&record(END_OF_FILE, 0, &[]));
+
+    text
+}
+
+fn record(kind: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8, (address >> 8) as u8, address as u8, kind];
+    bytes.extend(data);
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(sum.wrapping_neg());
+
+    let digits: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\n", digits.concat())
+}
@@ -0,0 +1,175 @@
+#[cfg(test)]
+mod tests;
+
+mod ihex;
+mod srec;
+
+pub use self::ihex::{parse_intel_hex, write_intel_hex};
+pub use self::srec::{parse_srecord, write_srecord};
+
+use asm::Segment;
+use std::fmt;
+use std::path::Path;
+
+/// Executable file formats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Raw memory image, loaded at a given address.
+    Binary,
+    /// Intel HEX text records, with their own addresses.
+    IntelHex,
+    /// Motorola S-records, with their own addresses.
+    SRecord,
+    /// CP/M program, loaded and started at 0x100.
+    Com,
+}
+
+impl Format {
+    /// Parse a format name: bin, hex, srec or com.
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "bin" | "binary" | "raw" => Some(Format::Binary),
+            "hex" | "ihex" | "ihx" => Some(Format::IntelHex),
+            "srec" | "s19" | "s28" | "s37" | "mot" => Some(Format::SRecord),
+            "com" => Some(Format::Com),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the file extension, binary by default.
+    pub fn from_path(path: &Path) -> Format {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Format::parse)
+            .unwrap_or(Format::Binary)
+    }
+}
+
+/// An error found while loading a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadError {
+    pub message: String,
+}
+
+impl LoadError {
+    pub fn new(message: String) -> LoadError {
+        LoadError { message }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A loaded program: memory blocks and where execution starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    pub start: u16,
+}
+
+impl Image {
+    /// Image of the memory ranges, START to END included.
+    pub fn from_memory(
+        memory: &[u8],
+        ranges: &[(u16, u16)],
+        start: u16,
+    ) -> Result<Image, LoadError> {
+        let mut segments = Vec::new();
+        for &(first, last) in ranges {
+            let bytes = memory
+                .get(usize::from(first)..=usize::from(last))
+                .ok_or_else(|| {
+                    LoadError::new(format!(
+                        "Range 0x{:04x}-0x{:04x} out of memory",
+                        first, last
+                    ))
+                })?;
+            segments.push(Segment {
+                address: first,
+                bytes: bytes.to_vec(),
+            });
+        }
+
+        Ok(Image { segments, start })
+    }
+
+    /// Copy the segments into memory.
+    pub fn load(&self, memory: &mut [u8]) -> Result<(), LoadError> {
+        for segment in &self.segments {
+            let start = usize::from(segment.address);
+            let end = start + segment.bytes.len();
+
+            if end > memory.len() {
+                return Err(LoadError::new(format!(
+                    "Data at 0x{:04x}-0x{:04x} does not fit in memory",
+                    start,
+                    end - 1
+                )));
+            }
+
+            memory[start..end].copy_from_slice(&segment.bytes);
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode the content of a file. Address is used by binary files only
+/// and is also their start address.
+pub fn decode(data: &[u8], format: Format, address: u16) -> Result<Image, LoadError> {
+    match format {
+        Format::Binary => Ok(Image {
+            segments: vec![Segment {
+                address,
+                bytes: data.to_vec(),
+            }],
+            start: address,
+        }),
+        Format::Com => decode(data, Format::Binary, 0x100),
+        Format::IntelHex | Format::SRecord => {
+            let text = String::from_utf8_lossy(data);
+            let (segments, start) = if format == Format::IntelHex {
+                parse_intel_hex(&text)?
+            } else {
+                parse_srecord(&text)?
+            };
+            let start = start.unwrap_or_else(|| {
+                segments
+                    .iter()
+                    .map(|segment| segment.address)
+                    .min()
+                    .unwrap_or(0)
+            });
+            Ok(Image { segments, start })
+        }
+    }
+}
+
+/// Encode an image in a format, the counterpart of `decode`. Binary and
+/// .com files hold the bytes from the lowest address of the segments to
+/// the highest, gaps filled with zeros, and no start address.
+pub fn encode(image: &Image, format: Format) -> Vec<u8> {
+    match format {
+        Format::Binary | Format::Com => {
+            let first = image.segments.iter().map(|segment| segment.address).min();
+            let first = usize::from(first.unwrap_or(0));
+            let mut data = Vec::new();
+
+            for segment in &image.segments {
+                let start = usize::from(segment.address) - first;
+                let end = start + segment.bytes.len();
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[start..end].copy_from_slice(&segment.bytes);
+            }
+
+            data
+        }
+        Format::IntelHex => write_intel_hex(&image.segments, Some(image.start)).into_bytes(),
+        Format::SRecord => write_srecord(&image.segments, Some(image.start)).into_bytes(),
+    }
+}
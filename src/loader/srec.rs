@@ -0,0 +1,153 @@
+use asm::Segment;
+use loader::LoadError;
+
+// === Motorola S-records ===
+//
+// One record per line:
+//
+//   STCCAAAADD...KK
+//
+// T record type, CC count of the bytes that follow, AAAA address, DD
+// data bytes and KK the one's complement of the sum of the count, address
+// and data bytes. S1, S2 and S3 records hold data at 16, 24 and 32 bit
+// addresses, S9, S8 and S7 the start address, S5 and S6 the number of
+// data records so far. The S0 header is ignored. As with Intel HEX,
+// addresses beyond 64K are rejected.
+
+/// Data bytes written per S1 record.
+const RECORD_SIZE: usize = 16;
+
+/// Parse S-records into segments, merging contiguous data, and the start
+/// address if present.
+pub fn parse_srecord(text: &str) -> Result<(Vec<Segment>, Option<u16>), LoadError> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut start = None;
+    let mut records: u32 = 0;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |message: &str| LoadError::new(format!("line {}: {}", number + 1, message));
+
+        let (kind, record) = parse_record(line).map_err(|message| error(&message))?;
+        let address_size = match kind {
+            b'0' | b'1' | b'5' | b'9' => 2,
+            b'2' | b'6' | b'8' => 3,
+            b'3' | b'7' => 4,
+            _ => return Err(error(&format!("Unknown record type S{}", char::from(kind)))),
+        };
+        if record.len() < address_size {
+            return Err(error("Truncated record"));
+        }
+        let address = record[..address_size]
+            .iter()
+            .fold(0u32, |address, byte| address << 8 | u32::from(*byte));
+        let data = &record[address_size..];
+
+        match kind {
+            b'0' => {}
+            b'1' | b'2' | b'3' => {
+                match address.checked_add(data.len() as u32) {
+                    Some(end) if end <= 0x10000 => {}
+                    _ => return Err(error("Address beyond 64K")),
+                }
+                records += 1;
+
+                let address = address as u16;
+                match segments.last_mut() {
+                    Some(ref mut last)
+                        if u32::from(last.address) + last.bytes.len() as u32
+                            == u32::from(address) =>
+                    {
+                        last.bytes.extend(data);
+                    }
+                    _ => segments.push(Segment {
+                        address,
+                        bytes: data.to_vec(),
+                    }),
+                }
+            }
+            b'5' | b'6' => {
+                if address != records {
+                    return Err(error(&format!(
+                        "Record count {} but {} data records",
+                        address, records
+                    )));
+                }
+            }
+            _ => {
+                if address > 0xffff {
+                    return Err(error("Start address beyond 64K"));
+                }
+                start = Some(address as u16);
+                break;
+            }
+        }
+    }
+
+    Ok((segments, start))
+}
+
+/// Decode and verify a record: its type digit, and the address and data
+/// bytes.
+fn parse_record(line: &str) -> Result<(u8, Vec<u8>), String> {
+    if !line.starts_with('S') && !line.starts_with('s') {
+        return Err("Missing 'S' at the start of the record".to_string());
+    }
+
+    let kind = line.as_bytes().get(1).cloned().unwrap_or(b' ');
+    let digits = line.get(2..).unwrap_or("");
+    if !digits.len().is_multiple_of(2) || digits.len() < 4 {
+        return Err("Truncated record".to_string());
+    }
+
+    let record = (0..digits.len())
+        .step_by(2)
+        .map(|pos| u8::from_str_radix(&digits[pos..pos + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| "Invalid hex digit".to_string())?;
+
+    if record.len() != usize::from(record[0]) + 1 {
+        return Err("Record length mismatch".to_string());
+    }
+
+    let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    if sum != 0xff {
+        return Err("Checksum mismatch".to_string());
+    }
+
+    Ok((kind, record[1..record.len() - 1].to_vec()))
+}
+
+/// Write segments as S1 records, with an S0 header, an S5 count of the
+/// data records and an S9 record with the start address, 0 by default.
+pub fn write_srecord(segments: &[Segment], start: Option<u16>) -> String {
+    let mut text = record('0', 0, &[]);
+    let mut records = 0usize;
+
+    for segment in segments {
+        for (index, chunk) in segment.bytes.chunks(RECORD_SIZE).enumerate() {
+            let address = usize::from(segment.address) + index * RECORD_SIZE;
+            text.push_str(&record('1', address as u16, chunk));
+            records += 1;
+        }
+    }
+
+    // 64K of memory is at most 4096 records, S5 always holds the count.
+    text.push_str(&record('5', records as u16, &[]));
+    text.push_str(&record('9', start.unwrap_or(0), &[]));
+    text
+}
+
+fn record(kind: char, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![(data.len() + 3) as u8, (address >> 8) as u8, address as u8];
+    bytes.extend(data);
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(!sum);
+
+    let digits: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!("S{}{}\n", kind, digits.concat())
+}
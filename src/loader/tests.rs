@@ -0,0 +1,150 @@
+use asm::Segment;
+use loader::{decode, encode, parse_intel_hex, parse_srecord, Format, Image};
+use std::path::Path;
+
+#[test]
+fn formats() {
+    assert_eq!(Format::from_path(Path::new("game.HEX")), Format::IntelHex);
+    assert_eq!(Format::from_path(Path::new("a/b.com")), Format::Com);
+    assert_eq!(Format::from_path(Path::new("rom")), Format::Binary);
+    assert_eq!(Format::from_path(Path::new("rom.s19")), Format::SRecord);
+    assert_eq!(Format::parse("bin"), Some(Format::Binary));
+    assert_eq!(Format::parse("elf"), None);
+}
+
+#[test]
+fn binary_and_com() {
+    let image = decode(&[1, 2], Format::Binary, 0x8000).unwrap();
+    assert_eq!(image.start, 0x8000);
+    assert_eq!(image.segments[0].address, 0x8000);
+
+    let image = decode(&[1, 2], Format::Com, 0x8000).unwrap();
+    assert_eq!(image.start, 0x100);
+
+    let mut memory = vec![0; 0x104];
+    image.load(&mut memory).unwrap();
+    assert_eq!(&memory[0x100..], &[1, 2, 0, 0]);
+
+    let mut small = vec![0; 0x100];
+    assert!(image.load(&mut small).is_err());
+}
+
+#[test]
+fn intel_hex() {
+    let text = ":0300000002000AF1\n\
+                :020003000102F8\n\
+                :01001000FFF0\n\
+                :0400000500001234B1\n\
+                :00000001FF\n\
+                :01002000AA35\n";
+
+    let (segments, start) = parse_intel_hex(text).unwrap();
+
+    assert_eq!(
+        segments,
+        vec![
+            Segment {
+                address: 0,
+                bytes: vec![0x02, 0x00, 0x0a, 0x01, 0x02],
+            },
+            Segment {
+                address: 0x10,
+                bytes: vec![0xff],
+            },
+        ]
+    );
+    assert_eq!(start, Some(0x1234));
+
+    let image = decode(text.as_bytes(), Format::IntelHex, 0).unwrap();
+    assert_eq!(image.start, 0x1234);
+}
+
+#[test]
+fn intel_hex_errors() {
+    assert!(parse_intel_hex("0300000002000AF1").is_err());
+    assert!(parse_intel_hex(":0300000002000AF2").is_err());
+    assert!(parse_intel_hex(":0300000002000A").is_err());
+    assert!(parse_intel_hex(":02000004000119E1\n:01000000FF00").is_err());
+
+    let error = parse_intel_hex(":00000001FF\n").map(|_| ());
+    assert_eq!(error, Ok(()));
+
+    let error = parse_intel_hex("\n:0100000xFF00").unwrap_err();
+    assert_eq!(error.message, "line 2: Invalid hex digit");
+
+    let error = parse_intel_hex(":02000004FFFFFC\n:01FFFF000001").unwrap_err();
+    assert_eq!(error.message, "line 2: Address beyond 64K");
+}
+
+#[test]
+fn srecord() {
+    let text = "S00F000068656C6C6F202020202000003C\n\
+                S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026\n\
+                S11F001C4BFFFFE5398000007D83637880010014382100107C0803A64E800020E9\n\
+                S111003848656C6C6F20776F726C642E0A0042\n\
+                S5030003F9\n\
+                S9030100FB\n";
+
+    let (segments, start) = parse_srecord(text).unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0].address, 0);
+    assert_eq!(segments[0].bytes.len(), 70);
+    assert_eq!(&segments[0].bytes[0x38..0x3d], b"Hello");
+    assert_eq!(start, Some(0x100));
+
+    // Data at a 24 bit address within 64K.
+    let (segments, start) = parse_srecord("S2050080000179\n").unwrap();
+    assert_eq!(segments[0].address, 0x8000);
+    assert_eq!(start, None);
+}
+
+#[test]
+fn srecord_errors() {
+    let error = |text| parse_srecord(text).unwrap_err().message;
+
+    assert_eq!(error("S9030000FD"), "line 1: Checksum mismatch");
+    assert_eq!(error("\nS90400000"), "line 2: Truncated record");
+    assert_eq!(
+        error(":0300000002000AF1"),
+        "line 1: Missing 'S' at the start of the record"
+    );
+    assert_eq!(error("S4030000FC"), "line 1: Unknown record type S4");
+    assert_eq!(error("S20501000001F8"), "line 1: Address beyond 64K");
+    assert_eq!(error("S306FFFFFFFF00FD"), "line 1: Address beyond 64K");
+    assert_eq!(
+        error("S10400000FEC\nS5030002FA"),
+        "line 2: Record count 2 but 1 data records"
+    );
+}
+
+#[test]
+fn saving() {
+    let mut memory = vec![0; 0x103];
+    memory[0x100..].copy_from_slice(&[0x3e, 0x01, 0x76]);
+    let image = Image::from_memory(&memory, &[(0x100, 0x102)], 0x100).unwrap();
+    assert!(Image::from_memory(&memory, &[(0x100, 0x103)], 0).is_err());
+
+    let hex = encode(&image, Format::IntelHex);
+    assert_eq!(
+        String::from_utf8(hex.clone()).unwrap(),
+        ":030100003E017647\n:0400000500000100F6\n:00000001FF\n"
+    );
+    assert_eq!(decode(&hex, Format::IntelHex, 0).unwrap(), image);
+
+    let srec = encode(&image, Format::SRecord);
+    assert_eq!(
+        String::from_utf8(srec.clone()).unwrap(),
+        "S0030000FC\nS10601003E017643\nS5030001FB\nS9030100FB\n"
+    );
+    assert_eq!(decode(&srec, Format::SRecord, 0).unwrap(), image);
+
+    // Long segments are split into records of 16 bytes.
+    let image = Image::from_memory(&[0xaa; 40], &[(0, 39)], 0).unwrap();
+    let text = String::from_utf8(encode(&image, Format::SRecord)).unwrap();
+    assert_eq!(text.lines().count(), 6);
+    assert_eq!(decode(text.as_bytes(), Format::SRecord, 0).unwrap(), image);
+
+    // Binary files fill the gaps between ranges.
+    let image = Image::from_memory(&memory, &[(0x102, 0x102), (0x100, 0x100)], 0).unwrap();
+    assert_eq!(encode(&image, Format::Binary), vec![0x3e, 0, 0x76]);
+}
@@ -1,6 +1,23 @@
-mod cpu;
+extern crate z80;
+
+mod cli;
+
+use std::env;
+use std::process;
 
 fn main() {
-    unimplemented!();
-}
+    let args: Vec<String> = env::args().skip(1).collect();
 
+    let result = cli::parse_args(&args).and_then(|options| match options {
+        Some(options) => cli::run(&options),
+        None => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    });
+
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
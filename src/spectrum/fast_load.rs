@@ -0,0 +1,125 @@
+use super::tape::Tape;
+use cpu::{Cpu, Limits, StopReason};
+use std::cell::RefCell;
+
+// === Fast loading ===
+//
+// LD-BYTES of the 48K ROM loads a block from the tape: A is the flag byte
+// expected, IX the address and DE the length of the data, and the carry
+// is set to load, reset to verify. The block is the flag, the data and a
+// checksum byte, the XOR of the others; the routine returns with the
+// carry set when it got them all right, IX past the data and DE the
+// bytes left, and the interrupts enabled.
+//
+// The trap takes the next data block of the tape instead of its pulses,
+// when the CPU is about to run the routine, and returns as it does, in no
+// time. It only fires when the first bytes of the routine are there, so
+// that another ROM or a program at the address runs. The load writes
+// through the bus, and is one entry of the history, undone as a whole.
+
+/// Address of LD-BYTES.
+pub const LD_BYTES: u16 = 0x0556;
+
+/// INC D; EX AF,AF'; DEC D; DI
+const LD_BYTES_CODE: [u8; 4] = [0x14, 0x08, 0x15, 0xf3];
+
+/// When the PC is at LD-BYTES, load the next data block of the tape as
+/// the routine does, and return from it. Returns true when it did.
+pub fn fast_load(cpu: &mut Cpu, tape: &mut Tape) -> bool {
+    let start = usize::from(LD_BYTES);
+    let code = cpu.memory.get(start..start + LD_BYTES_CODE.len());
+    if cpu.pc != LD_BYTES || code != Some(&LD_BYTES_CODE[..]) {
+        return false;
+    }
+
+    cpu.accesses.clear();
+    cpu.history_begin();
+    let verify = !cpu.get_c();
+    let loaded = match tape.next_data(cpu.cycles) {
+        Some(block) => load(cpu, &block, verify),
+        None => false,
+    };
+
+    cpu.set_c(loaded);
+    cpu.iff1 = true;
+    cpu.iff2 = true;
+    cpu._pop_pc();
+    cpu.history_end();
+    true
+}
+
+/// Load or verify the data of the block at IX, as the ROM does.
+fn load(cpu: &mut Cpu, block: &[u8], verify: bool) -> bool {
+    let (&flag, data) = match block.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+    if flag != cpu.a {
+        return false;
+    }
+
+    let length = usize::from(cpu.read_de());
+    let mut checksum = flag;
+    for &byte in data.iter().take(length) {
+        let loaded = match cpu.memory.get(usize::from(cpu.ix)) {
+            Some(&loaded) => loaded,
+            None => return false,
+        };
+        if verify {
+            if loaded != byte {
+                return false;
+            }
+        } else {
+            cpu.write_byte(cpu.ix, byte);
+        }
+        checksum ^= byte;
+        cpu.ix = cpu.ix.wrapping_add(1);
+        cpu.add_de(-1);
+    }
+
+    match data.get(length) {
+        Some(&byte) if cpu.read_de() == 0 => checksum ^ byte == 0,
+        _ => false,
+    }
+}
+
+/// Run the CPU as `Cpu::run` does, loading the blocks of the tape with
+/// the trap of LD-BYTES. The trap counts as one instruction, stops at a
+/// breakpoint at LD-BYTES first, and hits the watchpoints on the bytes
+/// it loads.
+pub fn run_fast_load(cpu: &mut Cpu, tape: &RefCell<Tape>, limits: Limits) -> (StopReason, u64) {
+    let start = cpu.cycles;
+    let mut executed = 0;
+
+    loop {
+        if cpu.halted {
+            return (StopReason::Halted, executed);
+        }
+
+        if limits.instructions.is_some_and(|max| executed >= max) {
+            return (StopReason::InstructionLimit, executed);
+        }
+
+        if limits.cycles.is_some_and(|max| cpu.cycles - start >= max) {
+            return (StopReason::CycleLimit, executed);
+        }
+
+        if executed > 0 {
+            if let Some(id) = cpu.breakpoints.execution_hit(cpu) {
+                return (StopReason::Breakpoint(id), executed);
+            }
+        }
+
+        let reason = if fast_load(cpu, &mut tape.borrow_mut()) {
+            cpu.breakpoints
+                .access_hit(cpu)
+                .map(|(id, access)| StopReason::Watchpoint(id, access))
+        } else {
+            cpu.step_checked(true)
+        };
+        executed += 1;
+        if let Some(reason) = reason {
+            return (reason, executed);
+        }
+    }
+}
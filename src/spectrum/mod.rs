@@ -0,0 +1,244 @@
+#[cfg(test)]
+mod tests;
+
+mod fast_load;
+mod sna;
+mod szx;
+mod tape;
+mod tzx;
+mod ula;
+mod z80;
+mod zlib;
+
+pub use self::fast_load::{fast_load, run_fast_load, LD_BYTES};
+pub use self::tape::{Block, DataBlock, Tape, TapeFormat, MILLISECOND};
+pub use self::ula::Ula;
+
+use cpu::{Cpu, CpuBuilder, CpuState};
+use loader::LoadError;
+use std::path::Path;
+
+// === ZX Spectrum snapshots ===
+//
+// A snapshot is the state of a Spectrum stopped between two instructions:
+// the registers, the border colour, and the RAM. The 48K has three banks
+// of 16K at 0x4000, 0x8000 and 0xc000; the 128K has eight, bank 5 at
+// 0x4000, bank 2 at 0x8000 and the one selected by port 0x7ffd at 0xc000,
+// with the ROM also paged by that port. The ROM is not in the snapshots:
+// a CPU is built from a snapshot and a ROM image.
+//
+// Tapes play to the EAR input of the ULA port, as the T states of the
+// CPU go by, or load at once through a trap of the ROM loading routine.
+
+/// Size of a memory bank, and of a ROM.
+pub const BANK: usize = 0x4000;
+
+/// The Spectrum models of the snapshots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Machine {
+    Spectrum48,
+    Spectrum128,
+}
+
+impl Machine {
+    /// Number of RAM banks.
+    pub fn banks(self) -> usize {
+        match self {
+            Machine::Spectrum48 => 3,
+            Machine::Spectrum128 => 8,
+        }
+    }
+}
+
+/// Snapshot file formats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotFormat {
+    /// Registers and memory dump, with the PC on the stack on the 48K.
+    Sna,
+    /// Registers, hardware and run length compressed memory pages,
+    /// written as version 3.
+    Z80,
+    /// ZX-State chunks of registers, hardware and zlib compressed pages.
+    Szx,
+}
+
+impl SnapshotFormat {
+    /// Parse a format name: sna, z80 or szx.
+    pub fn parse(name: &str) -> Option<SnapshotFormat> {
+        match name.to_lowercase().as_str() {
+            "sna" => Some(SnapshotFormat::Sna),
+            "z80" => Some(SnapshotFormat::Z80),
+            "szx" => Some(SnapshotFormat::Szx),
+            _ => None,
+        }
+    }
+
+    /// The format of a file extension, if it is a snapshot.
+    pub fn from_path(path: &Path) -> Option<SnapshotFormat> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(SnapshotFormat::parse)
+    }
+}
+
+/// A ZX Spectrum snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub machine: Machine,
+    /// All the registers; the memory is in the banks.
+    pub registers: CpuState,
+    pub border: u8,
+    /// RAM banks: those at 0x4000, 0x8000 and 0xc000 on the 48K, banks 0
+    /// to 7 on the 128K.
+    pub banks: Vec<Vec<u8>>,
+    /// Last byte written to port 0x7ffd on the 128K: bank at 0xc000 in
+    /// bits 0 to 2, ROM in bit 4.
+    pub paging: u8,
+    /// AY sound chip of the 128K: selected register, and the registers.
+    pub ay_register: u8,
+    pub ay: [u8; 16],
+}
+
+impl Snapshot {
+    /// A machine with cleared registers and memory, bank 0 paged in.
+    pub fn new(machine: Machine) -> Snapshot {
+        let cpu = CpuBuilder::new().with_memory(Vec::new()).build();
+        Snapshot {
+            machine,
+            registers: cpu.state(),
+            border: 0,
+            banks: vec![vec![0; BANK]; machine.banks()],
+            paging: 0,
+            ay_register: 0,
+            ay: [0; 16],
+        }
+    }
+
+    /// A 48K snapshot of the CPU, with the RAM above 16K.
+    pub fn from_cpu(cpu: &Cpu) -> Snapshot {
+        let mut snapshot = Snapshot::new(Machine::Spectrum48);
+        snapshot.update(cpu);
+        snapshot
+    }
+
+    /// Read a snapshot file.
+    pub fn read(data: &[u8], format: SnapshotFormat) -> Result<Snapshot, LoadError> {
+        match format {
+            SnapshotFormat::Sna => sna::read(data),
+            SnapshotFormat::Z80 => z80::read(data),
+            SnapshotFormat::Szx => szx::read(data),
+        }
+    }
+
+    /// Write a snapshot file.
+    pub fn write(&self, format: SnapshotFormat) -> Result<Vec<u8>, LoadError> {
+        match format {
+            SnapshotFormat::Sna => sna::write(self),
+            SnapshotFormat::Z80 => z80::write(self),
+            SnapshotFormat::Szx => szx::write(self),
+        }
+    }
+
+    /// The banks at 0x4000, 0x8000 and 0xc000.
+    pub fn paged(&self) -> [usize; 3] {
+        match self.machine {
+            Machine::Spectrum48 => [0, 1, 2],
+            Machine::Spectrum128 => [5, 2, usize::from(self.paging & 7)],
+        }
+    }
+
+    /// The 64K seen by the CPU. The ROM is the first 16K of ROM on the
+    /// 48K; on the 128K, the page selected by port 0x7ffd if ROM holds
+    /// both, missing bytes reading 0.
+    pub fn memory(&self, rom: &[u8]) -> Vec<u8> {
+        let mut memory = vec![0; 0x10000];
+        let page = if self.machine == Machine::Spectrum128 && rom.len() >= 2 * BANK {
+            usize::from(self.paging >> 4 & 1) * BANK
+        } else {
+            0
+        };
+        let rom = &rom[page.min(rom.len())..(page + BANK).min(rom.len())];
+        memory[..rom.len()].copy_from_slice(rom);
+
+        for (slot, &bank) in self.paged().iter().enumerate() {
+            let start = (slot + 1) * BANK;
+            memory[start..start + BANK].copy_from_slice(&self.banks[bank]);
+        }
+        memory
+    }
+
+    /// A CPU in the state of the snapshot, running the ROM.
+    pub fn cpu(&self, rom: &[u8]) -> Cpu {
+        let registers = &self.registers;
+        let word = |high: u8, low: u8| u16::from(high) << 8 | u16::from(low);
+
+        let mut cpu = CpuBuilder::new()
+            .with_memory(self.memory(rom))
+            .with_pc(registers.pc)
+            .with_sp(registers.sp)
+            .with_ix(registers.ix)
+            .with_iy(registers.iy)
+            .with_i(registers.i)
+            .with_r(registers.r)
+            .with_af(word(registers.a, registers.f))
+            .with_bc(word(registers.b, registers.c))
+            .with_de(word(registers.d, registers.e))
+            .with_hl(word(registers.h, registers.l))
+            .with_af1(word(registers.a1, registers.f1))
+            .with_bc1(word(registers.b1, registers.c1))
+            .with_de1(word(registers.d1, registers.e1))
+            .with_hl1(word(registers.h1, registers.l1))
+            .with_iff1(registers.iff1)
+            .with_iff2(registers.iff2)
+            .with_im(registers.im)
+            .with_wz(registers.wz)
+            .build();
+        cpu.halted = registers.halted;
+        cpu
+    }
+
+    /// Take the registers of the CPU, and the RAM it sees into the banks
+    /// paged in.
+    pub fn update(&mut self, cpu: &Cpu) {
+        let mut registers = cpu.state();
+        registers.memory = Vec::new();
+        self.registers = registers;
+
+        for (slot, &bank) in self.paged().iter().enumerate() {
+            let start = (slot + 1) * BANK;
+            let end = (start + BANK).min(cpu.memory.len());
+            if start < end {
+                self.banks[bank][..end - start].copy_from_slice(&cpu.memory[start..end]);
+            }
+        }
+    }
+
+    /// Byte of the RAM seen by the CPU, 0 in the ROM.
+    fn peek(&self, address: u16) -> u8 {
+        let slot = usize::from(address) / BANK;
+        if slot == 0 {
+            return 0;
+        }
+        self.banks[self.paged()[slot - 1]][usize::from(address) % BANK]
+    }
+
+    /// Write to the RAM seen by the CPU, false in the ROM.
+    fn poke(&mut self, address: u16, value: u8) -> bool {
+        let slot = usize::from(address) / BANK;
+        if slot == 0 {
+            return false;
+        }
+        let bank = self.paged()[slot - 1];
+        self.banks[bank][usize::from(address) % BANK] = value;
+        true
+    }
+}
+
+/// Little endian word at the offset.
+fn word(data: &[u8], offset: usize) -> u16 {
+    u16::from(data[offset]) | u16::from(data[offset + 1]) << 8
+}
+
+fn put_word(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
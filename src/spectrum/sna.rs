@@ -0,0 +1,151 @@
+use super::{put_word, word, Machine, Snapshot, BANK};
+use loader::LoadError;
+
+// === SNA snapshots ===
+//
+// A 27 byte header of registers, then the 48K of RAM from 0x4000:
+//
+//   0  I        1  HL'    3  DE'    5  BC'    7  AF'
+//   9  HL      11  DE    13  BC    15  IY    17  IX
+//  19  IFF2 in bit 2     20  R     21  AF    23  SP    25  IM    26  border
+//
+// On the 48K the PC is pushed on the stack. The 128K has the banks paged
+// in instead, followed by the PC, port 0x7ffd, a TR-DOS byte, and the
+// banks not paged in, in ascending order.
+
+const HEADER: usize = 27;
+const SIZE_48K: usize = HEADER + 3 * BANK;
+/// The PC, port 0x7ffd and the TR-DOS ROM flag of the 128K.
+const EXTENSION: usize = 4;
+
+pub(super) fn read(data: &[u8]) -> Result<Snapshot, LoadError> {
+    let machine = if data.len() == SIZE_48K {
+        Machine::Spectrum48
+    } else if data.len() > SIZE_48K + EXTENSION {
+        Machine::Spectrum128
+    } else {
+        return Err(LoadError::new(format!(
+            "SNA snapshot of {} bytes, {} expected for the 48K",
+            data.len(),
+            SIZE_48K
+        )));
+    };
+
+    let mut snapshot = Snapshot::new(machine);
+    {
+        let registers = &mut snapshot.registers;
+        registers.i = data[0];
+        registers.l1 = data[1];
+        registers.h1 = data[2];
+        registers.e1 = data[3];
+        registers.d1 = data[4];
+        registers.c1 = data[5];
+        registers.b1 = data[6];
+        registers.f1 = data[7];
+        registers.a1 = data[8];
+        registers.l = data[9];
+        registers.h = data[10];
+        registers.e = data[11];
+        registers.d = data[12];
+        registers.c = data[13];
+        registers.b = data[14];
+        registers.iy = word(data, 15);
+        registers.ix = word(data, 17);
+        // The format only has IFF2, which IFF1 equals outside NMIs.
+        registers.iff2 = data[19] & 0x04 != 0;
+        registers.iff1 = registers.iff2;
+        registers.r = data[20];
+        registers.f = data[21];
+        registers.a = data[22];
+        registers.sp = word(data, 23);
+        registers.im = data[25] & 3;
+    }
+    snapshot.border = data[26] & 7;
+
+    if machine == Machine::Spectrum128 {
+        snapshot.registers.pc = word(data, SIZE_48K);
+        snapshot.paging = data[SIZE_48K + 2];
+    }
+
+    let paged = snapshot.paged();
+    for (slot, ram) in data[HEADER..SIZE_48K].chunks(BANK).enumerate() {
+        snapshot.banks[paged[slot]].copy_from_slice(ram);
+    }
+
+    if machine == Machine::Spectrum48 {
+        // RETN from the snapshot.
+        let sp = snapshot.registers.sp;
+        snapshot.registers.pc =
+            u16::from(snapshot.peek(sp)) | u16::from(snapshot.peek(sp.wrapping_add(1))) << 8;
+        snapshot.registers.sp = sp.wrapping_add(2);
+        return Ok(snapshot);
+    }
+
+    let rest: Vec<usize> = (0..8).filter(|bank| !paged.contains(bank)).collect();
+    let size = SIZE_48K + EXTENSION + rest.len() * BANK;
+    if data.len() != size {
+        return Err(LoadError::new(format!(
+            "SNA snapshot of {} bytes, {} expected for the 128K with bank {} paged in",
+            data.len(),
+            size,
+            paged[2]
+        )));
+    }
+    for (&bank, ram) in rest.iter().zip(data[SIZE_48K + EXTENSION..].chunks(BANK)) {
+        snapshot.banks[bank].copy_from_slice(ram);
+    }
+
+    Ok(snapshot)
+}
+
+pub(super) fn write(snapshot: &Snapshot) -> Result<Vec<u8>, LoadError> {
+    let mut snapshot = snapshot.clone();
+    if snapshot.machine == Machine::Spectrum48 {
+        let pc = snapshot.registers.pc;
+        let sp = snapshot.registers.sp.wrapping_sub(2);
+        if !snapshot.poke(sp, pc.to_le_bytes()[0])
+            || !snapshot.poke(sp.wrapping_add(1), pc.to_le_bytes()[1])
+        {
+            return Err(LoadError::new(format!(
+                "Cannot push the PC on the stack at 0x{:04x}, in the ROM",
+                sp
+            )));
+        }
+        snapshot.registers.sp = sp;
+    }
+
+    let registers = &snapshot.registers;
+    let pair = |high: u8, low: u8| u16::from(high) << 8 | u16::from(low);
+    let mut data = vec![registers.i];
+    put_word(&mut data, pair(registers.h1, registers.l1));
+    put_word(&mut data, pair(registers.d1, registers.e1));
+    put_word(&mut data, pair(registers.b1, registers.c1));
+    put_word(&mut data, pair(registers.a1, registers.f1));
+    put_word(&mut data, pair(registers.h, registers.l));
+    put_word(&mut data, pair(registers.d, registers.e));
+    put_word(&mut data, pair(registers.b, registers.c));
+    put_word(&mut data, registers.iy);
+    put_word(&mut data, registers.ix);
+    data.push(if registers.iff2 { 0x04 } else { 0 });
+    data.push(registers.r);
+    put_word(&mut data, pair(registers.a, registers.f));
+    put_word(&mut data, registers.sp);
+    data.push(registers.im);
+    data.push(snapshot.border);
+
+    let paged = snapshot.paged();
+    for &bank in &paged {
+        data.extend_from_slice(&snapshot.banks[bank]);
+    }
+
+    if snapshot.machine == Machine::Spectrum128 {
+        put_word(&mut data, registers.pc);
+        data.push(snapshot.paging);
+        data.push(0);
+        for bank in (0..8).filter(|bank| !paged.contains(bank)) {
+            data.extend_from_slice(&snapshot.banks[bank]);
+        }
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,250 @@
+use super::{put_word, word, zlib, Machine, Snapshot, BANK};
+use loader::LoadError;
+
+// === SZX snapshots ===
+//
+// ZX-State files: an 8 byte header, "ZXST", the version, the machine and
+// flags, then chunks of a 4 byte id, a 32 bit length and the data. The
+// chunks read are:
+//
+//   Z80R  registers, as words from AF to PC, then I R IFF1 IFF2 IM, the
+//         T states of the frame, flags with HALT in bit 1, and MEMPTR
+//   SPCR  border, port 0x7ffd, port 0x1ffd, last write to port 0xfe
+//   RAMP  a page: flags with zlib compression in bit 0, the bank, data
+//   AY    flags, selected register and the 16 registers
+//
+// Other chunks, such as the keyboard or the interfaces, are skipped, and
+// not written. Banks are numbered as on the 128K: 5, 2 and 0 are the 48K
+// at 0x4000, 0x8000 and 0xc000.
+
+const MAGIC: &[u8; 4] = b"ZXST";
+const HEADER: usize = 8;
+const MAJOR: u8 = 1;
+const MINOR: u8 = 4;
+
+const ZX48K: u8 = 1;
+const ZX128K: u8 = 2;
+/// The +2, +2A, +3, +3e, NTSC 48K and 128Ke.
+const OTHERS_128K: [u8; 5] = [3, 4, 5, 6, 16];
+const NTSC48K: u8 = 15;
+
+/// Banks of the 48K at 0x4000, 0x8000 and 0xc000.
+const BANKS_48K: [u8; 3] = [5, 2, 0];
+
+const Z80R_SIZE: usize = 37;
+const HALTED: u8 = 0x02;
+
+pub(super) fn read(data: &[u8]) -> Result<Snapshot, LoadError> {
+    if data.len() < HEADER || &data[..4] != MAGIC {
+        return Err(LoadError::new("Not an SZX snapshot".to_string()));
+    }
+    if data[4] != MAJOR {
+        return Err(LoadError::new(format!(
+            "SZX snapshot version {}.{} is not supported",
+            data[4], data[5]
+        )));
+    }
+    let machine = match data[6] {
+        ZX48K | NTSC48K => Machine::Spectrum48,
+        ZX128K => Machine::Spectrum128,
+        id if OTHERS_128K.contains(&id) => Machine::Spectrum128,
+        id => {
+            return Err(LoadError::new(format!(
+                "Unsupported machine {} in the SZX snapshot",
+                id
+            )))
+        }
+    };
+
+    let mut snapshot = Snapshot::new(machine);
+    let mut offset = HEADER;
+    while offset < data.len() {
+        let header = data
+            .get(offset..offset + 8)
+            .ok_or_else(|| LoadError::new("Truncated SZX chunk header".to_string()))?;
+        let id = &header[..4];
+        let size = word(header, 4) as usize | (word(header, 6) as usize) << 16;
+        let chunk = data.get(offset + 8..offset + 8 + size).ok_or_else(|| {
+            LoadError::new(format!(
+                "Truncated SZX chunk {}",
+                String::from_utf8_lossy(id).trim_end_matches('\0')
+            ))
+        })?;
+        offset += 8 + size;
+
+        match id {
+            b"Z80R" => registers(&mut snapshot, chunk)?,
+            b"SPCR" if chunk.len() >= 4 => {
+                snapshot.border = chunk[0] & 7;
+                if machine == Machine::Spectrum128 {
+                    snapshot.paging = chunk[1];
+                }
+            }
+            b"RAMP" if chunk.len() >= 3 => page(&mut snapshot, chunk)?,
+            b"AY\0\0" if chunk.len() >= 18 => {
+                snapshot.ay_register = chunk[1];
+                snapshot.ay.copy_from_slice(&chunk[2..18]);
+            }
+            b"SPCR" | b"RAMP" | b"AY\0\0" => {
+                return Err(LoadError::new(format!(
+                    "SZX chunk {} of {} bytes is too short",
+                    String::from_utf8_lossy(id).trim_end_matches('\0'),
+                    size
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(snapshot)
+}
+
+fn registers(snapshot: &mut Snapshot, chunk: &[u8]) -> Result<(), LoadError> {
+    // Version 1.3 has no MEMPTR.
+    if chunk.len() < Z80R_SIZE - 2 {
+        return Err(LoadError::new(format!(
+            "SZX chunk Z80R of {} bytes is too short",
+            chunk.len()
+        )));
+    }
+
+    let registers = &mut snapshot.registers;
+    let pair = |offset: usize| (chunk[offset + 1], chunk[offset]);
+    let (a, f) = pair(0);
+    registers.a = a;
+    registers.f = f;
+    let (b, c) = pair(2);
+    registers.b = b;
+    registers.c = c;
+    let (d, e) = pair(4);
+    registers.d = d;
+    registers.e = e;
+    let (h, l) = pair(6);
+    registers.h = h;
+    registers.l = l;
+    let (a1, f1) = pair(8);
+    registers.a1 = a1;
+    registers.f1 = f1;
+    let (b1, c1) = pair(10);
+    registers.b1 = b1;
+    registers.c1 = c1;
+    let (d1, e1) = pair(12);
+    registers.d1 = d1;
+    registers.e1 = e1;
+    let (h1, l1) = pair(14);
+    registers.h1 = h1;
+    registers.l1 = l1;
+    registers.ix = word(chunk, 16);
+    registers.iy = word(chunk, 18);
+    registers.sp = word(chunk, 20);
+    registers.pc = word(chunk, 22);
+    registers.i = chunk[24];
+    registers.r = chunk[25];
+    registers.iff1 = chunk[26] != 0;
+    registers.iff2 = chunk[27] != 0;
+    registers.im = chunk[28] & 3;
+    registers.halted = chunk[34] & HALTED != 0;
+    if chunk.len() >= Z80R_SIZE {
+        registers.wz = word(chunk, 35);
+    }
+    Ok(())
+}
+
+fn page(snapshot: &mut Snapshot, chunk: &[u8]) -> Result<(), LoadError> {
+    let compressed = word(chunk, 0) & 1 != 0;
+    let number = chunk[2];
+    let bank = match snapshot.machine {
+        Machine::Spectrum48 => BANKS_48K.iter().position(|&bank| bank == number),
+        Machine::Spectrum128 if number < 8 => Some(usize::from(number)),
+        Machine::Spectrum128 => None,
+    };
+    let bank = match bank {
+        Some(bank) => bank,
+        None => return Ok(()),
+    };
+
+    let ram = if compressed {
+        zlib::decompress(&chunk[3..], BANK)
+            .map_err(|e| LoadError::new(format!("SZX page {}: {}", number, e)))?
+    } else {
+        chunk[3..].to_vec()
+    };
+    if ram.len() != BANK {
+        return Err(LoadError::new(format!(
+            "SZX page {} of {} bytes, {} expected",
+            number,
+            ram.len(),
+            BANK
+        )));
+    }
+    snapshot.banks[bank].copy_from_slice(&ram);
+    Ok(())
+}
+
+fn chunk(data: &mut Vec<u8>, id: &[u8; 4], chunk: &[u8]) {
+    data.extend_from_slice(id);
+    data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    data.extend_from_slice(chunk);
+}
+
+pub(super) fn write(snapshot: &Snapshot) -> Result<Vec<u8>, LoadError> {
+    let mut data = MAGIC.to_vec();
+    let machine = match snapshot.machine {
+        Machine::Spectrum48 => ZX48K,
+        Machine::Spectrum128 => ZX128K,
+    };
+    data.extend_from_slice(&[MAJOR, MINOR, machine, 0]);
+
+    let registers = &snapshot.registers;
+    let pair = |high: u8, low: u8| u16::from(high) << 8 | u16::from(low);
+    let mut z80r = Vec::with_capacity(Z80R_SIZE);
+    put_word(&mut z80r, pair(registers.a, registers.f));
+    put_word(&mut z80r, pair(registers.b, registers.c));
+    put_word(&mut z80r, pair(registers.d, registers.e));
+    put_word(&mut z80r, pair(registers.h, registers.l));
+    put_word(&mut z80r, pair(registers.a1, registers.f1));
+    put_word(&mut z80r, pair(registers.b1, registers.c1));
+    put_word(&mut z80r, pair(registers.d1, registers.e1));
+    put_word(&mut z80r, pair(registers.h1, registers.l1));
+    put_word(&mut z80r, registers.ix);
+    put_word(&mut z80r, registers.iy);
+    put_word(&mut z80r, registers.sp);
+    put_word(&mut z80r, registers.pc);
+    z80r.extend_from_slice(&[
+        registers.i,
+        registers.r,
+        registers.iff1 as u8,
+        registers.iff2 as u8,
+        registers.im,
+    ]);
+    // T states into the frame, and of the interrupt request.
+    z80r.extend_from_slice(&[0; 5]);
+    z80r.push(if registers.halted { HALTED } else { 0 });
+    put_word(&mut z80r, registers.wz);
+    chunk(&mut data, b"Z80R", &z80r);
+
+    let border = snapshot.border & 7;
+    chunk(
+        &mut data,
+        b"SPCR",
+        &[border, snapshot.paging, 0, border, 0, 0, 0, 0],
+    );
+
+    if snapshot.machine == Machine::Spectrum128 {
+        let mut ay = vec![0, snapshot.ay_register];
+        ay.extend_from_slice(&snapshot.ay);
+        chunk(&mut data, b"AY\0\0", &ay);
+    }
+
+    for (bank, ram) in snapshot.banks.iter().enumerate() {
+        let number = match snapshot.machine {
+            Machine::Spectrum48 => BANKS_48K[bank],
+            Machine::Spectrum128 => bank as u8,
+        };
+        let mut page = vec![1, 0, number];
+        page.extend_from_slice(&zlib::compress(ram));
+        chunk(&mut data, b"RAMP", &page);
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,361 @@
+use super::tzx;
+use loader::LoadError;
+use std::collections::VecDeque;
+use std::path::Path;
+
+// === Tapes ===
+//
+// A tape is a list of blocks played as a stream of pulses: each pulse
+// keeps the EAR level for a number of T states, then flips it. Data
+// blocks are a pilot tone, two sync pulses, and two pulses per bit from
+// the most significant, of the zero or one length; the pause after them
+// ends the last pulse 1 ms later, then keeps the level low. Blocks are
+// turned into pulses one at a time, as the tape plays.
+
+/// T states in a millisecond, at 3.5 MHz.
+pub const MILLISECOND: u32 = 3500;
+
+/// Timings of the ROM saving routine, in T states.
+const PILOT: u16 = 2168;
+const HEADER_PILOT_PULSES: u16 = 8063;
+const DATA_PILOT_PULSES: u16 = 3223;
+const SYNC1: u16 = 667;
+const SYNC2: u16 = 735;
+const ZERO: u16 = 855;
+const ONE: u16 = 1710;
+/// Pause after the blocks of .tap files, in milliseconds.
+const TAP_PAUSE: u16 = 1000;
+
+/// Tape file formats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TapeFormat {
+    /// Blocks of the ROM saving routine, each a length and the data.
+    Tap,
+    /// Blocks of data with their timings, tones, pulses and pauses.
+    Tzx,
+}
+
+impl TapeFormat {
+    /// Parse a format name: tap or tzx.
+    pub fn parse(name: &str) -> Option<TapeFormat> {
+        match name.to_lowercase().as_str() {
+            "tap" => Some(TapeFormat::Tap),
+            "tzx" => Some(TapeFormat::Tzx),
+            _ => None,
+        }
+    }
+
+    /// The format of a file extension, if it is a tape.
+    pub fn from_path(path: &Path) -> Option<TapeFormat> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(TapeFormat::parse)
+    }
+}
+
+/// A block of data, with the lengths of its pulses in T states.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataBlock {
+    pub pilot: u16,
+    /// Pulses of the pilot tone, none for pure data.
+    pub pilot_pulses: u16,
+    /// Sync pulses, skipped when 0.
+    pub sync1: u16,
+    pub sync2: u16,
+    pub zero: u16,
+    pub one: u16,
+    /// Bits played of the last byte, from its most significant.
+    pub last_bits: u8,
+    /// Pause after the block, in milliseconds.
+    pub pause: u16,
+    pub data: Vec<u8>,
+}
+
+impl DataBlock {
+    /// A block as saved by the ROM: a header when its flag byte is below
+    /// 128, with a longer pilot tone.
+    pub fn standard(data: Vec<u8>, pause: u16) -> DataBlock {
+        let header = data.first().is_some_and(|&flag| flag < 128);
+        DataBlock {
+            pilot: PILOT,
+            pilot_pulses: if header {
+                HEADER_PILOT_PULSES
+            } else {
+                DATA_PILOT_PULSES
+            },
+            sync1: SYNC1,
+            sync2: SYNC2,
+            zero: ZERO,
+            one: ONE,
+            last_bits: 8,
+            pause,
+            data,
+        }
+    }
+}
+
+/// A block of a tape.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Data(DataBlock),
+    /// Pulses of the same length.
+    Tone {
+        length: u16,
+        pulses: u16,
+    },
+    /// Pulses of their lengths.
+    Pulses(Vec<u16>),
+    /// Silence, in milliseconds; 0 stops the tape.
+    Pause(u16),
+    GroupStart(String),
+    GroupEnd,
+    /// The blocks up to the loop end, played this number of times.
+    LoopStart(u16),
+    LoopEnd,
+}
+
+/// What happens at the end of a pulse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edge {
+    Flip,
+    Low,
+    Keep,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pulse {
+    length: u32,
+    edge: Edge,
+}
+
+impl Pulse {
+    fn flip(length: u16) -> Pulse {
+        Pulse {
+            length: u32::from(length),
+            edge: Edge::Flip,
+        }
+    }
+}
+
+/// A tape in a player: its blocks, where it is, and the EAR level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tape {
+    blocks: Vec<Block>,
+    /// Next block to turn into pulses.
+    block: usize,
+    /// Block of the pulses queued.
+    current: usize,
+    /// First block and plays left of the loops entered.
+    loops: Vec<(usize, u16)>,
+    pulses: VecDeque<Pulse>,
+    level: bool,
+    /// T state at the end of the pulse playing, while the tape plays.
+    end: Option<u64>,
+}
+
+impl Tape {
+    /// A stopped tape, at its start.
+    pub fn new(blocks: Vec<Block>) -> Tape {
+        Tape {
+            blocks,
+            block: 0,
+            current: 0,
+            loops: Vec::new(),
+            pulses: VecDeque::new(),
+            level: false,
+            end: None,
+        }
+    }
+
+    /// Read a tape file.
+    pub fn read(data: &[u8], format: TapeFormat) -> Result<Tape, LoadError> {
+        let blocks = match format {
+            TapeFormat::Tap => read_tap(data)?,
+            TapeFormat::Tzx => tzx::read(data)?,
+        };
+        Ok(Tape::new(blocks))
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Start playing at the T state, where the tape stopped.
+    pub fn play(&mut self, cycles: u64) {
+        if self.end.is_none() {
+            self.end = self
+                .next_pulse()
+                .map(|pulse| cycles + u64::from(pulse.length));
+        }
+    }
+
+    /// Stop playing; the pulse interrupted plays again in full.
+    pub fn stop(&mut self) {
+        self.end = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// True when every block was played.
+    pub fn is_finished(&self) -> bool {
+        self.end.is_none() && self.pulses.is_empty() && self.block >= self.blocks.len()
+    }
+
+    /// The EAR level at the T state, playing the pulses until then.
+    pub fn ear(&mut self, cycles: u64) -> bool {
+        while let Some(end) = self.end {
+            if cycles < end {
+                break;
+            }
+            let pulse = self.pulses.pop_front().expect("pulse playing");
+            match pulse.edge {
+                Edge::Flip => self.level = !self.level,
+                Edge::Low => self.level = false,
+                Edge::Keep => {}
+                Edge::Stop => {
+                    self.end = None;
+                    break;
+                }
+            }
+            self.end = self.next_pulse().map(|next| end + u64::from(next.length));
+        }
+        self.level
+    }
+
+    /// The pulse to play next, left at the front of the queue.
+    fn next_pulse(&mut self) -> Option<Pulse> {
+        while self.pulses.is_empty() {
+            let block = self.blocks.get(self.block)?;
+            self.current = self.block;
+            self.block += 1;
+            match *block {
+                Block::Data(ref data) => data_pulses(data, &mut self.pulses),
+                Block::Tone { length, pulses } => {
+                    self.pulses.extend((0..pulses).map(|_| Pulse::flip(length)));
+                }
+                Block::Pulses(ref lengths) => {
+                    self.pulses
+                        .extend(lengths.iter().map(|&length| Pulse::flip(length)));
+                }
+                Block::Pause(0) => self.pulses.push_back(Pulse {
+                    length: 0,
+                    edge: Edge::Stop,
+                }),
+                Block::Pause(pause) => pause_pulses(pause, &mut self.pulses),
+                Block::LoopStart(count) => self.loops.push((self.block, count)),
+                Block::LoopEnd => self.loop_end(),
+                Block::GroupStart(_) | Block::GroupEnd => {}
+            }
+        }
+        self.pulses.front().cloned()
+    }
+
+    /// Skip to the next data block and take its bytes, as a trap of the
+    /// ROM loading routine does: the block playing unless it is in its
+    /// pause. A playing tape goes on with the block after it, from the
+    /// T state.
+    pub fn next_data(&mut self, cycles: u64) -> Option<Vec<u8>> {
+        let playing = self.end.take().is_some();
+        if self
+            .pulses
+            .front()
+            .is_some_and(|pulse| pulse.edge == Edge::Flip)
+        {
+            self.block = self.current;
+        }
+        self.pulses.clear();
+
+        let mut data = None;
+        while let Some(block) = self.blocks.get(self.block) {
+            self.block += 1;
+            match *block {
+                Block::Data(ref block) => {
+                    data = Some(block.data.clone());
+                    break;
+                }
+                Block::LoopStart(count) => self.loops.push((self.block, count)),
+                Block::LoopEnd => self.loop_end(),
+                _ => {}
+            }
+        }
+
+        if playing {
+            self.play(cycles);
+        }
+        data
+    }
+
+    /// Play the blocks of the loop again, or leave it after the last time.
+    fn loop_end(&mut self) {
+        if let Some(&mut (first, ref mut left)) = self.loops.last_mut() {
+            if *left > 1 {
+                *left -= 1;
+                self.block = first;
+            } else {
+                self.loops.pop();
+            }
+        }
+    }
+}
+
+fn data_pulses(block: &DataBlock, pulses: &mut VecDeque<Pulse>) {
+    pulses.extend((0..block.pilot_pulses).map(|_| Pulse::flip(block.pilot)));
+    for &sync in &[block.sync1, block.sync2] {
+        if sync != 0 {
+            pulses.push_back(Pulse::flip(sync));
+        }
+    }
+
+    for (index, &byte) in block.data.iter().enumerate() {
+        let bits = if index + 1 == block.data.len() {
+            block.last_bits.min(8)
+        } else {
+            8
+        };
+        for bit in 0..bits {
+            let length = if byte << bit & 0x80 != 0 {
+                block.one
+            } else {
+                block.zero
+            };
+            pulses.push_back(Pulse::flip(length));
+            pulses.push_back(Pulse::flip(length));
+        }
+    }
+
+    if block.pause != 0 {
+        pause_pulses(block.pause, pulses);
+    }
+}
+
+/// End the last pulse after a millisecond, then stay low.
+fn pause_pulses(pause: u16, pulses: &mut VecDeque<Pulse>) {
+    pulses.push_back(Pulse {
+        length: MILLISECOND,
+        edge: Edge::Low,
+    });
+    pulses.push_back(Pulse {
+        length: (u32::from(pause) - 1) * MILLISECOND,
+        edge: Edge::Keep,
+    });
+}
+
+/// The blocks of a .tap file.
+fn read_tap(data: &[u8]) -> Result<Vec<Block>, LoadError> {
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let length = data
+            .get(offset..offset + 2)
+            .map(|length| usize::from(length[0]) | usize::from(length[1]) << 8);
+        let block = length
+            .and_then(|length| data.get(offset + 2..offset + 2 + length))
+            .ok_or_else(|| LoadError::new(format!("Truncated TAP block at offset {}", offset)))?;
+        blocks.push(Block::Data(DataBlock::standard(block.to_vec(), TAP_PAUSE)));
+        offset += 2 + block.len();
+    }
+    Ok(blocks)
+}
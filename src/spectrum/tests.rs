@@ -0,0 +1,993 @@
+use super::z80::{compress, decompress};
+use super::zlib;
+use super::{fast_load, run_fast_load, LD_BYTES};
+use super::{Block, DataBlock, Tape, TapeFormat, Ula, MILLISECOND};
+use super::{Machine, Snapshot, SnapshotFormat, BANK};
+use cpu::{Assertor, CpuBuilder, History, Kind, Limits, StopReason};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+/// A snapshot with every register distinct, and numbered banks.
+fn snapshot(machine: Machine) -> Snapshot {
+    let mut snapshot = Snapshot::new(machine);
+    {
+        let registers = &mut snapshot.registers;
+        registers.pc = 0x8123;
+        registers.sp = 0xff00;
+        registers.ix = 0x1122;
+        registers.iy = 0x5c3a;
+        registers.i = 0x3f;
+        registers.r = 0x9b;
+        registers.a = 0x01;
+        registers.f = 0xc5;
+        registers.b = 0x02;
+        registers.c = 0x03;
+        registers.d = 0x04;
+        registers.e = 0x05;
+        registers.h = 0x06;
+        registers.l = 0x07;
+        registers.a1 = 0x11;
+        registers.f1 = 0x28;
+        registers.b1 = 0x12;
+        registers.c1 = 0x13;
+        registers.d1 = 0x14;
+        registers.e1 = 0x15;
+        registers.h1 = 0x16;
+        registers.l1 = 0x17;
+        registers.iff1 = true;
+        registers.iff2 = true;
+        registers.im = 2;
+    }
+    snapshot.border = 5;
+    for (bank, ram) in snapshot.banks.iter_mut().enumerate() {
+        ram[0] = bank as u8 + 1;
+        ram[BANK - 1] = 0xed;
+        ram[100] = 0xed;
+        ram[101] = 0xed;
+    }
+    snapshot
+}
+
+#[test]
+fn formats_from_the_extension() {
+    assert_eq!(
+        SnapshotFormat::from_path(Path::new("game.SNA")),
+        Some(SnapshotFormat::Sna)
+    );
+    assert_eq!(
+        SnapshotFormat::from_path(Path::new("game.z80")),
+        Some(SnapshotFormat::Z80)
+    );
+    assert_eq!(
+        SnapshotFormat::from_path(Path::new("game.szx")),
+        Some(SnapshotFormat::Szx)
+    );
+    assert_eq!(SnapshotFormat::from_path(Path::new("game.bin")), None);
+}
+
+#[test]
+fn compress_runs() {
+    assert_eq!(compress(&[1, 1, 1, 1, 1, 2]), vec![0xed, 0xed, 5, 1, 2]);
+    assert_eq!(compress(&[1, 1, 1, 1, 2]), vec![1, 1, 1, 1, 2]);
+    assert_eq!(compress(&[0xed, 0xed, 3]), vec![0xed, 0xed, 2, 0xed, 3]);
+    // The byte after a single ED stays out of runs.
+    assert_eq!(
+        compress(&[0xed, 0, 0, 0, 0, 0, 0]),
+        vec![0xed, 0, 0xed, 0xed, 5, 0]
+    );
+
+    let data: Vec<u8> = (0..BANK)
+        .map(|i| if i % 700 < 600 { 0 } else { i as u8 })
+        .collect();
+    let compressed = compress(&data);
+    assert!(compressed.len() < data.len() / 4);
+    assert_eq!(decompress(&compressed, BANK).unwrap(), data);
+}
+
+#[test]
+fn sna_48k() {
+    let snapshot = snapshot(Machine::Spectrum48);
+    let data = snapshot.write(SnapshotFormat::Sna).unwrap();
+    assert_eq!(data.len(), 49179);
+    // SP in the header, below the PC pushed.
+    assert_eq!(&data[23..25], &[0xfe, 0xfe]);
+    assert_eq!(
+        &data[27 + 0xfefe - 0x4000..27 + 0xff00 - 0x4000],
+        &[0x23, 0x81]
+    );
+    assert_eq!(data[19], 0x04);
+    assert_eq!(data[26], 5);
+
+    let read = Snapshot::read(&data, SnapshotFormat::Sna).unwrap();
+    assert_eq!(read.registers, snapshot.registers);
+    assert_eq!(read.border, 5);
+    assert_eq!(read.banks[2][0x3efe..0x3f00], [0x23, 0x81]);
+    assert_eq!(read.banks[0][0], 1);
+}
+
+#[test]
+fn sna_48k_cannot_push_the_pc_in_the_rom() {
+    let mut snapshot = snapshot(Machine::Spectrum48);
+    snapshot.registers.sp = 0x4001;
+    let error = snapshot.write(SnapshotFormat::Sna).unwrap_err();
+    assert_eq!(
+        error.message,
+        "Cannot push the PC on the stack at 0x3fff, in the ROM"
+    );
+}
+
+#[test]
+fn sna_128k() {
+    let mut snapshot = snapshot(Machine::Spectrum128);
+    snapshot.paging = 0x13;
+    let data = snapshot.write(SnapshotFormat::Sna).unwrap();
+    assert_eq!(data.len(), 131103);
+    // Banks 5, 2 and 3, then the others.
+    assert_eq!(data[27], 6);
+    assert_eq!(data[27 + BANK], 3);
+    assert_eq!(data[27 + 2 * BANK], 4);
+    assert_eq!(&data[49179..49183], &[0x23, 0x81, 0x13, 0]);
+    assert_eq!(data[49183], 1);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Sna).unwrap(),
+        snapshot
+    );
+
+    // With bank 2 paged in twice, the six others follow.
+    snapshot.paging = 0x02;
+    let data = snapshot.write(SnapshotFormat::Sna).unwrap();
+    assert_eq!(data.len(), 147487);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Sna).unwrap(),
+        snapshot
+    );
+
+    let error = Snapshot::read(&data[..131103], SnapshotFormat::Sna).unwrap_err();
+    assert_eq!(
+        error.message,
+        "SNA snapshot of 131103 bytes, 147487 expected for the 128K with bank 2 paged in"
+    );
+}
+
+#[test]
+fn sna_of_a_wrong_size() {
+    let error = Snapshot::read(&[0; 100], SnapshotFormat::Sna).unwrap_err();
+    assert_eq!(
+        error.message,
+        "SNA snapshot of 100 bytes, 49179 expected for the 48K"
+    );
+}
+
+#[test]
+fn z80_version_1() {
+    let snapshot = snapshot(Machine::Spectrum48);
+    let registers = &snapshot.registers;
+    let mut data = vec![
+        registers.a,
+        registers.f,
+        registers.c,
+        registers.b,
+        registers.l,
+        registers.h,
+        0x23,
+        0x81,
+        0x00,
+        0xff,
+        registers.i,
+        registers.r & 0x7f,
+        // R bit 7, border 5, compressed
+        0x01 | 5 << 1 | 0x20,
+        registers.e,
+        registers.d,
+        registers.c1,
+        registers.b1,
+        registers.e1,
+        registers.d1,
+        registers.l1,
+        registers.h1,
+        registers.a1,
+        registers.f1,
+        0x3a,
+        0x5c,
+        0x22,
+        0x11,
+        1,
+        1,
+        0x42,
+    ];
+    let ram: Vec<u8> = snapshot.banks.concat();
+    data.extend_from_slice(&compress(&ram));
+    data.extend_from_slice(&[0x00, 0xed, 0xed, 0x00]);
+
+    let read = Snapshot::read(&data, SnapshotFormat::Z80).unwrap();
+    assert_eq!(read, snapshot);
+    assert_eq!(read.registers.r, 0x9b);
+    assert_eq!(read.registers.im, 2);
+
+    // Not compressed.
+    data[12] = 0x01 | 5 << 1;
+    data.truncate(30);
+    data.extend_from_slice(&ram);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Z80).unwrap(),
+        snapshot
+    );
+}
+
+#[test]
+fn z80_version_3_48k() {
+    let snapshot = snapshot(Machine::Spectrum48);
+    let data = snapshot.write(SnapshotFormat::Z80).unwrap();
+    assert_eq!(&data[6..8], &[0, 0]);
+    assert_eq!(&data[30..35], &[54, 0, 0x23, 0x81, 0]);
+    // Pages 8, 4 and 5, compressed.
+    assert_eq!(data[88], 8);
+    assert!(data.len() < 1000);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Z80).unwrap(),
+        snapshot
+    );
+}
+
+#[test]
+fn z80_version_3_128k() {
+    let mut snapshot = snapshot(Machine::Spectrum128);
+    snapshot.paging = 0x17;
+    snapshot.ay_register = 7;
+    snapshot.ay[7] = 0x38;
+    // A bank that does not compress.
+    snapshot.banks[6] = (0..BANK).map(|i| (i * 7 + i / 256) as u8).collect();
+
+    let data = snapshot.write(SnapshotFormat::Z80).unwrap();
+    assert_eq!(&data[34..36], &[4, 0x17]);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Z80).unwrap(),
+        snapshot
+    );
+}
+
+#[test]
+fn z80_version_2_128k() {
+    let mut snapshot = snapshot(Machine::Spectrum128);
+    snapshot.paging = 0x01;
+    let mut data = snapshot.write(SnapshotFormat::Z80).unwrap();
+
+    // Shorten the extra header to version 2, where 128K is mode 3.
+    data[30] = 23;
+    data[34] = 3;
+    data.drain(32 + 23..32 + 54);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Z80).unwrap(),
+        snapshot
+    );
+
+    data[34] = 2;
+    let error = Snapshot::read(&data, SnapshotFormat::Z80).unwrap_err();
+    assert_eq!(error.message, "Unsupported hardware 2 in the Z80 snapshot");
+}
+
+#[test]
+fn cpu_from_a_snapshot() {
+    let mut snapshot = snapshot(Machine::Spectrum128);
+    snapshot.paging = 0x14;
+    snapshot.registers.iff1 = false;
+    let mut rom = vec![0xaa; BANK];
+    rom.extend(vec![0xbb; BANK]);
+
+    let cpu = snapshot.cpu(&rom);
+    Assertor::new(cpu)
+        .program_counter_is(0x8123)
+        .stack_pointer_is(0xff00)
+        .index_register_ix_is(0x1122)
+        .index_register_iy_is(0x5c3a)
+        .interrupt_vector_is(0x3f)
+        .memory_refresh_register_is(0x9b)
+        .register_af_is(0x01c5)
+        .register_bc_is(0x0203)
+        .register_de_is(0x0405)
+        .register_hl_is(0x0607)
+        .register_a1_is(0x11)
+        .register_f1_is(0x28)
+        .register_b1_is(0x12)
+        .register_c1_is(0x13)
+        .register_d1_is(0x14)
+        .register_e1_is(0x15)
+        .register_h1_is(0x16)
+        .register_l1_is(0x17)
+        .interrupt_flip_flop_1_is_reset()
+        .interrupt_flip_flop_2_is_set()
+        .sign_is_negative()
+        .carry_flag_is_set()
+        // ROM 1, banks 5, 2 and 4.
+        .memory_at_address_is(0x0000, 0xbb)
+        .memory_at_address_is(0x4000, 6)
+        .memory_at_address_is(0x8000, 3)
+        .memory_at_address_is(0xc000, 5);
+}
+
+#[test]
+fn update_from_the_cpu() {
+    let mut snapshot = snapshot(Machine::Spectrum128);
+    snapshot.paging = 0x03;
+    let mut cpu = snapshot.cpu(&[]);
+    cpu.im = 1;
+    cpu.memory[0x4001] = 0x55;
+    cpu.memory[0xc001] = 0x66;
+
+    snapshot.update(&cpu);
+    assert_eq!(snapshot.registers.im, 1);
+    assert!(snapshot.registers.memory.is_empty());
+    assert_eq!(snapshot.banks[5][1], 0x55);
+    assert_eq!(snapshot.banks[3][1], 0x66);
+
+    let cpu = Snapshot::new(Machine::Spectrum48).cpu(&[]);
+    assert_eq!(Snapshot::from_cpu(&cpu), Snapshot::new(Machine::Spectrum48));
+}
+
+#[test]
+fn refresh_register_round_trips() {
+    let formats = [
+        SnapshotFormat::Sna,
+        SnapshotFormat::Z80,
+        SnapshotFormat::Szx,
+    ];
+    for &r in &[0x00, 0x7f, 0x80, 0x9b, 0xff] {
+        for &format in &formats {
+            let mut snapshot = snapshot(Machine::Spectrum48);
+            snapshot.registers.r = r;
+            let data = snapshot.write(format).unwrap();
+            let read = Snapshot::read(&data, format).unwrap();
+            assert_eq!(read.registers.r, r, "{:?} R {:#04x}", format, r);
+        }
+    }
+
+    // Bit 7 stays set while the low 7 bits count the fetches.
+    let mut snapshot = snapshot(Machine::Spectrum48);
+    snapshot.registers.r = 0xfe;
+    let mut cpu = snapshot.cpu(&[]);
+    cpu.memory[0x8123] = 0x00;
+    cpu.memory[0x8124] = 0xdd;
+    cpu.memory[0x8125] = 0x23;
+    cpu.step();
+    cpu.step();
+    snapshot.update(&cpu);
+    assert_eq!(snapshot.registers.r, 0x81);
+    for &format in &formats {
+        let data = snapshot.write(format).unwrap();
+        let read = Snapshot::read(&data, format).unwrap();
+        assert_eq!(read.registers.r, 0x81, "{:?}", format);
+    }
+}
+
+/// The text compressed with dynamic codes by zlib.
+fn text() -> Vec<u8> {
+    (0..40)
+        .flat_map(|line| {
+            format!(
+                "line {} of the test text, with words repeated: spectrum snapshot\n",
+                line
+            )
+            .into_bytes()
+        })
+        .collect()
+}
+
+#[test]
+fn zlib_streams() {
+    // Stored, fixed and dynamic blocks, as written by zlib.
+    let stored = [120, 1, 1, 3, 0, 252, 255, 97, 98, 99, 2, 77, 1, 39];
+    assert_eq!(zlib::decompress(&stored, 3).unwrap(), b"abc");
+
+    let fixed = [
+        120, 218, 203, 72, 205, 201, 201, 87, 200, 64, 39, 1, 104, 3, 8, 177,
+    ];
+    assert_eq!(
+        zlib::decompress(&fixed, 23).unwrap(),
+        b"hello hello hello hello"
+    );
+
+    let dynamic = [
+        120, 218, 165, 213, 203, 13, 194, 64, 12, 69, 209, 61, 85, 184, 0, 22, 216, 230, 223, 77,
+        68, 140, 38, 82, 200, 68, 51, 70, 161, 124, 160, 134, 187, 241, 238, 238, 142, 158, 231,
+        105, 9, 57, 72, 125, 74, 150, 144, 140, 158, 191, 243, 201, 189, 108, 83, 22, 217, 106, 27,
+        187, 180, 88, 99, 200, 24, 239, 210, 215, 120, 100, 123, 191, 164, 47, 195, 218, 75, 205,
+        221, 252, 239, 21, 246, 6, 123, 135, 253, 17, 246, 39, 216, 159, 97, 127, 129, 253, 21,
+        246, 55, 234, 7, 3, 164, 2, 149, 18, 84, 106, 80, 41, 66, 165, 10, 149, 50, 84, 234, 80,
+        41, 68, 165, 18, 141, 74, 52, 188, 133, 84, 162, 81, 137, 70, 37, 26, 149, 104, 84, 162,
+        81, 137, 70, 37, 26, 149, 232, 84, 162, 83, 137, 142, 223, 50, 149, 232, 84, 162, 83, 137,
+        78, 37, 58, 149, 232, 84, 162, 3, 137, 95, 28, 238, 167, 246,
+    ];
+    assert_eq!(zlib::decompress(&dynamic, text().len()).unwrap(), text());
+
+    let mut corrupt = fixed;
+    corrupt[15] ^= 1;
+    assert_eq!(
+        zlib::decompress(&corrupt, 23).unwrap_err().message,
+        "Invalid zlib data: bad checksum"
+    );
+    assert_eq!(
+        zlib::decompress(&[0x78, 0x9d, 0, 0, 0, 0], 0)
+            .unwrap_err()
+            .message,
+        "Invalid zlib data: not a DEFLATE stream"
+    );
+
+    // Output past the size expected, or short of it.
+    assert_eq!(
+        zlib::decompress(&fixed, 22).unwrap_err().message,
+        "Invalid zlib data: too long"
+    );
+    assert_eq!(
+        zlib::decompress(&stored, 2).unwrap_err().message,
+        "Invalid zlib data: too long"
+    );
+    assert_eq!(
+        zlib::decompress(&dynamic, text().len() + 1)
+            .unwrap_err()
+            .message,
+        "Invalid zlib data: too short"
+    );
+}
+
+#[test]
+fn zlib_compress() {
+    for data in [
+        Vec::new(),
+        text(),
+        vec![0; BANK],
+        (0..BANK).map(|i| ((i * i) >> 5) as u8).collect(),
+    ] {
+        let compressed = zlib::compress(&data);
+        assert_eq!(zlib::decompress(&compressed, data.len()).unwrap(), data);
+    }
+    assert!(zlib::compress(&vec![0; BANK]).len() < 200);
+}
+
+#[test]
+fn szx_48k() {
+    let mut snapshot = snapshot(Machine::Spectrum48);
+    snapshot.registers.halted = true;
+    snapshot.registers.wz = 0x1234;
+    let data = snapshot.write(SnapshotFormat::Szx).unwrap();
+    assert_eq!(&data[..8], b"ZXST\x01\x04\x01\x00");
+    assert_eq!(&data[8..16], b"Z80R\x25\x00\x00\x00");
+    assert!(data.len() < 1000);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Szx).unwrap(),
+        snapshot
+    );
+}
+
+#[test]
+fn szx_128k() {
+    let mut snapshot = snapshot(Machine::Spectrum128);
+    snapshot.paging = 0x16;
+    snapshot.ay_register = 14;
+    snapshot.ay[14] = 0xff;
+    snapshot.banks[3] = text().into_iter().cycle().take(BANK).collect();
+    let data = snapshot.write(SnapshotFormat::Szx).unwrap();
+    assert_eq!(data[6], 2);
+    assert_eq!(
+        Snapshot::read(&data, SnapshotFormat::Szx).unwrap(),
+        snapshot
+    );
+}
+
+#[test]
+fn szx_uncompressed_pages_and_other_chunks() {
+    let mut data = b"ZXST\x01\x05\x01\x00".to_vec();
+    // Keyboard, skipped.
+    data.extend_from_slice(b"KEYB\x05\x00\x00\x00\x00\x00\x00\x00\x00");
+    data.extend_from_slice(b"SPCR\x08\x00\x00\x00\x03\x00\x00\x03\x00\x00\x00\x00");
+    data.extend_from_slice(b"RAMP\x03\x40\x00\x00\x00\x00\x02");
+    data.extend(vec![0x77; BANK]);
+
+    let snapshot = Snapshot::read(&data, SnapshotFormat::Szx).unwrap();
+    assert_eq!(snapshot.machine, Machine::Spectrum48);
+    assert_eq!(snapshot.border, 3);
+    // Bank 2 at 0x8000.
+    assert_eq!(snapshot.banks[1], vec![0x77; BANK]);
+    assert_eq!(snapshot.banks[0], vec![0; BANK]);
+
+    data.truncate(data.len() - 1);
+    let error = Snapshot::read(&data, SnapshotFormat::Szx).unwrap_err();
+    assert_eq!(error.message, "Truncated SZX chunk RAMP");
+
+    data[6] = 7;
+    let error = Snapshot::read(&data, SnapshotFormat::Szx).unwrap_err();
+    assert_eq!(error.message, "Unsupported machine 7 in the SZX snapshot");
+
+    let error = Snapshot::read(b"ZXST", SnapshotFormat::Szx).unwrap_err();
+    assert_eq!(error.message, "Not an SZX snapshot");
+}
+
+/// T states at which the EAR level changes, playing from 0.
+fn edges(tape: &mut Tape, end: u64) -> Vec<u64> {
+    tape.play(0);
+    let mut level = false;
+    let mut edges = Vec::new();
+    for cycles in 0..end {
+        if tape.ear(cycles) != level {
+            level = !level;
+            edges.push(cycles);
+        }
+    }
+    edges
+}
+
+#[test]
+fn tap_blocks() {
+    let data = [3, 0, 0x00, 0x03, 0xaa, 2, 0, 0xff, 0x55];
+    let tape = Tape::read(&data, TapeFormat::Tap).unwrap();
+    assert_eq!(
+        tape.blocks(),
+        &[
+            Block::Data(DataBlock::standard(vec![0x00, 0x03, 0xaa], 1000)),
+            Block::Data(DataBlock::standard(vec![0xff, 0x55], 1000)),
+        ]
+    );
+    match tape.blocks()[0] {
+        Block::Data(ref block) => assert_eq!(block.pilot_pulses, 8063),
+        _ => unreachable!(),
+    }
+    match tape.blocks()[1] {
+        Block::Data(ref block) => assert_eq!(block.pilot_pulses, 3223),
+        _ => unreachable!(),
+    }
+
+    let error = Tape::read(&data[..8], TapeFormat::Tap).unwrap_err();
+    assert_eq!(error.message, "Truncated TAP block at offset 5");
+    assert_eq!(
+        TapeFormat::from_path(Path::new("game.TZX")),
+        Some(TapeFormat::Tzx)
+    );
+}
+
+#[test]
+fn tzx_blocks() {
+    let mut data = b"ZXTape!\x1a\x01\x14".to_vec();
+    data.extend_from_slice(&[0x10, 0xe8, 0x03, 2, 0, 0xff, 0x01]);
+    data.extend_from_slice(&[0x11, 0x78, 0x08, 0x9b, 0x02, 0xdf, 0x02, 0x57, 0x03]);
+    data.extend_from_slice(&[0xae, 0x06, 0x97, 0x0c, 6, 0x00, 0x00, 1, 0, 0, 0x80]);
+    data.extend_from_slice(&[0x30, 3, b'a', b'b', b'c']);
+    data.extend_from_slice(&[0x12, 100, 0, 4, 0]);
+    data.extend_from_slice(&[0x13, 2, 10, 0, 20, 0]);
+    data.extend_from_slice(&[0x14, 50, 0, 100, 0, 4, 0, 0, 2, 0, 0, 0x12, 0x34]);
+    data.extend_from_slice(&[0x21, 4, b'g', b'a', b'm', b'e', 0x24, 2, 0, 0x25, 0x22]);
+    data.extend_from_slice(&[0x20, 0, 0]);
+
+    let tape = Tape::read(&data, TapeFormat::Tzx).unwrap();
+    assert_eq!(
+        tape.blocks(),
+        &[
+            Block::Data(DataBlock::standard(vec![0xff, 0x01], 1000)),
+            Block::Data(DataBlock {
+                pilot: 2168,
+                pilot_pulses: 3223,
+                sync1: 667,
+                sync2: 735,
+                zero: 855,
+                one: 1710,
+                last_bits: 6,
+                pause: 0,
+                data: vec![0x80],
+            }),
+            Block::Tone {
+                length: 100,
+                pulses: 4,
+            },
+            Block::Pulses(vec![10, 20]),
+            Block::Data(DataBlock {
+                pilot: 0,
+                pilot_pulses: 0,
+                sync1: 0,
+                sync2: 0,
+                zero: 50,
+                one: 100,
+                last_bits: 4,
+                pause: 0,
+                data: vec![0x12, 0x34],
+            }),
+            Block::GroupStart("game".to_string()),
+            Block::LoopStart(2),
+            Block::LoopEnd,
+            Block::GroupEnd,
+            Block::Pause(0),
+        ]
+    );
+
+    data.extend_from_slice(&[0x15, 0, 0]);
+    let error = Tape::read(&data, TapeFormat::Tzx).unwrap_err();
+    assert_eq!(error.message, "Unsupported TZX block 0x15 at offset 80");
+
+    data.truncate(79);
+    let error = Tape::read(&data, TapeFormat::Tzx).unwrap_err();
+    assert_eq!(error.message, "Truncated TZX block 0x20 at offset 78");
+
+    let error = Tape::read(b"ZXTape!", TapeFormat::Tzx).unwrap_err();
+    assert_eq!(error.message, "Not a TZX tape");
+}
+
+#[test]
+fn tzx_flow_control_blocks() {
+    let mut data = b"ZXTape!\x1a\x01\x14".to_vec();
+    data.extend_from_slice(&[0x23, 2, 0, 0x26, 2, 0, 1, 0, 3, 0, 0x27]);
+    data.extend_from_slice(&[0x28, 5, 0, 1, 0x02, 0, 1, b'a']);
+    data.extend_from_slice(&[0x2a, 0, 0, 0, 0, 0x2b, 1, 0, 0, 0, 1]);
+    data.extend_from_slice(&[0x20, 0, 0]);
+
+    let tape = Tape::read(&data, TapeFormat::Tzx).unwrap();
+    assert_eq!(tape.blocks(), &[Block::Pause(0)]);
+}
+
+#[test]
+fn tape_pulses() {
+    // Two pulses per bit, from the most significant.
+    let mut tape = Tape::new(vec![Block::Data(DataBlock {
+        pilot: 0,
+        pilot_pulses: 0,
+        sync1: 0,
+        sync2: 0,
+        zero: 10,
+        one: 20,
+        last_bits: 3,
+        pause: 1,
+        data: vec![0b1010_0000],
+    })]);
+    assert_eq!(edges(&mut tape, 200), vec![20, 40, 50, 60, 80, 100]);
+    assert!(tape.is_playing());
+    assert_eq!(edges(&mut tape, 200 + u64::from(MILLISECOND)), vec![]);
+    assert!(tape.is_finished());
+
+    // The pause ends a high level after a millisecond.
+    let mut tape = Tape::new(vec![
+        Block::Tone {
+            length: 100,
+            pulses: 3,
+        },
+        Block::Pause(2),
+        Block::Pulses(vec![5]),
+    ]);
+    let end = 300 + u64::from(MILLISECOND);
+    assert_eq!(
+        edges(&mut tape, 8000),
+        vec![100, 200, 300, end, end + 3500 + 5]
+    );
+}
+
+#[test]
+fn tape_loops_and_stops() {
+    let mut tape = Tape::new(vec![
+        Block::LoopStart(3),
+        Block::Pulses(vec![10]),
+        Block::LoopEnd,
+        Block::Pause(0),
+        Block::Pulses(vec![10]),
+    ]);
+    assert_eq!(edges(&mut tape, 100), vec![10, 20, 30]);
+    assert!(!tape.is_playing());
+    assert!(!tape.is_finished());
+
+    // High after three pulses.
+    tape.play(1000);
+    assert!(tape.ear(1009));
+    assert!(!tape.ear(1010));
+    assert!(tape.is_finished());
+}
+
+#[test]
+fn tape_on_the_ula_port() {
+    let tape = Rc::new(RefCell::new(Tape::new(vec![Block::Tone {
+        length: 1000,
+        pulses: 2,
+    }])));
+    tape.borrow_mut().play(0);
+
+    // IN A,(0xFE); OUT (0xFE),A; IN A,(0xFE); IN A,(0xFF)
+    let mut cpu = CpuBuilder::new()
+        .with_memory(asm!(
+            "IN A,(0FEh)",
+            "OUT (0FEh),A",
+            "IN A,(0FEh)",
+            "IN A,(0FFh)"
+        ))
+        .with_ports(Box::new(Ula::new().with_tape(tape.clone())))
+        .build();
+    cpu.a = 0xfe;
+    cpu.step();
+    assert_eq!(cpu.a, 0xbf);
+
+    cpu.cycles = 1000;
+    cpu.step();
+    cpu.a = 0xfe;
+    cpu.step();
+    assert_eq!(cpu.a, 0xff);
+    cpu.step();
+    assert_eq!(cpu.a, 0xff);
+
+    cpu.cycles = 2000;
+    assert_eq!(cpu.input(0xfe), 0xbf);
+    assert!(tape.borrow().is_finished());
+}
+
+#[test]
+fn tape_loads_at_the_signal_level() {
+    let tape = Rc::new(RefCell::new(Tape::new(vec![Block::Data(DataBlock {
+        pilot: 0,
+        pilot_pulses: 0,
+        sync1: 0,
+        sync2: 0,
+        zero: 1000,
+        one: 2000,
+        last_bits: 8,
+        pause: 0,
+        data: vec![0xa5],
+    })])));
+    tape.borrow_mut().play(0);
+
+    // Times the second pulse of each bit, in loops of 39 T states.
+    let mut memory = asm!(
+        "        LD SP,0x100",
+        "        LD C,0xfe",
+        "        LD H,0",
+        "        LD DE,0x0800",
+        "next:   CALL edge",
+        "        CALL edge",
+        "        LD A,B",
+        "        ADD A,256-38",
+        "        RL E",
+        "        DEC D",
+        "        JR NZ,next",
+        "        LD A,E",
+        "        HALT",
+        "edge:   LD B,0",
+        "wait:   INC B",
+        "        IN A,(C)",
+        "        AND 0x40",
+        "        XOR H",
+        "        JR Z,wait",
+        "        LD A,H",
+        "        XOR 0x40",
+        "        LD H,A",
+        "        RET",
+    );
+    memory.resize(0x100, 0);
+    let mut cpu = CpuBuilder::new()
+        .with_memory(memory)
+        .with_ports(Box::new(Ula::new().with_tape(tape.clone())))
+        .build();
+
+    cpu.run(Default::default());
+    assert_eq!(cpu.a, 0xa5);
+    assert!(tape.borrow().is_finished());
+}
+
+/// A block saved by the ROM: the flag, the data and the checksum.
+fn saved(flag: u8, data: &[u8]) -> Vec<u8> {
+    let mut block = vec![flag];
+    block.extend_from_slice(data);
+    block.push(block.iter().fold(0, |checksum, &byte| checksum ^ byte));
+    block
+}
+
+/// 64K with the start of LD-BYTES, called from 0x8000 with the stack at
+/// 0xff00.
+fn ld_bytes_call(tape: Vec<Block>) -> (::cpu::Cpu, Tape) {
+    let mut memory = vec![0; 0x10000];
+    let start = usize::from(LD_BYTES);
+    memory[start..start + 4].copy_from_slice(&[0x14, 0x08, 0x15, 0xf3]);
+    memory[0xff00..0xff02].copy_from_slice(&[0x03, 0x80]);
+    let mut cpu = CpuBuilder::new().with_memory(memory).build();
+    cpu.pc = LD_BYTES;
+    cpu.sp = 0xff00;
+    (cpu, Tape::new(tape))
+}
+
+#[test]
+fn fast_load_loads_the_next_data_block() {
+    let data = saved(0xff, &[0x12, 0x34, 0x56]);
+    let (mut cpu, mut tape) = ld_bytes_call(vec![
+        Block::Tone {
+            length: 2168,
+            pulses: 100,
+        },
+        Block::Data(DataBlock::standard(data, 1000)),
+        Block::Pause(0),
+    ]);
+    cpu.a = 0xff;
+    cpu.ix = 0x9000;
+    cpu.write_de(3);
+    cpu.set_c(true);
+
+    assert!(fast_load(&mut cpu, &mut tape));
+    assert_eq!(&cpu.memory[0x9000..0x9004], &[0x12, 0x34, 0x56, 0]);
+    assert!(cpu.get_c());
+    assert_eq!(cpu.ix, 0x9003);
+    assert_eq!(cpu.read_de(), 0);
+    assert_eq!(cpu.pc, 0x8003);
+    assert_eq!(cpu.sp, 0xff02);
+    assert!(cpu.iff1 && cpu.iff2);
+
+    // Not at LD-BYTES any more.
+    assert!(!fast_load(&mut cpu, &mut tape));
+    assert!(!tape.is_finished());
+}
+
+#[test]
+fn fast_load_errors() {
+    let block = || Block::Data(DataBlock::standard(saved(0xff, &[1, 2, 3]), 1000));
+    let load = |tape: Vec<Block>, a: u8, length: u16, carry: bool| {
+        let (mut cpu, mut tape) = ld_bytes_call(tape);
+        cpu.memory[0x9000..0x9003].copy_from_slice(&[1, 2, 4]);
+        cpu.a = a;
+        cpu.ix = 0x9000;
+        cpu.write_de(length);
+        cpu.set_c(carry);
+        assert!(fast_load(&mut cpu, &mut tape));
+        assert_eq!(cpu.pc, 0x8003);
+        (cpu.get_c(), cpu.ix, cpu.memory[0x9002])
+    };
+
+    // Loaded, verified, a wrong flag, a short block, a bad checksum.
+    assert_eq!(load(vec![block()], 0xff, 3, true), (true, 0x9003, 3));
+    let verified = Block::Data(DataBlock::standard(saved(0xff, &[1, 2]), 1000));
+    assert_eq!(load(vec![verified], 0xff, 2, false), (true, 0x9002, 4));
+    assert_eq!(load(vec![block()], 0xff, 3, false), (false, 0x9002, 4));
+    assert_eq!(load(vec![block()], 0x00, 3, true), (false, 0x9000, 4));
+    assert_eq!(load(vec![block()], 0xff, 4, true), (false, 0x9004, 3));
+    let bad = Block::Data(DataBlock::standard(vec![0xff, 1, 2, 3, 1], 1000));
+    assert_eq!(load(vec![bad], 0xff, 3, true), (false, 0x9003, 3));
+    assert_eq!(load(vec![], 0xff, 3, true), (false, 0x9000, 4));
+}
+
+#[test]
+fn fast_load_is_undone_as_one_instruction() {
+    let data = saved(0xff, &[0x12, 0x34]);
+    let (mut cpu, mut tape) = ld_bytes_call(vec![Block::Data(DataBlock::standard(data, 1000))]);
+    cpu.history = Some(History::default());
+    cpu.a = 0xff;
+    cpu.ix = 0x9000;
+    cpu.write_de(2);
+    cpu.set_c(true);
+
+    assert!(fast_load(&mut cpu, &mut tape));
+    assert_eq!(&cpu.memory[0x9000..0x9002], &[0x12, 0x34]);
+    assert!(cpu.step_back());
+    assert_eq!(&cpu.memory[0x9000..0x9002], &[0, 0]);
+    assert_eq!(cpu.pc, LD_BYTES);
+    assert_eq!(cpu.ix, 0x9000);
+    assert!(!cpu.step_back());
+}
+
+#[test]
+fn fast_load_without_the_routine_in_memory() {
+    let mut cpu = CpuBuilder::new().with_memory(vec![0; 0x557]).build();
+    cpu.pc = LD_BYTES;
+    let mut tape = Tape::new(vec![]);
+    assert!(!fast_load(&mut cpu, &mut tape));
+
+    // Loading past the end of memory fails.
+    let data = saved(0xff, &[1, 2, 3]);
+    let mut memory = vec![0; 0x1000];
+    memory[usize::from(LD_BYTES)..usize::from(LD_BYTES) + 4]
+        .copy_from_slice(&[0x14, 0x08, 0x15, 0xf3]);
+    memory[0xf00..0xf02].copy_from_slice(&[0x03, 0x08]);
+    let mut cpu = CpuBuilder::new().with_memory(memory).build();
+    cpu.pc = LD_BYTES;
+    cpu.sp = 0xf00;
+    cpu.a = 0xff;
+    cpu.ix = 0xffe;
+    cpu.write_de(3);
+    cpu.set_c(true);
+    let mut tape = Tape::new(vec![Block::Data(DataBlock::standard(data, 1000))]);
+    assert!(fast_load(&mut cpu, &mut tape));
+    assert!(!cpu.get_c());
+    assert_eq!(&cpu.memory[0xffe..], &[1, 2]);
+    assert_eq!(cpu.pc, 0x0803);
+}
+
+#[test]
+fn fast_load_runs_with_the_tape() {
+    let tape = RefCell::new(Tape::new(vec![
+        Block::Data(DataBlock::standard(saved(0x00, &[0xaa; 17]), 1000)),
+        Block::LoopStart(2),
+        Block::Data(DataBlock::standard(saved(0xff, &[0x11, 0x22]), 1000)),
+        Block::LoopEnd,
+    ]));
+
+    // Loads the header, then the data block twice.
+    let program = asm!(
+        "        LD SP,0xff00",
+        "        LD IX,0x9000",
+        "        LD DE,17",
+        "        XOR A",
+        "        SCF",
+        "        CALL 0x0556",
+        "        JR NC,fail",
+        "        LD DE,2",
+        "        LD A,0xff",
+        "        SCF",
+        "        CALL 0x0556",
+        "        JR NC,fail",
+        "        LD DE,2",
+        "        LD A,0xff",
+        "        SCF",
+        "        CALL 0x0556",
+        "        JR NC,fail",
+        "        LD A,1",
+        "        HALT",
+        "fail:   LD A,2",
+        "        HALT",
+    );
+    let mut memory = vec![0; 0x10000];
+    memory[..program.len()].copy_from_slice(&program);
+    let start = usize::from(LD_BYTES);
+    memory[start..start + 4].copy_from_slice(&[0x14, 0x08, 0x15, 0xf3]);
+    let mut cpu = CpuBuilder::new().with_memory(memory).build();
+    tape.borrow_mut().play(0);
+
+    let (reason, _) = run_fast_load(&mut cpu, &tape, Default::default());
+    assert_eq!(reason, ::cpu::StopReason::Halted);
+    assert_eq!(cpu.a, 1);
+    assert_eq!(cpu.ix, 0x9015);
+    assert_eq!(
+        &cpu.memory[0x9010..0x9016],
+        &[0xaa, 0x11, 0x22, 0x11, 0x22, 0]
+    );
+    assert!(tape.borrow().is_finished());
+}
+
+#[test]
+fn run_fast_load_stops_at_a_breakpoint_and_counts_the_trap() {
+    let data = saved(0xff, &[0x12, 0x34]);
+    let (mut cpu, tape) = ld_bytes_call(vec![Block::Data(DataBlock::standard(data, 1000))]);
+    let tape = RefCell::new(tape);
+    cpu.memory[0x8000..0x8004].copy_from_slice(&[0xcd, 0x56, 0x05, 0x76]);
+    cpu.pc = 0x8000;
+    cpu.sp = 0xff02;
+    cpu.a = 0xff;
+    cpu.ix = 0x9000;
+    cpu.write_de(2);
+    cpu.set_c(true);
+    let id = cpu.breakpoints.add(Kind::Execute(LD_BYTES), None).unwrap();
+
+    let (reason, executed) = run_fast_load(&mut cpu, &tape, Default::default());
+    assert_eq!((reason, executed), (StopReason::Breakpoint(id), 1));
+    assert_eq!((cpu.pc, cpu.memory[0x9000]), (LD_BYTES, 0));
+
+    let limits = Limits {
+        instructions: Some(1),
+        cycles: None,
+    };
+    let (reason, executed) = run_fast_load(&mut cpu, &tape, limits);
+    assert_eq!((reason, executed), (StopReason::InstructionLimit, 1));
+    assert_eq!((cpu.pc, cpu.memory[0x9000]), (0x8003, 0x12));
+}
+
+#[test]
+fn tape_next_data() {
+    let block = |data: Vec<u8>| {
+        Block::Data(DataBlock {
+            pilot: 0,
+            pilot_pulses: 0,
+            sync1: 0,
+            sync2: 0,
+            zero: 1000,
+            one: 2000,
+            last_bits: 8,
+            pause: 10,
+            data,
+        })
+    };
+    let mut tape = Tape::new(vec![block(vec![1]), block(vec![2]), block(vec![3])]);
+
+    // The block playing, but the next one in the pause of the second.
+    tape.play(0);
+    assert_eq!(tape.next_data(0), Some(vec![1]));
+    assert!(tape.is_playing());
+    tape.ear(16000 + MILLISECOND as u64);
+    assert_eq!(tape.next_data(20000), Some(vec![3]));
+    assert_eq!(tape.next_data(20000), None);
+    assert!(tape.is_finished());
+}
@@ -0,0 +1,207 @@
+use super::tape::{Block, DataBlock};
+use super::word;
+use loader::LoadError;
+
+// === TZX tapes ===
+//
+// A 10 byte header, "ZXTape!", 0x1a and the version, then blocks of an
+// id and their fields, little endian:
+//
+//   10  standard speed data: pause, length, data
+//   11  turbo speed data: pilot, sync1, sync2, zero and one lengths,
+//       pilot pulses, bits of the last byte, pause, 24 bit length, data
+//   12  pure tone: pulse length, pulses
+//   13  pulse sequence: count, lengths
+//   14  pure data: zero and one lengths, bits of the last byte, pause,
+//       24 bit length, data
+//   20  pause, or stop the tape when 0
+//   21  group start: name    22  group end
+//   24  loop start: repeats  25  loop end
+//
+// Text, archive, hardware, custom and glue blocks are skipped, and so
+// are the jump (23), call and return (26, 27), select (28), stop in 48K
+// mode (2a) and signal level (2b) blocks: the tape plays straight
+// through.
+
+const MAGIC: &[u8; 8] = b"ZXTape!\x1a";
+const HEADER: usize = 10;
+
+/// The blocks of a .tzx file.
+pub(super) fn read(data: &[u8]) -> Result<Vec<Block>, LoadError> {
+    if data.len() < HEADER || &data[..8] != MAGIC {
+        return Err(LoadError::new("Not a TZX tape".to_string()));
+    }
+    if data[8] != 1 {
+        return Err(LoadError::new(format!(
+            "TZX tape version {}.{} is not supported",
+            data[8], data[9]
+        )));
+    }
+
+    let mut blocks = Vec::new();
+    let mut offset = HEADER;
+    while offset < data.len() {
+        let id = data[offset];
+        let mut reader = Reader {
+            data,
+            offset: offset + 1,
+            id,
+        };
+        if let Some(block) = block(&mut reader)? {
+            blocks.push(block);
+        }
+        offset = reader.offset;
+    }
+    Ok(blocks)
+}
+
+/// Fields of the block being read.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    id: u8,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], LoadError> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + count)
+            .ok_or_else(|| {
+                LoadError::new(format!(
+                    "Truncated TZX block 0x{:02x} at offset {}",
+                    self.id, self.offset
+                ))
+            })?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, LoadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u16, LoadError> {
+        Ok(word(self.bytes(2)?, 0))
+    }
+
+    fn length24(&mut self) -> Result<usize, LoadError> {
+        let bytes = self.bytes(3)?;
+        Ok(usize::from(bytes[0]) | usize::from(bytes[1]) << 8 | usize::from(bytes[2]) << 16)
+    }
+
+    fn length32(&mut self) -> Result<usize, LoadError> {
+        let low = usize::from(self.word()?);
+        Ok(low | usize::from(self.word()?) << 16)
+    }
+}
+
+fn block(reader: &mut Reader) -> Result<Option<Block>, LoadError> {
+    let block = match reader.id {
+        0x10 => {
+            let pause = reader.word()?;
+            let length = usize::from(reader.word()?);
+            let data = reader.bytes(length)?.to_vec();
+            Block::Data(DataBlock::standard(data, pause))
+        }
+        0x11 => {
+            let pilot = reader.word()?;
+            let sync1 = reader.word()?;
+            let sync2 = reader.word()?;
+            let zero = reader.word()?;
+            let one = reader.word()?;
+            let pilot_pulses = reader.word()?;
+            let last_bits = reader.byte()?;
+            let pause = reader.word()?;
+            let length = reader.length24()?;
+            Block::Data(DataBlock {
+                pilot,
+                pilot_pulses,
+                sync1,
+                sync2,
+                zero,
+                one,
+                last_bits,
+                pause,
+                data: reader.bytes(length)?.to_vec(),
+            })
+        }
+        0x12 => {
+            let length = reader.word()?;
+            let pulses = reader.word()?;
+            Block::Tone { length, pulses }
+        }
+        0x13 => {
+            let count = reader.byte()?;
+            let lengths = (0..count)
+                .map(|_| reader.word())
+                .collect::<Result<Vec<u16>, LoadError>>()?;
+            Block::Pulses(lengths)
+        }
+        0x14 => {
+            let zero = reader.word()?;
+            let one = reader.word()?;
+            let last_bits = reader.byte()?;
+            let pause = reader.word()?;
+            let length = reader.length24()?;
+            Block::Data(DataBlock {
+                pilot: 0,
+                pilot_pulses: 0,
+                sync1: 0,
+                sync2: 0,
+                zero,
+                one,
+                last_bits,
+                pause,
+                data: reader.bytes(length)?.to_vec(),
+            })
+        }
+        0x20 => Block::Pause(reader.word()?),
+        0x21 => {
+            let length = usize::from(reader.byte()?);
+            Block::GroupStart(String::from_utf8_lossy(reader.bytes(length)?).into_owned())
+        }
+        0x22 => Block::GroupEnd,
+        0x24 => Block::LoopStart(reader.word()?),
+        0x25 => Block::LoopEnd,
+        // Jump, call sequence, return, select block.
+        0x23 => return skip(reader, |_| Ok(2)),
+        0x26 => return skip(reader, |reader| Ok(usize::from(reader.word()?) * 2)),
+        0x27 => return Ok(None),
+        0x28 => return skip(reader, |reader| Ok(usize::from(reader.word()?))),
+        // Stop the tape in 48K mode, set signal level.
+        0x2a | 0x2b => return skip(reader, |reader| reader.length32()),
+        // Text description, message, archive info, hardware type.
+        0x30 => return skip(reader, |reader| Ok(usize::from(reader.byte()?))),
+        0x31 => {
+            reader.byte()?;
+            return skip(reader, |reader| Ok(usize::from(reader.byte()?)));
+        }
+        0x32 => return skip(reader, |reader| Ok(usize::from(reader.word()?))),
+        0x33 => return skip(reader, |reader| Ok(usize::from(reader.byte()?) * 3)),
+        // Custom info, glue.
+        0x35 => {
+            reader.bytes(16)?;
+            return skip(reader, |reader| reader.length32());
+        }
+        0x5a => return skip(reader, |_| Ok(9)),
+        id => {
+            return Err(LoadError::new(format!(
+                "Unsupported TZX block 0x{:02x} at offset {}",
+                id,
+                reader.offset - 1
+            )))
+        }
+    };
+    Ok(Some(block))
+}
+
+/// Skip a block of the length read first.
+fn skip<F>(reader: &mut Reader, length: F) -> Result<Option<Block>, LoadError>
+where
+    F: FnOnce(&mut Reader) -> Result<usize, LoadError>,
+{
+    let length = length(reader)?;
+    reader.bytes(length)?;
+    Ok(None)
+}
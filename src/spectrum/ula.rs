@@ -0,0 +1,53 @@
+use super::tape::Tape;
+use cpu::Ports;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The port of the Spectrum ULA, at the even addresses: reads give the
+/// keyboard, no key pressed, and the EAR input from the tape in bit 6;
+/// writes set the border. Other ports read 0xff.
+#[derive(Debug, Default)]
+pub struct Ula {
+    /// The tape in the player, shared with who controls it.
+    pub tape: Option<Rc<RefCell<Tape>>>,
+    pub border: u8,
+    cycles: u64,
+}
+
+impl Ula {
+    pub fn new() -> Ula {
+        Ula::default()
+    }
+
+    pub fn with_tape(mut self, tape: Rc<RefCell<Tape>>) -> Ula {
+        self.tape = Some(tape);
+        self
+    }
+}
+
+impl Ports for Ula {
+    fn input(&mut self, port: u16) -> u8 {
+        if port & 1 != 0 {
+            return 0xff;
+        }
+        let ear = match self.tape {
+            Some(ref tape) => tape.borrow_mut().ear(self.cycles),
+            None => false,
+        };
+        if ear {
+            0xff
+        } else {
+            0xbf
+        }
+    }
+
+    fn output(&mut self, port: u16, value: u8) {
+        if port & 1 == 0 {
+            self.border = value & 7;
+        }
+    }
+
+    fn clock(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+}
@@ -0,0 +1,302 @@
+use super::{put_word, word, Machine, Snapshot, BANK};
+use loader::LoadError;
+
+// === Z80 snapshots ===
+//
+// A 30 byte header of registers:
+//
+//   0  A     1  F     2  BC    4  HL    6  PC    8  SP   10  I   11  R
+//  12  R bit 7 in bit 0, border in bits 1 to 3, compressed RAM in bit 5
+//  13  DE   15  BC'  17  DE'  19  HL'  21  A'   22  F'   23  IY  25  IX
+//  27  IFF1 28  IFF2 29  IM in bits 0 and 1
+//
+// Version 1 follows with the 48K from 0x4000, compressed or not. A PC of
+// 0 marks versions 2 and 3: an extra header, of 23 or 54 bytes after its
+// length, with the PC, the hardware, port 0x7ffd and the AY registers;
+// then blocks of a 16K page: a length, 0xffff when not compressed, the
+// page number and the data. Pages 3 to 10 are the banks 0 to 7 of the
+// 128K; on the 48K page 8 is at 0x4000, 4 at 0x8000 and 5 at 0xc000.
+//
+// Runs of 5 bytes or more, or of 2 ED bytes, are compressed to ED ED
+// count byte; the byte after a single ED is never part of a run.
+
+const HEADER: usize = 30;
+const VERSION_2: u16 = 23;
+const VERSION_3: u16 = 54;
+const VERSION_3_1FFD: u16 = 55;
+/// Pages of the 48K, at 0x4000, 0x8000 and 0xc000.
+const PAGES_48K: [u8; 3] = [8, 4, 5];
+/// End of the compressed memory of version 1.
+const END_MARKER: [u8; 4] = [0x00, 0xed, 0xed, 0x00];
+
+pub(super) fn read(data: &[u8]) -> Result<Snapshot, LoadError> {
+    if data.len() < HEADER {
+        return Err(LoadError::new("Truncated Z80 snapshot".to_string()));
+    }
+
+    // Some version 1 writers leave 255 for 1.
+    let flags = if data[12] == 0xff { 1 } else { data[12] };
+    let pc = word(data, 6);
+
+    let (machine, extra) = if pc != 0 {
+        (Machine::Spectrum48, 0)
+    } else {
+        if data.len() < HEADER + 2 {
+            return Err(LoadError::new("Truncated Z80 snapshot".to_string()));
+        }
+        let length = word(data, HEADER);
+        if length != VERSION_2 && length != VERSION_3 && length != VERSION_3_1FFD {
+            return Err(LoadError::new(format!(
+                "Z80 snapshot header of {} bytes, version 2 or 3 expected",
+                length
+            )));
+        }
+        if data.len() < HEADER + 2 + usize::from(length) {
+            return Err(LoadError::new("Truncated Z80 snapshot".to_string()));
+        }
+        (machine(data[34], length)?, usize::from(length) + 2)
+    };
+
+    let mut snapshot = Snapshot::new(machine);
+    {
+        let registers = &mut snapshot.registers;
+        registers.a = data[0];
+        registers.f = data[1];
+        registers.c = data[2];
+        registers.b = data[3];
+        registers.l = data[4];
+        registers.h = data[5];
+        registers.pc = pc;
+        registers.sp = word(data, 8);
+        registers.i = data[10];
+        registers.r = data[11] & 0x7f | (flags & 1) << 7;
+        registers.e = data[13];
+        registers.d = data[14];
+        registers.c1 = data[15];
+        registers.b1 = data[16];
+        registers.e1 = data[17];
+        registers.d1 = data[18];
+        registers.l1 = data[19];
+        registers.h1 = data[20];
+        registers.a1 = data[21];
+        registers.f1 = data[22];
+        registers.iy = word(data, 23);
+        registers.ix = word(data, 25);
+        registers.iff1 = data[27] != 0;
+        registers.iff2 = data[28] != 0;
+        registers.im = data[29] & 3;
+    }
+    snapshot.border = flags >> 1 & 7;
+
+    if extra == 0 {
+        let memory = &data[HEADER..];
+        let ram = if flags & 0x20 != 0 {
+            let memory = if memory.ends_with(&END_MARKER) {
+                &memory[..memory.len() - END_MARKER.len()]
+            } else {
+                memory
+            };
+            decompress(memory, 3 * BANK)?
+        } else {
+            memory.to_vec()
+        };
+        if ram.len() != 3 * BANK {
+            return Err(LoadError::new(format!(
+                "Z80 snapshot memory of {} bytes, {} expected",
+                ram.len(),
+                3 * BANK
+            )));
+        }
+        for (bank, ram) in ram.chunks(BANK).enumerate() {
+            snapshot.banks[bank].copy_from_slice(ram);
+        }
+        return Ok(snapshot);
+    }
+
+    snapshot.registers.pc = word(data, 32);
+    if machine == Machine::Spectrum128 {
+        snapshot.paging = data[35];
+    }
+    snapshot.ay_register = data[38];
+    snapshot.ay.copy_from_slice(&data[39..55]);
+
+    let mut offset = HEADER + extra;
+    while offset < data.len() {
+        if offset + 3 > data.len() {
+            return Err(LoadError::new("Truncated Z80 snapshot".to_string()));
+        }
+        let length = word(data, offset);
+        let page = data[offset + 2];
+        offset += 3;
+
+        let size = if length == 0xffff {
+            BANK
+        } else {
+            usize::from(length)
+        };
+        let block = data
+            .get(offset..offset + size)
+            .ok_or_else(|| LoadError::new(format!("Truncated Z80 snapshot page {}", page)))?;
+        offset += size;
+
+        let bank = match machine {
+            Machine::Spectrum48 => PAGES_48K.iter().position(|&p| p == page),
+            Machine::Spectrum128 if (3..11).contains(&page) => Some(usize::from(page - 3)),
+            Machine::Spectrum128 => None,
+        };
+        // ROM pages and pages of interfaces.
+        let bank = match bank {
+            Some(bank) => bank,
+            None => continue,
+        };
+
+        let ram = if length == 0xffff {
+            block.to_vec()
+        } else {
+            decompress(block, BANK)?
+        };
+        if ram.len() != BANK {
+            return Err(LoadError::new(format!(
+                "Z80 snapshot page {} of {} bytes, {} expected",
+                page,
+                ram.len(),
+                BANK
+            )));
+        }
+        snapshot.banks[bank].copy_from_slice(&ram);
+    }
+
+    Ok(snapshot)
+}
+
+/// The machine of the hardware mode of a version 2 or 3 header.
+fn machine(mode: u8, length: u16) -> Result<Machine, LoadError> {
+    let machine = match (mode, length == VERSION_2) {
+        // 48K, with Interface 1
+        (0, _) | (1, _) => Some(Machine::Spectrum48),
+        // 48K with M.G.T.
+        (3, false) => Some(Machine::Spectrum48),
+        // 128K, with Interface 1
+        (3, true) | (4, true) => Some(Machine::Spectrum128),
+        // 128K, with Interface 1 or M.G.T., +3, +2 and +2A
+        (4, false) | (5, false) | (6, false) | (7, false) | (12, false) | (13, false) => {
+            Some(Machine::Spectrum128)
+        }
+        _ => None,
+    };
+    machine
+        .ok_or_else(|| LoadError::new(format!("Unsupported hardware {} in the Z80 snapshot", mode)))
+}
+
+pub(super) fn write(snapshot: &Snapshot) -> Result<Vec<u8>, LoadError> {
+    let registers = &snapshot.registers;
+    let pair = |high: u8, low: u8| u16::from(high) << 8 | u16::from(low);
+
+    let mut data = vec![registers.a, registers.f];
+    put_word(&mut data, pair(registers.b, registers.c));
+    put_word(&mut data, pair(registers.h, registers.l));
+    // The PC is in the extra header.
+    put_word(&mut data, 0);
+    put_word(&mut data, registers.sp);
+    data.push(registers.i);
+    data.push(registers.r & 0x7f);
+    data.push(registers.r >> 7 | (snapshot.border & 7) << 1);
+    put_word(&mut data, pair(registers.d, registers.e));
+    put_word(&mut data, pair(registers.b1, registers.c1));
+    put_word(&mut data, pair(registers.d1, registers.e1));
+    put_word(&mut data, pair(registers.h1, registers.l1));
+    data.push(registers.a1);
+    data.push(registers.f1);
+    put_word(&mut data, registers.iy);
+    put_word(&mut data, registers.ix);
+    data.push(registers.iff1 as u8);
+    data.push(registers.iff2 as u8);
+    data.push(registers.im & 3);
+
+    let mut extra = vec![0; usize::from(VERSION_3)];
+    extra[..2].copy_from_slice(&registers.pc.to_le_bytes());
+    match snapshot.machine {
+        Machine::Spectrum48 => extra[2] = 0,
+        Machine::Spectrum128 => {
+            extra[2] = 4;
+            extra[3] = snapshot.paging;
+            // AY sound chip in use.
+            extra[5] = 0x04;
+        }
+    }
+    extra[6] = snapshot.ay_register;
+    extra[7..23].copy_from_slice(&snapshot.ay);
+    // 0 to 16K is ROM.
+    extra[29] = 0xff;
+    extra[30] = 0xff;
+    put_word(&mut data, VERSION_3);
+    data.extend_from_slice(&extra);
+
+    for (bank, ram) in snapshot.banks.iter().enumerate() {
+        let page = match snapshot.machine {
+            Machine::Spectrum48 => PAGES_48K[bank],
+            Machine::Spectrum128 => bank as u8 + 3,
+        };
+        let compressed = compress(ram);
+        if compressed.len() < BANK {
+            put_word(&mut data, compressed.len() as u16);
+            data.push(page);
+            data.extend_from_slice(&compressed);
+        } else {
+            put_word(&mut data, 0xffff);
+            data.push(page);
+            data.extend_from_slice(ram);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Compress a block of memory.
+pub(super) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        let byte = data[index];
+        let run = data[index..]
+            .iter()
+            .take(255)
+            .take_while(|&&other| other == byte)
+            .count();
+
+        if run >= 5 || (byte == 0xed && run >= 2) {
+            compressed.extend_from_slice(&[0xed, 0xed, run as u8, byte]);
+            index += run;
+        } else {
+            compressed.push(byte);
+            index += 1;
+            if byte == 0xed && index < data.len() {
+                compressed.push(data[index]);
+                index += 1;
+            }
+        }
+    }
+    compressed
+}
+
+/// Decompress a block of memory, to at most SIZE bytes.
+pub(super) fn decompress(data: &[u8], size: usize) -> Result<Vec<u8>, LoadError> {
+    let mut memory = Vec::with_capacity(size);
+    let mut index = 0;
+    while index < data.len() && memory.len() < size {
+        if data[index] == 0xed && data.get(index + 1) == Some(&0xed) {
+            if index + 4 > data.len() {
+                return Err(LoadError::new("Truncated Z80 snapshot run".to_string()));
+            }
+            let (count, byte) = (usize::from(data[index + 2]), data[index + 3]);
+            let length = memory.len() + count;
+            memory.resize(length, byte);
+            index += 4;
+        } else {
+            memory.push(data[index]);
+            index += 1;
+        }
+    }
+    memory.truncate(size);
+    Ok(memory)
+}
@@ -0,0 +1,434 @@
+use loader::LoadError;
+
+// === zlib streams ===
+//
+// The RAM pages of SZX snapshots are zlib streams (RFC 1950): a two byte
+// header, DEFLATE data (RFC 1951) and the Adler-32 of the data. All the
+// block types are read: stored, fixed and dynamic Huffman codes. Pages
+// are written as a single block of fixed codes, with greedy matches on
+// the last positions of each 3 byte sequence.
+
+const MAX_BITS: usize = 15;
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Earlier positions of a sequence tried for a match.
+const MAX_CHAIN: usize = 64;
+
+/// Base lengths of the length symbols 257 to 285, and their extra bits.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances of the distance symbols, and their extra bits.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order of the code length code lengths of dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn error(message: &str) -> LoadError {
+    LoadError::new(format!("Invalid zlib data: {}", message))
+}
+
+/// Adler-32 checksum.
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// Bits of a DEFLATE stream, from the least significant of each byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, LoadError> {
+        let mut value = 0;
+        for index in 0..count {
+            let byte = *self
+                .data
+                .get(self.offset)
+                .ok_or_else(|| error("truncated"))?;
+            value |= u32::from(byte >> self.bit & 1) << index;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.offset += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Skip to the next byte.
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.offset += 1;
+        }
+    }
+}
+
+/// A canonical Huffman code: the number of codes of each length, and
+/// the symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; MAX_BITS + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = Vec::new();
+        for length in 1..=MAX_BITS {
+            for (symbol, &other) in lengths.iter().enumerate() {
+                if usize::from(other) == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, LoadError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(error("bad code"))
+    }
+}
+
+/// The fixed codes of literals and lengths, and of distances.
+fn fixed_lengths() -> (Vec<u8>, Vec<u8>) {
+    let literals = (0..288)
+        .map(|symbol| match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        })
+        .collect();
+    (literals, vec![5; 30])
+}
+
+/// The codes of a dynamic block, from its header.
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), LoadError> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+
+    let mut lengths = [0; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[index] = reader.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let symbol = code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| error("repeat of no length"))?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() != literals + distances {
+        return Err(error("code lengths overflow"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+/// Decompress a zlib stream of exactly size bytes, failing as soon as
+/// the output goes past it.
+pub(super) fn decompress(data: &[u8], size: usize) -> Result<Vec<u8>, LoadError> {
+    if data.len() < 6 {
+        return Err(error("truncated"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(error("not a DEFLATE stream"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(error("preset dictionary"));
+    }
+
+    let mut reader = BitReader {
+        data: &data[..data.len() - 4],
+        offset: 2,
+        bit: 0,
+    };
+    let mut output: Vec<u8> = Vec::with_capacity(size);
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let offset = reader.offset;
+                let header = reader
+                    .data
+                    .get(offset..offset + 4)
+                    .ok_or_else(|| error("truncated"))?;
+                let length = usize::from(header[0]) | usize::from(header[1]) << 8;
+                if length != !(usize::from(header[2]) | usize::from(header[3]) << 8) & 0xffff {
+                    return Err(error("bad stored block length"));
+                }
+                let block = reader
+                    .data
+                    .get(offset + 4..offset + 4 + length)
+                    .ok_or_else(|| error("truncated"))?;
+                if output.len() + length > size {
+                    return Err(error("too long"));
+                }
+                output.extend_from_slice(block);
+                reader.offset += 4 + length;
+            }
+            kind @ 1 | kind @ 2 => {
+                let (literals, distances) = if kind == 1 {
+                    let (literals, distances) = fixed_lengths();
+                    (Huffman::new(&literals), Huffman::new(&distances))
+                } else {
+                    dynamic_codes(&mut reader)?
+                };
+                inflate_block(&mut reader, &literals, &distances, &mut output, size)?;
+            }
+            _ => return Err(error("bad block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    if output.len() < size {
+        return Err(error("too short"));
+    }
+
+    let checksum = &data[data.len() - 4..];
+    let expected = u32::from(checksum[0]) << 24
+        | u32::from(checksum[1]) << 16
+        | u32::from(checksum[2]) << 8
+        | u32::from(checksum[3]);
+    if adler32(&output) != expected {
+        return Err(error("bad checksum"));
+    }
+    Ok(output)
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    literals: &Huffman,
+    distances: &Huffman,
+    output: &mut Vec<u8>,
+    size: usize,
+) -> Result<(), LoadError> {
+    loop {
+        let symbol = usize::from(literals.decode(reader)?);
+        if symbol < 256 {
+            if output.len() == size {
+                return Err(error("too long"));
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(error("bad length"));
+        }
+        let length =
+            usize::from(LENGTH_BASE[index]) + reader.bits(u32::from(LENGTH_EXTRA[index]))? as usize;
+        let index = usize::from(distances.decode(reader)?);
+        if index >= DISTANCE_BASE.len() {
+            return Err(error("bad distance"));
+        }
+        let distance = usize::from(DISTANCE_BASE[index])
+            + reader.bits(u32::from(DISTANCE_EXTRA[index]))? as usize;
+        if distance > output.len() {
+            return Err(error("distance too far back"));
+        }
+        if output.len() + length > size {
+            return Err(error("too long"));
+        }
+        let start = output.len() - distance;
+        for offset in 0..length {
+            let byte = output[start + offset];
+            output.push(byte);
+        }
+    }
+}
+
+/// Bits of a DEFLATE stream being written.
+struct BitWriter {
+    data: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.data.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// A Huffman code, sent from its most significant bit.
+    fn code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.data.push(self.bits as u8);
+        }
+        self.data
+    }
+}
+
+/// Fixed code of a literal or length symbol.
+fn write_literal(writer: &mut BitWriter, symbol: usize) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|&base| usize::from(base) <= length)
+        .unwrap();
+    write_literal(writer, 257 + index);
+    writer.bits(
+        (length - usize::from(LENGTH_BASE[index])) as u32,
+        u32::from(LENGTH_EXTRA[index]),
+    );
+
+    let index = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| usize::from(base) <= distance)
+        .unwrap();
+    writer.code(index as u32, 5);
+    writer.bits(
+        (distance - usize::from(DISTANCE_BASE[index])) as u32,
+        u32::from(DISTANCE_EXTRA[index]),
+    );
+}
+
+/// Compress data to a zlib stream.
+pub(super) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        data: vec![0x78, 0x9c],
+        bits: 0,
+        count: 0,
+    };
+    // Last block, fixed codes.
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let hash = |position: usize| {
+        (usize::from(data[position]) << 10
+            ^ usize::from(data[position + 1]) << 5
+            ^ usize::from(data[position + 2]))
+            & (WINDOW - 1)
+    };
+    let mut heads = vec![usize::MAX; WINDOW];
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut best = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let limit = (data.len() - position).min(MAX_MATCH);
+            let mut candidate = heads[hash(position)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || position - candidate > WINDOW {
+                    break;
+                }
+                let length = (0..limit)
+                    .take_while(|&offset| data[candidate + offset] == data[position + offset])
+                    .count();
+                if length > best.0 {
+                    best = (length, position - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+            }
+        }
+
+        let step = if best.0 >= MIN_MATCH {
+            write_match(&mut writer, best.0, best.1);
+            best.0
+        } else {
+            write_literal(&mut writer, usize::from(data[position]));
+            1
+        };
+        for (next, earlier) in previous.iter_mut().enumerate().skip(position).take(step) {
+            if next + MIN_MATCH <= data.len() {
+                let key = hash(next);
+                *earlier = heads[key];
+                heads[key] = next;
+            }
+        }
+        position += step;
+    }
+
+    write_literal(&mut writer, 256);
+    let mut stream = writer.finish();
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests;
+
+use asm::{assemble_file, parse_number, Program};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// === Symbol table ===
+//
+// Names of addresses, from the symbol files of assemblers and linkers or
+// from a program assembled here. Text files are read a line per symbol,
+// whatever the tool that wrote them:
+//
+//   start EQU 0x8000             z80asm -s, and hand written files
+//   start: EQU 0x00008000        sjasmplus --sym
+//   START EQU 08000H             pasmo
+//   _main = $8000 ; addr, ...    z88dk .map, constants left out
+//
+// Comments start with ';'. Addresses are shown as the closest symbol at
+// or before them and an offset, as in `print+3`.
+
+/// Symbols by name, and the first one defined at each address.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    values: BTreeMap<String, u16>,
+    names: BTreeMap<u16, String>,
+    /// Names in definition order, to pick the first one at an address.
+    order: Vec<String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// The labels and constants of an assembled program.
+    pub fn from_program(program: &Program) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        for (name, &value) in &program.symbols {
+            table.insert(name, value as u16);
+        }
+        table
+    }
+
+    /// Define a symbol, or change its value.
+    pub fn insert(&mut self, name: &str, value: u16) {
+        match self.values.insert(name.to_string(), value) {
+            Some(old) if old == value => {}
+            Some(_) => self.index(),
+            None => {
+                self.order.push(name.to_string());
+                self.names.entry(value).or_insert_with(|| name.to_string());
+            }
+        }
+    }
+
+    fn index(&mut self) {
+        self.names.clear();
+        for name in &self.order {
+            let value = self.values[name];
+            self.names.entry(value).or_insert_with(|| name.clone());
+        }
+    }
+
+    /// Add the symbols of a symbol file or map. Returns the number of
+    /// symbols read.
+    pub fn add_text(&mut self, text: &str) -> Result<usize, String> {
+        let mut count = 0;
+
+        for (number, line) in text.lines().enumerate() {
+            let (code, comment) = match line.find(';') {
+                Some(at) => (&line[..at], &line[at + 1..]),
+                None => (line, ""),
+            };
+            // z88dk maps tell constants from addresses in the comment.
+            if comment.trim_start().starts_with("const") {
+                continue;
+            }
+
+            let words: Vec<&str> = code.split_whitespace().collect();
+            let (name, value) = match words.as_slice() {
+                [] => continue,
+                [name, equ, value] if equ.eq_ignore_ascii_case("equ") || *equ == "=" => {
+                    (name, value)
+                }
+                // Older z88dk maps: `name = $8000, G: module`.
+                [name, "=", value, ..] if value.ends_with(',') => (name, value),
+                _ => {
+                    return Err(format!(
+                        "line {}: expected NAME EQU VALUE or NAME = VALUE",
+                        number + 1
+                    ))
+                }
+            };
+
+            let name = name.trim_end_matches(':');
+            let value = parse_number(value.trim_end_matches(','))
+                .map_err(|e| format!("line {}: {}", number + 1, e.message))?;
+            if name.is_empty() {
+                return Err(format!("line {}: missing name", number + 1));
+            }
+            self.insert(name, value as u16);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Add the symbols of a file: a symbol file or map, or an assembler
+    /// source (.asm, .z80 or .s) which is assembled. Returns the number
+    /// of symbols read.
+    pub fn load(&mut self, path: &Path) -> Result<usize, String> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("asm") | Some("z80") | Some("s") => {
+                let program = assemble_file(path).map_err(|e| match e.file {
+                    Some(_) => e.to_string(),
+                    None => format!("{}: {}", path.display(), e),
+                })?;
+                let table = SymbolTable::from_program(&program);
+                for name in &table.order {
+                    self.insert(name, table.values[name]);
+                }
+                Ok(table.len())
+            }
+            _ => {
+                let text =
+                    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                self.add_text(&text)
+                    .map_err(|e| format!("{}: {}", path.display(), e))
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Value of the symbol.
+    pub fn value(&self, name: &str) -> Option<u16> {
+        self.values.get(name).cloned()
+    }
+
+    /// The first symbol defined at address, if any.
+    pub fn name_at(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// Address as the closest symbol at or before it and an offset, as in
+    /// `print+3`, or in hexadecimal without symbols before it.
+    pub fn symbolize(&self, address: u16) -> String {
+        match self.names.range(..=address).next_back() {
+            Some((&value, name)) if value == address => name.clone(),
+            Some((&value, name)) => format!("{}+{}", name, address - value),
+            None => format!("0x{:04x}", address),
+        }
+    }
+
+    /// Symbols and their values, by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.values
+            .iter()
+            .map(|(name, &value)| (name.as_str(), value))
+    }
+}
@@ -0,0 +1,91 @@
+use asm::assemble;
+use std::env;
+use std::fs;
+use symbols::SymbolTable;
+
+#[test]
+fn formats() {
+    let mut table = SymbolTable::new();
+
+    // z80asm -s, sjasmplus and pasmo.
+    let count = table
+        .add_text(
+            "start EQU 0x8000\n\
+             ; File generated by sjasmplus\n\
+             main.loop: EQU 0x00008003\n\
+             \n\
+             PRINT\tEQU 08010H\n",
+        )
+        .unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(table.value("main.loop"), Some(0x8003));
+    assert_eq!(table.value("PRINT"), Some(0x8010));
+
+    // z88dk maps, old and new.
+    let count = table
+        .add_text(
+            "_main                 = $8020 ; addr, public, , main_c, code_compiler, main.c:4\n\
+             __CODE_size           = $0120 ; const, public, def, , ,\n\
+             _puts                 = $8040, G: puts\n",
+        )
+        .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(table.value("_main"), Some(0x8020));
+    assert_eq!(table.value("_puts"), Some(0x8040));
+    assert_eq!(table.value("__CODE_size"), None);
+    assert_eq!(table.len(), 5);
+
+    assert_eq!(
+        table.add_text("start\n").unwrap_err(),
+        "line 1: expected NAME EQU VALUE or NAME = VALUE"
+    );
+    assert_eq!(
+        table.add_text("\nstart EQU zz\n").unwrap_err(),
+        "line 2: Invalid number: zz"
+    );
+}
+
+#[test]
+fn names() {
+    let mut table = SymbolTable::new();
+    table
+        .add_text("print EQU 0x10\nputs EQU 0x10\nloop EQU 0x18\n")
+        .unwrap();
+
+    assert_eq!(table.name_at(0x10), Some("print"));
+    assert_eq!(table.name_at(0x11), None);
+    assert_eq!(table.symbolize(0x10), "print");
+    assert_eq!(table.symbolize(0x13), "print+3");
+    assert_eq!(table.symbolize(0x20), "loop+8");
+    assert_eq!(table.symbolize(0x0f), "0x000f");
+
+    // print moves: puts is the first name left at 0x10.
+    table.insert("print", 0x20);
+    assert_eq!(table.name_at(0x10), Some("puts"));
+    assert_eq!(table.name_at(0x20), Some("print"));
+    assert_eq!(
+        table.iter().collect::<Vec<_>>(),
+        vec![("loop", 0x18), ("print", 0x20), ("puts", 0x10)]
+    );
+}
+
+#[test]
+fn programs() {
+    let source = "       ORG 0x100\nstart: CALL print\n       HALT\nprint: RET\nSIZE   EQU 4\n";
+    let table = SymbolTable::from_program(&assemble(source).unwrap());
+    assert_eq!(table.value("print"), Some(0x104));
+    assert_eq!(table.value("SIZE"), Some(4));
+
+    let path = env::temp_dir().join(format!("z80-symbols-{}.asm", ::std::process::id()));
+    fs::write(&path, source).unwrap();
+    let mut loaded = SymbolTable::new();
+    assert_eq!(loaded.load(&path), Ok(3));
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.value("start"), Some(0x100));
+
+    let missing = env::temp_dir().join("z80-symbols-missing.sym");
+    assert!(loaded
+        .load(&missing)
+        .unwrap_err()
+        .starts_with(&missing.display().to_string()));
+}
@@ -0,0 +1,234 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+
+// === Trace comparison ===
+//
+// Traces are text files with a line per instruction. A layout tells where
+// each register is on a line: in a whitespace separated column, or in a
+// NAME=value or NAME:value token anywhere on the line. Lines without a
+// readable PC, as headers and messages, are skipped; the remaining
+// instructions are compared in order and the first one where a field
+// present in both layouts differs is reported.
+
+/// Where a field is on a trace line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Locator {
+    /// Whitespace separated column, from 1 as in cut and awk.
+    Column(usize),
+    /// A NAME=value or NAME:value token, the name in any case.
+    Key(String),
+}
+
+impl Locator {
+    fn find<'a>(&self, line: &'a str) -> Option<&'a str> {
+        match *self {
+            Locator::Column(column) => line.split_whitespace().nth(column - 1),
+            Locator::Key(ref key) => line.split_whitespace().find_map(|token| {
+                let at = token.find(['=', ':'])?;
+                if token[..at].eq_ignore_ascii_case(key) {
+                    Some(&token[at + 1..])
+                } else {
+                    None
+                }
+            }),
+        }
+    }
+}
+
+/// The fields of a trace format, by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    fields: Vec<(String, Locator)>,
+}
+
+impl Layout {
+    /// The format of the traces written by `cpu::Trace`.
+    pub fn native() -> Layout {
+        let mut fields = vec![("PC".to_string(), Locator::Column(1))];
+        for name in &[
+            "AF", "BC", "DE", "HL", "IX", "IY", "SP", "AF'", "BC'", "DE'", "HL'", "I", "R", "IFF",
+            "F", "T",
+        ] {
+            fields.push((name.to_string(), Locator::Key(name.to_string())));
+        }
+        Layout { fields }
+    }
+
+    /// Parse a layout such as `PC=1,AF=af,BC=bc,SP=7`: field names are
+    /// those of the native format, located by column when the locator is
+    /// a number, else by key. PC is required.
+    pub fn parse(spec: &str) -> Result<Layout, String> {
+        let mut fields = Vec::new();
+
+        for item in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let mut parts = item.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim().to_uppercase();
+            let locator = match parts.next().map(str::trim) {
+                Some(text) if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) => {
+                    match text.parse() {
+                        Ok(column) if column > 0 => Locator::Column(column),
+                        _ => return Err(format!("Invalid column: {}", item)),
+                    }
+                }
+                Some(text) if !text.is_empty() && !name.is_empty() => {
+                    Locator::Key(text.to_string())
+                }
+                _ => return Err(format!("Invalid field: {}", item)),
+            };
+            fields.push((name, locator));
+        }
+
+        if !fields.iter().any(|(name, _)| name == "PC") {
+            return Err("The layout has no PC".to_string());
+        }
+        Ok(Layout { fields })
+    }
+
+    /// Field names, in layout order.
+    pub fn names(&self) -> Vec<&str> {
+        self.fields.iter().map(|(name, _)| name.as_str()).collect()
+    }
+}
+
+/// An instruction of a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Line number in the file, from 1.
+    pub line: usize,
+    pub text: String,
+    /// Normalized values by field name.
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Record {
+    pub fn pc(&self) -> u16 {
+        u16::from_str_radix(&self.fields["PC"], 16).unwrap_or(0)
+    }
+}
+
+/// Hexadecimal numbers, with or without 0x, $ or h, are written in lower
+/// case without leading zeros so that 0x0100 matches 100h; anything else
+/// is kept as it is.
+fn normalize(value: &str) -> String {
+    let digits = value
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+        .trim_start_matches('$')
+        .trim_end_matches(['h', 'H']);
+
+    match u64::from_str_radix(digits, 16) {
+        Ok(number) => format!("{:x}", number),
+        Err(_) => value.to_string(),
+    }
+}
+
+/// The instructions of a trace: the lines with a PC below 0x10000.
+pub fn parse(text: &str, layout: &Layout) -> Vec<Record> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(number, line)| {
+            let fields: BTreeMap<String, String> = layout
+                .fields
+                .iter()
+                .filter_map(|(name, locator)| {
+                    locator
+                        .find(line)
+                        .map(|value| (name.clone(), normalize(value)))
+                })
+                .collect();
+
+            match fields.get("PC").map(|pc| u32::from_str_radix(pc, 16)) {
+                Some(Ok(pc)) if pc <= 0xffff => Some(Record {
+                    line: number + 1,
+                    text: line.to_string(),
+                    fields,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Records from the first one at the address, None if never reached.
+pub fn start_at(records: &[Record], address: u16) -> Option<&[Record]> {
+    records
+        .iter()
+        .position(|record| record.pc() == address)
+        .map(|start| &records[start..])
+}
+
+/// The first instruction where two traces differ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Index of the instruction in both traces.
+    pub index: usize,
+    /// Fields with different values: name, value in a, value in b.
+    /// Empty when one trace ends before the other.
+    pub fields: Vec<(String, String, String)>,
+}
+
+/// Compare the given fields of two traces; a field missing from a line
+/// is not compared.
+pub fn compare(a: &[Record], b: &[Record], names: &[String]) -> Option<Divergence> {
+    for (index, (x, y)) in a.iter().zip(b).enumerate() {
+        let fields: Vec<(String, String, String)> = names
+            .iter()
+            .filter_map(|name| match (x.fields.get(name), y.fields.get(name)) {
+                (Some(u), Some(v)) if u != v => Some((name.clone(), u.clone(), v.clone())),
+                _ => None,
+            })
+            .collect();
+
+        if !fields.is_empty() {
+            return Some(Divergence { index, fields });
+        }
+    }
+
+    if a.len() != b.len() {
+        Some(Divergence {
+            index: a.len().min(b.len()),
+            fields: Vec::new(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Describe a divergence, with the lines of the instructions before it
+/// in each trace.
+pub fn report(traces: [(&str, &[Record]); 2], divergence: &Divergence, context: usize) -> String {
+    let index = divergence.index;
+    let mut text = format!("First difference at instruction {}", index + 1);
+
+    if divergence.fields.is_empty() {
+        for &(name, records) in &traces {
+            if records.len() == index {
+                text.push_str(&format!(": {} ends", name));
+                break;
+            }
+        }
+        text.push('\n');
+    } else {
+        text.push_str(":\n");
+        for (name, a, b) in &divergence.fields {
+            text.push_str(&format!("  {}: {} != {}\n", name, a, b));
+        }
+    }
+
+    for &(name, records) in &traces {
+        text.push_str(&format!("\n{}:\n", name));
+        let first = index.saturating_sub(context);
+        for (i, record) in records.iter().enumerate().take(index + 1).skip(first) {
+            let mark = if i == index { '>' } else { ' ' };
+            text.push_str(&format!("{} {:6}  {}\n", mark, record.line, record.text));
+        }
+    }
+
+    text
+}
@@ -0,0 +1,161 @@
+use cpu::CpuBuilder;
+use tracediff::{compare, parse, report, start_at, Divergence, Layout};
+
+/// Native trace of the program, a line per instruction up to HALT.
+fn trace(code: Vec<u8>, a: u8) -> String {
+    let mut memory = code;
+    memory.resize(0x100, 0);
+    let mut cpu = CpuBuilder::new().with_memory(memory).with_a(a).build();
+
+    let mut text = String::new();
+    while !cpu.halted {
+        text.push_str(&cpu.trace_line());
+        text.push('\n');
+        cpu.step();
+    }
+    text
+}
+
+fn program() -> Vec<u8> {
+    asm!("ADD A,B", "LD B,3", "loop: DJNZ loop", "ADD A,1", "HALT")
+}
+
+fn native_names() -> Vec<String> {
+    Layout::native()
+        .names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+#[test]
+fn layouts() {
+    let layout = Layout::parse("pc=2, AF=af, SP=sp").unwrap();
+    assert_eq!(layout.names(), vec!["PC", "AF", "SP"]);
+    assert_eq!(layout, Layout::parse("PC=2,AF=af,SP=sp,").unwrap());
+
+    assert_eq!(Layout::parse("AF=1").unwrap_err(), "The layout has no PC");
+    assert_eq!(Layout::parse("PC=0").unwrap_err(), "Invalid column: PC=0");
+    assert_eq!(Layout::parse("PC=1,AF").unwrap_err(), "Invalid field: AF");
+    assert_eq!(Layout::parse("PC=1,=af").unwrap_err(), "Invalid field: =af");
+
+    assert!(Layout::native().names().contains(&"HL'"));
+}
+
+#[test]
+fn records() {
+    let text = format!("Trace of test\n\n{}end\n", trace(program(), 0));
+    let records = parse(&text, &Layout::native());
+
+    assert_eq!(records.len(), 7);
+    assert_eq!(records[0].line, 3);
+    assert_eq!(records[0].pc(), 0);
+    assert_eq!(records[1].fields["PC"], "1");
+    assert_eq!(records[6].pc(), 7);
+    assert_eq!(records[6].fields["T"], "52");
+    assert_eq!(records[6].fields["F"], "------");
+    assert_eq!(records[4].fields["BC'"], "0");
+    assert!(records[6].text.starts_with("0007  76"));
+}
+
+#[test]
+fn identical_traces() {
+    let text = trace(program(), 0);
+    let records = parse(&text, &Layout::native());
+
+    assert_eq!(compare(&records, &records, &native_names()), None);
+}
+
+#[test]
+fn different_registers() {
+    let a = parse(&trace(program(), 0), &Layout::native());
+    let b = parse(&trace(program(), 0x7f), &Layout::native());
+
+    let divergence = compare(&a, &b, &native_names()).unwrap();
+    assert_eq!(
+        divergence,
+        Divergence {
+            index: 0,
+            fields: vec![("AF".to_string(), "0".to_string(), "7f00".to_string())],
+        }
+    );
+
+    // Zero flag set by ADD A,B only in a.
+    let ignored: Vec<String> = native_names()
+        .into_iter()
+        .filter(|name| name != "AF")
+        .collect();
+    let divergence = compare(&a, &b, &ignored).unwrap();
+    assert_eq!(divergence.index, 1);
+    assert_eq!(
+        divergence.fields,
+        vec![("F".to_string(), "-Z----".to_string(), "------".to_string())]
+    );
+}
+
+#[test]
+fn other_emulator() {
+    let ours = parse(&trace(program(), 0), &Layout::native());
+
+    // PC first, registers in upper case hex after a colon, SP as a column.
+    let reference = "\
+        0000 ffff AF:0000 BC:0000\n\
+        0001 ffff AF:0040 BC:0000\n\
+        0003 ffff AF:0040 BC:0300\n\
+        0003 ffff AF:0040 BC:0200\n\
+        0003 ffff AF:0040 BC:0100\n\
+        0005 ffff AF:0040 BC:0000\n\
+        0007 ffff AF:0100 BC:0000\n";
+    let layout = Layout::parse("PC=1,SP=2,AF=af,BC=bc").unwrap();
+    let theirs = parse(reference, &layout);
+
+    let divergence = compare(&ours, &theirs, &native_names()).unwrap();
+    assert_eq!(divergence.index, 0);
+    assert_eq!(
+        divergence.fields,
+        vec![("SP".to_string(), "0".to_string(), "ffff".to_string())]
+    );
+
+    let names = vec!["PC".to_string(), "AF".to_string(), "BC".to_string()];
+    assert_eq!(compare(&ours, &theirs, &names), None);
+    assert_eq!(compare(&ours, &theirs[..6], &names).unwrap().index, 6);
+}
+
+#[test]
+fn alignment() {
+    let ours = parse(&trace(program(), 0), &Layout::native());
+    let reference = "0200 AF=0000\n0005 AF=0040\n0007 AF=0100\n";
+    let theirs = parse(reference, &Layout::parse("PC=1,AF=af").unwrap());
+
+    let names = vec!["PC".to_string(), "AF".to_string()];
+    assert_eq!(compare(&ours, &theirs, &names).unwrap().index, 0);
+
+    let ours = start_at(&ours, 5).unwrap();
+    let theirs = start_at(&theirs, 5).unwrap();
+    assert_eq!(compare(ours, theirs, &names), None);
+    assert_eq!(start_at(ours, 0x1234), None);
+}
+
+#[test]
+fn reports() {
+    let text = trace(program(), 0);
+    let a = parse(&text, &Layout::native());
+    let b = parse(&text.replace("BC=0100", "BC=0101"), &Layout::native());
+
+    let divergence = compare(&a, &b, &native_names()).unwrap();
+    let text = report([("ours", &a[..]), ("theirs", &b[..])], &divergence, 1);
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "First difference at instruction 5:");
+    assert_eq!(lines[1], "  BC: 100 != 101");
+    assert_eq!(lines[3], "ours:");
+    assert!(lines[4].starts_with("       4  0003  10 fe "));
+    assert!(lines[5].starts_with(">      5  0003  10 fe ") && lines[5].contains("BC=0100"));
+    assert_eq!(lines[7], "theirs:");
+    assert!(lines[9].contains("BC=0101"));
+
+    let divergence = compare(&a, &b[..2], &native_names()).unwrap();
+    let text = report([("ours", &a[..]), ("theirs", &b[..2])], &divergence, 0);
+    assert!(text.starts_with("First difference at instruction 3: theirs ends\n"));
+    assert!(text.ends_with("theirs:\n"));
+}